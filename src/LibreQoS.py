@@ -28,8 +28,11 @@ from liblqos_python import is_lqosd_alive, clear_ip_mappings, delete_ip_mapping,
 	is_libre_already_running, create_lock_file, free_lock_file, add_ip_mapping, BatchedCommands
 
 # Automatically account for TCP overhead of plans. For example a 100Mbps plan needs to be set to 109Mbps for the user to ever see that result on a speed test
-# Does not apply to nodes of any sort, just endpoint devices
-tcpOverheadFactor = 1.09
+# Does not apply to nodes of any sort, just endpoint devices. Set by tcp_overhead_factor in /etc/lqos.conf.
+try:
+	from ispConfig import tcpOverheadFactor
+except ImportError:
+	tcpOverheadFactor = 1.09
 
 def shell(command):
 	if enableActualShellCommands:
@@ -260,8 +263,21 @@ def validateNetworkAndDevices():
 	else:
 		return False
 
-def loadSubscriberCircuits(shapedDevicesFile):
+def nodeNamesById(data, namesById=None):
+	# Map the optional node ids in network.json to node names. Queues are built by name,
+	# but ShapedDevices.csv may name a Parent Node by its id.
+	if namesById is None:
+		namesById = {}
+	for elem in data:
+		if 'id' in data[elem]:
+			namesById[str(data[elem]['id'])] = elem
+		if 'children' in data[elem]:
+			nodeNamesById(data[elem]['children'], namesById)
+	return namesById
+
+def loadSubscriberCircuits(shapedDevicesFile, network):
 	# Load Subscriber Circuits & Devices
+	namesById = nodeNamesById(network)
 	subscriberCircuits = []
 	knownCircuitIDs = []
 	counterForCircuitsWithoutParentNodes = 0
@@ -277,6 +293,8 @@ def loadSubscriberCircuits(shapedDevicesFile):
 		commentsRemoved.pop(0)
 		for row in commentsRemoved:
 			circuitID, circuitName, deviceID, deviceName, ParentNode, mac, ipv4_input, ipv6_input, downloadMin, uploadMin, downloadMax, uploadMax, comment = row
+			# A Parent Node may be given by id; ids win over names, as in lqosd
+			ParentNode = namesById.get(ParentNode.strip(), ParentNode)
 			# If in monitorOnlyMode, override bandwidth rates to where no shaping will actually occur
 			if monitorOnlyMode == True:
 				downloadMin = 10000
@@ -443,13 +461,12 @@ def refreshShapers():
 	
 	if safeToRunRefresh == True:
 		
-		# Load Subscriber Circuits & Devices
-		subscriberCircuits,	dictForCircuitsWithoutParentNodes = loadSubscriberCircuits(shapedDevicesFile)
-		
-
 		# Load network hierarchy
 		with open(networkJSONfile, 'r') as j:
 			network = json.loads(j.read())
+
+		# Load Subscriber Circuits & Devices
+		subscriberCircuits,	dictForCircuitsWithoutParentNodes = loadSubscriberCircuits(shapedDevicesFile, network)
 		
 		
 		# Pull rx/tx queues / CPU cores available
@@ -961,8 +978,8 @@ def refreshShapersUpdateOnly():
 		lastUsedClassIDCounterByCPU = queuingStructure['lastUsedClassIDCounterByCPU']
 		generatedPNs = queuingStructure['generatedPNs']
 
-		newlyUpdatedSubscriberCircuits,	newlyUpdatedDictForCircuitsWithoutParentNodes = loadSubscriberCircuits('ShapedDevices.csv')					
-		lastLoadedSubscriberCircuits, lastLoadedDictForCircuitsWithoutParentNodes = loadSubscriberCircuits('ShapedDevices.lastLoaded.csv')		
+		newlyUpdatedSubscriberCircuits,	newlyUpdatedDictForCircuitsWithoutParentNodes = loadSubscriberCircuits('ShapedDevices.csv', network)					
+		lastLoadedSubscriberCircuits, lastLoadedDictForCircuitsWithoutParentNodes = loadSubscriberCircuits('ShapedDevices.lastLoaded.csv', network)		
 		
 		
 		# Load stats files
@@ -0,0 +1,114 @@
+//! Checks `network.json` and `ShapedDevices.csv` for nodes whose
+//! children are allowed more than the node itself, or whose circuits'
+//! minimum rates add up to more than it can carry. For example:
+//!
+//! `lqcapacity --all`
+//!
+//! Exits with status 1 if any problems are found, so it can be used
+//! from scripts.
+
+use anyhow::{Error, Result};
+use clap::Parser;
+use lqos_bus::{bus_request, BusRequest, BusResponse};
+use lqos_config::{ConfigShapedDevices, NetworkJson, NodeCapacity};
+use std::process::exit;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+  /// Read network.json and ShapedDevices.csv from disk, rather than
+  /// asking lqosd. Useful for checking files before lqosd loads them.
+  #[arg(long)]
+  offline: bool,
+
+  /// List every node, not just those with problems
+  #[arg(long)]
+  all: bool,
+
+  /// Print the report as JSON
+  #[arg(long)]
+  json: bool,
+}
+
+async fn report_from_lqosd() -> Result<Vec<NodeCapacity>> {
+  let responses = bus_request(vec![BusRequest::CheckNetworkCapacity]).await?;
+  match &responses[0] {
+    BusResponse::NetworkCapacity(report) => Ok(report.clone()),
+    BusResponse::Fail(err) => Err(Error::msg(err.clone())),
+    _ => Err(Error::msg("Unexpected response from lqosd")),
+  }
+}
+
+fn report_from_disk() -> Result<Vec<NodeCapacity>> {
+  let network = NetworkJson::load()
+    .map_err(|e| Error::msg(format!("network.json: {e}")))?;
+  let devices = ConfigShapedDevices::load()
+    .map_err(|e| Error::msg(format!("ShapedDevices.csv: {e}")))?;
+  Ok(network.check_capacity(&devices.devices))
+}
+
+fn mbps(rate: u64) -> String {
+  if rate == 0 {
+    "-".to_string()
+  } else {
+    rate.to_string()
+  }
+}
+
+fn ratio(ratio: Option<f64>) -> String {
+  ratio.map(|r| format!("{r:.2}x")).unwrap_or_else(|| "-".to_string())
+}
+
+fn print_report(report: &[NodeCapacity], all: bool) {
+  println!(
+    "{:<30} {:>15} {:>15} {:>8} {:>15} {:>15}",
+    "Node", "Limit", "Children", "Circuits", "Oversubscribed", "Guaranteed"
+  );
+  for node in report.iter().filter(|n| all || !n.issues.is_empty()) {
+    println!(
+      "{:<30} {:>15} {:>15} {:>8} {:>15} {:>15}",
+      node.name,
+      format!(
+        "{}/{}",
+        mbps(node.max_throughput.0 as u64),
+        mbps(node.max_throughput.1 as u64)
+      ),
+      format!("{}/{}", node.child_node_capacity.0, node.child_node_capacity.1),
+      node.circuits,
+      format!(
+        "{}/{}",
+        ratio(node.oversubscription.0),
+        ratio(node.oversubscription.1)
+      ),
+      format!("{}/{}", node.guaranteed.0, node.guaranteed.1),
+    );
+    for issue in node.issues.iter() {
+      println!("  ! {issue}");
+    }
+  }
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn main() -> Result<()> {
+  let args = Args::parse();
+  let report = if args.offline {
+    report_from_disk()?
+  } else {
+    report_from_lqosd().await?
+  };
+
+  if args.json {
+    println!("{}", serde_json::to_string_pretty(&report)?);
+  } else {
+    print_report(&report, args.all);
+  }
+
+  let problems: usize = report.iter().map(|n| n.issues.len()).sum();
+  if !args.json {
+    println!("\n{problems} problem(s) found. Rates are download/upload Mbps.");
+  }
+  if problems > 0 {
+    exit(1);
+  }
+  Ok(())
+}
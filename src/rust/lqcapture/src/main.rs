@@ -0,0 +1,47 @@
+//! Streams live packet captures from `lqosd` to standard output in
+//! libpcap format, for example:
+//!
+//! `lqcapture 100.64.1.2 --snaplen 1522 | wireshark -k -i -`
+
+use anyhow::{Error, Result};
+use clap::Parser;
+use lqos_bus::{BusRequest, BusResponse, BusStream};
+use std::io::Write;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+  /// IP address to capture
+  ip: String,
+
+  /// Number of octets to capture per packet (up to 1522). Defaults to
+  /// `packet_capture_snaplen` in /etc/lqos.conf.
+  #[arg(long)]
+  snaplen: Option<u32>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn main() -> Result<()> {
+  let args = Args::parse();
+  let mut stream = BusStream::new(BusRequest::StreamPacketData {
+    ip: args.ip,
+    snaplen: args.snaplen,
+  })
+  .await?;
+
+  let mut out = std::io::stdout().lock();
+  while let Some(response) = stream.next().await? {
+    match response {
+      BusResponse::PcapStream(data) => {
+        // Stop quietly if whatever we're piping into has gone away.
+        let written = out.write_all(&data).and_then(|_| out.flush());
+        if written.is_err() {
+          break;
+        }
+      }
+      BusResponse::Fail(e) => return Err(Error::msg(e)),
+      _ => {}
+    }
+  }
+  Ok(())
+}
@@ -4,6 +4,7 @@ mod reply;
 mod request;
 mod response;
 mod session;
+mod stream;
 mod unix_socket_server;
 mod queue_data;
 pub use client::bus_request;
@@ -13,6 +14,7 @@ pub use reply::BusReply;
 pub use request::BusRequest;
 pub use response::BusResponse;
 pub use session::BusSession;
+pub use stream::{BusStream, BusStreamReceiver, BusStreamSender};
 use thiserror::Error;
 pub use unix_socket_server::UnixSocketServer;
 pub use queue_data::*;
@@ -1,3 +1,4 @@
+use crate::TcHandle;
 use serde::{Serialize, Deserialize};
 
 
@@ -14,6 +15,8 @@ pub struct QueueStoreTransit {
   pub current_upload: CakeTransit,
 }
 
+/// Per-period queue statistics. Despite the name, this carries every
+/// qdisc kind: kinds without CAKE's tins report a single tin.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 #[allow(missing_docs)]
 pub struct CakeDiffTransit {
@@ -54,6 +57,8 @@ pub struct CakeTransit {
     //pub avg_hdr_offset: u16,
     //pub tins: Vec<CakeTinTransit>,
     //pub drops: u32,
+    /// The qdisc kind, e.g. `cake` or `fq_codel`.
+    pub kind: String,
 }
 
 /*
@@ -100,4 +105,229 @@ pub struct CakeTinTransit {
     //pub max_pkt_len: u16,
     //pub flow_quantum: u16,
 }
-*/
\ No newline at end of file
+*/
+/// One queue sweep's worth of data for a circuit. Tuples are
+/// `(download, upload)`. Drops and marks are counted since the previous
+/// sweep; delays are as reported by CAKE at sweep time (always zero for
+/// `fq_codel`, which doesn't report delay).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueSweepSample {
+  /// When the sample was taken, in seconds since the UNIX epoch.
+  pub unix_time: u64,
+  /// Packets dropped by the queue since the last sweep.
+  pub drops: (u64, u64),
+  /// Packets ECN-marked by the queue since the last sweep.
+  pub marks: (u64, u64),
+  /// Highest average delay across the queue's tins, in microseconds.
+  pub avg_delay_us: (u32, u32),
+  /// Highest peak delay across the queue's tins, in microseconds.
+  pub peak_delay_us: (u32, u32),
+}
+
+/// Drop, mark and delay summary for a circuit, gathered by the
+/// whole-box queue sweep.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CircuitQueueSummary {
+  /// The circuit ID, from `ShapedDevices.csv`.
+  pub circuit_id: String,
+  /// Packets dropped since `lqosd` started, `(download, upload)`.
+  pub total_drops: (u64, u64),
+  /// Packets ECN-marked since `lqosd` started, `(download, upload)`.
+  pub total_marks: (u64, u64),
+  /// Recent sweep samples, oldest first.
+  pub history: Vec<QueueSweepSample>,
+}
+
+/// A queue sweep sample from the on-disk queue history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueHistoryRecord {
+  /// When the sample was taken, in seconds since the Unix epoch.
+  pub unix_time: u64,
+  /// The circuit ID, from `ShapedDevices.csv`.
+  pub circuit_id: String,
+  /// Download queue change since the previous sample.
+  pub download: CakeDiffTransit,
+  /// Upload queue change since the previous sample.
+  pub upload: CakeDiffTransit,
+}
+
+/// Bufferbloat grade, from the queueing delay a circuit sees while it is
+/// busy. Thresholds follow the commonly used bufferbloat tests: under
+/// 30ms of added latency is an A, and 400ms or more is an F.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum BufferbloatGrade {
+  A,
+  B,
+  C,
+  D,
+  F,
+}
+
+/// A circuit's rolling bufferbloat grade. Tuples are `(download,
+/// upload)`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CircuitBufferbloat {
+  /// The circuit ID, from `ShapedDevices.csv`.
+  pub circuit_id: String,
+  /// The grade for the worse direction, or `None` if the circuit hasn't
+  /// been busy enough, recently enough, to grade.
+  pub grade: Option<BufferbloatGrade>,
+  /// Average queueing delay (CAKE's average delay, less its base delay)
+  /// while the circuit was busy, in microseconds.
+  pub queue_delay_us: (u32, u32),
+  /// How many sweep samples were taken while the circuit was busy.
+  pub loaded_samples: (u32, u32),
+}
+
+/// How many circuits have each bufferbloat grade.
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct BufferbloatDistribution {
+  pub a: u32,
+  pub b: u32,
+  pub c: u32,
+  pub d: u32,
+  pub f: u32,
+  /// Circuits that haven't been busy enough to grade.
+  pub ungraded: u32,
+}
+
+/// A node or circuit from `queuingStructure.json`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueNodeSummary {
+  /// The node's name, or the circuit's name.
+  pub name: Option<String>,
+  /// The circuit ID, if this is a circuit.
+  pub circuit_id: Option<String>,
+  /// Download HTB class.
+  pub class_id: TcHandle,
+  /// Upload HTB class.
+  pub up_class_id: TcHandle,
+  /// Download parent class.
+  pub parent_class_id: TcHandle,
+  /// Upload parent class.
+  pub up_parent_class_id: TcHandle,
+  /// The CPU that handles download traffic.
+  pub cpu: u32,
+  /// The CPU that handles upload traffic.
+  pub up_cpu: u32,
+  /// Guaranteed and maximum download, in Mbps.
+  pub download_mbps: (u64, u64),
+  /// Guaranteed and maximum upload, in Mbps.
+  pub upload_mbps: (u64, u64),
+}
+
+/// Where a circuit sits in the queue structure.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CircuitQueueInfo {
+  /// The circuit itself.
+  pub circuit: QueueNodeSummary,
+  /// The nodes above the circuit, nearest first.
+  pub parents: Vec<QueueNodeSummary>,
+}
+
+/// How many circuits, and how much bandwidth, each CPU has been given.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CpuQueueTotals {
+  /// The CPU number.
+  pub cpu: u32,
+  /// Circuits whose download queue is on this CPU.
+  pub circuits: u32,
+  /// Total guaranteed and maximum download, in Mbps.
+  pub download_mbps: (u64, u64),
+  /// Total guaranteed and maximum upload, in Mbps.
+  pub upload_mbps: (u64, u64),
+}
+
+/// Differences between the live `tc` tree and `queuingStructure.json`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueTreeAudit {
+  /// Classes in `queuingStructure.json` that the kernel doesn't have.
+  pub missing_classes: Vec<QueueTreeClass>,
+  /// HTB classes in the kernel that `queuingStructure.json` doesn't
+  /// mention. LibreQoS's own per-CPU classes (`x:1` and `x:2`) are not
+  /// reported.
+  pub extra_classes: Vec<QueueTreeClass>,
+  /// Classes whose rate or ceiling differs from `queuingStructure.json`.
+  pub rate_mismatches: Vec<QueueTreeRateMismatch>,
+  /// Circuit classes with no CAKE or fq_codel qdisc attached. Always
+  /// empty in monitor mode, which doesn't attach them.
+  pub missing_qdiscs: Vec<QueueTreeClass>,
+}
+
+impl QueueTreeAudit {
+  /// True if the live tree matches `queuingStructure.json`.
+  pub fn is_clean(&self) -> bool {
+    self.missing_classes.is_empty()
+      && self.extra_classes.is_empty()
+      && self.rate_mismatches.is_empty()
+      && self.missing_qdiscs.is_empty()
+  }
+}
+
+/// A class found (or not found) by a queue tree audit.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueTreeClass {
+  /// The interface the class is (or should be) on.
+  pub interface: String,
+  /// The class handle.
+  pub class_id: TcHandle,
+  /// Circuit ID or node name, if the class is in `queuingStructure.json`.
+  pub name: Option<String>,
+}
+
+/// A class whose rates don't match `queuingStructure.json`. Rates are
+/// in bits per second.
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QueueTreeRateMismatch {
+  pub class: QueueTreeClass,
+  pub expected_rate: u64,
+  pub expected_ceil: u64,
+  pub actual_rate: u64,
+  pub actual_ceil: u64,
+}
+
+/// Type used for displaying HTB class history. Mirrors
+/// `QueueStoreTransit`: a ring buffer of `(download, upload)` diffs,
+/// plus the current state of both classes.
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct HtbClassStoreTransit {
+  pub history: Vec<(HtbClassDiffTransit, HtbClassDiffTransit)>,
+  pub history_head: usize,
+  pub current_download: HtbClassTransit,
+  pub current_upload: HtbClassTransit,
+}
+
+/// Change in an HTB class's counters over one queue check period.
+/// `tokens` and `ctokens` are current values: negative means the class
+/// is over its rate (`tokens`) or ceiling (`ctokens`).
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct HtbClassDiffTransit {
+  pub bytes: u64,
+  pub packets: u32,
+  pub drops: u32,
+  pub overlimits: u32,
+  pub borrows: u32,
+  pub lends: u32,
+  pub tokens: i32,
+  pub ctokens: i32,
+}
+
+/// Current state of an HTB class. Rates are in bytes per second.
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct HtbClassTransit {
+  pub rate: u64,
+  pub ceil: u64,
+  pub bytes: u64,
+  pub drops: u32,
+  pub overlimits: u32,
+  pub borrows: u32,
+  pub lends: u32,
+  pub tokens: i32,
+  pub ctokens: i32,
+}
@@ -108,15 +108,110 @@ pub enum BusRequest {
   /// Request that we start watching a circuit's queue
   WatchQueue(String),
 
-  /// Request that the Rust side of things validate the CSV
+  /// Retrieve HTB class history for a circuit ID, or a network node
+  /// name. Requires `htb_class_stats` to be enabled.
+  GetHtbClassData(String),
+
+  /// Retrieve the queue sweep's drop, mark and delay history for a
+  /// circuit, by circuit ID. Requires `[queue_sweep]` to be enabled.
+  GetCircuitQueueSummary(String),
+
+  /// Retrieve a circuit's on-disk queue history between two Unix times,
+  /// inclusive and at most a day apart. Requires `[queue_history]` to be
+  /// enabled.
+  GetQueueHistory {
+    /// The circuit ID to retrieve
+    circuit_id: String,
+    /// Earliest sample time, in seconds since the Unix epoch
+    start: u64,
+    /// Latest sample time, in seconds since the Unix epoch
+    end: u64,
+  },
+
+  /// Retrieve queue sweep totals for every circuit. Only the most
+  /// recent sample is included in each circuit's history.
+  AllCircuitQueueSummaries,
+
+  /// Retrieve a circuit's rolling bufferbloat grade, by circuit ID.
+  /// Requires `[queue_sweep]` to be enabled.
+  GetCircuitBufferbloat(String),
+
+  /// Retrieve how many circuits have each bufferbloat grade.
+  BufferbloatDistribution,
+
+  /// Compare the live `tc` class and qdisc tree with
+  /// `queuingStructure.json`.
+  AuditQueueTree,
+
+  /// Change one circuit's HTB rates in place, without reloading
+  /// LibreQoS. `queuingStructure.json` is updated to match;
+  /// `ShapedDevices.csv` is not, so the next LibreQoS.py run restores
+  /// whatever it says. Rates are plan rates, as in `ShapedDevices.csv`:
+  /// the `tcp_overhead_factor` from `/etc/lqos.conf` is applied to them,
+  /// just as LibreQoS.py applies it.
+  UpdateCircuitRates {
+    /// The circuit ID, from `ShapedDevices.csv`
+    circuit_id: String,
+    /// Guaranteed download rate, in Mbps
+    download_min_mbps: u64,
+    /// Maximum download rate, in Mbps
+    download_max_mbps: u64,
+    /// Guaranteed upload rate, in Mbps
+    upload_min_mbps: u64,
+    /// Maximum upload rate, in Mbps
+    upload_max_mbps: u64,
+  },
+
+  /// Look up a circuit's classes, CPU and parent chain, by circuit ID.
+  GetCircuitQueueInfo(String),
+
+  /// List the circuits whose download queue is on a CPU.
+  CircuitsOnCpu(u32),
+
+  /// List the circuits below a class, at any depth.
+  CircuitsUnderClass(TcHandle),
+
+  /// Retrieve per-CPU circuit counts and bandwidth totals.
+  QueueCpuTotals,
+
+  /// Add, change or remove devices and circuits in ShapedDevices.csv.
+  /// The changes are applied together, or not at all, and the file is
+  /// replaced atomically.
+  ChangeShapedDevices {
+    /// The changes, applied in order
+    changes: Vec<lqos_config::ShapedDevicesChange>,
+    /// Run `LibreQoS.py --updateonly` afterwards, to apply the changes
+    /// to the queues
+    reload: bool,
+  },
+
+  /// Request that the Rust side of things validate the CSV, reporting
+  /// every problem it finds.
   ValidateShapedDevicesCsv,
 
+  /// Add, rename, move or delete nodes in network.json, or change their
+  /// bandwidth. The changes are applied together, or not at all, and the
+  /// file is replaced atomically.
+  ChangeNetworkJson {
+    /// The changes, applied in order
+    changes: Vec<lqos_config::NetworkJsonChange>,
+    /// Reload LibreQoS afterwards, to rebuild the queues
+    reload: bool,
+  },
+
   /// Request details of part of the network tree
   GetNetworkMap {
     /// The parent of the map to retrieve
     parent: usize,
   },
 
+  /// Request details of part of the network tree, by the parent's
+  /// stable `id` from network.json
+  GetNetworkMapById {
+    /// The `id` of the parent of the map to retrieve
+    id: String,
+  },
+
   /// Retrieves the top N queues from the root level, and summarizes
   /// the others as "other"
   TopMapQueues(usize),
@@ -124,29 +219,77 @@ pub enum BusRequest {
   /// Retrieve node names from network.json
   GetNodeNamesFromIds(Vec<usize>),
 
+  /// Find the current node indices of network.json nodes by their
+  /// stable `id`s
+  GetNodeIndicesFromIds(Vec<String>),
+
   /// Retrieve stats for all queues above a named circuit id
   GetFunnel {
-    /// Circuit being analyzed, as the named circuit id
+    /// Circuit being analyzed, as the named circuit id. A network.json
+    /// node `id` also works.
     target: String,
   },
 
+  /// Check network.json and ShapedDevices.csv for nodes that promise more
+  /// capacity than they have
+  CheckNetworkCapacity,
+
   /// Obtain the lqosd statistics
   GetLqosStats,
 
   /// Tell me flow stats for a given IP address
   GetFlowStats(String),
 
+  /// Tell me flow stats for every IP address in a circuit, by circuit id
+  GetCircuitFlows(String),
+
+  /// Retrieve the top N applications (grouped by protocol and server
+  /// port) by current throughput, across all tracked flows.
+  TopFlowApplications(usize),
+
+  /// Retrieve the top N remote IP addresses by current throughput,
+  /// across all tracked flows.
+  TopFlowDestinations(usize),
+
   /// Tell Heimdall to hyper-focus on an IP address for a bit
-  GatherPacketData(String),
+  GatherPacketData {
+    /// The IP address to watch
+    ip: String,
+    /// Number of octets to capture per packet. `None` uses the
+    /// `packet_capture_snaplen` default from `/etc/lqos.conf`.
+    snaplen: Option<u32>,
+  },
 
   /// Give me a dump of the last 10 seconds of packet headers
   GetPacketHeaderDump(usize),
 
-  /// Give me a libpcap format packet dump (shortened) of the last 10 seconds
+  /// Give me a libpcap format packet dump (truncated to the session's
+  /// snap length) of the last 10 seconds
   GetPcapDump(usize),
 
+  /// Stream libpcap format packet data for an IP address as it is
+  /// captured, until the client disconnects. This is a streaming
+  /// request: use `BusStream` rather than `bus_request`, and send it
+  /// on its own. Replies are a series of `BusResponse::PcapStream`
+  /// messages, the first of which holds the pcap file header.
+  StreamPacketData {
+    /// The IP address to watch
+    ip: String,
+    /// Number of octets to capture per packet. `None` uses the
+    /// `packet_capture_snaplen` default from `/etc/lqos.conf`.
+    snaplen: Option<u32>,
+  },
+
   /// If running on Equinix (the `equinix_test` feature is enabled),
   /// display a "run bandwidht test" link.
   #[cfg(feature = "equinix_tests")]
   RequestLqosEquinixTest,
 }
+
+impl BusRequest {
+  /// Returns `true` if the request keeps its connection open and
+  /// streams replies, rather than returning a single `BusReply`.
+  pub fn is_stream(&self) -> bool {
+    matches!(self, BusRequest::StreamPacketData { .. })
+  }
+}
@@ -1,7 +1,11 @@
-use crate::{IpMapping, IpStats, XdpPpingResult, FlowTransport, ip_stats::PacketHeader};
+use crate::{IpMapping, IpStats, XdpPpingResult, FlowTransport, FlowSummary, ip_stats::PacketHeader};
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
-use super::QueueStoreTransit;
+use super::{
+  BufferbloatDistribution, CircuitBufferbloat, CircuitQueueInfo,
+  CircuitQueueSummary, CpuQueueTotals, HtbClassStoreTransit, QueueNodeSummary,
+  QueueHistoryRecord, QueueStoreTransit, QueueTreeAudit,
+};
 
 /// A `BusResponse` object represents a single
 /// reply generated from a `BusRequest`, and batched
@@ -63,19 +67,56 @@ pub enum BusResponse {
   /// The results of reloading LibreQoS.
   ReloadLibreQoS(String),
 
-  /// Validation results for checking ShapedDevices.csv
-  ShapedDevicesValidation(String),
+  /// Every problem found when validating ShapedDevices.csv
+  ShapedDevicesValidation(Box<lqos_config::ShapedDevicesReport>),
 
   /// A string containing a JSON dump of a queue stats. Analagos to
   /// the response from `tc show qdisc`.
   RawQueueData(Option<Box<QueueStoreTransit>>),
 
+  /// HTB class history for a circuit or network node
+  HtbClassData(Option<Box<HtbClassStoreTransit>>),
+
+  /// Queue sweep history for a single circuit
+  CircuitQueueSummary(Option<Box<CircuitQueueSummary>>),
+
+  /// Queue sweep totals for every circuit
+  CircuitQueueSummaries(Vec<CircuitQueueSummary>),
+
+  /// On-disk queue history for a circuit, oldest first
+  QueueHistory(Vec<QueueHistoryRecord>),
+
+  /// A circuit's bufferbloat grade
+  CircuitBufferbloat(Option<CircuitBufferbloat>),
+
+  /// Bufferbloat grades across every circuit
+  BufferbloatDistribution(BufferbloatDistribution),
+
+  /// Differences between the live `tc` tree and the queue structure
+  QueueTreeAudit(Box<QueueTreeAudit>),
+
+  /// Where a circuit sits in the queue structure
+  CircuitQueueInfo(Option<Box<CircuitQueueInfo>>),
+
+  /// Circuits from the queue structure
+  QueueNodes(Vec<QueueNodeSummary>),
+
+  /// Per-CPU totals from the queue structure
+  QueueCpuTotals(Vec<CpuQueueTotals>),
+
   /// Results from network map queries
   NetworkMap(Vec<(usize, lqos_config::NetworkJsonTransport)>),
 
   /// Named nodes from network.json
   NodeNames(Vec<(usize, String)>),
 
+  /// Capacity totals and problems for every network.json node
+  NetworkCapacity(Vec<lqos_config::NodeCapacity>),
+
+  /// Current indices of network.json nodes, by `id`. Unknown ids are
+  /// left out.
+  NodeIndices(Vec<(String, usize)>),
+
   /// Statistics from lqosd
   LqosdStats{
     /// Number of bus requests handled
@@ -91,6 +132,12 @@ pub enum BusResponse {
   /// Flow Data
   FlowData(Vec<(FlowTransport, Option<FlowTransport>)>),
 
+  /// Top applications by current throughput
+  TopFlowApplications(Vec<FlowSummary>),
+
+  /// Top remote IP addresses by current throughput
+  TopFlowDestinations(Vec<FlowSummary>),
+
   /// The index of the new packet collection session
   PacketCollectionSession{ 
     /// The identifier of the capture session
@@ -104,4 +151,8 @@ pub enum BusResponse {
 
   /// Pcap format dump
   PcapDump(Option<String>),
+
+  /// A chunk of a live libpcap format stream. Concatenating the chunks,
+  /// in order, yields a valid pcap file.
+  PcapStream(Vec<u8>),
 }
@@ -0,0 +1,124 @@
+use crate::{
+  bus::BusClientError, decode_response, encode_request, encode_response,
+  BusReply, BusRequest, BusResponse, BusSession, BUS_SOCKET_PATH,
+};
+use log::error;
+use tokio::{
+  io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+  net::UnixStream,
+};
+
+/// Sending half of a bus stream. `lqosd` sub-systems push responses
+/// into this, and the bus server forwards each one to the client as
+/// it arrives.
+pub type BusStreamSender = tokio::sync::mpsc::Sender<BusResponse>;
+
+/// Receiving half of a bus stream, handed to the bus server by
+/// the stream request handler.
+pub type BusStreamReceiver = tokio::sync::mpsc::Receiver<BusResponse>;
+
+/// A client connection to a streaming bus request (such as
+/// `BusRequest::StreamPacketData`). Unlike `bus_request`, the connection
+/// stays open and `lqosd` keeps sending responses until either side
+/// hangs up.
+///
+/// Each message on the wire is a little-endian `u32` length, followed
+/// by a `bincode` encoded `BusReply` holding a single response.
+pub struct BusStream {
+  stream: UnixStream,
+}
+
+impl BusStream {
+  /// Connects to the bus and starts a streaming request.
+  ///
+  /// ## Arguments
+  ///
+  /// * `request` - the streaming request to make.
+  pub async fn new(request: BusRequest) -> Result<Self, BusClientError> {
+    let stream = UnixStream::connect(BUS_SOCKET_PATH).await;
+    if stream.is_err() {
+      error!("Unable to access {BUS_SOCKET_PATH}. Check that lqosd is running and you have appropriate permissions.");
+      return Err(BusClientError::SocketNotFound);
+    }
+    let mut stream = stream.unwrap();
+    let session = BusSession { persist: false, requests: vec![request] };
+    let msg = encode_request(&session);
+    if msg.is_err() {
+      error!("Unable to encode request {:?}", session);
+      return Err(BusClientError::EncodingError);
+    }
+    if let Err(e) = stream.write_all(&msg.unwrap()).await {
+      error!("Unable to write to {BUS_SOCKET_PATH} stream.");
+      error!("{:?}", e);
+      return Err(BusClientError::StreamWriteError);
+    }
+    Ok(Self { stream })
+  }
+
+  /// Waits for the next response on the stream.
+  ///
+  /// **Returns** `Ok(None)` once `lqosd` has closed the stream.
+  pub async fn next(&mut self) -> Result<Option<BusResponse>, BusClientError> {
+    let mut len = [0u8; 4];
+    if let Err(e) = self.stream.read_exact(&mut len).await {
+      if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        return Ok(None);
+      }
+      error!("Unable to read from {BUS_SOCKET_PATH} stream.");
+      error!("{:?}", e);
+      return Err(BusClientError::StreamReadError);
+    }
+    let mut buf = vec![0u8; u32::from_le_bytes(len) as usize];
+    if let Err(e) = self.stream.read_exact(&mut buf).await {
+      error!("Unable to read from {BUS_SOCKET_PATH} stream.");
+      error!("{:?}", e);
+      return Err(BusClientError::StreamReadError);
+    }
+    let reply = decode_response(&buf);
+    if reply.is_err() {
+      error!("Unable to decode response from socket.");
+      return Err(BusClientError::DecodingError);
+    }
+    Ok(reply.unwrap().responses.pop())
+  }
+}
+
+/// Writes a single length-prefixed stream message to a client socket.
+pub(crate) async fn write_stream_message<W: AsyncWrite + Unpin>(
+  response: BusResponse,
+  socket: &mut W,
+) -> Result<(), BusClientError> {
+  let reply = BusReply { responses: vec![response] };
+  let bytes =
+    encode_response(&reply).map_err(|_| BusClientError::EncodingError)?;
+  let mut msg = Vec::with_capacity(bytes.len() + 4);
+  msg.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+  msg.extend_from_slice(&bytes);
+  socket
+    .write_all(&msg)
+    .await
+    .map_err(|_| BusClientError::StreamWriteError)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_stream_message_roundtrip() {
+    let (mut server, client) = UnixStream::pair().unwrap();
+    let mut client = BusStream { stream: client };
+    write_stream_message(BusResponse::PcapStream(vec![1, 2, 3]), &mut server)
+      .await
+      .unwrap();
+    write_stream_message(BusResponse::Ack, &mut server).await.unwrap();
+    std::mem::drop(server);
+
+    assert_eq!(
+      client.next().await.unwrap(),
+      Some(BusResponse::PcapStream(vec![1, 2, 3]))
+    );
+    assert_eq!(client.next().await.unwrap(), Some(BusResponse::Ack));
+    assert_eq!(client.next().await.unwrap(), None);
+  }
+}
@@ -1,6 +1,6 @@
 use crate::{
-  decode_request, encode_response, BusReply, BusRequest, BusResponse,
-  BUS_SOCKET_PATH,
+  bus::stream::write_stream_message, decode_request, encode_response,
+  BusReply, BusRequest, BusResponse, BusStreamReceiver, BUS_SOCKET_PATH,
 };
 use log::{error, warn};
 use std::{ffi::CString, fs::remove_file};
@@ -89,9 +89,14 @@ impl UnixSocketServer {
 
   /// Start listening for bus traffic, forward requests to the `handle_bus_requests`
   /// function for procesing.
+  ///
+  /// Streaming requests (see `BusRequest::is_stream`) are instead passed to
+  /// `handle_stream_request`, which either returns a channel whose messages
+  /// are forwarded to the client until it closes, or an error message.
   pub async fn listen(
     &self,
     handle_bus_requests: fn(&[BusRequest], &mut Vec<BusResponse>),
+    handle_stream_request: fn(&BusRequest) -> Result<BusStreamReceiver, String>,
   ) -> Result<(), UnixSocketServerError> {
     // Setup the listener and grant permissions to it
     let listener = UnixListener::bind(BUS_SOCKET_PATH);
@@ -124,6 +129,14 @@ impl UnixSocketServer {
           }
 
           if let Ok(request) = decode_request(&buf) {
+            if request.requests.len() == 1 && request.requests[0].is_stream() {
+              stream_unix(
+                handle_stream_request(&request.requests[0]),
+                &mut socket,
+              )
+              .await;
+              break;
+            }
             let mut response = BusReply { responses: Vec::with_capacity(8) };
             handle_bus_requests(&request.requests, &mut response.responses);
             let _ =
@@ -162,6 +175,37 @@ async fn reply_unix(
   Ok(())
 }
 
+async fn stream_unix(
+  stream: Result<BusStreamReceiver, String>,
+  socket: &mut UnixStream,
+) {
+  match stream {
+    Ok(mut rx) => {
+      // Clients don't send anything once a stream has started, so a
+      // read completing means that they hung up. Returning drops the
+      // receiver, which tells the producer to stop.
+      let (mut reader, mut writer) = socket.split();
+      let mut hangup = [0u8; 1];
+      loop {
+        tokio::select! {
+          response = rx.recv() => match response {
+            Some(response) => {
+              if write_stream_message(response, &mut writer).await.is_err() {
+                break;
+              }
+            }
+            None => break,
+          },
+          _ = reader.read(&mut hangup) => break,
+        }
+      }
+    }
+    Err(e) => {
+      let _ = write_stream_message(BusResponse::Fail(e), socket).await;
+    }
+  }
+}
+
 #[derive(Error, Debug)]
 pub enum UnixSocketServerError {
   #[error("Unable to create directory")]
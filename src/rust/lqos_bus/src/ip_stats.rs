@@ -100,6 +100,45 @@ pub struct FlowTransport {
   pub dscp: u8,
   /// Detected ECN bit status (0-3)
   pub ecn: u8,
+  /// TCP analytics, for TCP flows on which Heimdall sees every packet.
+  pub tcp: Option<TcpFlowStats>,
+}
+
+/// TCP analytics for one direction of a flow.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct TcpFlowStats {
+  /// Smallest RTT seen, in milliseconds. RTTs are measured from the
+  /// shaper to the flow's destination and back, so a download flow
+  /// shows the last mile and an upload flow shows the Internet side.
+  pub rtt_min_ms: f32,
+  /// Median of recent RTT samples, in milliseconds.
+  pub rtt_median_ms: f32,
+  /// Largest RTT seen, in milliseconds.
+  pub rtt_max_ms: f32,
+  /// Number of RTT samples taken. If 0, the RTT fields are meaningless
+  /// (e.g. the endpoints don't use TCP timestamps).
+  pub rtt_samples: u32,
+  /// Data segments that appear to have been retransmitted.
+  pub retransmits: u32,
+  /// Data segments that arrived out of order.
+  pub out_of_order: u32,
+  /// Zero-window advertisements (the receiver's buffer was full).
+  pub zero_windows: u32,
+}
+
+/// Aggregated flow data for the "top applications" and "top
+/// destinations" views.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FlowSummary {
+  /// What the flows were grouped by: an application (e.g. "HTTPS")
+  /// or a remote IP address.
+  pub name: String,
+  /// Current throughput (download, upload) in bits per second.
+  pub bits_per_second: (u64, u64),
+  /// Total bytes (download, upload) across the tracked flows.
+  pub bytes: (u64, u64),
+  /// Number of (one-directional) flows in the group.
+  pub flows: u32,
 }
 
 /// Extract the 6-bit DSCP and 2-bit ECN code from a TOS field
@@ -13,14 +13,19 @@
 mod bus;
 mod ip_stats;
 pub use ip_stats::{
-  tos_parser, FlowProto, FlowTransport, IpMapping, IpStats, PacketHeader,
-  XdpPpingResult,
+  tos_parser, FlowProto, FlowSummary, FlowTransport, IpMapping, IpStats,
+  PacketHeader, TcpFlowStats, XdpPpingResult,
 };
 mod tc_handle;
 pub use bus::{
   bus_request, decode_request, decode_response, encode_request,
   encode_response, BusClient, BusReply, BusRequest, BusResponse, BusSession,
-  CakeDiffTinTransit, CakeDiffTransit, CakeTransit, QueueStoreTransit,
+  BusStream, BusStreamReceiver, BusStreamSender, BufferbloatDistribution,
+  BufferbloatGrade, CakeDiffTinTransit, CakeDiffTransit, CakeTransit,
+  CircuitBufferbloat, CircuitQueueInfo, CircuitQueueSummary, CpuQueueTotals,
+  HtbClassDiffTransit,
+  HtbClassStoreTransit, HtbClassTransit, QueueHistoryRecord, QueueStoreTransit,
+  QueueNodeSummary, QueueSweepSample, QueueTreeAudit, QueueTreeClass, QueueTreeRateMismatch,
   UnixSocketServer, BUS_SOCKET_PATH,
 };
 pub use tc_handle::TcHandle;
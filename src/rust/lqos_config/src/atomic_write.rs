@@ -0,0 +1,45 @@
+//! Replaces configuration files without readers (such as the file
+//! watchers in `lqosd`) ever seeing a partly written file.
+
+use std::{fs, io, path::Path};
+
+/// Writes `contents` to a hidden temporary file next to `path`, then
+/// renames it over `path`. The temporary file is removed if the rename
+/// fails.
+pub(crate) fn write_atomically(
+  path: &Path,
+  contents: &[u8],
+) -> io::Result<()> {
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  let temp_path = path.with_file_name(format!(".{name}.tmp"));
+  fs::write(&temp_path, contents)?;
+  if let Err(e) = fs::rename(&temp_path, path) {
+    let _ = fs::remove_file(&temp_path);
+    return Err(e);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn replaces_files_without_leaving_temporaries() {
+    let dir = std::env::temp_dir()
+      .join(format!("lqos_atomic_write_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("network.json");
+    fs::write(&path, "old").unwrap();
+    write_atomically(&path, b"new").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    assert!(!dir.join(".network.json.tmp").exists());
+
+    // Renaming over a directory fails, and the temporary file goes.
+    let blocked = dir.join("blocked");
+    fs::create_dir_all(blocked.join("inside")).unwrap();
+    assert!(write_atomically(&blocked, b"new").is_err());
+    assert!(!dir.join(".blocked.tmp").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,131 @@
+//! API keys, for scripts and other machines using the node manager's API.
+//! Keys are random, shown once when created, and stored in `lqusers.toml`
+//! only as a SHA-256 hash. Each key carries the scopes it may use and an
+//! optional expiry.
+
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt::Display, str::FromStr};
+
+/// Prefix on every API key, so that keys are easy to recognize (and to
+/// search for in places they shouldn't be).
+const KEY_PREFIX: &str = "lqos_";
+
+/// Something an API key may be allowed to do.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+  /// Read statistics, queues, flows and the shaped device list.
+  ReadStats,
+  /// Change `ShapedDevices.csv` and `network.json`.
+  ManageDevices,
+  /// Reload LibreQoS.
+  Reload,
+  /// Start packet captures and download the results.
+  CapturePackets,
+}
+
+impl FromStr for ApiScope {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().replace('-', "_").as_str() {
+      "read_stats" => Ok(Self::ReadStats),
+      "manage_devices" => Ok(Self::ManageDevices),
+      "reload" => Ok(Self::Reload),
+      "capture_packets" => Ok(Self::CapturePackets),
+      _ => Err(format!(
+        "Unknown scope {s}. Use read_stats, manage_devices, reload or capture_packets"
+      )),
+    }
+  }
+}
+
+impl Display for ApiScope {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::ReadStats => write!(f, "read_stats"),
+      Self::ManageDevices => write!(f, "manage_devices"),
+      Self::Reload => write!(f, "reload"),
+      Self::CapturePackets => write!(f, "capture_packets"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ApiKey {
+  /// Names the key, so it can be listed and removed
+  pub(crate) name: String,
+  /// SHA-256 of the key, in hex
+  pub(crate) key_hash: String,
+  /// What the key may be used for
+  pub(crate) scopes: Vec<ApiScope>,
+  /// When the key was created, in Unix seconds
+  pub(crate) created: u64,
+  /// When the key stops working, in Unix seconds
+  pub(crate) expires: Option<u64>,
+}
+
+impl ApiKey {
+  /// Creates a key, returning it along with the key itself. The key
+  /// isn't stored anywhere, so this is the only chance to show it.
+  pub(crate) fn new(
+    name: &str,
+    scopes: &[ApiScope],
+    now: u64,
+    expires: Option<u64>,
+  ) -> (Self, String) {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let key: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let key = format!("{KEY_PREFIX}{key}");
+    let api_key = Self {
+      name: name.to_string(),
+      key_hash: hash_key(&key),
+      scopes: scopes.to_vec(),
+      created: now,
+      expires,
+    };
+    (api_key, key)
+  }
+
+  pub(crate) fn matches(&self, key: &str) -> bool {
+    self.key_hash == hash_key(key)
+  }
+
+  pub(crate) fn is_expired(&self, now: u64) -> bool {
+    self.expires.map(|e| now >= e).unwrap_or(false)
+  }
+}
+
+/// Keys are long and random, so a plain hash is enough to keep them
+/// from being read out of `lqusers.toml`.
+fn hash_key(key: &str) -> String {
+  let mut sha256 = Sha256::new();
+  sha256.update(key);
+  format!("{:x}", sha256.finalize())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn keys_are_stored_hashed() {
+    let (api_key, key) = ApiKey::new("ci", &[ApiScope::Reload], 10, Some(20));
+    assert!(key.starts_with(KEY_PREFIX));
+    assert!(!api_key.key_hash.contains(&key[KEY_PREFIX.len()..]));
+    assert!(api_key.matches(&key));
+    assert!(!api_key.matches("lqos_0000"));
+    assert!(!api_key.is_expired(19));
+    assert!(api_key.is_expired(20));
+  }
+
+  #[test]
+  fn parses_scopes() {
+    assert_eq!("read-stats".parse(), Ok(ApiScope::ReadStats));
+    assert_eq!("Capture_Packets".parse(), Ok(ApiScope::CapturePackets));
+    assert!("everything".parse::<ApiScope>().is_err());
+  }
+}
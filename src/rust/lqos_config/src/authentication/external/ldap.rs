@@ -0,0 +1,147 @@
+//! LDAP logins: bind as the user, then search for their groups while
+//! still bound as them.
+
+use super::ExternalAuthError;
+use crate::LdapConfig;
+use ldap3::{
+  dn_escape, ldap_escape, LdapConn, LdapConnSettings, Scope, SearchEntry,
+};
+use std::{collections::HashMap, time::Duration};
+
+/// The LDAP result code for a wrong DN or password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Returns the user's groups if the directory accepts the password, or
+/// `None` if it doesn't.
+pub(crate) fn groups(
+  config: &LdapConfig,
+  username: &str,
+  password: &str,
+) -> Result<Option<Vec<String>>, ExternalAuthError> {
+  if !uses_tls(config) {
+    return Err(ExternalAuthError::Cleartext(config.url.clone()));
+  }
+  let timeout = Duration::from_secs(config.timeout_secs);
+  let settings = LdapConnSettings::new()
+    .set_conn_timeout(timeout)
+    .set_starttls(config.starttls);
+  let mut ldap = LdapConn::with_settings(settings, &config.url)?;
+
+  let dn = bind_dn(config, username);
+  let bind = ldap.with_timeout(timeout).simple_bind(&dn, password)?;
+  if bind.rc == INVALID_CREDENTIALS {
+    return Ok(None);
+  }
+  bind.success()?;
+
+  let (entries, _) = ldap
+    .with_timeout(timeout)
+    .search(
+      &config.group_base,
+      Scope::Subtree,
+      &group_filter(config, &dn, username),
+      vec![config.group_attribute.as_str()],
+    )?
+    .success()?;
+  let _ = ldap.unbind();
+
+  let groups = entries
+    .into_iter()
+    .map(|entry| SearchEntry::construct(entry).attrs)
+    .flat_map(|attrs| group_names(config, attrs))
+    .collect();
+  Ok(Some(groups))
+}
+
+/// Plain `ldap://` would send the password in the clear, unless it is
+/// upgraded with StartTLS. `ldapi://` never leaves the machine.
+fn uses_tls(config: &LdapConfig) -> bool {
+  let url = config.url.to_ascii_lowercase();
+  url.starts_with("ldaps://")
+    || url.starts_with("ldapi://")
+    || (url.starts_with("ldap://") && config.starttls)
+}
+
+/// The DN to bind as, with the username escaped so that it can't add
+/// RDNs of its own.
+fn bind_dn(config: &LdapConfig, username: &str) -> String {
+  config.bind_dn.replace("{username}", &dn_escape(username))
+}
+
+/// The group search filter, escaped so that the username can't widen
+/// the search.
+fn group_filter(config: &LdapConfig, dn: &str, username: &str) -> String {
+  config
+    .group_filter
+    .replace("{dn}", &ldap_escape(dn))
+    .replace("{username}", &ldap_escape(username))
+}
+
+/// The values of the group attribute in one search result. Attribute
+/// names aren't case sensitive, so the server may not spell it the way
+/// the config does.
+fn group_names(
+  config: &LdapConfig,
+  attrs: HashMap<String, Vec<String>>,
+) -> Vec<String> {
+  attrs
+    .into_iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(&config.group_attribute))
+    .map(|(_, values)| values)
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn refuses_cleartext_passwords() {
+    let mut config = LdapConfig::default();
+    assert!(uses_tls(&config));
+    config.url = "ldap://ldap.example.com".to_string();
+    assert!(!uses_tls(&config));
+    assert!(matches!(
+      groups(&config, "alice", "secret"),
+      Err(ExternalAuthError::Cleartext(_))
+    ));
+    config.starttls = true;
+    assert!(uses_tls(&config));
+    config.url = "LDAPI://%2fvar%2frun%2fslapd%2fldapi".to_string();
+    config.starttls = false;
+    assert!(uses_tls(&config));
+  }
+
+  #[test]
+  fn escapes_usernames() {
+    let config = LdapConfig::default();
+    assert_eq!(
+      bind_dn(&config, "alice"),
+      "uid=alice,ou=people,dc=example,dc=com"
+    );
+    assert_eq!(
+      bind_dn(&config, "alice,ou=admins"),
+      "uid=alice\\2cou\\3dadmins,ou=people,dc=example,dc=com"
+    );
+
+    let dn = bind_dn(&config, "alice");
+    assert_eq!(
+      group_filter(&config, &dn, "alice"),
+      "(|(member=uid=alice,ou=people,dc=example,dc=com)\
+       (uniqueMember=uid=alice,ou=people,dc=example,dc=com)\
+       (memberUid=alice))"
+    );
+    let filter = group_filter(&config, &dn, "*)(cn=*");
+    assert!(filter.ends_with("(memberUid=\\2a\\29\\28cn=\\2a))"));
+  }
+
+  #[test]
+  fn reads_group_attribute() {
+    let config = LdapConfig::default();
+    let mut attrs = HashMap::new();
+    attrs.insert("CN".to_string(), vec!["lqos-admins".to_string()]);
+    attrs.insert("description".to_string(), vec!["Admins".to_string()]);
+    assert_eq!(group_names(&config, attrs), vec!["lqos-admins".to_string()]);
+    assert!(group_names(&config, HashMap::new()).is_empty());
+  }
+}
@@ -0,0 +1,117 @@
+//! Checks web UI logins against the LDAP directory or RADIUS server
+//! configured in `/etc/lqos.conf`, if any. The groups the server reports
+//! for the user decide their role. If no server accepts the login, the
+//! local users in `lqusers.toml` are tried instead.
+
+mod ldap;
+mod radius;
+use crate::{EtcLqos, UserRole};
+use log::{info, warn};
+use thiserror::Error;
+
+/// Asks the configured LDAP and RADIUS servers, in that order, whether
+/// `username` and `password` are valid. Returns the user's role if one of
+/// them accepts the login and places the user in a mapped group.
+pub(crate) fn authenticate(
+  username: &str,
+  password: &str,
+) -> Option<UserRole> {
+  // An empty password is an anonymous bind to many LDAP servers, which
+  // always "succeeds".
+  if username.is_empty() || password.is_empty() {
+    return None;
+  }
+  let config = EtcLqos::load().ok()?;
+
+  if let Some(ldap_config) = &config.ldap {
+    match ldap::groups(ldap_config, username, password) {
+      Ok(Some(groups)) => {
+        let role = role_for_groups(
+          &groups,
+          &ldap_config.admin_groups,
+          &ldap_config.read_only_groups,
+        );
+        if role.is_some() {
+          return role;
+        }
+        info!("LDAP accepted {username}, but they aren't in a mapped group");
+      }
+      Ok(None) => {}
+      Err(e) => warn!("Unable to check {username} with LDAP: {e}"),
+    }
+  }
+
+  if let Some(radius_config) = &config.radius {
+    match radius::groups(radius_config, username, password) {
+      Ok(Some(groups)) => {
+        let role = role_for_groups(
+          &groups,
+          &radius_config.admin_groups,
+          &radius_config.read_only_groups,
+        );
+        if role.is_some() {
+          return role;
+        }
+        info!("RADIUS accepted {username}, but they aren't in a mapped group");
+      }
+      Ok(None) => {}
+      Err(e) => warn!("Unable to check {username} with RADIUS: {e}"),
+    }
+  }
+
+  None
+}
+
+/// Admin groups win over read-only groups. Group names aren't case
+/// sensitive.
+fn role_for_groups(
+  groups: &[String],
+  admin_groups: &[String],
+  read_only_groups: &[String],
+) -> Option<UserRole> {
+  let member_of = |wanted: &[String]| {
+    groups.iter().any(|g| wanted.iter().any(|w| w.eq_ignore_ascii_case(g)))
+  };
+  if member_of(admin_groups) {
+    Some(UserRole::Admin)
+  } else if member_of(read_only_groups) {
+    Some(UserRole::ReadOnly)
+  } else {
+    None
+  }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum ExternalAuthError {
+  #[error("{0}")]
+  Ldap(#[from] ldap3::LdapError),
+  #[error("{0}")]
+  Io(#[from] std::io::Error),
+  #[error("{0}")]
+  Radius(String),
+  #[error("Refusing to send passwords to {0} without TLS. Use ldaps://, or set starttls = true.")]
+  Cleartext(String),
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn groups_map_to_roles() {
+    let admins = vec!["lqos-admins".to_string()];
+    let noc = vec!["noc".to_string()];
+    let groups = |g: &[&str]| -> Vec<String> {
+      g.iter().map(|s| s.to_string()).collect()
+    };
+    assert_eq!(
+      role_for_groups(&groups(&["NOC", "LQOS-Admins"]), &admins, &noc),
+      Some(UserRole::Admin)
+    );
+    assert_eq!(
+      role_for_groups(&groups(&["noc"]), &admins, &noc),
+      Some(UserRole::ReadOnly)
+    );
+    assert_eq!(role_for_groups(&groups(&["sales"]), &admins, &noc), None);
+  }
+}
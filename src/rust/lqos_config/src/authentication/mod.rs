@@ -0,0 +1,673 @@
+//! The `authentication` module provides authorization for use of the
+//! local web UI on LibreQoS boxes. It maps to `/<install dir>/lqusers.toml`
+//!
+//! Logging in starts a session, identified by a random token that expires
+//! after `session_hours`. Sessions live in memory, in whichever process
+//! holds the `WebUsers`; revoking a user's sessions is also recorded in
+//! `lqusers.toml`, so that `lqusers` can log a user out of the web UI.
+//!
+//! API keys, created with `lqusers`, let scripts use the node manager's
+//! API without logging in. Each key is limited to a set of `ApiScope`s.
+//!
+//! If `/etc/lqos.conf` has an `[ldap]` or `[radius]` section, logins are
+//! checked there first (see `external`), and local users are the
+//! fallback.
+
+mod api_keys;
+mod external;
+mod password;
+mod sessions;
+use api_keys::ApiKey;
+pub use api_keys::ApiScope;
+use log::{error, info, warn};
+use password::{hash_password, verify_password, PasswordCheck};
+use serde::{Deserialize, Serialize};
+use sessions::{LoginLimiter, WebSession};
+use std::{
+  fmt::Display,
+  fs::{read_to_string, remove_file, OpenOptions},
+  io::Write,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+/// Access rights of a user
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum UserRole {
+  /// The user may view data but not change it.
+  ReadOnly,
+  /// The user may make any changes they request.
+  Admin,
+}
+
+impl From<&str> for UserRole {
+  fn from(s: &str) -> Self {
+    let s = s.to_lowercase();
+    if s == "admin" {
+      UserRole::Admin
+    } else {
+      UserRole::ReadOnly
+    }
+  }
+}
+
+impl Display for UserRole {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UserRole::Admin => write!(f, "admin"),
+      UserRole::ReadOnly => write!(f, "read-only"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct WebUser {
+  username: String,
+  password_hash: String,
+  role: UserRole,
+  /// Sessions started before this Unix time are no longer valid.
+  #[serde(default)]
+  sessions_revoked_at: u64,
+}
+
+/// Container holding the authorized web users.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebUsers {
+  allow_unauthenticated_to_view: bool,
+  #[serde(default = "default_session_hours")]
+  session_hours: u64,
+  users: Vec<WebUser>,
+  #[serde(default)]
+  api_keys: Vec<ApiKey>,
+  #[serde(skip)]
+  sessions: Vec<WebSession>,
+  #[serde(skip)]
+  limiter: LoginLimiter,
+  #[serde(skip)]
+  modified: Option<SystemTime>,
+  /// Where `lqusers.toml` was loaded from, so that checking it for
+  /// changes doesn't mean reading `/etc/lqos.conf` every time.
+  #[serde(skip)]
+  path: Option<PathBuf>,
+}
+
+fn default_session_hours() -> u64 {
+  12
+}
+
+impl Default for WebUsers {
+  fn default() -> Self {
+    Self {
+      allow_unauthenticated_to_view: false,
+      session_hours: default_session_hours(),
+      users: Vec::new(),
+      api_keys: Vec::new(),
+      sessions: Vec::new(),
+      limiter: LoginLimiter::default(),
+      modified: None,
+      path: None,
+    }
+  }
+}
+
+/// The login limiter's keys for a username and a client.
+fn limiter_keys(username: &str, client: &str) -> (String, String) {
+  (format!("user:{username}"), format!("client:{client}"))
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+impl WebUsers {
+  fn path() -> Result<PathBuf, AuthenticationError> {
+    let base_path = crate::EtcLqos::load()
+      .map_err(|_| AuthenticationError::UnableToLoadEtcLqos)?
+      .lqos_directory;
+    let filename = Path::new(&base_path).join("lqusers.toml");
+    Ok(filename)
+  }
+
+  fn save_to_disk(&self) -> Result<(), AuthenticationError> {
+    let path = Self::path()?;
+    let new_contents = toml::to_string(&self);
+    if let Err(e) = new_contents {
+      return Err(AuthenticationError::SerializationError(e));
+    }
+    let new_contents = new_contents.unwrap();
+    if path.exists() && remove_file(&path).is_err() {
+      error!("Unable to delete web users file");
+      return Err(AuthenticationError::UnableToDelete);
+    }
+    if let Ok(mut file) =
+      OpenOptions::new().write(true).create_new(true).open(path)
+    {
+      if file.write_all(new_contents.as_bytes()).is_err() {
+        error!("Unable to write web users file to disk.");
+        return Err(AuthenticationError::UnableToWrite);
+      }
+    } else {
+      error!("Unable to open web users file for writing.");
+      return Err(AuthenticationError::UnableToWrite);
+    }
+    Ok(())
+  }
+
+  /// Does the user's file exist? True if it does, false otherwise.
+  pub fn does_users_file_exist() -> Result<bool, AuthenticationError> {
+    Ok(Self::path()?.exists())
+  }
+
+  /// Try to load `lqusers.toml`. If it is unavailable, create a new--empty--
+  /// file.
+  pub fn load_or_create() -> Result<Self, AuthenticationError> {
+    let path = Self::path()?;
+    if !path.exists() {
+      // Create a new users file, save it and return the
+      // empty file
+      let mut new_users = Self::default();
+      new_users.save_to_disk()?;
+      new_users.path = Some(path);
+      Ok(new_users)
+    } else {
+      // Load from disk
+      if let Ok(raw) = read_to_string(&path) {
+        let parse_result = toml::from_str::<Self>(&raw);
+        if let Ok(mut users) = parse_result {
+          users.modified = path.metadata().and_then(|m| m.modified()).ok();
+          users.path = Some(path);
+          Ok(users)
+        } else {
+          error!("Unable to deserialize lqusers.toml. Error in next message.");
+          error!("{:?}", parse_result);
+          Err(AuthenticationError::UnableToParse)
+        }
+      } else {
+        error!("Unable to read lqusers.toml");
+        Err(AuthenticationError::UnableToRead)
+      }
+    }
+  }
+
+  /// Re-reads `lqusers.toml` if it has changed since it was loaded, such
+  /// as when `lqusers` adds a user or revokes their sessions. Sessions and
+  /// login limits are kept.
+  pub fn reload_if_changed(&mut self) -> Result<(), AuthenticationError> {
+    let path = match &self.path {
+      Some(path) => path.clone(),
+      None => Self::path()?,
+    };
+    let modified = path.metadata().and_then(|m| m.modified()).ok();
+    if modified.is_some() && modified != self.modified {
+      let mut fresh = Self::load_or_create()?;
+      fresh.sessions = std::mem::take(&mut self.sessions);
+      fresh.limiter = std::mem::take(&mut self.limiter);
+      *self = fresh;
+    }
+    Ok(())
+  }
+
+  /// If a user exists with this username, update their details to the
+  /// provided values. If the user does not exist, create them with the
+  /// provided values. Changing a user's password logs them out
+  /// everywhere.
+  pub fn add_or_update_user(
+    &mut self,
+    username: &str,
+    password: &str,
+    role: UserRole,
+  ) -> Result<(), AuthenticationError> {
+    let now = unix_now();
+    if let Some(user) = self.users.iter_mut().find(|u| u.username == username)
+    {
+      user.password_hash = hash_password(password);
+      user.role = role;
+      user.sessions_revoked_at = now;
+      self.sessions.retain(|s| s.username != username);
+    } else {
+      let new_user = WebUser {
+        username: username.to_string(),
+        password_hash: hash_password(password),
+        role,
+        sessions_revoked_at: 0,
+      };
+      self.users.push(new_user);
+    }
+
+    self.save_to_disk()
+  }
+
+  /// Delete a user from `lqusers.toml`
+  pub fn remove_user(
+    &mut self,
+    username: &str,
+  ) -> Result<(), AuthenticationError> {
+    let old_len = self.users.len();
+    self.users.retain(|u| u.username != username);
+    if old_len == self.users.len() {
+      error!("User {username} not found, hence not deleted.");
+      return Err(AuthenticationError::UserNotFound);
+    }
+    self.save_to_disk()?;
+    Ok(())
+  }
+
+  /// Attempt a login with the specified username and password, from
+  /// `client` (such as an IP address). Any LDAP or RADIUS server in
+  /// `/etc/lqos.conf` is asked first, then the local users. If the login
+  /// succeeds, starts a session and returns its token. If it fails,
+  /// returns an `Err`. Repeated failures for the same username or client
+  /// are refused for a while, even if the password is right.
+  ///
+  /// Asking an external server can take seconds. If the `WebUsers` is
+  /// shared, call `check_login_allowed`, `authenticate_externally` and
+  /// `finish_login` instead, so that it needn't be locked meanwhile.
+  pub fn login(
+    &mut self,
+    username: &str,
+    password: &str,
+    client: &str,
+  ) -> Result<String, AuthenticationError> {
+    self.check_login_allowed(username, client)?;
+    let external_role = Self::authenticate_externally(username, password);
+    self.finish_login(username, password, client, external_role)
+  }
+
+  /// Returns `TooManyAttempts` if logins for `username`, or from
+  /// `client`, have failed too often lately.
+  pub fn check_login_allowed(
+    &self,
+    username: &str,
+    client: &str,
+  ) -> Result<(), AuthenticationError> {
+    let (user_key, client_key) = limiter_keys(username, client);
+    if self.limiter.is_blocked(&[&user_key, &client_key], unix_now()) {
+      warn!("Too many failed logins for {username} from {client}");
+      return Err(AuthenticationError::TooManyAttempts);
+    }
+    Ok(())
+  }
+
+  /// Asks the LDAP or RADIUS servers in `/etc/lqos.conf`, if any, whether
+  /// the login is valid. Returns the user's role if it is.
+  pub fn authenticate_externally(
+    username: &str,
+    password: &str,
+  ) -> Option<UserRole> {
+    external::authenticate(username, password)
+  }
+
+  /// Completes a login started with `check_login_allowed`. If
+  /// `external_role` is set, an external server has already accepted the
+  /// login; otherwise the password is checked against the local users.
+  pub fn finish_login(
+    &mut self,
+    username: &str,
+    password: &str,
+    client: &str,
+    external_role: Option<UserRole>,
+  ) -> Result<String, AuthenticationError> {
+    // Other attempts may have failed while the caller wasn't holding us
+    self.check_login_allowed(username, client)?;
+    let now = unix_now();
+    let (user_key, client_key) = limiter_keys(username, client);
+    let keys = [user_key.as_str(), client_key.as_str()];
+
+    if let Some(role) = external_role {
+      info!("{username} logged in as {role} with an external server");
+      self.limiter.clear(&keys);
+      return Ok(self.new_session(username, Some(role)));
+    }
+
+    let check = self
+      .users
+      .iter()
+      .find(|u| u.username == username)
+      .map(|u| verify_password(password, &u.password_hash))
+      .unwrap_or_else(|| {
+        // Take as long as a real check, so usernames can't be guessed
+        // from the response time.
+        hash_password(password);
+        PasswordCheck::Invalid
+      });
+    if check == PasswordCheck::Invalid {
+      warn!("Failed login for {username} from {client}");
+      self.limiter.record_failure(&keys, now);
+      return if self.allow_unauthenticated_to_view {
+        Ok("default".to_string())
+      } else {
+        Err(AuthenticationError::InvalidLogin)
+      };
+    }
+
+    self.limiter.clear(&keys);
+    if check == PasswordCheck::ValidLegacy {
+      info!("Upgrading the password hash for {username}");
+      if let Some(user) =
+        self.users.iter_mut().find(|u| u.username == username)
+      {
+        user.password_hash = hash_password(password);
+      }
+      self.save_to_disk()?;
+    }
+    Ok(self.start_session(username))
+  }
+
+  /// Starts a session for `username` without checking a password, such
+  /// as when the first user is created. Returns the session token.
+  pub fn start_session(&mut self, username: &str) -> String {
+    self.new_session(username, None)
+  }
+
+  fn new_session(
+    &mut self,
+    username: &str,
+    external_role: Option<UserRole>,
+  ) -> String {
+    let now = unix_now();
+    self.sessions.retain(|s| s.expires > now);
+    let session =
+      WebSession::new(username, now, self.session_hours * 3600, external_role);
+    let token = session.token.clone();
+    self.sessions.push(session);
+    token
+  }
+
+  /// Ends the session with this token.
+  pub fn logout(&mut self, token: &str) {
+    self.sessions.retain(|s| s.token != token);
+  }
+
+  /// Ends every session belonging to `username`, including any held by
+  /// other processes the next time they re-read `lqusers.toml`. Users
+  /// who logged in with LDAP or RADIUS aren't in `lqusers.toml`, so only
+  /// this process's sessions end for them.
+  pub fn revoke_sessions(
+    &mut self,
+    username: &str,
+  ) -> Result<(), AuthenticationError> {
+    let old_len = self.sessions.len();
+    self.sessions.retain(|s| s.username != username);
+    if let Some(user) = self.users.iter_mut().find(|u| u.username == username)
+    {
+      user.sessions_revoked_at = unix_now();
+      self.save_to_disk()
+    } else if old_len != self.sessions.len() {
+      Ok(())
+    } else {
+      Err(AuthenticationError::UserNotFound)
+    }
+  }
+
+  /// The username and role for this session, if it is still valid.
+  fn user_for_token(&self, token: &str) -> Option<(&str, UserRole)> {
+    let now = unix_now();
+    let session =
+      self.sessions.iter().find(|s| s.token == token && s.expires > now)?;
+    if let Some(role) = session.external_role {
+      return Some((&session.username, role));
+    }
+    self
+      .users
+      .iter()
+      .find(|u| {
+        u.username == session.username
+          && session.created >= u.sessions_revoked_at
+      })
+      .map(|u| (u.username.as_str(), u.role))
+  }
+
+  /// Given a token, lookup the matching user and return their role.
+  pub fn get_role_from_token(
+    &self,
+    token: &str,
+  ) -> Result<UserRole, AuthenticationError> {
+    if let Some((_, role)) = self.user_for_token(token) {
+      Ok(role)
+    } else if self.allow_unauthenticated_to_view {
+      Ok(UserRole::ReadOnly)
+    } else {
+      warn!("Session not found or expired, invalid data access attempt.");
+      Err(AuthenticationError::InvalidToken)
+    }
+  }
+
+  /// Given a token, lookup the matching user and return their username.
+  pub fn get_username(&self, token: &str) -> String {
+    if let Some((username, _)) = self.user_for_token(token) {
+      username.to_string()
+    } else {
+      "Anonymous".to_string()
+    }
+  }
+
+  /// Dump all users to the console.
+  pub fn print_users(&self) -> Result<(), AuthenticationError> {
+    self.users.iter().for_each(|u| {
+      println!("{:<40} {:<10}", u.username, u.role.to_string());
+    });
+    Ok(())
+  }
+
+  /// Creates an API key called `name`, allowed to use `scopes` and
+  /// expiring at `expires` (in Unix seconds) if given. Returns the key,
+  /// which can't be retrieved again later.
+  pub fn add_api_key(
+    &mut self,
+    name: &str,
+    scopes: &[ApiScope],
+    expires: Option<u64>,
+  ) -> Result<String, AuthenticationError> {
+    if self.api_keys.iter().any(|k| k.name == name) {
+      return Err(AuthenticationError::ApiKeyExists(name.to_string()));
+    }
+    let (api_key, key) = ApiKey::new(name, scopes, unix_now(), expires);
+    self.api_keys.push(api_key);
+    self.save_to_disk()?;
+    Ok(key)
+  }
+
+  /// Deletes the API key called `name`.
+  pub fn remove_api_key(
+    &mut self,
+    name: &str,
+  ) -> Result<(), AuthenticationError> {
+    let old_len = self.api_keys.len();
+    self.api_keys.retain(|k| k.name != name);
+    if old_len == self.api_keys.len() {
+      return Err(AuthenticationError::ApiKeyNotFound(name.to_string()));
+    }
+    self.save_to_disk()
+  }
+
+  /// Given an API key, returns the scopes it may use.
+  pub fn get_api_key_scopes(
+    &self,
+    key: &str,
+  ) -> Result<Vec<ApiScope>, AuthenticationError> {
+    let api_key = self
+      .api_keys
+      .iter()
+      .find(|k| k.matches(key))
+      .ok_or(AuthenticationError::InvalidApiKey)?;
+    if api_key.is_expired(unix_now()) {
+      warn!("Expired API key {} was used", api_key.name);
+      return Err(AuthenticationError::InvalidApiKey);
+    }
+    Ok(api_key.scopes.clone())
+  }
+
+  /// Dump all API keys (but not the keys themselves) to the console.
+  pub fn print_api_keys(&self) {
+    let now = unix_now();
+    self.api_keys.iter().for_each(|k| {
+      let scopes: Vec<String> =
+        k.scopes.iter().map(|s| s.to_string()).collect();
+      let expires = match k.expires {
+        None => "never expires".to_string(),
+        Some(_) if k.is_expired(now) => "expired".to_string(),
+        Some(e) => format!("expires in {} days", (e - now) / 86400),
+      };
+      println!("{:<30} {:<15} {}", k.name, expires, scopes.join(","));
+    });
+  }
+
+  /// Sets the "allow unauthenticated users" field. If true,
+  /// unauthenticated users gain read-only access. This is useful
+  /// for demonstration purposes.
+  pub fn allow_anonymous(
+    &mut self,
+    allow: bool,
+  ) -> Result<(), AuthenticationError> {
+    self.allow_unauthenticated_to_view = allow;
+    self.save_to_disk()?;
+    Ok(())
+  }
+
+  /// Do we allow unauthenticated users to read site data?
+  pub fn do_we_allow_anonymous(&self) -> bool {
+    self.allow_unauthenticated_to_view
+  }
+}
+
+/// Errors from loading, changing or checking web users.
+#[derive(Error, Debug)]
+pub enum AuthenticationError {
+  /// `/etc/lqos.conf` couldn't be read
+  #[error("Unable to load /etc/lqos.conf")]
+  UnableToLoadEtcLqos,
+  /// The users couldn't be written as TOML
+  #[error("Unable to serialize to TOML")]
+  SerializationError(toml::ser::Error),
+  /// The old users file couldn't be removed
+  #[error("Unable to remove existing web users file")]
+  UnableToDelete,
+  /// The users file couldn't be written
+  #[error("Unable to open lqusers.toml for writing. Check permissions?")]
+  UnableToWrite,
+  /// The users file couldn't be read
+  #[error("Unable to read lqusers.toml")]
+  UnableToRead,
+  /// The users file isn't valid
+  #[error("Unable to parse lqusers.toml")]
+  UnableToParse,
+  /// No user has that name
+  #[error("User not found")]
+  UserNotFound,
+  /// Wrong username or password
+  #[error("Invalid Login")]
+  InvalidLogin,
+  /// The session doesn't exist, has expired or was revoked
+  #[error("Invalid User Token")]
+  InvalidToken,
+  /// Too many recent failed logins for the user or client
+  #[error("Too many failed logins, try again later")]
+  TooManyAttempts,
+  /// An API key already has that name
+  #[error("An API key named {0} already exists")]
+  ApiKeyExists(String),
+  /// No API key has that name
+  #[error("No API key is named {0}")]
+  ApiKeyNotFound(String),
+  /// The API key doesn't exist or has expired
+  #[error("Invalid or expired API key")]
+  InvalidApiKey,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn users() -> WebUsers {
+    WebUsers {
+      users: vec![WebUser {
+        username: "alice".to_string(),
+        password_hash: hash_password("secret"),
+        role: UserRole::Admin,
+        sessions_revoked_at: 0,
+      }],
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn logins_start_sessions() {
+    let mut users = users();
+    let token = users.login("alice", "secret", "10.0.0.1").unwrap();
+    assert_eq!(users.get_role_from_token(&token).unwrap(), UserRole::Admin);
+    assert_eq!(users.get_username(&token), "alice");
+
+    // Each login gets its own session
+    let second = users.login("alice", "secret", "10.0.0.1").unwrap();
+    assert_ne!(token, second);
+    users.logout(&token);
+    assert!(users.get_role_from_token(&token).is_err());
+    assert!(users.get_role_from_token(&second).is_ok());
+  }
+
+  #[test]
+  fn sessions_expire_and_can_be_revoked() {
+    let mut users = users();
+    let token = users.start_session("alice");
+    users.sessions[0].expires = unix_now() - 1;
+    assert!(users.get_role_from_token(&token).is_err());
+
+    let token = users.start_session("alice");
+    users.users[0].sessions_revoked_at = unix_now() + 1;
+    assert!(users.get_role_from_token(&token).is_err());
+    assert_eq!(users.get_username(&token), "Anonymous");
+  }
+
+  #[test]
+  fn failed_logins_are_limited() {
+    let mut users = users();
+    for _ in 0..5 {
+      assert!(matches!(
+        users.login("alice", "wrong", "10.0.0.1"),
+        Err(AuthenticationError::InvalidLogin)
+      ));
+    }
+    assert!(matches!(
+      users.login("alice", "secret", "10.0.0.2"),
+      Err(AuthenticationError::TooManyAttempts)
+    ));
+  }
+
+  #[test]
+  fn api_keys_carry_scopes() {
+    let mut users = users();
+    let (api_key, key) =
+      ApiKey::new("ci", &[ApiScope::ReadStats], unix_now(), None);
+    users.api_keys.push(api_key);
+    assert_eq!(users.get_api_key_scopes(&key).unwrap(), [ApiScope::ReadStats]);
+    assert!(users.get_api_key_scopes("lqos_nope").is_err());
+
+    // Keys aren't sessions
+    assert!(users.get_role_from_token(&key).is_err());
+
+    users.api_keys[0].expires = Some(unix_now() - 1);
+    assert!(matches!(
+      users.get_api_key_scopes(&key),
+      Err(AuthenticationError::InvalidApiKey)
+    ));
+  }
+
+  #[test]
+  fn external_sessions_keep_their_role() {
+    let mut users = users();
+    let token = users.new_session("bob", Some(UserRole::ReadOnly));
+    assert_eq!(users.get_role_from_token(&token).unwrap(), UserRole::ReadOnly);
+    assert_eq!(users.get_username(&token), "bob");
+
+    users.revoke_sessions("bob").unwrap();
+    assert!(users.get_role_from_token(&token).is_err());
+    assert!(matches!(
+      users.revoke_sessions("bob"),
+      Err(AuthenticationError::UserNotFound)
+    ));
+  }
+}
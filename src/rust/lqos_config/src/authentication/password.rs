@@ -0,0 +1,88 @@
+//! Password hashing. Passwords are hashed with Argon2id, using a random
+//! salt for each user, and stored as PHC strings (`$argon2id$v=19$...`)
+//! which carry their own salt and parameters.
+//!
+//! Older installs used SHA-256 with a salt that was the same everywhere.
+//! Those hashes are still accepted, and replaced the next time the user
+//! logs in.
+
+use argon2::{
+  password_hash::{
+    PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+  },
+  Argon2,
+};
+use log::error;
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+
+/// The result of checking a password against a stored hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PasswordCheck {
+  /// The password matches.
+  Valid,
+  /// The password matches an old-style hash, which should be replaced.
+  ValidLegacy,
+  /// The password doesn't match.
+  Invalid,
+}
+
+/// Hashes a password with Argon2id and a new random salt.
+pub(crate) fn hash_password(password: &str) -> String {
+  let salt = SaltString::generate(&mut OsRng);
+  match Argon2::default().hash_password(password.as_bytes(), &salt) {
+    Ok(hash) => hash.to_string(),
+    Err(e) => {
+      // Only possible with invalid parameters, and ours are the defaults.
+      error!("Unable to hash password: {e:?}");
+      String::new()
+    }
+  }
+}
+
+/// Checks a password against a stored hash of either kind.
+pub(crate) fn verify_password(password: &str, hash: &str) -> PasswordCheck {
+  if let Ok(parsed) = PasswordHash::new(hash) {
+    if Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok()
+    {
+      PasswordCheck::Valid
+    } else {
+      PasswordCheck::Invalid
+    }
+  } else if !hash.is_empty() && legacy_hash(password) == hash {
+    PasswordCheck::ValidLegacy
+  } else {
+    PasswordCheck::Invalid
+  }
+}
+
+/// The hash used before Argon2.
+fn legacy_hash(password: &str) -> String {
+  let salted = format!("!x{password}_LibreQosLikesPasswordsForDinner");
+  let mut sha256 = Sha256::new();
+  sha256.update(salted);
+  format!("{:X}", sha256.finalize())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn hashes_are_salted() {
+    let a = hash_password("hunter2");
+    let b = hash_password("hunter2");
+    assert!(a.starts_with("$argon2id$"));
+    assert_ne!(a, b);
+    assert_eq!(verify_password("hunter2", &a), PasswordCheck::Valid);
+    assert_eq!(verify_password("hunter3", &a), PasswordCheck::Invalid);
+  }
+
+  #[test]
+  fn accepts_legacy_hashes() {
+    let old = legacy_hash("hunter2");
+    assert_eq!(verify_password("hunter2", &old), PasswordCheck::ValidLegacy);
+    assert_eq!(verify_password("hunter3", &old), PasswordCheck::Invalid);
+    assert_eq!(verify_password("", ""), PasswordCheck::Invalid);
+  }
+}
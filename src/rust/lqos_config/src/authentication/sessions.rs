@@ -0,0 +1,128 @@
+//! Web UI sessions and login rate limiting. Both are kept in memory by
+//! the process serving the web UI; a restart logs everyone out.
+
+use super::UserRole;
+use rand_core::{OsRng, RngCore};
+use std::collections::HashMap;
+
+/// How many failed logins are allowed, per username and per client,
+/// within `FAILURE_WINDOW_SECS`.
+const MAX_FAILURES: usize = 5;
+
+/// How long failed logins are remembered, in seconds. Once the limit is
+/// reached, further attempts are refused until the oldest failure is this
+/// old.
+const FAILURE_WINDOW_SECS: u64 = 900;
+
+/// A logged-in user.
+#[derive(Clone, Debug)]
+pub(crate) struct WebSession {
+  /// The random token handed to the browser
+  pub(crate) token: String,
+  /// Who is logged in
+  pub(crate) username: String,
+  /// When the session started, in Unix seconds
+  pub(crate) created: u64,
+  /// When the session ends, in Unix seconds
+  pub(crate) expires: u64,
+  /// The role given by an LDAP or RADIUS server, for users who aren't
+  /// in `lqusers.toml`
+  pub(crate) external_role: Option<UserRole>,
+}
+
+impl WebSession {
+  pub(crate) fn new(
+    username: &str,
+    now: u64,
+    lifetime_secs: u64,
+    external_role: Option<UserRole>,
+  ) -> Self {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    Self {
+      token: bytes.iter().map(|b| format!("{b:02x}")).collect(),
+      username: username.to_string(),
+      created: now,
+      expires: now + lifetime_secs,
+      external_role,
+    }
+  }
+}
+
+/// Counts recent failed logins, by username and by client address.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LoginLimiter {
+  failures: HashMap<String, Vec<u64>>,
+}
+
+impl LoginLimiter {
+  /// Should a login with any of `keys` be refused?
+  pub(crate) fn is_blocked(&self, keys: &[&str], now: u64) -> bool {
+    keys.iter().any(|key| {
+      let recent = self.failures.get(*key).map(|times| {
+        times.iter().filter(|t| now < *t + FAILURE_WINDOW_SECS).count()
+      });
+      recent.unwrap_or(0) >= MAX_FAILURES
+    })
+  }
+
+  pub(crate) fn record_failure(&mut self, keys: &[&str], now: u64) {
+    // Forget failures that have aged out, and the keys left with none,
+    // so that attempts with ever-changing usernames can't grow the map
+    // without bound.
+    self.failures.retain(|_, times| {
+      times.retain(|t| now < *t + FAILURE_WINDOW_SECS);
+      !times.is_empty()
+    });
+    for key in keys.iter() {
+      self.failures.entry(key.to_string()).or_default().push(now);
+    }
+  }
+
+  pub(crate) fn clear(&mut self, keys: &[&str]) {
+    for key in keys.iter() {
+      self.failures.remove(*key);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn tokens_are_random() {
+    let a = WebSession::new("a", 10, 60, None);
+    let b = WebSession::new("a", 10, 60, None);
+    assert_eq!(a.token.len(), 64);
+    assert_ne!(a.token, b.token);
+    assert_eq!(a.expires, 70);
+  }
+
+  #[test]
+  fn blocks_after_repeated_failures() {
+    let mut limiter = LoginLimiter::default();
+    for t in 0..MAX_FAILURES as u64 {
+      assert!(!limiter.is_blocked(&["user:bob"], t));
+      limiter.record_failure(&["user:bob", "client:10.0.0.1"], t);
+    }
+    assert!(limiter.is_blocked(&["user:bob"], 10));
+    assert!(limiter.is_blocked(&["user:alice", "client:10.0.0.1"], 10));
+    assert!(!limiter.is_blocked(&["user:alice", "client:10.0.0.2"], 10));
+    // The first failure ages out of the window
+    assert!(!limiter.is_blocked(&["user:bob"], FAILURE_WINDOW_SECS));
+    limiter.clear(&["user:bob"]);
+    assert!(!limiter.is_blocked(&["user:bob"], 10));
+  }
+
+  #[test]
+  fn forgets_old_failures() {
+    let mut limiter = LoginLimiter::default();
+    for i in 0..100 {
+      limiter.record_failure(&[&format!("user:guess{i}")], i);
+    }
+    assert_eq!(limiter.failures.len(), 100);
+    limiter.record_failure(&["user:bob"], FAILURE_WINDOW_SECS + 50);
+    assert_eq!(limiter.failures.len(), 50);
+  }
+}
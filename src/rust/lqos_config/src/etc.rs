@@ -1,4 +1,9 @@
 //! Manages the `/etc/lqos.conf` file.
+use crate::libre_qos_config::{
+  InfluxDbConfig, IntegrationConfig, LibreQoSConfig, PythonApiConfig,
+  RestHttpConfig, SplynxConfig, UispConfig,
+};
+use crate::snapshots::ConfigSnapshot;
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::{fs, path::Path};
@@ -15,6 +20,23 @@ pub struct EtcLqos {
   /// In ms.
   pub queue_check_period_ms: u64,
 
+  /// How should `lqosd` read queue statistics? Defaults to running
+  /// `tc`.
+  pub queue_stats_backend: Option<QueueStatsBackend>,
+
+  /// If present, periodically reads every circuit's queue - not just the
+  /// ones being watched - to keep drop, mark and delay history.
+  pub queue_sweep: Option<QueueSweepConfig>,
+
+  /// If present, writes the queue sweep's per-circuit queue diffs to
+  /// disk, so they survive restarts and can be exported later.
+  pub queue_history: Option<QueueHistoryConfig>,
+
+  /// If true, HTB class statistics (rates, tokens, borrowing) are read
+  /// for every node in the queue structure on each queue check. Defaults
+  /// to false.
+  pub htb_class_stats: Option<bool>,
+
   /// If present, provides a unique ID for the node. Used for
   /// anonymous stats (to identify nodes without providing an actual
   /// identity), and will be used for long-term data retention to
@@ -35,6 +57,209 @@ pub struct EtcLqos {
   /// run. Short times are good, there's a real performance penalty to
   /// capturing high-throughput streams. Defaults to 10 seconds.
   pub packet_capture_time: Option<usize>,
+
+  /// Defines how many octets of each packet a capture session should
+  /// record, when the session doesn't specify. Larger values let you
+  /// see TLS handshakes and DNS answers, at the cost of more memory
+  /// and CPU while capturing. Defaults to 128, and is limited to
+  /// between 74 (enough for the packet headers) and a full-MTU frame.
+  pub packet_capture_snaplen: Option<u32>,
+
+  /// If present, defines how flows are tracked for hosts that nobody
+  /// is currently watching.
+  pub flows: Option<FlowConfig>,
+
+  /// If present, sends flow records to NetFlow v9 or IPFIX collectors.
+  pub flow_export: Option<FlowExportConfig>,
+
+  /// How LibreQoS builds its queues. Replaces most of `ispConfig.py`;
+  /// `lqosd` won't start without it.
+  pub shaping: Option<LibreQoSConfig>,
+
+  /// Settings shared by the CRM/NMS integrations.
+  pub integration: Option<IntegrationConfig>,
+
+  /// InfluxDB graphing.
+  pub influxdb: Option<InfluxDbConfig>,
+
+  /// Splynx integration.
+  pub splynx: Option<SplynxConfig>,
+
+  /// UISP integration.
+  pub uisp: Option<UispConfig>,
+
+  /// REST/HTTP integration.
+  pub rest_http: Option<RestHttpConfig>,
+
+  /// The Python side's HTTP API.
+  pub python_api: Option<PythonApiConfig>,
+
+  /// Where to keep snapshots of the configuration files, and how many.
+  /// Snapshots are kept with the defaults if this is missing.
+  pub snapshots: Option<SnapshotConfig>,
+
+  /// If present, web UI logins are checked against an LDAP directory
+  /// before the local users.
+  pub ldap: Option<LdapConfig>,
+
+  /// If present, web UI logins are checked against a RADIUS server
+  /// before the local users.
+  pub radius: Option<RadiusConfig>,
+}
+
+/// Ways of reading queue statistics from the kernel.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueStatsBackend {
+  /// Run `tc -s -j qdisc show` and parse its JSON output.
+  #[default]
+  Tc,
+  /// Ask the kernel directly, over rtnetlink. Avoids spawning a process
+  /// for every queue read.
+  Netlink,
+}
+
+/// Low-frequency whole-box queue sweep. Every CAKE and `fq_codel` qdisc
+/// on the shaping interfaces is read in one pass, and attributed to its
+/// circuit via `queuingStructure.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueSweepConfig {
+  /// Should the sweep run?
+  pub enabled: bool,
+
+  /// How often to sweep, in seconds.
+  pub interval_secs: u64,
+}
+
+/// On-disk history of per-circuit queue diffs. Samples are taken by the
+/// queue sweep, so it must be enabled too. Files are removed once they
+/// are older than `retention_hours`, or - oldest first - once the
+/// history grows beyond `max_megabytes`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueHistoryConfig {
+  /// Should queue history be written?
+  pub enabled: bool,
+
+  /// The directory in which to store history files.
+  pub directory: String,
+
+  /// How many hours of history to keep.
+  pub retention_hours: u64,
+
+  /// The most disk space the history may use, in megabytes.
+  pub max_megabytes: u64,
+}
+
+/// Snapshots of `/etc/lqos.conf`, `ShapedDevices.csv` and `network.json`,
+/// taken whenever one of them changes. The oldest are removed once there
+/// are more than `retain`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotConfig {
+  /// The directory in which to keep snapshots.
+  pub directory: String,
+
+  /// How many snapshots to keep. 0 turns snapshots off.
+  pub retain: usize,
+}
+
+impl Default for SnapshotConfig {
+  fn default() -> Self {
+    Self { directory: "/var/lib/lqos/snapshots".to_string(), retain: 50 }
+  }
+}
+
+/// Checks web UI logins by binding to an LDAP directory as the user, then
+/// looks up the user's groups to decide their role. Users who aren't in
+/// `admin_groups` or `read_only_groups` can't log in this way.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LdapConfig {
+  /// The server, e.g. `ldaps://ldap.example.com`. Passwords are only
+  /// sent over TLS, so `ldap://` URLs need `starttls`.
+  pub url: String,
+
+  /// Upgrade `ldap://` connections with StartTLS.
+  pub starttls: bool,
+
+  /// The DN to bind as. `{username}` is replaced with the name typed
+  /// into the login page.
+  pub bind_dn: String,
+
+  /// Where to search for the user's groups
+  pub group_base: String,
+
+  /// The filter that finds the user's groups. `{dn}` is replaced with
+  /// the DN bound as, and `{username}` with the login name.
+  pub group_filter: String,
+
+  /// The group attribute compared with `admin_groups` and
+  /// `read_only_groups`
+  pub group_attribute: String,
+
+  /// Members of these groups are admins.
+  pub admin_groups: Vec<String>,
+
+  /// Members of these groups get read-only access.
+  pub read_only_groups: Vec<String>,
+
+  /// How long to wait for the server, in seconds.
+  pub timeout_secs: u64,
+}
+
+impl Default for LdapConfig {
+  fn default() -> Self {
+    Self {
+      url: "ldaps://localhost".to_string(),
+      starttls: false,
+      bind_dn: "uid={username},ou=people,dc=example,dc=com".to_string(),
+      group_base: "ou=groups,dc=example,dc=com".to_string(),
+      group_filter: "(|(member={dn})(uniqueMember={dn})(memberUid={username}))"
+        .to_string(),
+      group_attribute: "cn".to_string(),
+      admin_groups: vec!["lqos-admins".to_string()],
+      read_only_groups: Vec::new(),
+      timeout_secs: 3,
+    }
+  }
+}
+
+/// Checks web UI logins with a RADIUS Access-Request (PAP). The
+/// `Filter-Id` and `Class` attributes of the server's Access-Accept are
+/// the user's groups, and decide their role.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RadiusConfig {
+  /// The server's `host:port`, usually port 1812
+  pub server: String,
+
+  /// The secret shared with the server
+  pub secret: String,
+
+  /// Sent as the `NAS-Identifier`, so the server can recognize
+  /// LibreQoS.
+  pub nas_identifier: String,
+
+  /// Users with these groups are admins.
+  pub admin_groups: Vec<String>,
+
+  /// Users with these groups get read-only access.
+  pub read_only_groups: Vec<String>,
+
+  /// How long to wait for the server, in seconds, across all retries.
+  pub timeout_secs: u64,
+}
+
+impl Default for RadiusConfig {
+  fn default() -> Self {
+    Self {
+      server: "127.0.0.1:1812".to_string(),
+      secret: String::new(),
+      nas_identifier: "libreqos".to_string(),
+      admin_groups: vec!["lqos-admins".to_string()],
+      read_only_groups: Vec::new(),
+      timeout_secs: 3,
+    }
+  }
 }
 
 /// Represents a set of `sysctl` and `ethtool` tweaks that may be
@@ -118,6 +343,56 @@ pub struct BridgeVlan {
   pub redirect_to: u32,
 }
 
+/// Always-on flow accounting. By default, Heimdall only tracks flows for
+/// hosts that are being watched (e.g. by having their circuit page open).
+/// Enabling this tracks flows for every shaped host, feeding the top
+/// application, top destination and per-circuit flow views.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FlowConfig {
+  /// Should flows be tracked for every shaped host?
+  pub track_all_hosts: bool,
+
+  /// Only count 1 in every `sample_rate` packets, scaling the counts
+  /// back up. Higher values reduce CPU load at the cost of accuracy.
+  /// 1 counts every packet.
+  pub sample_rate: u32,
+
+  /// The maximum number of flows to hold in memory. New flows are
+  /// ignored until older ones expire.
+  pub max_flows: usize,
+}
+
+/// Which flow export protocol to speak to collectors.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowExportProtocol {
+  /// IPFIX (RFC 7011)
+  Ipfix,
+  /// Cisco NetFlow version 9 (RFC 3954)
+  Netflow9,
+}
+
+/// Exports Heimdall's flow records to external flow collectors. Only
+/// flows that Heimdall is tracking are exported, so you generally want
+/// `track_all_hosts` enabled in the `[flows]` section too.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FlowExportConfig {
+  /// The protocol to send.
+  pub protocol: FlowExportProtocol,
+
+  /// A list of `ip:port` UDP collector addresses. Every record is sent
+  /// to every collector.
+  pub collectors: Vec<String>,
+
+  /// Long-lived flows are exported every `active_timeout_secs`, rather
+  /// than only when they end.
+  pub active_timeout_secs: u64,
+
+  /// The IANA Private Enterprise Number used for LibreQoS-specific
+  /// fields, such as the circuit ID.
+  pub enterprise_id: u32,
+}
+
 /// Definitions for anonymous usage submission
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UsageStats {
@@ -179,6 +454,9 @@ impl EtcLqos {
         }
       }
     }
+    if let Err(e) = ConfigSnapshot::take_with(self, "lqos.conf saved") {
+      log::warn!("Unable to snapshot the configuration: {e}");
+    }
     Ok(())
   }
 }
@@ -1,24 +1,46 @@
 //! The `lqos_config` crate stores and handles LibreQoS configuration.
 //! Configuration is drawn from:
-//! * The `ispConfig.py` file.
-//! * The `/etc/lqos.conf` file.
+//! * The `/etc/lqos.conf` file, which also holds the settings that used
+//!   to live in `ispConfig.py`.
 //! * `ShapedDevices.csv` files.
 //! * `network.json` files.
+//!
+//! Snapshots of all three are kept as they change, so that they can be
+//! compared and rolled back.
 
 #![warn(missing_docs)]
+mod atomic_write;
 mod authentication;
 mod etc;
 mod libre_qos_config;
 mod network_json;
 mod program_control;
 mod shaped_devices;
+mod snapshots;
 
-pub use authentication::{UserRole, WebUsers};
-pub use etc::{BridgeConfig, BridgeInterface, BridgeVlan, EtcLqos, Tunables};
-pub use libre_qos_config::LibreQoSConfig;
-pub use network_json::{NetworkJson, NetworkJsonNode, NetworkJsonTransport};
-pub use program_control::load_libreqos;
-pub use shaped_devices::{ConfigShapedDevices, ShapedDevice};
+pub use authentication::{ApiScope, AuthenticationError, UserRole, WebUsers};
+pub use etc::{
+  BridgeConfig, BridgeInterface, BridgeVlan, EtcLqos, FlowConfig,
+  FlowExportConfig, FlowExportProtocol, LdapConfig, QueueHistoryConfig,
+  QueueStatsBackend, QueueSweepConfig, RadiusConfig, SnapshotConfig,
+  Tunables,
+};
+pub use libre_qos_config::{
+  migrate_isp_config, python_values, InfluxDbConfig, IntegrationConfig,
+  LibreQoSConfig, PythonApiConfig, RestHttpConfig, SplynxConfig, UispConfig,
+  ISP_CONFIG_SHIM,
+};
+pub use network_json::{
+  CapacityDirection, CapacityIssue, NetworkJson, NetworkJsonChange,
+  NetworkJsonNode, NetworkJsonTransport, NodeCapacity, NodeType,
+};
+pub use program_control::{load_libreqos, update_libreqos};
+pub use shaped_devices::{
+  ConfigShapedDevices, ShapedCircuit, ShapedDevice, ShapedDevicesChange,
+  ShapedDevicesIssue, ShapedDevicesIssueKind, ShapedDevicesReport,
+  ShapedDevicesRow, SHAPED_DEVICES_COLUMNS,
+};
+pub use snapshots::{ConfigSnapshot, CURRENT_FILES, SNAPSHOT_FILES};
 
 /// Used as a constant in determining buffer preallocation
 pub const SUPPORTED_CUSTOMERS: usize = 16_000_000;
@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The `[integration]` section of `/etc/lqos.conf`: settings shared by
+/// every CRM/NMS integration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct IntegrationConfig {
+  /// Use the customer's address, rather than their name, as the
+  /// circuit name.
+  pub circuit_name_use_address: bool,
+
+  /// Devices with a WAN IP in these subnets are assumed to be behind NAT,
+  /// and are ignored.
+  pub ignore_subnets: Vec<String>,
+
+  /// Only devices with an IP in these subnets are shaped.
+  pub allowed_subnets: Vec<String>,
+
+  /// Sites to leave out of the imported network.
+  pub exclude_sites: Vec<String>,
+
+  /// Look up customers' IPv6 prefixes on Mikrotik routers, listed in
+  /// `mikrotikDHCPRouterList.csv`.
+  pub find_ipv6_using_mikrotik: bool,
+
+  /// Plan rates are multiplied by this, to give customers a little
+  /// headroom in speed tests. 1.0 shapes at exactly the plan rate.
+  pub bandwidth_overhead_factor: f64,
+
+  /// Parent nodes for CPEs the integration can't place, by CPE name.
+  pub exception_cpes: BTreeMap<String, String>,
+}
+
+impl Default for IntegrationConfig {
+  fn default() -> Self {
+    Self {
+      circuit_name_use_address: true,
+      ignore_subnets: vec!["192.168.0.0/16".to_string()],
+      allowed_subnets: vec!["100.64.0.0/10".to_string()],
+      exclude_sites: Vec::new(),
+      find_ipv6_using_mikrotik: false,
+      bandwidth_overhead_factor: 1.0,
+      exception_cpes: BTreeMap::new(),
+    }
+  }
+}
+
+/// The `[influxdb]` section of `/etc/lqos.conf`: bandwidth and latency
+/// graphing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InfluxDbConfig {
+  /// Should statistics be sent to InfluxDB?
+  pub enabled: bool,
+
+  /// The InfluxDB server, e.g. `http://localhost:8086`
+  pub url: String,
+
+  /// The bucket to write to
+  pub bucket: String,
+
+  /// The InfluxDB organization
+  pub org: String,
+
+  /// The InfluxDB API token
+  pub token: String,
+}
+
+impl Default for InfluxDbConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      url: "http://localhost:8086".to_string(),
+      bucket: "libreqos".to_string(),
+      org: "Your ISP Name Here".to_string(),
+      token: String::new(),
+    }
+  }
+}
+
+/// The `[splynx]` section of `/etc/lqos.conf`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SplynxConfig {
+  /// Should the scheduler import from Splynx?
+  pub enable_splynx: bool,
+
+  /// Splynx API key
+  pub api_key: String,
+
+  /// Splynx API secret
+  pub api_secret: String,
+
+  /// Everything before `/api/2.0/` on your Splynx instance
+  pub url: String,
+}
+
+impl Default for SplynxConfig {
+  fn default() -> Self {
+    Self {
+      enable_splynx: false,
+      api_key: String::new(),
+      api_secret: String::new(),
+      url: "https://YOUR_URL.splynx.app".to_string(),
+    }
+  }
+}
+
+/// The `[uisp]` section of `/etc/lqos.conf`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct UispConfig {
+  /// Should the scheduler import from UISP?
+  pub enable_uisp: bool,
+
+  /// UISP API token
+  pub token: String,
+
+  /// Everything before `/nms/` on your UISP instance
+  pub url: String,
+
+  /// The root site of your network tree
+  pub site: String,
+
+  /// `full` builds the whole network map; `flat` puts every client
+  /// site at the top of the tree.
+  pub strategy: String,
+}
+
+impl Default for UispConfig {
+  fn default() -> Self {
+    Self {
+      enable_uisp: false,
+      token: String::new(),
+      url: "https://examplesite.com".to_string(),
+      site: String::new(),
+      strategy: "full".to_string(),
+    }
+  }
+}
+
+/// The `[rest_http]` section of `/etc/lqos.conf`: importing
+/// `network.json` and `ShapedDevices.csv` from your own HTTP service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RestHttpConfig {
+  /// Should the REST integration run?
+  pub enabled: bool,
+
+  /// The service's base URL
+  pub base_url: String,
+
+  /// Path of the network definition, below `base_url`
+  pub network_uri: String,
+
+  /// Path of the shaped devices list, below `base_url`
+  pub shaper_uri: String,
+
+  /// Verify the service's TLS certificate?
+  pub verify: bool,
+
+  /// Query string parameters to send with each request
+  pub params: BTreeMap<String, String>,
+
+  /// Headers to send with each request
+  pub headers: BTreeMap<String, String>,
+
+  /// If set, a timestamped copy of each imported file is kept here.
+  pub log_changes: Option<String>,
+}
+
+impl Default for RestHttpConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      base_url: "https://domain".to_string(),
+      network_uri: "/some/path".to_string(),
+      shaper_uri: "/some/path/etc".to_string(),
+      verify: true,
+      params: BTreeMap::new(),
+      headers: BTreeMap::new(),
+      log_changes: None,
+    }
+  }
+}
+
+/// The `[python_api]` section of `/etc/lqos.conf`: the Python side's
+/// HTTP API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PythonApiConfig {
+  /// API username
+  pub username: String,
+
+  /// API password
+  pub password: String,
+
+  /// Address to listen on
+  pub host_ip: String,
+
+  /// Port to listen on
+  pub port: u16,
+}
+
+impl Default for PythonApiConfig {
+  fn default() -> Self {
+    Self {
+      username: "testUser".to_string(),
+      password: "changeme8343486806".to_string(),
+      host_ip: "127.0.0.1".to_string(),
+      port: 5000,
+    }
+  }
+}
@@ -0,0 +1,219 @@
+//! Settings for the Python side of LibreQoS. These used to be read from
+//! `ispConfig.py`; they now live in typed sections of `/etc/lqos.conf`,
+//! and `ispConfig.py` is replaced by a shim that reads them from there.
+//! See `python.rs` for the one-shot migration.
+
+mod integrations;
+mod python;
+use crate::etc;
+pub use integrations::{
+  InfluxDbConfig, IntegrationConfig, PythonApiConfig, RestHttpConfig,
+  SplynxConfig, UispConfig,
+};
+use log::error;
+pub use python::{migrate_isp_config, python_values, ISP_CONFIG_SHIM};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The `[shaping]` section of `/etc/lqos.conf`: how LibreQoS builds its
+/// queues. Missing fields take the defaults from `ispConfig.example.py`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LibreQoSConfig {
+  /// Interface facing the Internet
+  pub internet_interface: String,
+
+  /// Interface facing the ISP Core Router
+  pub isp_interface: String,
+
+  /// Are we in "on a stick" (single interface) mode?
+  pub on_a_stick_mode: bool,
+
+  /// If we are, which VLAN represents which direction?
+  /// In (internet, ISP) order.
+  pub stick_vlans: (u16, u16),
+
+  /// The queueing discipline for each circuit, e.g. `cake diffserv4`
+  pub sqm: String,
+
+  /// Are we in monitor-only mode (not shaping)?
+  pub monitor_mode: bool,
+
+  /// Total available download (in Mbps)
+  pub total_download_mbps: u32,
+
+  /// Total available upload (in Mbps)
+  pub total_upload_mbps: u32,
+
+  /// If a node is generated, how much download (Mbps) should it offer?
+  pub generated_download_mbps: u32,
+
+  /// If a node is generated, how much upload (Mbps) should it offer?
+  pub generated_upload_mbps: u32,
+
+  /// Circuit plan rates are multiplied by this when their classes are
+  /// built, so that a subscriber can reach their plan rate on a speed
+  /// test despite TCP overhead. Doesn't apply to nodes.
+  pub tcp_overhead_factor: f64,
+
+  /// Should the Python queue builder use the bin packing strategy to
+  /// try to optimize CPU assignment?
+  pub use_binpacking: bool,
+
+  /// Should the Python program use actual shell commands (and execute)
+  /// them?
+  pub enable_shell_commands: bool,
+
+  /// Should every issued command be prefixed with `sudo`?
+  pub run_as_sudo: bool,
+
+  /// WARNING: generally don't touch this.
+  pub override_queue_count: u32,
+}
+
+impl Default for LibreQoSConfig {
+  fn default() -> Self {
+    Self {
+      internet_interface: "eth2".to_string(),
+      isp_interface: "eth1".to_string(),
+      on_a_stick_mode: false,
+      stick_vlans: (0, 0),
+      sqm: "cake diffserv4".to_string(),
+      monitor_mode: false,
+      total_download_mbps: 1000,
+      total_upload_mbps: 1000,
+      generated_download_mbps: 1000,
+      generated_upload_mbps: 1000,
+      tcp_overhead_factor: 1.09,
+      use_binpacking: false,
+      enable_shell_commands: true,
+      run_as_sudo: false,
+      override_queue_count: 0,
+    }
+  }
+}
+
+impl LibreQoSConfig {
+  /// Does `/etc/lqos.conf` have a `[shaping]` section?
+  pub fn config_exists() -> bool {
+    if let Ok(cfg) = etc::EtcLqos::load() {
+      cfg.shaping.is_some()
+    } else {
+      false
+    }
+  }
+
+  /// Loads the `[shaping]` section of `/etc/lqos.conf`.
+  pub fn load() -> Result<Self, LibreQoSConfigError> {
+    let cfg = etc::EtcLqos::load().map_err(|_| {
+      error!("Unable to read LibreQoS config from /etc/lqos.conf");
+      LibreQoSConfigError::CannotOpenEtcLqos
+    })?;
+    if let Some(shaping) = cfg.shaping {
+      shaping.validate()?;
+      Ok(shaping)
+    } else {
+      error!("/etc/lqos.conf has no [shaping] section.");
+      error!("Run `lqos_setup migrate` to import your ispConfig.py.");
+      Err(LibreQoSConfigError::NotMigrated)
+    }
+  }
+
+  /// Checks for settings that LibreQoS can't shape with.
+  pub fn validate(&self) -> Result<(), LibreQoSConfigError> {
+    let problem = if self.isp_interface.is_empty()
+      || self.internet_interface.is_empty()
+    {
+      Some("isp_interface and internet_interface must be set")
+    } else if self.on_a_stick_mode && self.stick_vlans.0 == self.stick_vlans.1
+    {
+      Some("on_a_stick_mode needs two different stick_vlans")
+    } else if self.sqm.is_empty() {
+      Some("sqm must be set")
+    } else if self.total_download_mbps == 0 || self.total_upload_mbps == 0 {
+      Some("total_download_mbps and total_upload_mbps must be above 0")
+    } else if !self.tcp_overhead_factor.is_finite()
+      || self.tcp_overhead_factor <= 0.0
+    {
+      Some("tcp_overhead_factor must be above 0")
+    } else {
+      None
+    };
+    if let Some(problem) = problem {
+      error!("Invalid [shaping] section in /etc/lqos.conf: {problem}");
+      Err(LibreQoSConfigError::Invalid(problem.to_string()))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Saves the current values to the `[shaping]` section of
+  /// `/etc/lqos.conf`, which is backed up to `/etc/lqos.conf.backup`
+  /// first.
+  pub fn save(&self) -> Result<(), LibreQoSConfigError> {
+    self.validate()?;
+    let mut cfg = etc::EtcLqos::load()
+      .map_err(|_| LibreQoSConfigError::CannotOpenEtcLqos)?;
+    cfg.shaping = Some(self.clone());
+    cfg.save().map_err(|_| LibreQoSConfigError::CannotWrite)
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum LibreQoSConfigError {
+  #[error("Unable to read /etc/lqos.conf. See other errors for details.")]
+  CannotOpenEtcLqos,
+  #[error("/etc/lqos.conf has no [shaping] section. Run `lqos_setup migrate` to import ispConfig.py.")]
+  NotMigrated,
+  #[error("Invalid [shaping] section in /etc/lqos.conf")]
+  Invalid(String),
+  #[error("Unable to write to /etc/lqos.conf")]
+  CannotWrite,
+  #[error(
+    "Unable to read the contents of ispConfig.py. Check file permissions."
+  )]
+  CannotReadFile,
+  #[error("Unable to run python3 to read ispConfig.py")]
+  CannotRunPython,
+  #[error("python3 could not evaluate ispConfig.py")]
+  PythonError(String),
+  #[error("A setting in ispConfig.py has the wrong type")]
+  BadValue(String),
+  #[error("Unable to replace ispConfig.py")]
+  CannotReplace,
+  #[error("ispConfig.py has already been migrated to /etc/lqos.conf")]
+  AlreadyMigrated,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn defaults_are_valid() {
+    assert!(LibreQoSConfig::default().validate().is_ok());
+  }
+
+  #[test]
+  fn missing_fields_take_defaults() {
+    let cfg: LibreQoSConfig =
+      toml::from_str("isp_interface = 'enp1s0f1'\nmonitor_mode = true")
+        .unwrap();
+    assert_eq!(cfg.isp_interface, "enp1s0f1");
+    assert!(cfg.monitor_mode);
+    assert_eq!(cfg.sqm, "cake diffserv4");
+    assert!(cfg.enable_shell_commands);
+  }
+
+  #[test]
+  fn stick_mode_needs_two_vlans() {
+    let cfg = LibreQoSConfig {
+      on_a_stick_mode: true,
+      stick_vlans: (3, 3),
+      ..Default::default()
+    };
+    assert!(matches!(cfg.validate(), Err(LibreQoSConfigError::Invalid(_))));
+    let cfg = LibreQoSConfig { stick_vlans: (3, 4), ..cfg };
+    assert!(cfg.validate().is_ok());
+  }
+}
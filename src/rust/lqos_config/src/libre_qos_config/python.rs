@@ -0,0 +1,393 @@
+//! Moves settings between `/etc/lqos.conf` and the names the Python side
+//! of LibreQoS knows them by. `ispConfig.py` is evaluated by Python itself,
+//! so multi-line values, comments and expressions are read exactly as
+//! `LibreQoS.py` would read them.
+
+use super::{
+  InfluxDbConfig, IntegrationConfig, LibreQoSConfig, LibreQoSConfigError,
+  PythonApiConfig, RestHttpConfig, SplynxConfig, UispConfig,
+};
+use crate::etc::EtcLqos;
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+/// Runs `ispConfig.py` and prints every plain setting it defines as JSON.
+const EVALUATE_ISP_CONFIG: &str = "import json, runpy, sys
+values = runpy.run_path(sys.argv[1])
+print(json.dumps({k: v for k, v in values.items() if not k.startswith('_')
+  and isinstance(v, (str, int, float, bool, list, dict))}))";
+
+/// Replaces `ispConfig.py` once it has been migrated, so that the Python
+/// side reads the same settings as `lqosd`.
+pub const ISP_CONFIG_SHIM: &str =
+  "# LibreQoS settings now live in /etc/lqos.conf. This file loads them,
+# under their old names, for the Python side of LibreQoS. The original
+# file was kept as ispConfig.py.migrated.
+import json
+from liblqos_python import isp_config_json
+globals().update(json.loads(isp_config_json()))
+";
+
+/// `ispConfig.py` settings, by Python name. Settings are removed as they
+/// are read, so anything left over wasn't recognised.
+struct PythonValues(Map<String, Value>);
+
+impl PythonValues {
+  fn take<T: DeserializeOwned>(
+    &mut self,
+    name: &str,
+    target: &mut T,
+  ) -> Result<(), LibreQoSConfigError> {
+    if let Some(value) = self.0.remove(name) {
+      *target = serde_json::from_value(value.clone()).map_err(|_| {
+        error!("Unexpected value for {name} in ispConfig.py: {value}");
+        LibreQoSConfigError::BadValue(name.to_string())
+      })?;
+    }
+    Ok(())
+  }
+
+  fn take_map(&mut self, name: &str) -> Result<Self, LibreQoSConfigError> {
+    let mut map = Map::new();
+    self.take(name, &mut map)?;
+    Ok(Self(map))
+  }
+}
+
+/// Fills the Python-side sections of `cfg` from `ispConfig.py` settings.
+/// Settings that aren't set keep their defaults. Returns the names of any
+/// settings that weren't recognised.
+fn apply_python_values(
+  cfg: &mut EtcLqos,
+  values: Map<String, Value>,
+) -> Result<Vec<String>, LibreQoSConfigError> {
+  let mut values = PythonValues(values);
+
+  let mut shaping = LibreQoSConfig::default();
+  values.take("sqm", &mut shaping.sqm)?;
+  values.take("monitorOnlyMode", &mut shaping.monitor_mode)?;
+  values.take(
+    "upstreamBandwidthCapacityDownloadMbps",
+    &mut shaping.total_download_mbps,
+  )?;
+  values.take(
+    "upstreamBandwidthCapacityUploadMbps",
+    &mut shaping.total_upload_mbps,
+  )?;
+  values
+    .take("generatedPNDownloadMbps", &mut shaping.generated_download_mbps)?;
+  values.take("generatedPNUploadMbps", &mut shaping.generated_upload_mbps)?;
+  values.take("tcpOverheadFactor", &mut shaping.tcp_overhead_factor)?;
+  values.take("interfaceA", &mut shaping.isp_interface)?;
+  values.take("interfaceB", &mut shaping.internet_interface)?;
+  values.take("OnAStick", &mut shaping.on_a_stick_mode)?;
+  values.take("StickVlanA", &mut shaping.stick_vlans.0)?;
+  values.take("StickVlanB", &mut shaping.stick_vlans.1)?;
+  values
+    .take("enableActualShellCommands", &mut shaping.enable_shell_commands)?;
+  values.take("runShellCommandsAsSudo", &mut shaping.run_as_sudo)?;
+  values.take("queuesAvailableOverride", &mut shaping.override_queue_count)?;
+  values.take("useBinPackingToBalanceCPU", &mut shaping.use_binpacking)?;
+
+  let mut integration = IntegrationConfig::default();
+  values.take(
+    "circuitNameUseAddress",
+    &mut integration.circuit_name_use_address,
+  )?;
+  values.take("ignoreSubnets", &mut integration.ignore_subnets)?;
+  values.take("allowedSubnets", &mut integration.allowed_subnets)?;
+  values.take("excludeSites", &mut integration.exclude_sites)?;
+  values.take(
+    "findIPv6usingMikrotik",
+    &mut integration.find_ipv6_using_mikrotik,
+  )?;
+  values.take(
+    "bandwidthOverheadFactor",
+    &mut integration.bandwidth_overhead_factor,
+  )?;
+  values.take("exceptionCPEs", &mut integration.exception_cpes)?;
+
+  let mut influxdb = InfluxDbConfig::default();
+  values.take("influxDBEnabled", &mut influxdb.enabled)?;
+  values.take("influxDBurl", &mut influxdb.url)?;
+  values.take("influxDBBucket", &mut influxdb.bucket)?;
+  values.take("influxDBOrg", &mut influxdb.org)?;
+  values.take("influxDBtoken", &mut influxdb.token)?;
+
+  let mut splynx = SplynxConfig::default();
+  values.take("automaticImportSplynx", &mut splynx.enable_splynx)?;
+  values.take("splynx_api_key", &mut splynx.api_key)?;
+  values.take("splynx_api_secret", &mut splynx.api_secret)?;
+  values.take("splynx_api_url", &mut splynx.url)?;
+
+  let mut uisp = UispConfig::default();
+  values.take("automaticImportUISP", &mut uisp.enable_uisp)?;
+  values.take("uispAuthToken", &mut uisp.token)?;
+  values.take("UISPbaseURL", &mut uisp.url)?;
+  values.take("uispSite", &mut uisp.site)?;
+  values.take("uispStrategy", &mut uisp.strategy)?;
+
+  // The example file calls this httpRestIntegrationConfig, but the
+  // integration imports automaticImportRestHttp. Accept either.
+  let mut rest_http = RestHttpConfig::default();
+  let mut rest = values.take_map("httpRestIntegrationConfig")?;
+  if rest.0.is_empty() {
+    rest = values.take_map("automaticImportRestHttp")?;
+  } else {
+    values.0.remove("automaticImportRestHttp");
+  }
+  rest.take("enabled", &mut rest_http.enabled)?;
+  rest.take("baseURL", &mut rest_http.base_url)?;
+  rest.take("networkURI", &mut rest_http.network_uri)?;
+  rest.take("shaperURI", &mut rest_http.shaper_uri)?;
+  rest.take("logChanges", &mut rest_http.log_changes)?;
+  let mut requests = rest.take_map("requestsConfig")?;
+  requests.take("verify", &mut rest_http.verify)?;
+  requests.take("params", &mut rest_http.params)?;
+  requests.take("headers", &mut rest_http.headers)?;
+
+  let mut python_api = PythonApiConfig::default();
+  values.take("apiUsername", &mut python_api.username)?;
+  values.take("apiPassword", &mut python_api.password)?;
+  values.take("apiHostIP", &mut python_api.host_ip)?;
+  values.take("apiHostPost", &mut python_api.port)?;
+
+  cfg.shaping = Some(shaping);
+  cfg.integration = Some(integration);
+  cfg.influxdb = Some(influxdb);
+  cfg.splynx = Some(splynx);
+  cfg.uisp = Some(uisp);
+  cfg.rest_http = Some(rest_http);
+  cfg.python_api = Some(python_api);
+
+  let mut unknown: Vec<String> = values.0.keys().cloned().collect();
+  unknown
+    .extend(rest.0.keys().map(|k| format!("httpRestIntegrationConfig.{k}")));
+  Ok(unknown)
+}
+
+/// Every Python-side setting in `cfg`, by its `ispConfig.py` name.
+/// Sections that are missing from `/etc/lqos.conf` give their defaults.
+pub fn python_values(cfg: &EtcLqos) -> Map<String, Value> {
+  let shaping = cfg.shaping.clone().unwrap_or_default();
+  let integration = cfg.integration.clone().unwrap_or_default();
+  let influxdb = cfg.influxdb.clone().unwrap_or_default();
+  let splynx = cfg.splynx.clone().unwrap_or_default();
+  let uisp = cfg.uisp.clone().unwrap_or_default();
+  let rest_http = cfg.rest_http.clone().unwrap_or_default();
+  let python_api = cfg.python_api.clone().unwrap_or_default();
+  let rest = json!({
+    "enabled": rest_http.enabled,
+    "baseURL": rest_http.base_url,
+    "networkURI": rest_http.network_uri,
+    "shaperURI": rest_http.shaper_uri,
+    "logChanges": rest_http.log_changes,
+    "requestsConfig": {
+      "verify": rest_http.verify,
+      "params": rest_http.params,
+      "headers": rest_http.headers,
+    },
+  });
+
+  vec![
+    ("sqm", json!(shaping.sqm)),
+    ("monitorOnlyMode", json!(shaping.monitor_mode)),
+    (
+      "upstreamBandwidthCapacityDownloadMbps",
+      json!(shaping.total_download_mbps),
+    ),
+    ("upstreamBandwidthCapacityUploadMbps", json!(shaping.total_upload_mbps)),
+    ("generatedPNDownloadMbps", json!(shaping.generated_download_mbps)),
+    ("generatedPNUploadMbps", json!(shaping.generated_upload_mbps)),
+    ("tcpOverheadFactor", json!(shaping.tcp_overhead_factor)),
+    ("interfaceA", json!(shaping.isp_interface)),
+    ("interfaceB", json!(shaping.internet_interface)),
+    ("OnAStick", json!(shaping.on_a_stick_mode)),
+    ("StickVlanA", json!(shaping.stick_vlans.0)),
+    ("StickVlanB", json!(shaping.stick_vlans.1)),
+    ("enableActualShellCommands", json!(shaping.enable_shell_commands)),
+    ("runShellCommandsAsSudo", json!(shaping.run_as_sudo)),
+    ("queuesAvailableOverride", json!(shaping.override_queue_count)),
+    ("useBinPackingToBalanceCPU", json!(shaping.use_binpacking)),
+    ("circuitNameUseAddress", json!(integration.circuit_name_use_address)),
+    ("ignoreSubnets", json!(integration.ignore_subnets)),
+    ("allowedSubnets", json!(integration.allowed_subnets)),
+    ("excludeSites", json!(integration.exclude_sites)),
+    ("findIPv6usingMikrotik", json!(integration.find_ipv6_using_mikrotik)),
+    ("bandwidthOverheadFactor", json!(integration.bandwidth_overhead_factor)),
+    ("exceptionCPEs", json!(integration.exception_cpes)),
+    ("influxDBEnabled", json!(influxdb.enabled)),
+    ("influxDBurl", json!(influxdb.url)),
+    ("influxDBBucket", json!(influxdb.bucket)),
+    ("influxDBOrg", json!(influxdb.org)),
+    ("influxDBtoken", json!(influxdb.token)),
+    ("automaticImportSplynx", json!(splynx.enable_splynx)),
+    ("splynx_api_key", json!(splynx.api_key)),
+    ("splynx_api_secret", json!(splynx.api_secret)),
+    ("splynx_api_url", json!(splynx.url)),
+    ("automaticImportUISP", json!(uisp.enable_uisp)),
+    ("uispAuthToken", json!(uisp.token)),
+    ("UISPbaseURL", json!(uisp.url)),
+    ("uispSite", json!(uisp.site)),
+    ("uispStrategy", json!(uisp.strategy)),
+    ("httpRestIntegrationConfig", rest.clone()),
+    ("automaticImportRestHttp", rest),
+    ("apiUsername", json!(python_api.username)),
+    ("apiPassword", json!(python_api.password)),
+    ("apiHostIP", json!(python_api.host_ip)),
+    ("apiHostPost", json!(python_api.port)),
+  ]
+  .into_iter()
+  .map(|(name, value)| (name.to_string(), value))
+  .collect()
+}
+
+fn evaluate_isp_config(
+  path: &Path,
+) -> Result<Map<String, Value>, LibreQoSConfigError> {
+  let output = Command::new("python3")
+    .arg("-c")
+    .arg(EVALUATE_ISP_CONFIG)
+    .arg(path)
+    .output()
+    .map_err(|e| {
+      error!("Unable to run python3: {e:?}");
+      LibreQoSConfigError::CannotRunPython
+    })?;
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    error!("python3 could not evaluate {}: {stderr}", path.display());
+    return Err(LibreQoSConfigError::PythonError(stderr));
+  }
+  serde_json::from_slice(&output.stdout).map_err(|e| {
+    error!("Unexpected output from python3: {e:?}");
+    LibreQoSConfigError::PythonError(e.to_string())
+  })
+}
+
+/// One-shot migration from `ispConfig.py` to `/etc/lqos.conf`. The
+/// settings are written to `/etc/lqos.conf` (which is backed up first),
+/// `ispConfig.py` is renamed to `ispConfig.py.migrated`, and a shim that
+/// loads the new settings takes its place. Returns the names of any
+/// settings that weren't recognised, and so weren't migrated.
+pub fn migrate_isp_config() -> Result<Vec<String>, LibreQoSConfigError> {
+  let mut cfg =
+    EtcLqos::load().map_err(|_| LibreQoSConfigError::CannotOpenEtcLqos)?;
+  let base_path = PathBuf::from(&cfg.lqos_directory);
+  let isp_config = base_path.join("ispConfig.py");
+  let original = fs::read_to_string(&isp_config).map_err(|e| {
+    error!("Unable to read {}: {e:?}", isp_config.display());
+    LibreQoSConfigError::CannotReadFile
+  })?;
+  if original == ISP_CONFIG_SHIM {
+    info!("ispConfig.py has already been migrated.");
+    return Err(LibreQoSConfigError::AlreadyMigrated);
+  }
+
+  let values = evaluate_isp_config(&isp_config)?;
+  let unknown = apply_python_values(&mut cfg, values)?;
+  if let Some(shaping) = &cfg.shaping {
+    shaping.validate()?;
+  }
+  cfg.save().map_err(|_| LibreQoSConfigError::CannotWrite)?;
+
+  let migrated = base_path.join("ispConfig.py.migrated");
+  if let Err(e) = fs::rename(&isp_config, &migrated)
+    .and_then(|_| fs::write(&isp_config, ISP_CONFIG_SHIM))
+  {
+    error!("Unable to replace {}: {e:?}", isp_config.display());
+    return Err(LibreQoSConfigError::CannotReplace);
+  }
+  Ok(unknown)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn empty_etc_lqos() -> EtcLqos {
+    toml::from_str(
+      "lqos_directory = '/opt/libreqos/src'\nqueue_check_period_ms = 1000",
+    )
+    .unwrap()
+  }
+
+  fn values(raw: Value) -> Map<String, Value> {
+    if let Value::Object(map) = raw {
+      map
+    } else {
+      panic!("not an object")
+    }
+  }
+
+  #[test]
+  fn reads_python_names() {
+    let mut cfg = empty_etc_lqos();
+    let unknown = apply_python_values(
+      &mut cfg,
+      values(json!({
+        "interfaceA": "enp1s0f1",
+        "interfaceB": "enp1s0f2",
+        "upstreamBandwidthCapacityDownloadMbps": 2500,
+        "bandwidthOverheadFactor": 1,
+        "exceptionCPEs": { "CPE-1": "AP-1" },
+        "httpRestIntegrationConfig": {
+          "enabled": true,
+          "requestsConfig": { "verify": false, "params": { "a": "b" } },
+        },
+        "someLocalTweak": 42,
+      })),
+    )
+    .unwrap();
+    let shaping = cfg.shaping.unwrap();
+    assert_eq!(shaping.isp_interface, "enp1s0f1");
+    assert_eq!(shaping.internet_interface, "enp1s0f2");
+    assert_eq!(shaping.total_download_mbps, 2500);
+    assert_eq!(shaping.total_upload_mbps, 1000);
+    let integration = cfg.integration.unwrap();
+    assert_eq!(integration.bandwidth_overhead_factor, 1.0);
+    assert_eq!(integration.exception_cpes["CPE-1"], "AP-1");
+    let rest = cfg.rest_http.unwrap();
+    assert!(rest.enabled);
+    assert!(!rest.verify);
+    assert_eq!(rest.params["a"], "b");
+    assert_eq!(unknown, vec!["someLocalTweak".to_string()]);
+  }
+
+  #[test]
+  fn wrong_types_are_errors() {
+    let mut cfg = empty_etc_lqos();
+    let result = apply_python_values(
+      &mut cfg,
+      values(json!({ "upstreamBandwidthCapacityDownloadMbps": "lots" })),
+    );
+    assert!(matches!(result, Err(LibreQoSConfigError::BadValue(name))
+      if name == "upstreamBandwidthCapacityDownloadMbps"));
+  }
+
+  #[test]
+  fn python_values_round_trip() {
+    let mut cfg = empty_etc_lqos();
+    apply_python_values(
+      &mut cfg,
+      values(json!({ "sqm": "fq_codel", "uispSite": "Root" })),
+    )
+    .unwrap();
+    let exported = python_values(&cfg);
+    assert_eq!(exported["sqm"], "fq_codel");
+    assert_eq!(exported["automaticImportRestHttp"]["enabled"], false);
+
+    let mut round_trip = empty_etc_lqos();
+    let unknown = apply_python_values(&mut round_trip, exported).unwrap();
+    assert!(unknown.is_empty());
+    assert_eq!(round_trip.shaping, cfg.shaping);
+    assert_eq!(round_trip.uisp, cfg.uisp);
+    assert_eq!(round_trip.rest_http, cfg.rest_http);
+  }
+}
@@ -0,0 +1,370 @@
+//! Capacity checks for the network tree. `network.json` limits and
+//! `ShapedDevices.csv` plans are each read on their own, so nothing
+//! stops a child from being given more bandwidth than its parent, or a
+//! site from promising its customers more than it can carry. This pass
+//! totals up each node's children and circuits so that those mistakes
+//! can be found.
+
+use super::NetworkJson;
+use crate::ShapedDevice;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display};
+
+/// Which way traffic is flowing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityDirection {
+  /// Towards the customer
+  Download,
+  /// Away from the customer
+  Upload,
+}
+
+impl Display for CapacityDirection {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Download => write!(f, "download"),
+      Self::Upload => write!(f, "upload"),
+    }
+  }
+}
+
+/// A capacity problem at a node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CapacityIssue {
+  /// A child node is allowed more than this node. LibreQoS clamps the
+  /// child to this node's limit.
+  ChildExceedsParent {
+    /// The child's name
+    child: String,
+    /// The direction that is over
+    direction: CapacityDirection,
+    /// The child's limit, in Mbps
+    child_mbps: u32,
+    /// This node's limit, in Mbps
+    parent_mbps: u32,
+  },
+
+  /// The minimum rates of every circuit below this node add up to more
+  /// than the node can carry, so they can't all be guaranteed.
+  MinimumsExceedCapacity {
+    /// The direction that is over
+    direction: CapacityDirection,
+    /// Total of the circuits' minimum rates, in Mbps
+    guaranteed_mbps: u64,
+    /// This node's limit, in Mbps
+    capacity_mbps: u32,
+  },
+}
+
+impl Display for CapacityIssue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::ChildExceedsParent {
+        child,
+        direction,
+        child_mbps,
+        parent_mbps,
+      } => {
+        write!(
+          f,
+          "child {child} allows {child_mbps} Mbps {direction}, above this node's {parent_mbps} Mbps"
+        )
+      }
+      Self::MinimumsExceedCapacity {
+        direction,
+        guaranteed_mbps,
+        capacity_mbps,
+      } => {
+        write!(
+          f,
+          "circuits below guarantee {guaranteed_mbps} Mbps {direction}, above this node's {capacity_mbps} Mbps"
+        )
+      }
+    }
+  }
+}
+
+/// Capacity figures for one node of `network.json`. All rates are in
+/// Mbps, as (download, upload).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeCapacity {
+  /// The node's index in `NetworkJson::nodes`
+  pub index: usize,
+
+  /// The node's name
+  pub name: String,
+
+  /// The node's stable `id`, if it has one
+  pub id: Option<String>,
+
+  /// The node's own limit. 0 means unlimited.
+  pub max_throughput: (u32, u32),
+
+  /// How many nodes sit directly below this one
+  pub child_nodes: usize,
+
+  /// The total of the limits of the nodes directly below this one
+  pub child_node_capacity: (u64, u64),
+
+  /// How many circuits are attached directly to this node
+  pub circuits: usize,
+
+  /// The total of the maximum rates of circuits attached directly to
+  /// this node
+  pub circuit_capacity: (u64, u64),
+
+  /// The total of the minimum rates of every circuit anywhere below
+  /// this node
+  pub guaranteed: (u64, u64),
+
+  /// Child node and circuit capacity, divided by this node's limit.
+  /// `None` where the node is unlimited.
+  pub oversubscription: (Option<f64>, Option<f64>),
+
+  /// Problems found at this node
+  pub issues: Vec<CapacityIssue>,
+}
+
+/// A circuit's parent and plan, taken from the first of its devices.
+struct CircuitPlan {
+  parent: usize,
+  min: (u64, u64),
+  max: (u64, u64),
+}
+
+impl NetworkJson {
+  /// Totals up the capacity below every node, and reports nodes whose
+  /// children are allowed more than the node itself, or whose circuits'
+  /// minimum rates can't all be met. Circuits whose parent node isn't
+  /// in `network.json` are counted at the root.
+  pub fn check_capacity(&self, devices: &[ShapedDevice]) -> Vec<NodeCapacity> {
+    let mut report: Vec<NodeCapacity> = self
+      .nodes
+      .iter()
+      .enumerate()
+      .map(|(index, node)| NodeCapacity {
+        index,
+        name: node.name.clone(),
+        id: node.id.clone(),
+        max_throughput: node.max_throughput,
+        child_nodes: 0,
+        child_node_capacity: (0, 0),
+        circuits: 0,
+        circuit_capacity: (0, 0),
+        guaranteed: (0, 0),
+        oversubscription: (None, None),
+        issues: Vec::new(),
+      })
+      .collect();
+    if report.is_empty() {
+      return report;
+    }
+
+    // Child nodes
+    for (index, node) in self.nodes.iter().enumerate() {
+      let Some(parent) = node.immediate_parent else { continue };
+      let (child_down, child_up) = node.max_throughput;
+      let entry = &mut report[parent];
+      entry.child_nodes += 1;
+      entry.child_node_capacity.0 += child_down as u64;
+      entry.child_node_capacity.1 += child_up as u64;
+      let (parent_down, parent_up) = entry.max_throughput;
+      for (direction, child_mbps, parent_mbps) in [
+        (CapacityDirection::Download, child_down, parent_down),
+        (CapacityDirection::Upload, child_up, parent_up),
+      ] {
+        if parent_mbps > 0 && child_mbps > parent_mbps {
+          entry.issues.push(CapacityIssue::ChildExceedsParent {
+            child: self.nodes[index].name.clone(),
+            direction,
+            child_mbps,
+            parent_mbps,
+          });
+        }
+      }
+    }
+
+    // Circuits
+    for circuit in self.circuit_plans(devices).values() {
+      let entry = &mut report[circuit.parent];
+      entry.circuits += 1;
+      entry.circuit_capacity.0 += circuit.max.0;
+      entry.circuit_capacity.1 += circuit.max.1;
+      // A node's parents list ends with the node itself; the root's is
+      // empty.
+      let parents = &self.nodes[circuit.parent].parents;
+      let above = if parents.is_empty() { &[0][..] } else { &parents[..] };
+      for index in above {
+        report[*index].guaranteed.0 += circuit.min.0;
+        report[*index].guaranteed.1 += circuit.min.1;
+      }
+    }
+
+    // Ratios and guarantees
+    for entry in report.iter_mut() {
+      let ratio = |used: u64, limit: u32| {
+        (limit > 0).then(|| used as f64 / limit as f64)
+      };
+      entry.oversubscription = (
+        ratio(
+          entry.child_node_capacity.0 + entry.circuit_capacity.0,
+          entry.max_throughput.0,
+        ),
+        ratio(
+          entry.child_node_capacity.1 + entry.circuit_capacity.1,
+          entry.max_throughput.1,
+        ),
+      );
+      for (direction, guaranteed_mbps, capacity_mbps) in [
+        (
+          CapacityDirection::Download,
+          entry.guaranteed.0,
+          entry.max_throughput.0,
+        ),
+        (
+          CapacityDirection::Upload,
+          entry.guaranteed.1,
+          entry.max_throughput.1,
+        ),
+      ] {
+        if capacity_mbps > 0 && guaranteed_mbps > capacity_mbps as u64 {
+          entry.issues.push(CapacityIssue::MinimumsExceedCapacity {
+            direction,
+            guaranteed_mbps,
+            capacity_mbps,
+          });
+        }
+      }
+    }
+    report
+  }
+
+  fn circuit_plans(
+    &self,
+    devices: &[ShapedDevice],
+  ) -> HashMap<String, CircuitPlan> {
+    let mut circuits = HashMap::new();
+    for device in devices.iter() {
+      circuits.entry(device.circuit_id.clone()).or_insert_with(|| {
+        CircuitPlan {
+          parent: self
+            .get_index_for_id_or_name(&device.parent_node)
+            .unwrap_or(0),
+          min: (
+            device.download_min_mbps as u64,
+            device.upload_min_mbps as u64,
+          ),
+          max: (
+            device.download_max_mbps as u64,
+            device.upload_max_mbps as u64,
+          ),
+        }
+      });
+    }
+    circuits
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const NETWORK: &str = r#"{
+    "Site_1": {
+      "downloadBandwidthMbps": 1000,
+      "uploadBandwidthMbps": 100,
+      "children": {
+        "AP_A": {
+          "downloadBandwidthMbps": 500,
+          "uploadBandwidthMbps": 200
+        },
+        "AP_B": {
+          "downloadBandwidthMbps": 800,
+          "uploadBandwidthMbps": 50
+        }
+      }
+    }
+  }"#;
+
+  fn device(circuit: &str, parent: &str, min: u32, max: u32) -> ShapedDevice {
+    ShapedDevice {
+      circuit_id: circuit.to_string(),
+      parent_node: parent.to_string(),
+      download_min_mbps: min,
+      upload_min_mbps: min,
+      download_max_mbps: max,
+      upload_max_mbps: max,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn totals_children_and_circuits() {
+    let net = NetworkJson::from_json_str(NETWORK).unwrap();
+    let devices = vec![
+      device("1", "AP_A", 10, 100),
+      device("1", "AP_A", 10, 100), // A second device in the same circuit
+      device("2", "AP_A", 20, 300),
+      device("3", "Site_1", 5, 50),
+      device("4", "Nowhere", 1, 10),
+    ];
+    let report = net.check_capacity(&devices);
+    let names: Vec<&str> = report.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["Root", "Site_1", "AP_A", "AP_B"]);
+
+    let site = &report[1];
+    assert_eq!(site.child_nodes, 2);
+    assert_eq!(site.child_node_capacity, (1300, 250));
+    assert_eq!(site.circuits, 1);
+    assert_eq!(site.circuit_capacity, (50, 50));
+    assert_eq!(site.guaranteed, (35, 35));
+    assert_eq!(site.oversubscription, (Some(1.35), Some(3.0)));
+
+    let ap = &report[2];
+    assert_eq!(ap.circuits, 2);
+    assert_eq!(ap.circuit_capacity, (400, 400));
+    assert_eq!(ap.guaranteed, (30, 30));
+
+    let root = &report[0];
+    assert_eq!(root.circuits, 1);
+    assert_eq!(root.guaranteed, (36, 36));
+    assert_eq!(root.oversubscription, (None, None));
+  }
+
+  #[test]
+  fn finds_children_over_their_parent() {
+    let net = NetworkJson::from_json_str(NETWORK).unwrap();
+    let report = net.check_capacity(&[]);
+    assert_eq!(
+      report[1].issues,
+      vec![CapacityIssue::ChildExceedsParent {
+        child: "AP_A".to_string(),
+        direction: CapacityDirection::Upload,
+        child_mbps: 200,
+        parent_mbps: 100,
+      }]
+    );
+    assert!(report[2].issues.is_empty());
+  }
+
+  #[test]
+  fn finds_impossible_minimums() {
+    let net = NetworkJson::from_json_str(NETWORK).unwrap();
+    let devices =
+      vec![device("1", "AP_B", 30, 100), device("2", "AP_B", 30, 100)];
+    let report = net.check_capacity(&devices);
+    assert_eq!(
+      report[3].issues,
+      vec![CapacityIssue::MinimumsExceedCapacity {
+        direction: CapacityDirection::Upload,
+        guaranteed_mbps: 60,
+        capacity_mbps: 50,
+      }]
+    );
+    // Site_1 can carry 60 Mbps of upload
+    assert!(!report[1]
+      .issues
+      .iter()
+      .any(|i| matches!(i, CapacityIssue::MinimumsExceedCapacity { .. })));
+  }
+}
@@ -0,0 +1,460 @@
+//! Editing `network.json` one node at a time, rather than rewriting the
+//! whole file.
+
+use super::{NetworkJson, NetworkJsonError, NODE_ATTRIBUTES};
+use crate::atomic_write::write_atomically;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+
+/// A single edit to `network.json`. Nodes are found by `id`, falling back
+/// to their name, in the same way as `ShapedDevices.csv` parent nodes.
+/// A `parent` of `None` means the top of the tree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum NetworkJsonChange {
+  /// Add a node, with no children.
+  AddNode {
+    /// The node to add it below
+    parent: Option<String>,
+    /// The new node's name
+    name: String,
+    /// The new node's stable `id`, if it should have one
+    id: Option<String>,
+    /// Download limit, in Mbps
+    download_mbps: u32,
+    /// Upload limit, in Mbps
+    upload_mbps: u32,
+  },
+
+  /// Rename a node. Circuits in `ShapedDevices.csv` that name the node
+  /// as their parent need changing too; those that use its `id` don't.
+  RenameNode {
+    /// The node to rename
+    node: String,
+    /// Its new name
+    name: String,
+  },
+
+  /// Move a node, and everything below it, to a new parent.
+  MoveNode {
+    /// The node to move
+    node: String,
+    /// The node to move it below
+    parent: Option<String>,
+  },
+
+  /// Change a node's bandwidth limits.
+  SetBandwidth {
+    /// The node to change
+    node: String,
+    /// Download limit, in Mbps
+    download_mbps: u32,
+    /// Upload limit, in Mbps
+    upload_mbps: u32,
+  },
+
+  /// Remove a node. Nodes that still have children can't be removed.
+  DeleteNode {
+    /// The node to remove
+    node: String,
+  },
+}
+
+impl NetworkJson {
+  /// Loads `network.json`, applies `changes` in order, and writes it back
+  /// (atomically). Either every change is applied or none are. Each
+  /// change is checked against the tree as the previous changes left it.
+  pub fn change(
+    changes: &[NetworkJsonChange],
+  ) -> Result<Self, NetworkJsonError> {
+    let path = Self::path()?;
+    let raw = if path.exists() {
+      fs::read_to_string(&path)
+        .map_err(|_| NetworkJsonError::ConfigLoadError)?
+    } else {
+      "{}".to_string()
+    };
+    let mut json: Value = serde_json::from_str(&raw)
+      .map_err(|_| NetworkJsonError::ConfigLoadError)?;
+    apply_changes(&mut json, changes)?;
+
+    let raw = serde_json::to_string_pretty(&json)
+      .map_err(|_| NetworkJsonError::WriteFail)?;
+    let result = Self::from_json_value(&json);
+    write_atomically(&path, raw.as_bytes()).map_err(|e| {
+      error!("Unable to replace {}: {e:?}", path.display());
+      NetworkJsonError::WriteFail
+    })?;
+    info!("network.json changed");
+    Ok(result)
+  }
+}
+
+/// Applies `changes` to the parsed contents of `network.json`.
+pub(crate) fn apply_changes(
+  json: &mut Value,
+  changes: &[NetworkJsonChange],
+) -> Result<(), NetworkJsonError> {
+  if !json.is_object() {
+    return Err(NetworkJsonError::ConfigLoadError);
+  }
+  for change in changes.iter() {
+    // Re-read the tree each time, so that lookups see earlier changes.
+    let tree = NetworkJson::from_json_value(json);
+    match change {
+      NetworkJsonChange::AddNode {
+        parent,
+        name,
+        id,
+        download_mbps,
+        upload_mbps,
+      } => {
+        check_name(name)?;
+        check_bandwidth(*download_mbps, *upload_mbps)?;
+        if let Some(id) = id {
+          if tree.get_index_for_id(id).is_some() {
+            return Err(invalid(format!("A node already has the id {id}")));
+          }
+        }
+        let parent_path = tree.parent_path(parent.as_deref())?;
+        let siblings = children_mut(json, &parent_path)?;
+        if siblings.contains_key(name) {
+          return Err(invalid(format!("{name} already exists there")));
+        }
+        let mut node = Map::new();
+        if let Some(id) = id {
+          node.insert("id".to_string(), Value::String(id.clone()));
+        }
+        node.insert(
+          "downloadBandwidthMbps".to_string(),
+          (*download_mbps).into(),
+        );
+        node.insert("uploadBandwidthMbps".to_string(), (*upload_mbps).into());
+        node.insert("children".to_string(), Value::Object(Map::new()));
+        siblings.insert(name.clone(), Value::Object(node));
+      }
+      NetworkJsonChange::RenameNode { node, name } => {
+        check_name(name)?;
+        let path = tree.node_path(node)?;
+        let old_name = path.last().unwrap();
+        let siblings = siblings_mut(json, &path)?;
+        if name != old_name && siblings.contains_key(name) {
+          return Err(invalid(format!("{name} already exists there")));
+        }
+        // Rebuild the map, so the node keeps its place in the file.
+        *siblings = std::mem::take(siblings)
+          .into_iter()
+          .map(
+            |(k, v)| if &k == old_name { (name.clone(), v) } else { (k, v) },
+          )
+          .collect();
+      }
+      NetworkJsonChange::MoveNode { node, parent } => {
+        let index = tree.node_index(node)?;
+        let path = tree.node_path(node)?;
+        let name = path.last().unwrap();
+        let parent_path = tree.parent_path(parent.as_deref())?;
+        if let Some(parent) = parent {
+          let parent_index = tree.node_index(parent)?;
+          if tree.nodes[parent_index].parents.contains(&index) {
+            return Err(invalid(format!(
+              "{node} can't be moved below itself"
+            )));
+          }
+        }
+        if children_mut(json, &parent_path)?.contains_key(name) {
+          return Err(invalid(format!("{name} already exists there")));
+        }
+        let value = remove_key(siblings_mut(json, &path)?, name)
+          .ok_or_else(|| NetworkJsonError::NodeNotFound(node.clone()))?;
+        children_mut(json, &parent_path)?.insert(name.clone(), value);
+      }
+      NetworkJsonChange::SetBandwidth { node, download_mbps, upload_mbps } => {
+        check_bandwidth(*download_mbps, *upload_mbps)?;
+        let path = tree.node_path(node)?;
+        let entry = node_mut(json, &path)?;
+        entry.insert(
+          "downloadBandwidthMbps".to_string(),
+          (*download_mbps).into(),
+        );
+        entry.insert("uploadBandwidthMbps".to_string(), (*upload_mbps).into());
+      }
+      NetworkJsonChange::DeleteNode { node } => {
+        let index = tree.node_index(node)?;
+        if tree.nodes.iter().any(|n| n.immediate_parent == Some(index)) {
+          return Err(invalid(format!(
+            "{node} has children; move or delete them first"
+          )));
+        }
+        let path = tree.node_path(node)?;
+        remove_key(siblings_mut(json, &path)?, path.last().unwrap());
+      }
+    }
+  }
+  Ok(())
+}
+
+impl NetworkJson {
+  fn node_index(&self, key: &str) -> Result<usize, NetworkJsonError> {
+    match self.get_index_for_id_or_name(key) {
+      Some(index) if index > 0 => Ok(index),
+      _ => Err(NetworkJsonError::NodeNotFound(key.to_string())),
+    }
+  }
+
+  /// The names of a node and each of its parents, from the top down.
+  fn node_path(&self, key: &str) -> Result<Vec<String>, NetworkJsonError> {
+    let index = self.node_index(key)?;
+    Ok(
+      self.nodes[index]
+        .parents
+        .iter()
+        .skip(1)
+        .map(|i| self.nodes[*i].name.clone())
+        .collect(),
+    )
+  }
+
+  fn parent_path(
+    &self,
+    key: Option<&str>,
+  ) -> Result<Vec<String>, NetworkJsonError> {
+    key.map(|key| self.node_path(key)).unwrap_or(Ok(Vec::new()))
+  }
+}
+
+fn invalid(message: String) -> NetworkJsonError {
+  NetworkJsonError::InvalidChange(message)
+}
+
+fn check_name(name: &str) -> Result<(), NetworkJsonError> {
+  if name.trim().is_empty() {
+    Err(invalid("Node names can't be empty".to_string()))
+  } else if name == "children" || NODE_ATTRIBUTES.contains(&name) {
+    Err(invalid(format!("{name} can't be used as a node name")))
+  } else {
+    Ok(())
+  }
+}
+
+fn check_bandwidth(
+  download: u32,
+  upload: u32,
+) -> Result<(), NetworkJsonError> {
+  if download == 0 || upload == 0 {
+    Err(invalid("Bandwidth limits must be above zero".to_string()))
+  } else {
+    Ok(())
+  }
+}
+
+/// Removes a key without disturbing the order of the rest of the map.
+fn remove_key(map: &mut Map<String, Value>, key: &str) -> Option<Value> {
+  let mut removed = None;
+  *map = std::mem::take(map)
+    .into_iter()
+    .filter_map(|(k, v)| {
+      if k == key {
+        removed = Some(v);
+        None
+      } else {
+        Some((k, v))
+      }
+    })
+    .collect();
+  removed
+}
+
+/// The map holding the node at the end of `path`, keyed by node name.
+/// That's usually its parent's `children`, but older files also put
+/// child nodes directly in their parent.
+fn siblings_mut<'a>(
+  json: &'a mut Value,
+  path: &[String],
+) -> Result<&'a mut Map<String, Value>, NetworkJsonError> {
+  let not_found = || NetworkJsonError::NodeNotFound(path.join("/"));
+  let (name, parents) = path.split_last().ok_or_else(not_found)?;
+  if parents.is_empty() {
+    return json.as_object_mut().ok_or_else(not_found);
+  }
+  let parent = node_mut(json, parents)?;
+  if parent.get("children").and_then(|c| c.get(name)).is_some() {
+    parent
+      .get_mut("children")
+      .and_then(|c| c.as_object_mut())
+      .ok_or_else(not_found)
+  } else if parent.contains_key(name) {
+    Ok(parent)
+  } else {
+    Err(not_found())
+  }
+}
+
+fn node_mut<'a>(
+  json: &'a mut Value,
+  path: &[String],
+) -> Result<&'a mut Map<String, Value>, NetworkJsonError> {
+  let not_found = || NetworkJsonError::NodeNotFound(path.join("/"));
+  let name = path.last().ok_or_else(not_found)?;
+  siblings_mut(json, path)?
+    .get_mut(name)
+    .and_then(|v| v.as_object_mut())
+    .ok_or_else(not_found)
+}
+
+/// The map new children of the node at `path` go in, creating the node's
+/// `children` if it has none. An empty path is the top of the tree.
+fn children_mut<'a>(
+  json: &'a mut Value,
+  path: &[String],
+) -> Result<&'a mut Map<String, Value>, NetworkJsonError> {
+  if path.is_empty() {
+    return json.as_object_mut().ok_or(NetworkJsonError::ConfigLoadError);
+  }
+  node_mut(json, path)?
+    .entry("children")
+    .or_insert_with(|| Value::Object(Map::new()))
+    .as_object_mut()
+    .ok_or_else(|| NetworkJsonError::NodeNotFound(path.join("/")))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const NETWORK: &str = r#"{
+    "Site_1": {
+      "id": "site-1",
+      "downloadBandwidthMbps": 1000,
+      "uploadBandwidthMbps": 1000,
+      "children": {
+        "AP_A": {
+          "downloadBandwidthMbps": 500,
+          "uploadBandwidthMbps": 500
+        },
+        "AP_B": {
+          "downloadBandwidthMbps": 500,
+          "uploadBandwidthMbps": 500
+        }
+      }
+    },
+    "Site_2": {
+      "downloadBandwidthMbps": 200,
+      "uploadBandwidthMbps": 200
+    }
+  }"#;
+
+  fn apply(changes: &[NetworkJsonChange]) -> Result<Value, NetworkJsonError> {
+    let mut json: Value = serde_json::from_str(NETWORK).unwrap();
+    apply_changes(&mut json, changes)?;
+    Ok(json)
+  }
+
+  fn names(json: &Value) -> Vec<String> {
+    NetworkJson::from_json_value(json)
+      .nodes
+      .iter()
+      .map(|n| n.name.clone())
+      .collect()
+  }
+
+  #[test]
+  fn adds_renames_and_deletes_nodes() {
+    let json = apply(&[
+      NetworkJsonChange::AddNode {
+        parent: Some("site-1".to_string()),
+        name: "AP_C".to_string(),
+        id: Some("ap-c".to_string()),
+        download_mbps: 100,
+        upload_mbps: 50,
+      },
+      NetworkJsonChange::RenameNode {
+        node: "AP_A".to_string(),
+        name: "AP_Z".to_string(),
+      },
+      NetworkJsonChange::DeleteNode { node: "AP_B".to_string() },
+      NetworkJsonChange::SetBandwidth {
+        node: "Site_2".to_string(),
+        download_mbps: 300,
+        upload_mbps: 30,
+      },
+    ])
+    .unwrap();
+    assert_eq!(names(&json), vec!["Root", "Site_1", "AP_Z", "AP_C", "Site_2"]);
+    let tree = NetworkJson::from_json_value(&json);
+    assert_eq!(tree.get_index_for_id("ap-c"), Some(3));
+    assert_eq!(tree.nodes[3].max_throughput, (100, 50));
+    assert_eq!(tree.nodes[4].max_throughput, (300, 30));
+  }
+
+  #[test]
+  fn moves_nodes() {
+    let json = apply(&[NetworkJsonChange::MoveNode {
+      node: "AP_B".to_string(),
+      parent: Some("Site_2".to_string()),
+    }])
+    .unwrap();
+    let tree = NetworkJson::from_json_value(&json);
+    let ap = tree.get_index_for_name("AP_B").unwrap();
+    let site = tree.get_index_for_name("Site_2").unwrap();
+    assert_eq!(tree.nodes[ap].immediate_parent, Some(site));
+    assert_eq!(tree.nodes[ap].parents, vec![0, site, ap]);
+
+    let json = apply(&[NetworkJsonChange::MoveNode {
+      node: "AP_A".to_string(),
+      parent: None,
+    }])
+    .unwrap();
+    let tree = NetworkJson::from_json_value(&json);
+    let ap = tree.get_index_for_name("AP_A").unwrap();
+    assert_eq!(tree.nodes[ap].immediate_parent, Some(0));
+  }
+
+  #[test]
+  fn rejects_bad_changes() {
+    let bad = [
+      NetworkJsonChange::MoveNode {
+        node: "Site_1".to_string(),
+        parent: Some("AP_A".to_string()),
+      },
+      NetworkJsonChange::DeleteNode { node: "Site_1".to_string() },
+      NetworkJsonChange::RenameNode {
+        node: "AP_A".to_string(),
+        name: "AP_B".to_string(),
+      },
+      NetworkJsonChange::AddNode {
+        parent: None,
+        name: "children".to_string(),
+        id: None,
+        download_mbps: 10,
+        upload_mbps: 10,
+      },
+      NetworkJsonChange::AddNode {
+        parent: None,
+        name: "Site_3".to_string(),
+        id: Some("site-1".to_string()),
+        download_mbps: 10,
+        upload_mbps: 10,
+      },
+      NetworkJsonChange::SetBandwidth {
+        node: "Site_2".to_string(),
+        download_mbps: 0,
+        upload_mbps: 10,
+      },
+    ];
+    for change in bad {
+      assert!(
+        matches!(
+          apply(std::slice::from_ref(&change)),
+          Err(NetworkJsonError::InvalidChange(_))
+        ),
+        "{change:?}"
+      );
+    }
+    assert!(matches!(
+      apply(&[NetworkJsonChange::DeleteNode { node: "Nowhere".to_string() }]),
+      Err(NetworkJsonError::NodeNotFound(_))
+    ));
+  }
+}
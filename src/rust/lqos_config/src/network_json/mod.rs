@@ -1,9 +1,14 @@
+mod capacity;
+mod changes;
 use crate::etc;
+pub use capacity::{CapacityDirection, CapacityIssue, NodeCapacity};
+pub use changes::NetworkJsonChange;
 use dashmap::DashSet;
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use std::{
+  collections::HashSet,
   fs,
   path::{Path, PathBuf}, sync::atomic::AtomicU64,
 };
@@ -15,6 +20,17 @@ pub struct NetworkJsonNode {
   /// The node name, as it appears in `network.json`
   pub name: String,
 
+  /// The node's optional `id` from `network.json`. Unlike the name or
+  /// the node's index, this stays the same when a node is renamed or
+  /// the tree is rearranged.
+  pub id: Option<String>,
+
+  /// What sort of node this is, from the optional `type` field.
+  pub node_type: Option<NodeType>,
+
+  /// Free-form tags, from the optional `tags` array.
+  pub tags: Vec<String>,
+
   /// The maximum throughput allowed per `network.json` for this node
   pub max_throughput: (u32, u32), // In mbps
 
@@ -48,6 +64,9 @@ impl NetworkJsonNode {
       rtts: self.rtts.iter().map(|n| *n as f32 / 100.0).collect(),
       parents: self.parents.clone(),
       immediate_parent: self.immediate_parent,
+      id: self.id.clone(),
+      node_type: self.node_type,
+      tags: self.tags.clone(),
     }
   }
 }
@@ -69,15 +88,52 @@ pub struct NetworkJsonTransport {
   pub parents: Vec<usize>,
   /// The immediate parent node in the tree
   pub immediate_parent: Option<usize>,
+  /// Stable node ID, if `network.json` gives one
+  pub id: Option<String>,
+  /// Node type, if `network.json` gives one
+  pub node_type: Option<NodeType>,
+  /// Free-form tags
+  pub tags: Vec<String>,
+}
+
+/// The kinds of node that can be marked with `type` in `network.json`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeType {
+  /// A site, such as a tower or PoP
+  Site,
+  /// An access point
+  Ap,
+  /// A fiber OLT
+  Olt,
+  /// A backhaul link
+  Backhaul,
+}
+
+impl NodeType {
+  fn from_str(name: &str) -> Option<Self> {
+    match name.to_lowercase().as_str() {
+      "site" => Some(Self::Site),
+      "ap" => Some(Self::Ap),
+      "olt" => Some(Self::Olt),
+      "backhaul" => Some(Self::Backhaul),
+      _ => None,
+    }
+  }
 }
 
+/// Keys in a `network.json` node that describe the node itself, rather
+/// than naming a child node.
+const NODE_ATTRIBUTES: [&str; 5] =
+  ["downloadBandwidthMbps", "uploadBandwidthMbps", "id", "type", "tags"];
+
 /// Holder for the network.json representation.
 /// This is condensed into a single level vector with index-based referencing
 /// for easy use in funnel calculations.
 #[derive(Debug)]
 pub struct NetworkJson {
   /// Nodes that make up the tree, flattened and referenced by index number.
-  /// TODO: We should add a primary key to nodes in network.json.
+  /// Nodes with an `id` can also be found with `get_index_for_id`.
   pub nodes: Vec<NetworkJsonNode>,
 }
 
@@ -114,26 +170,39 @@ impl NetworkJson {
 
   /// Attempt to load network.json from disk
   pub fn load() -> Result<Self, NetworkJsonError> {
+    if !Self::exists() {
+      return Err(NetworkJsonError::FileNotFound);
+    }
+    let path = Self::path()?;
+    let raw = fs::read_to_string(path)
+      .map_err(|_| NetworkJsonError::ConfigLoadError)?;
+    Self::from_json_str(&raw)
+  }
+
+  /// Builds the flattened tree from the contents of a `network.json`
+  /// file.
+  pub fn from_json_str(raw: &str) -> Result<Self, NetworkJsonError> {
+    let json: Value = serde_json::from_str(raw)
+      .map_err(|_| NetworkJsonError::ConfigLoadError)?;
+    Ok(Self::from_json_value(&json))
+  }
+
+  pub(crate) fn from_json_value(json: &Value) -> Self {
     let mut nodes = vec![NetworkJsonNode {
       name: "Root".to_string(),
+      id: None,
+      node_type: None,
+      tags: Vec::new(),
       max_throughput: (0, 0),
       current_throughput: (AtomicU64::new(0), AtomicU64::new(0)),
       parents: Vec::new(),
       immediate_parent: None,
       rtts: DashSet::new(),
     }];
-    if !Self::exists() {
-      return Err(NetworkJsonError::FileNotFound);
-    }
-    let path = Self::path()?;
-    let raw = fs::read_to_string(path)
-      .map_err(|_| NetworkJsonError::ConfigLoadError)?;
-    let json: Value = serde_json::from_str(&raw)
-      .map_err(|_| NetworkJsonError::ConfigLoadError)?;
 
     // Start reading from the top. We are at the root node.
     let parents = vec![0];
-    if let Value::Object(map) = &json {
+    if let Value::Object(map) = json {
       for (key, value) in map.iter() {
         if let Value::Object(inner_map) = value {
           recurse_node(&mut nodes, key, inner_map, &parents, 0);
@@ -141,7 +210,8 @@ impl NetworkJson {
       }
     }
 
-    Ok(Self { nodes })
+    warn_about_duplicates(&nodes);
+    Self { nodes }
   }
 
   /// Find the index of a circuit_id
@@ -149,6 +219,18 @@ impl NetworkJson {
     self.nodes.iter().position(|n| n.name == name)
   }
 
+  /// Find the index of the node with a given `id`
+  pub fn get_index_for_id(&self, id: &str) -> Option<usize> {
+    self.nodes.iter().position(|n| n.id.as_deref() == Some(id))
+  }
+
+  /// Find a node by `id`, falling back to its name. This is how
+  /// `ShapedDevices.csv` parent nodes are resolved, so a parent can be
+  /// given by ID where two nodes share a name.
+  pub fn get_index_for_id_or_name(&self, key: &str) -> Option<usize> {
+    self.get_index_for_id(key).or_else(|| self.get_index_for_name(key))
+  }
+
   /// Retrieve a cloned copy of a NetworkJsonNode entry, or None if there isn't
   /// an entry at that index.
   pub fn get_cloned_entry_by_index(
@@ -181,10 +263,8 @@ impl NetworkJson {
   ) -> Option<Vec<usize>> {
     //println!("Looking for parents of {circuit_id}");
     self
-      .nodes
-      .iter()
-      .find(|n| n.name == circuit_id)
-      .map(|node| node.parents.clone())
+      .get_index_for_id_or_name(circuit_id)
+      .map(|index| self.nodes[index].parents.clone())
   }
 
   /// Sets all current throughput values to zero
@@ -243,6 +323,51 @@ fn json_to_u32(val: Option<&Value>) -> u32 {
   }
 }
 
+fn json_to_id(val: Option<&Value>) -> Option<String> {
+  match val {
+    Some(Value::String(id)) if !id.is_empty() => Some(id.clone()),
+    Some(Value::Number(id)) => Some(id.to_string()),
+    _ => None,
+  }
+}
+
+fn json_to_node_type(name: &str, val: Option<&Value>) -> Option<NodeType> {
+  let node_type = val?.as_str()?;
+  let result = NodeType::from_str(node_type);
+  if result.is_none() {
+    warn!("Unknown node type '{node_type}' for {name} in network.json");
+  }
+  result
+}
+
+fn json_to_tags(val: Option<&Value>) -> Vec<String> {
+  if let Some(Value::Array(tags)) = val {
+    tags.iter().filter_map(|t| t.as_str()).map(|t| t.to_string()).collect()
+  } else {
+    Vec::new()
+  }
+}
+
+/// IDs are meant to be unique, and names are used as keys by
+/// `ShapedDevices.csv`; either being repeated makes lookups ambiguous.
+fn warn_about_duplicates(nodes: &[NetworkJsonNode]) {
+  let mut ids = HashSet::new();
+  let mut names = HashSet::new();
+  for node in nodes.iter().skip(1) {
+    if let Some(id) = &node.id {
+      if !ids.insert(id) {
+        warn!("Duplicate node id '{id}' in network.json");
+      }
+    }
+    if !names.insert(&node.name) {
+      warn!(
+        "Duplicate node name '{}' in network.json. Give nodes an id to tell them apart.",
+        node.name
+      );
+    }
+  }
+}
+
 fn recurse_node(
   nodes: &mut Vec<NetworkJsonNode>,
   name: &str,
@@ -266,6 +391,9 @@ fn recurse_node(
     ),
     current_throughput: (AtomicU64::new(0), AtomicU64::new(0)),
     name: name.to_string(),
+    id: json_to_id(json.get("id")),
+    node_type: json_to_node_type(name, json.get("type")),
+    tags: json_to_tags(json.get("tags")),
     immediate_parent: Some(immediate_parent),
     rtts: DashSet::new(),
   };
@@ -277,7 +405,8 @@ fn recurse_node(
   // Recurse children
   for (key, value) in json.iter() {
     let key_str = key.as_str();
-    if key_str != "uploadBandwidthMbps" && key_str != "downloadBandwidthMbps" {
+    // Inside "children", every key is a child node's name.
+    if name == "children" || !NODE_ATTRIBUTES.contains(&key_str) {
       if let Value::Object(value) = value {
         recurse_node(nodes, key, value, &parents, my_id);
       }
@@ -291,4 +420,77 @@ pub enum NetworkJsonError {
   ConfigLoadError,
   #[error("network.json not found or does not exist")]
   FileNotFound,
+  #[error("Unable to write network.json")]
+  WriteFail,
+  #[error("No node {0} in network.json")]
+  NodeNotFound(String),
+  #[error("Invalid network.json change: {0}")]
+  InvalidChange(String),
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const NETWORK: &str = r#"{
+    "Site_1": {
+      "id": "site-1",
+      "type": "site",
+      "tags": ["rural", "fiber"],
+      "downloadBandwidthMbps": 1000,
+      "uploadBandwidthMbps": 1000,
+      "children": {
+        "AP_A": {
+          "id": 17,
+          "type": "AP",
+          "downloadBandwidthMbps": 500,
+          "uploadBandwidthMbps": 500
+        },
+        "type": {
+          "downloadBandwidthMbps": 100,
+          "uploadBandwidthMbps": 100
+        }
+      }
+    },
+    "Site_2": {
+      "type": "lighthouse",
+      "downloadBandwidthMbps": 200,
+      "uploadBandwidthMbps": 200
+    }
+  }"#;
+
+  #[test]
+  fn reads_node_metadata() {
+    let net = NetworkJson::from_json_str(NETWORK).unwrap();
+    let names: Vec<&str> = net.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["Root", "Site_1", "AP_A", "type", "Site_2"]);
+
+    let site = &net.nodes[1];
+    assert_eq!(site.id.as_deref(), Some("site-1"));
+    assert_eq!(site.node_type, Some(NodeType::Site));
+    assert_eq!(site.tags, vec!["rural", "fiber"]);
+
+    let ap = net.get_cloned_entry_by_index(2).unwrap();
+    assert_eq!(ap.id.as_deref(), Some("17"));
+    assert_eq!(ap.node_type, Some(NodeType::Ap));
+    assert!(ap.tags.is_empty());
+    assert_eq!(ap.parents, vec![0, 1, 2]);
+
+    // Unknown types are ignored, and nodes don't need an id.
+    assert_eq!(net.nodes[4].node_type, None);
+    assert_eq!(net.nodes[4].id, None);
+  }
+
+  #[test]
+  fn finds_nodes_by_id() {
+    let net = NetworkJson::from_json_str(NETWORK).unwrap();
+    assert_eq!(net.get_index_for_id("17"), Some(2));
+    assert_eq!(net.get_index_for_id("AP_A"), None);
+    assert_eq!(net.get_index_for_id_or_name("site-1"), Some(1));
+    assert_eq!(net.get_index_for_id_or_name("Site_2"), Some(4));
+    assert_eq!(
+      net.get_parents_for_circuit_id("17"),
+      net.get_parents_for_circuit_id("AP_A")
+    );
+  }
 }
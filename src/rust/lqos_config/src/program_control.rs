@@ -26,6 +26,16 @@ fn working_directory() -> Result<PathBuf, ProgramControlError> {
 /// Shells out and reloads the `LibreQos.py` program, storing all
 /// emitted text and returning it.
 pub fn load_libreqos() -> Result<String, ProgramControlError> {
+  run_libreqos(&[])
+}
+
+/// Shells out to `LibreQoS.py --updateonly`, which only applies changes
+/// to `ShapedDevices.csv`, returning all emitted text.
+pub fn update_libreqos() -> Result<String, ProgramControlError> {
+  run_libreqos(&["--updateonly"])
+}
+
+fn run_libreqos(args: &[&str]) -> Result<String, ProgramControlError> {
   let path = path_to_libreqos()?;
   if !path.exists() {
     error!(
@@ -42,6 +52,7 @@ pub fn load_libreqos() -> Result<String, ProgramControlError> {
   let result = Command::new(PYTHON_PATH)
     .current_dir(working_directory()?)
     .arg("LibreQoS.py")
+    .args(args)
     .output()
     .map_err(|_| ProgramControlError::CommandFailed)?;
   let stdout = String::from_utf8(result.stdout)
@@ -0,0 +1,312 @@
+//! Editing `ShapedDevices.csv` one device or circuit at a time, rather
+//! than rewriting the whole file.
+
+use super::{ConfigShapedDevices, ShapedDevice, ShapedDevicesError};
+use crate::{NetworkJson, ShapedDevicesReport};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// The settings that every device in a circuit shares.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShapedCircuit {
+  /// The circuit to change
+  pub circuit_id: String,
+
+  /// The circuit's name
+  pub circuit_name: String,
+
+  /// The circuit's parent node, by name or `id`, from `network.json`
+  pub parent_node: String,
+
+  /// Guaranteed download rate, in Mbps
+  pub download_min_mbps: u32,
+
+  /// Guaranteed upload rate, in Mbps
+  pub upload_min_mbps: u32,
+
+  /// Maximum download rate, in Mbps
+  pub download_max_mbps: u32,
+
+  /// Maximum upload rate, in Mbps
+  pub upload_max_mbps: u32,
+}
+
+/// A single edit to `ShapedDevices.csv`. Devices are identified by
+/// device ID, circuits by circuit ID.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ShapedDevicesChange {
+  /// Add a device. Adding a device with a new circuit ID creates the
+  /// circuit; adding one to an existing circuit must match its name,
+  /// parent and rates.
+  AddDevice(ShapedDevice),
+
+  /// Replace the device with the same device ID.
+  UpdateDevice(ShapedDevice),
+
+  /// Remove a device, by device ID. Removing a circuit's last device
+  /// removes the circuit.
+  DeleteDevice(String),
+
+  /// Change a circuit's name, parent and rates, on every one of its
+  /// devices.
+  UpdateCircuit(ShapedCircuit),
+
+  /// Remove every device in a circuit, by circuit ID.
+  DeleteCircuit(String),
+}
+
+impl ConfigShapedDevices {
+  /// Loads `ShapedDevices.csv`, applies `changes` in order, and writes it
+  /// back (atomically). Either every change is applied or none are. The
+  /// result is validated, against `network.json` if there is one, and
+  /// rejected if any circuit that was added or changed has errors.
+  /// Problems elsewhere in the file don't block the change.
+  pub fn change(
+    changes: &[ShapedDevicesChange],
+  ) -> Result<Self, ShapedDevicesError> {
+    let mut shaped = Self::load()?;
+    let touched = shaped.apply_changes(changes)?;
+
+    let network = if NetworkJson::exists() {
+      Some(NetworkJson::load().map_err(|_| ShapedDevicesError::NetworkJson)?)
+    } else {
+      None
+    };
+    shaped.check_circuits(&touched, network.as_ref())?;
+
+    shaped.write_csv("ShapedDevices.csv")?;
+    shaped.trie = Self::make_trie(&shaped.devices);
+    Ok(shaped)
+  }
+
+  /// Applies `changes` to the in-memory device list, returning the IDs of
+  /// circuits that were added to or changed (but not deleted).
+  pub(crate) fn apply_changes(
+    &mut self,
+    changes: &[ShapedDevicesChange],
+  ) -> Result<HashSet<String>, ShapedDevicesError> {
+    let mut touched = HashSet::new();
+    for change in changes.iter() {
+      match change {
+        ShapedDevicesChange::AddDevice(device) => {
+          if self.device_index(&device.device_id).is_some() {
+            return Err(ShapedDevicesError::DeviceExists(
+              device.device_id.clone(),
+            ));
+          }
+          touched.insert(device.circuit_id.clone());
+          self.devices.push(device.clone());
+        }
+        ShapedDevicesChange::UpdateDevice(device) => {
+          let index =
+            self.device_index(&device.device_id).ok_or_else(|| {
+              ShapedDevicesError::DeviceNotFound(device.device_id.clone())
+            })?;
+          touched.insert(device.circuit_id.clone());
+          self.devices[index] = device.clone();
+        }
+        ShapedDevicesChange::DeleteDevice(device_id) => {
+          let index = self.device_index(device_id).ok_or_else(|| {
+            ShapedDevicesError::DeviceNotFound(device_id.clone())
+          })?;
+          self.devices.remove(index);
+        }
+        ShapedDevicesChange::UpdateCircuit(circuit) => {
+          let mut found = false;
+          for device in self
+            .devices
+            .iter_mut()
+            .filter(|d| d.circuit_id == circuit.circuit_id)
+          {
+            found = true;
+            device.circuit_name = circuit.circuit_name.clone();
+            device.parent_node = circuit.parent_node.clone();
+            device.download_min_mbps = circuit.download_min_mbps;
+            device.upload_min_mbps = circuit.upload_min_mbps;
+            device.download_max_mbps = circuit.download_max_mbps;
+            device.upload_max_mbps = circuit.upload_max_mbps;
+          }
+          if !found {
+            return Err(ShapedDevicesError::CircuitNotFound(
+              circuit.circuit_id.clone(),
+            ));
+          }
+          touched.insert(circuit.circuit_id.clone());
+        }
+        ShapedDevicesChange::DeleteCircuit(circuit_id) => {
+          let before = self.devices.len();
+          self.devices.retain(|d| &d.circuit_id != circuit_id);
+          if self.devices.len() == before {
+            return Err(ShapedDevicesError::CircuitNotFound(
+              circuit_id.clone(),
+            ));
+          }
+          touched.remove(circuit_id);
+        }
+      }
+    }
+    Ok(touched)
+  }
+
+  fn device_index(&self, device_id: &str) -> Option<usize> {
+    self.devices.iter().position(|d| d.device_id == device_id)
+  }
+
+  /// Validates the list as it would be written, failing if any error is
+  /// on a row belonging to one of `circuits`.
+  pub(crate) fn check_circuits(
+    &self,
+    circuits: &HashSet<String>,
+    network: Option<&NetworkJson>,
+  ) -> Result<(), ShapedDevicesError> {
+    let csv = self.to_csv_string()?;
+    let report = ShapedDevicesReport::validate(csv.as_bytes(), network);
+    let mut problems = String::new();
+    for issue in report.issues.iter().filter(|i| i.kind.is_error()) {
+      let row = report.rows.iter().find(|r| r.line == issue.line);
+      let circuit = row.and_then(|r| r.cells.first()).map(|c| c.trim());
+      if circuit.map(|c| circuits.contains(c)).unwrap_or(false) {
+        problems +=
+          &format!("Circuit {}: {}\n", circuit.unwrap(), issue.message);
+      }
+    }
+    if problems.is_empty() {
+      Ok(())
+    } else {
+      error!("Rejected ShapedDevices.csv change:\n{problems}");
+      Err(ShapedDevicesError::InvalidChange(problems))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn device(circuit: &str, device: &str, ip: &str) -> ShapedDevice {
+    ShapedDevice {
+      circuit_id: circuit.to_string(),
+      circuit_name: format!("Circuit {circuit}"),
+      device_id: device.to_string(),
+      device_name: format!("Device {device}"),
+      ipv4: ShapedDevice::parse_ipv4(ip),
+      download_min_mbps: 10,
+      upload_min_mbps: 10,
+      download_max_mbps: 100,
+      upload_max_mbps: 20,
+      ..Default::default()
+    }
+  }
+
+  fn shaped() -> ConfigShapedDevices {
+    ConfigShapedDevices {
+      devices: vec![
+        device("1", "1a", "100.64.0.1"),
+        device("1", "1b", "100.64.0.2"),
+        device("2", "2a", "100.64.0.3"),
+      ],
+      ..Default::default()
+    }
+  }
+
+  fn device_ids(shaped: &ConfigShapedDevices) -> Vec<&str> {
+    shaped.devices.iter().map(|d| d.device_id.as_str()).collect()
+  }
+
+  #[test]
+  fn adds_updates_and_deletes_devices() {
+    let mut shaped = shaped();
+    let mut moved = device("2", "1b", "100.64.0.9");
+    moved.comment = "moved".to_string();
+    let touched = shaped
+      .apply_changes(&[
+        ShapedDevicesChange::AddDevice(device("3", "3a", "100.64.0.4")),
+        ShapedDevicesChange::UpdateDevice(moved),
+        ShapedDevicesChange::DeleteDevice("1a".to_string()),
+      ])
+      .unwrap();
+    assert_eq!(device_ids(&shaped), vec!["1b", "2a", "3a"]);
+    assert_eq!(shaped.devices[0].circuit_id, "2");
+    assert_eq!(touched, HashSet::from(["2".to_string(), "3".to_string()]));
+
+    assert!(matches!(
+      shaped.apply_changes(&[ShapedDevicesChange::AddDevice(device(
+        "4", "2a", ""
+      ))]),
+      Err(ShapedDevicesError::DeviceExists(_))
+    ));
+    assert!(matches!(
+      shaped.apply_changes(&[ShapedDevicesChange::DeleteDevice(
+        "nope".to_string()
+      )]),
+      Err(ShapedDevicesError::DeviceNotFound(_))
+    ));
+  }
+
+  #[test]
+  fn updates_and_deletes_circuits() {
+    let mut shaped = shaped();
+    shaped
+      .apply_changes(&[ShapedDevicesChange::UpdateCircuit(ShapedCircuit {
+        circuit_id: "1".to_string(),
+        circuit_name: "Renamed".to_string(),
+        download_max_mbps: 500,
+        upload_max_mbps: 50,
+        ..Default::default()
+      })])
+      .unwrap();
+    assert!(shaped.devices[..2]
+      .iter()
+      .all(|d| d.circuit_name == "Renamed" && d.download_max_mbps == 500));
+    assert_eq!(shaped.devices[2].download_max_mbps, 100);
+
+    shaped
+      .apply_changes(&[ShapedDevicesChange::DeleteCircuit("1".to_string())])
+      .unwrap();
+    assert_eq!(device_ids(&shaped), vec!["2a"]);
+  }
+
+  #[test]
+  fn rejects_errors_only_in_changed_circuits() {
+    let mut shaped = shaped();
+    // Circuits 1 and 2 already overlap; that doesn't block adding
+    // circuit 3.
+    shaped.devices[2].ipv4 = ShapedDevice::parse_ipv4("100.64.0.1");
+    let changed = shaped
+      .apply_changes(&[ShapedDevicesChange::AddDevice(device(
+        "3",
+        "3a",
+        "100.64.0.7",
+      ))])
+      .unwrap();
+    assert!(shaped.check_circuits(&changed, None).is_ok());
+
+    // A device that overlaps another circuit does.
+    let changed = shaped
+      .apply_changes(&[ShapedDevicesChange::AddDevice(device(
+        "4",
+        "4a",
+        "100.64.0.0/30",
+      ))])
+      .unwrap();
+    assert!(matches!(
+      shaped.check_circuits(&changed, None),
+      Err(ShapedDevicesError::InvalidChange(_))
+    ));
+  }
+
+  #[test]
+  fn written_csv_reads_back() {
+    let mut shaped = shaped();
+    shaped.devices[0].ipv4 =
+      ShapedDevice::parse_ipv4("100.64.0.1, 100.64.1.0/24");
+    shaped.devices[0].ipv6 = ShapedDevice::parse_ipv6("fd77::1, fd77:1::/64");
+    let csv = shaped.to_csv_string().unwrap();
+    assert!(csv.starts_with("\"Circuit ID\",\"Circuit Name\""));
+    let report = ShapedDevicesReport::validate(csv.as_bytes(), None);
+    assert!(report.issues.is_empty(), "{report}");
+    assert!(csv.contains("\"100.64.0.1, 100.64.1.0/24\""));
+    assert!(csv.contains("\"fd77::1, fd77:1::/64\""));
+  }
+}
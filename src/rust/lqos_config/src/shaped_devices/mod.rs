@@ -1,12 +1,21 @@
+mod changes;
 mod serializable;
 mod shaped_device;
-use crate::{etc, SUPPORTED_CUSTOMERS};
+mod validation;
+use crate::{
+  atomic_write::write_atomically, etc, NetworkJson, SUPPORTED_CUSTOMERS,
+};
+pub use changes::{ShapedCircuit, ShapedDevicesChange};
 use csv::{QuoteStyle, ReaderBuilder, WriterBuilder};
 use log::error;
 use serializable::SerializableShapedDevice;
 pub use shaped_device::ShapedDevice;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
+pub use validation::{
+  ShapedDevicesIssue, ShapedDevicesIssueKind, ShapedDevicesReport,
+  ShapedDevicesRow, SHAPED_DEVICES_COLUMNS,
+};
 
 /// Provides handling of the `ShapedDevices.csv` file that maps
 /// circuits to traffic shaping.
@@ -108,6 +117,20 @@ impl ConfigShapedDevices {
     Ok(Self { devices, trie })
   }
 
+  /// Checks every row of `ShapedDevices.csv` against itself and
+  /// `network.json`, reporting every problem rather than stopping at the
+  /// first. Parent nodes aren't checked if there is no `network.json`.
+  pub fn validate() -> Result<ShapedDevicesReport, ShapedDevicesError> {
+    let file = std::fs::File::open(ConfigShapedDevices::path()?)
+      .map_err(|_| ShapedDevicesError::OpenFail)?;
+    let network = if NetworkJson::exists() {
+      Some(NetworkJson::load().map_err(|_| ShapedDevicesError::NetworkJson)?)
+    } else {
+      None
+    };
+    Ok(ShapedDevicesReport::validate(file, network.as_ref()))
+  }
+
   fn make_trie(
     devices: &[ShapedDevice],
   ) -> ip_network_table::IpNetworkTable<usize> {
@@ -143,18 +166,20 @@ impl ConfigShapedDevices {
     Ok(data)
   }
 
-  /// Saves the current shaped devices list to `ShapedDevices.csv`
+  /// Saves the current shaped devices list to `filename` in the LibreQoS
+  /// directory. The list is written to a temporary file which is then
+  /// renamed over the original, so readers (such as the file watcher in
+  /// `lqosd`) never see a partly written file.
   pub fn write_csv(&self, filename: &str) -> Result<(), ShapedDevicesError> {
     let cfg =
       etc::EtcLqos::load().map_err(|_| ShapedDevicesError::ConfigLoadError)?;
     let base_path = Path::new(&cfg.lqos_directory);
     let path = base_path.join(filename);
     let csv = self.to_csv_string()?;
-    if std::fs::write(path, csv).is_err() {
-      error!("Unable to write ShapedDevices.csv. Permissions?");
-      return Err(ShapedDevicesError::WriteFail);
-    }
-    Ok(())
+    write_atomically(&path, csv.as_bytes()).map_err(|e| {
+      error!("Unable to replace {}: {e:?}", path.display());
+      ShapedDevicesError::WriteFail
+    })
   }
 }
 
@@ -182,6 +207,16 @@ pub enum ShapedDevicesError {
   UnequalLengths(String),
   #[error("Unexpected CSV file error")]
   GenericCsvError(String),
+  #[error("Unable to load network.json")]
+  NetworkJson,
+  #[error("A device with ID {0} already exists")]
+  DeviceExists(String),
+  #[error("No device with ID {0}")]
+  DeviceNotFound(String),
+  #[error("No circuit with ID {0}")]
+  CircuitNotFound(String),
+  #[error("The change would leave ShapedDevices.csv invalid:\n{0}")]
+  InvalidChange(String),
 }
 
 #[cfg(test)]
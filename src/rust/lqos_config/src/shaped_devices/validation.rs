@@ -0,0 +1,617 @@
+//! Whole-file validation of `ShapedDevices.csv`. Unlike
+//! `ConfigShapedDevices::load`, which stops at the first bad row, this
+//! reads every row and reports every problem it finds, along with the
+//! line and column, so that a UI can highlight the offending cells.
+
+use super::ShapedDevice;
+use crate::NetworkJson;
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  fmt::Display,
+  io::Read,
+  net::Ipv6Addr,
+};
+
+/// The columns of `ShapedDevices.csv`, in order.
+pub const SHAPED_DEVICES_COLUMNS: [&str; 13] = [
+  "Circuit ID",
+  "Circuit Name",
+  "Device ID",
+  "Device Name",
+  "Parent Node",
+  "MAC",
+  "IPv4",
+  "IPv6",
+  "Download Min Mbps",
+  "Upload Min Mbps",
+  "Download Max Mbps",
+  "Upload Max Mbps",
+  "Comment",
+];
+
+const CIRCUIT_NAME: usize = 1;
+const PARENT_NODE: usize = 4;
+const MAC: usize = 5;
+const IPV4: usize = 6;
+const IPV6: usize = 7;
+const DOWNLOAD_MIN: usize = 8;
+const UPLOAD_MIN: usize = 9;
+const DOWNLOAD_MAX: usize = 10;
+const UPLOAD_MAX: usize = 11;
+
+/// The kinds of problem the validator looks for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapedDevicesIssueKind {
+  /// The row couldn't be read, or has the wrong number of columns.
+  BadRow,
+  /// A rate isn't a whole number.
+  BadNumber,
+  /// An IPv4 or IPv6 address or prefix couldn't be parsed.
+  BadAddress,
+  /// The MAC address isn't six hex octets.
+  BadMac,
+  /// A prefix is the same as, or overlaps, one used by another circuit.
+  OverlappingPrefix,
+  /// The parent node doesn't exist in `network.json`.
+  UnknownParent,
+  /// A minimum rate is above the matching maximum rate.
+  MinAboveMax,
+  /// Rows with the same circuit ID disagree on its name or rates.
+  CircuitMismatch,
+  /// A maximum rate is above the parent node's limit. LibreQoS clamps
+  /// these, so this is a warning rather than an error.
+  ExceedsParent,
+}
+
+impl ShapedDevicesIssueKind {
+  /// Does this kind of problem stop LibreQoS from shaping correctly?
+  pub fn is_error(&self) -> bool {
+    !matches!(self, Self::ExceedsParent)
+  }
+}
+
+/// A single problem in `ShapedDevices.csv`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShapedDevicesIssue {
+  /// The line of the file the problem is on, counting from 1.
+  pub line: u64,
+
+  /// The column the problem is in, counting from 1, or `None` if it
+  /// applies to the whole row.
+  pub column: Option<usize>,
+
+  /// What sort of problem this is
+  pub kind: ShapedDevicesIssueKind,
+
+  /// A human-readable description
+  pub message: String,
+}
+
+/// A row of `ShapedDevices.csv` that has at least one issue, as it
+/// appears in the file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShapedDevicesRow {
+  /// The line of the file the row is on, counting from 1.
+  pub line: u64,
+
+  /// The row's cells, untrimmed.
+  pub cells: Vec<String>,
+}
+
+/// Every problem found in `ShapedDevices.csv`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShapedDevicesReport {
+  /// The number of device rows read
+  pub rows_checked: usize,
+
+  /// Every problem, in line order
+  pub issues: Vec<ShapedDevicesIssue>,
+
+  /// The rows referred to by `issues`, in line order
+  pub rows: Vec<ShapedDevicesRow>,
+}
+
+impl ShapedDevicesReport {
+  /// Validates a `ShapedDevices.csv` file. `network` is used to check
+  /// parent nodes and their limits; pass `None` for a flat network
+  /// with no `network.json`.
+  pub fn validate<R: Read>(
+    csv: R,
+    network: Option<&NetworkJson>,
+  ) -> ShapedDevicesReport {
+    let parents = network.map(parent_limits);
+    let mut report = ShapedDevicesReport::default();
+    let mut all_rows = Vec::new();
+    let mut circuits: HashMap<String, (u64, StringRecord)> = HashMap::new();
+    let mut prefixes = Vec::new();
+
+    let mut reader =
+      ReaderBuilder::new().comment(Some(b'#')).flexible(true).from_reader(csv);
+    for record in reader.records() {
+      let record = match record {
+        Ok(record) => record,
+        Err(e) => {
+          let line = e.position().map(|p| p.line()).unwrap_or(0);
+          report.issue(line, None, ShapedDevicesIssueKind::BadRow, e);
+          continue;
+        }
+      };
+      report.rows_checked += 1;
+      let line = record.position().map(|p| p.line()).unwrap_or(0);
+      report.check_row(
+        line,
+        &record,
+        parents.as_ref(),
+        &mut circuits,
+        &mut prefixes,
+      );
+      all_rows.push(ShapedDevicesRow {
+        line,
+        cells: record.iter().map(|c| c.to_string()).collect(),
+      });
+    }
+
+    // Overlaps can only be found once every prefix has been read.
+    report.check_prefixes(prefixes);
+    report.issues.sort_by_key(|i| (i.line, i.column));
+    let bad_lines: HashSet<u64> =
+      report.issues.iter().map(|i| i.line).collect();
+    all_rows.retain(|r| bad_lines.contains(&r.line));
+    report.rows = all_rows;
+    report
+  }
+
+  /// Are there no errors? Warnings are allowed.
+  pub fn is_valid(&self) -> bool {
+    !self.issues.iter().any(|i| i.kind.is_error())
+  }
+
+  fn issue(
+    &mut self,
+    line: u64,
+    column: Option<usize>,
+    kind: ShapedDevicesIssueKind,
+    message: impl Display,
+  ) {
+    self.issues.push(ShapedDevicesIssue {
+      line,
+      column: column.map(|c| c + 1),
+      kind,
+      message: message.to_string(),
+    });
+  }
+
+  fn check_row(
+    &mut self,
+    line: u64,
+    record: &StringRecord,
+    parents: Option<&HashMap<String, (u32, u32)>>,
+    circuits: &mut HashMap<String, (u64, StringRecord)>,
+    prefixes: &mut Vec<Prefix>,
+  ) {
+    use ShapedDevicesIssueKind::*;
+    if record.len() != SHAPED_DEVICES_COLUMNS.len() {
+      self.issue(
+        line,
+        None,
+        BadRow,
+        format!(
+          "Expected {} fields, found {}",
+          SHAPED_DEVICES_COLUMNS.len(),
+          record.len()
+        ),
+      );
+      return;
+    }
+    let cell = |column: usize| record[column].trim();
+
+    // Rates
+    let mut rates = [0u32; 4];
+    let mut rates_ok = true;
+    for (i, column) in (DOWNLOAD_MIN..=UPLOAD_MAX).enumerate() {
+      if let Ok(rate) = cell(column).parse() {
+        rates[i] = rate;
+      } else {
+        rates_ok = false;
+        self.issue(
+          line,
+          Some(column),
+          BadNumber,
+          format!(
+            "{} must be a whole number, not '{}'",
+            SHAPED_DEVICES_COLUMNS[column],
+            cell(column)
+          ),
+        );
+      }
+    }
+    let [download_min, upload_min, download_max, upload_max] = rates;
+    if rates_ok {
+      for (min, max, column) in [
+        (download_min, download_max, DOWNLOAD_MIN),
+        (upload_min, upload_max, UPLOAD_MIN),
+      ] {
+        if min > max {
+          self.issue(
+            line,
+            Some(column),
+            MinAboveMax,
+            format!(
+              "{} ({min}) is above {} ({max})",
+              SHAPED_DEVICES_COLUMNS[column],
+              SHAPED_DEVICES_COLUMNS[column + 2]
+            ),
+          );
+        }
+      }
+    }
+
+    // MAC
+    if !cell(MAC).is_empty() && !is_valid_mac(cell(MAC)) {
+      self.issue(
+        line,
+        Some(MAC),
+        BadMac,
+        format!("'{}' is not a valid MAC address", cell(MAC)),
+      );
+    }
+
+    // Addresses
+    let circuit_id = cell(0).to_string();
+    for (column, is_v4) in [(IPV4, true), (IPV6, false)] {
+      for address in cell(column).split(',').map(str::trim) {
+        if address.is_empty() {
+          continue;
+        }
+        let parsed = if is_v4 {
+          ShapedDevice::parse_cidr_v4(address)
+            .ok()
+            .filter(|(_, cidr)| *cidr <= 32)
+            .map(|(ip, cidr)| (ip.to_ipv6_mapped(), cidr + 96))
+        } else {
+          ShapedDevice::parse_cidr_v6(address).ok().filter(|(_, c)| *c <= 128)
+        };
+        if let Some((ip, cidr)) = parsed {
+          prefixes.push(Prefix {
+            start: network_start(ip, cidr),
+            cidr,
+            text: address.to_string(),
+            circuit_id: circuit_id.clone(),
+            line,
+            column,
+          });
+        } else {
+          self.issue(
+            line,
+            Some(column),
+            BadAddress,
+            format!(
+              "'{address}' is not a valid {} address or prefix",
+              if is_v4 { "IPv4" } else { "IPv6" }
+            ),
+          );
+        }
+      }
+    }
+
+    // Parent node
+    let parent = cell(PARENT_NODE);
+    if let (Some(parents), false) = (parents, parent.is_empty()) {
+      if let Some((down, up)) = parents.get(parent) {
+        for (max, limit, column) in
+          [(download_max, *down, DOWNLOAD_MAX), (upload_max, *up, UPLOAD_MAX)]
+        {
+          // A limit of 0 means network.json doesn't set one.
+          if rates_ok && limit > 0 && max > limit {
+            self.issue(
+              line,
+              Some(column),
+              ExceedsParent,
+              format!(
+                "{} ({max}) is above {parent}'s limit of {limit}",
+                SHAPED_DEVICES_COLUMNS[column]
+              ),
+            );
+          }
+        }
+      } else {
+        self.issue(
+          line,
+          Some(PARENT_NODE),
+          UnknownParent,
+          format!("Parent node '{parent}' is not in network.json"),
+        );
+      }
+    }
+
+    // Circuit consistency
+    if let Some((first_line, first)) = circuits.get(&circuit_id) {
+      for column in
+        std::iter::once(CIRCUIT_NAME).chain(DOWNLOAD_MIN..=UPLOAD_MAX)
+      {
+        if first[column].trim() != cell(column) {
+          self.issue(
+            line,
+            Some(column),
+            CircuitMismatch,
+            format!(
+              "{} '{}' differs from '{}' on line {first_line} for circuit {circuit_id}",
+              SHAPED_DEVICES_COLUMNS[column],
+              cell(column),
+              first[column].trim(),
+            ),
+          );
+        }
+      }
+    } else {
+      circuits.insert(circuit_id, (line, record.clone()));
+    }
+  }
+
+  /// Finds prefixes that are the same as, or contained by, a prefix
+  /// belonging to another circuit. Sorting by start address and then
+  /// prefix length puts every prefix directly after the prefixes that
+  /// contain it, so a stack of enclosing prefixes finds every overlap
+  /// in one pass. Both sides of an overlap are reported, but a prefix
+  /// that contains many others is only reported once.
+  fn check_prefixes(&mut self, mut prefixes: Vec<Prefix>) {
+    prefixes.sort_by_key(|p| (p.start, p.cidr, p.line));
+    let mut enclosing: Vec<(Prefix, bool)> = Vec::new();
+    for prefix in prefixes {
+      while let Some((outer, _)) = enclosing.last() {
+        if outer.contains(&prefix) {
+          break;
+        }
+        enclosing.pop();
+      }
+      let mut overlapped = false;
+      if let Some((outer, reported)) = enclosing
+        .iter_mut()
+        .rev()
+        .find(|(o, _)| o.circuit_id != prefix.circuit_id)
+      {
+        self.overlap(&prefix, outer);
+        if !*reported {
+          self.overlap(outer, &prefix);
+          *reported = true;
+        }
+        overlapped = true;
+      }
+      enclosing.push((prefix, overlapped));
+    }
+  }
+
+  fn overlap(&mut self, prefix: &Prefix, other: &Prefix) {
+    let message = format!(
+      "{} overlaps {} (circuit {}, line {})",
+      prefix.text, other.text, other.circuit_id, other.line
+    );
+    self.issue(
+      prefix.line,
+      Some(prefix.column),
+      ShapedDevicesIssueKind::OverlappingPrefix,
+      message,
+    );
+  }
+}
+
+impl Display for ShapedDevicesReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for issue in self.issues.iter() {
+      let level = if issue.kind.is_error() { "ERROR" } else { "WARNING" };
+      if let Some(column) = issue.column {
+        let name = SHAPED_DEVICES_COLUMNS[column - 1];
+        writeln!(
+          f,
+          "{level}: line {}, column {column} ({name}): {}",
+          issue.line, issue.message
+        )?;
+      } else {
+        writeln!(f, "{level}: line {}: {}", issue.line, issue.message)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// An IPv4 (mapped to IPv6) or IPv6 prefix, and where it was found.
+struct Prefix {
+  start: u128,
+  cidr: u32,
+  text: String,
+  circuit_id: String,
+  line: u64,
+  column: usize,
+}
+
+impl Prefix {
+  fn contains(&self, other: &Prefix) -> bool {
+    self.cidr <= other.cidr
+      && network_start_u128(other.start, self.cidr) == self.start
+  }
+}
+
+fn network_start(ip: Ipv6Addr, cidr: u32) -> u128 {
+  network_start_u128(u128::from(ip), cidr)
+}
+
+fn network_start_u128(ip: u128, cidr: u32) -> u128 {
+  if cidr == 0 {
+    0
+  } else {
+    ip & (u128::MAX << (128 - cidr))
+  }
+}
+
+/// Download and upload limits for every node in `network.json`, by name
+/// and by `id`, since parent nodes can be given either way.
+fn parent_limits(network: &NetworkJson) -> HashMap<String, (u32, u32)> {
+  let mut limits = HashMap::new();
+  for node in network.nodes.iter().skip(1) {
+    // The first node is the synthetic root
+    limits.entry(node.name.clone()).or_insert(node.max_throughput);
+  }
+  for node in network.nodes.iter().skip(1) {
+    if let Some(id) = &node.id {
+      limits.insert(id.clone(), node.max_throughput);
+    }
+  }
+  limits
+}
+
+/// Accepts six hex octets separated by `:` or `-`, e.g.
+/// `00:11:22:aa:bb:cc`.
+fn is_valid_mac(mac: &str) -> bool {
+  let separator = if mac.contains('-') { '-' } else { ':' };
+  let octets: Vec<&str> = mac.split(separator).collect();
+  octets.len() == 6
+    && octets
+      .iter()
+      .all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::NetworkJsonNode;
+  use dashmap::DashSet;
+  use std::sync::atomic::AtomicU64;
+
+  const HEADER: &str = "Circuit ID,Circuit Name,Device ID,Device Name,Parent Node,MAC,IPv4,IPv6,Download Min Mbps,Upload Min Mbps,Download Max Mbps,Upload Max Mbps,Comment\n";
+
+  fn node(name: &str, max_throughput: (u32, u32)) -> NetworkJsonNode {
+    NetworkJsonNode {
+      name: name.to_string(),
+      id: None,
+      node_type: None,
+      tags: Vec::new(),
+      max_throughput,
+      current_throughput: (AtomicU64::new(0), AtomicU64::new(0)),
+      rtts: DashSet::new(),
+      parents: Vec::new(),
+      immediate_parent: None,
+    }
+  }
+
+  fn check(rows: &str, network: Option<&NetworkJson>) -> ShapedDevicesReport {
+    let csv =
+      format!("#LibreQoS - autogenerated file - START\n{HEADER}{rows}");
+    ShapedDevicesReport::validate(csv.as_bytes(), network)
+  }
+
+  fn found(
+    report: &ShapedDevicesReport,
+  ) -> Vec<(u64, Option<usize>, ShapedDevicesIssueKind)> {
+    report.issues.iter().map(|i| (i.line, i.column, i.kind)).collect()
+  }
+
+  #[test]
+  fn valid_file_has_no_issues() {
+    let report = check(
+      "1,One,1,Dev 1,,00:11:22:aa:bb:cc,100.64.0.1,fd77::/64,25,5,155,20,\n\
+       1,One,2,Dev 2,,,100.64.0.2,,25,5,155,20,\n",
+      None,
+    );
+    assert_eq!(report.rows_checked, 2);
+    assert!(report.issues.is_empty());
+    assert!(report.rows.is_empty());
+    assert!(report.is_valid());
+  }
+
+  #[test]
+  fn reports_every_bad_cell() {
+    use ShapedDevicesIssueKind::*;
+    let report = check(
+      "1,One,1,Dev 1,,00:11:22:aa:bb,100.64.0.256,,30,5,20,x,\n\
+       2,Two,2,Dev 2,\n\
+       3,Three,3,Dev 3,,,100.64.1.1,bad wolf,1,1,10,10,\n",
+      None,
+    );
+    assert_eq!(
+      found(&report),
+      vec![
+        (3, Some(6), BadMac),
+        (3, Some(7), BadAddress),
+        (3, Some(12), BadNumber),
+        (4, None, BadRow),
+        (5, Some(8), BadAddress),
+      ]
+    );
+    assert_eq!(report.rows.len(), 3);
+    assert_eq!(report.rows[0].cells[5], "00:11:22:aa:bb");
+  }
+
+  #[test]
+  fn min_above_max() {
+    let report = check("1,One,1,Dev 1,,,,,30,5,20,20,\n", None);
+    assert_eq!(
+      found(&report),
+      vec![(3, Some(9), ShapedDevicesIssueKind::MinAboveMax)]
+    );
+  }
+
+  #[test]
+  fn overlapping_prefixes_across_circuits() {
+    use ShapedDevicesIssueKind::OverlappingPrefix;
+    let report = check(
+      "1,One,1,Dev 1,,,100.64.0.0/24,,1,1,10,10,\n\
+       1,One,2,Dev 2,,,100.64.0.5,fd77::/48,1,1,10,10,\n\
+       2,Two,3,Dev 3,,,100.64.0.9,,1,1,10,10,\n\
+       3,Three,4,Dev 4,,,100.64.1.1,fd77:0:0:1::/64,1,1,10,10,\n\
+       4,Four,5,Dev 5,,,100.64.1.1,,1,1,10,10,\n",
+      None,
+    );
+    assert_eq!(
+      found(&report),
+      vec![
+        (3, Some(7), OverlappingPrefix),
+        (4, Some(8), OverlappingPrefix),
+        (5, Some(7), OverlappingPrefix),
+        (6, Some(7), OverlappingPrefix),
+        (6, Some(8), OverlappingPrefix),
+        (7, Some(7), OverlappingPrefix),
+      ]
+    );
+    assert!(report.issues[0].message.contains("circuit 2, line 5"));
+    assert!(report.issues[2].message.contains("circuit 1, line 3"));
+  }
+
+  #[test]
+  fn circuit_rows_must_agree() {
+    let report = check(
+      "1,One,1,Dev 1,,,,,1,1,10,10,\n\
+       1,Uno,2,Dev 2,,,,,1,1,10,20,\n",
+      None,
+    );
+    assert_eq!(
+      found(&report),
+      vec![
+        (4, Some(2), ShapedDevicesIssueKind::CircuitMismatch),
+        (4, Some(12), ShapedDevicesIssueKind::CircuitMismatch),
+      ]
+    );
+  }
+
+  #[test]
+  fn parents_are_checked_against_network_json() {
+    use ShapedDevicesIssueKind::*;
+    let network = NetworkJson {
+      nodes: vec![node("Root", (0, 0)), node("AP_A", (100, 50))],
+    };
+    let report = check(
+      "1,One,1,Dev 1,AP_A,,,,1,1,100,60,\n\
+       2,Two,2,Dev 2,AP_B,,,,1,1,10,10,\n",
+      Some(&network),
+    );
+    assert_eq!(
+      found(&report),
+      vec![(3, Some(12), ExceedsParent), (4, Some(5), UnknownParent)]
+    );
+    assert!(!report.is_valid());
+
+    // Without network.json, parents aren't checked.
+    assert!(check("2,Two,2,Dev 2,AP_B,,,,1,1,10,10,\n", None).is_valid());
+  }
+}
@@ -0,0 +1,426 @@
+//! Timestamped copies of `/etc/lqos.conf`, `ShapedDevices.csv` and
+//! `network.json`, taken whenever one of them changes, so that a bad edit
+//! or integration sync can be rolled back. Each snapshot is a directory
+//! holding whichever of the three files existed, plus a `snapshot.toml`
+//! recording when it was taken and what changed.
+
+use crate::atomic_write::write_atomically;
+use crate::etc::{EtcLqos, SnapshotConfig};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+/// The files kept in each snapshot, by the name they are stored under.
+pub const SNAPSHOT_FILES: [&str; 3] =
+  ["lqos.conf", "ShapedDevices.csv", "network.json"];
+
+/// Refers to the live files, rather than a snapshot, in `diff`.
+pub const CURRENT_FILES: &str = "current";
+
+const METADATA: &str = "snapshot.toml";
+
+/// A saved copy of the configuration files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+  /// The snapshot's name: the UTC date and time it was taken, such as
+  /// `20230514-093012`.
+  #[serde(skip)]
+  pub id: String,
+
+  /// When the snapshot was taken, in seconds since the Unix epoch.
+  pub timestamp: u64,
+
+  /// What made the change, such as "lqos.conf saved" or an integration's
+  /// name.
+  pub source: String,
+
+  /// The files it holds. Files that didn't exist at the time are left
+  /// out.
+  pub files: Vec<String>,
+}
+
+impl ConfigSnapshot {
+  /// Takes a snapshot of the configuration as it is now, tagged with
+  /// `source`, and removes the oldest snapshots beyond the configured
+  /// retention. Returns `None`, without taking a snapshot, if nothing has
+  /// changed since the last one or snapshots are turned off.
+  pub fn take(source: &str) -> Result<Option<Self>, SnapshotError> {
+    let cfg = EtcLqos::load().map_err(|_| SnapshotError::ConfigLoadError)?;
+    Self::take_with(&cfg, source)
+  }
+
+  pub(crate) fn take_with(
+    cfg: &EtcLqos,
+    source: &str,
+  ) -> Result<Option<Self>, SnapshotError> {
+    let settings = cfg.snapshots.clone().unwrap_or_default();
+    if settings.retain == 0 {
+      return Ok(None);
+    }
+    let dir = Path::new(&settings.directory);
+    let result = take_in(dir, &live_files(cfg), source, unix_now())?;
+    prune(dir, settings.retain)?;
+    if let Some(snapshot) = &result {
+      info!("Configuration snapshot {} taken ({source})", snapshot.id);
+    }
+    Ok(result)
+  }
+
+  /// Lists the snapshots, newest first.
+  pub fn list() -> Result<Vec<Self>, SnapshotError> {
+    list_in(Path::new(&settings()?.directory))
+  }
+
+  /// A unified diff of each file that differs between two snapshots.
+  /// Either may be `CURRENT_FILES`, to compare with the live files.
+  pub fn diff(from: &str, to: &str) -> Result<String, SnapshotError> {
+    let cfg = EtcLqos::load().map_err(|_| SnapshotError::ConfigLoadError)?;
+    let settings = cfg.snapshots.clone().unwrap_or_default();
+    let dir = Path::new(&settings.directory);
+    let from_files = files_for(dir, &cfg, from)?;
+    let to_files = files_for(dir, &cfg, to)?;
+    Ok(diff_files(from, &from_files, to, &to_files))
+  }
+
+  /// Copies a snapshot's files back over the live files, replacing each
+  /// atomically. The live files are snapshotted first, so a restore can
+  /// itself be undone. Files missing from the snapshot are left alone.
+  pub fn restore(id: &str, source: &str) -> Result<Self, SnapshotError> {
+    let cfg = EtcLqos::load().map_err(|_| SnapshotError::ConfigLoadError)?;
+    let settings = cfg.snapshots.clone().unwrap_or_default();
+    let dir = Path::new(&settings.directory);
+    let live = live_files(&cfg);
+    read_snapshot(&snapshot_path(dir, id)?)?;
+
+    // Not pruned yet, so that restoring the oldest snapshot can't remove
+    // it before it is read.
+    if settings.retain > 0 {
+      take_in(dir, &live, &format!("before restoring {id}"), unix_now())?;
+    }
+    let snapshot = restore_in(dir, &live, id)?;
+    warn!("Restored configuration snapshot {id} ({source})");
+
+    // lqos.conf may have changed, so reload it before the next snapshot.
+    let cfg = EtcLqos::load().map_err(|_| SnapshotError::ConfigLoadError)?;
+    Self::take_with(&cfg, &format!("restored {id}: {source}"))?;
+    Ok(snapshot)
+  }
+}
+
+fn settings() -> Result<SnapshotConfig, SnapshotError> {
+  let cfg = EtcLqos::load().map_err(|_| SnapshotError::ConfigLoadError)?;
+  Ok(cfg.snapshots.unwrap_or_default())
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Where each of `SNAPSHOT_FILES` lives.
+fn live_files(cfg: &EtcLqos) -> Vec<(&'static str, PathBuf)> {
+  let base = Path::new(&cfg.lqos_directory);
+  vec![
+    (SNAPSHOT_FILES[0], PathBuf::from("/etc/lqos.conf")),
+    (SNAPSHOT_FILES[1], base.join(SNAPSHOT_FILES[1])),
+    (SNAPSHOT_FILES[2], base.join(SNAPSHOT_FILES[2])),
+  ]
+}
+
+/// Reads whichever of `files` exist.
+fn read_files(
+  files: &[(&'static str, PathBuf)],
+) -> Result<Vec<(&'static str, Vec<u8>)>, SnapshotError> {
+  let mut result = Vec::new();
+  for (name, path) in files.iter() {
+    if path.exists() {
+      result.push((*name, fs::read(path)?));
+    }
+  }
+  Ok(result)
+}
+
+/// The contents of a snapshot's files, or of the live files.
+fn files_for(
+  dir: &Path,
+  cfg: &EtcLqos,
+  id: &str,
+) -> Result<Vec<(&'static str, Vec<u8>)>, SnapshotError> {
+  if id == CURRENT_FILES {
+    read_files(&live_files(cfg))
+  } else {
+    let path = snapshot_path(dir, id)?;
+    read_snapshot(&path)?;
+    read_files(&SNAPSHOT_FILES.map(|name| (name, path.join(name))))
+  }
+}
+
+/// The directory of the snapshot called `id`. IDs come from users, so
+/// anything that could reach outside the snapshot directory is refused.
+fn snapshot_path(dir: &Path, id: &str) -> Result<PathBuf, SnapshotError> {
+  if id.is_empty() || id.starts_with('.') || id.contains('/') {
+    return Err(SnapshotError::NotFound(id.to_string()));
+  }
+  Ok(dir.join(id))
+}
+
+fn take_in(
+  dir: &Path,
+  live: &[(&'static str, PathBuf)],
+  source: &str,
+  now: u64,
+) -> Result<Option<ConfigSnapshot>, SnapshotError> {
+  let contents = read_files(live)?;
+  if let Some(latest) = list_in(dir)?.first() {
+    let latest_files = read_files(
+      &SNAPSHOT_FILES.map(|name| (name, dir.join(&latest.id).join(name))),
+    )?;
+    if latest_files == contents {
+      return Ok(None);
+    }
+  }
+
+  // Write into a temporary directory and rename it, so that a snapshot is
+  // never seen half written.
+  let base_id = utc_id(now);
+  let mut id = base_id.clone();
+  let mut n = 1;
+  while dir.join(&id).exists() {
+    n += 1;
+    id = format!("{base_id}-{n}");
+  }
+  let snapshot = ConfigSnapshot {
+    id: id.clone(),
+    timestamp: now,
+    source: source.to_string(),
+    files: contents.iter().map(|(name, _)| name.to_string()).collect(),
+  };
+  let temp_dir = dir.join(format!(".{id}.tmp"));
+  fs::create_dir_all(&temp_dir)?;
+  for (name, data) in contents.iter() {
+    fs::write(temp_dir.join(name), data)?;
+  }
+  let metadata = toml::to_string_pretty(&snapshot)
+    .map_err(|_| SnapshotError::Corrupt(id.clone()))?;
+  fs::write(temp_dir.join(METADATA), metadata)?;
+  fs::rename(&temp_dir, dir.join(&id))?;
+  Ok(Some(snapshot))
+}
+
+/// Copies snapshot `id`'s files over the `live` files.
+fn restore_in(
+  dir: &Path,
+  live: &[(&'static str, PathBuf)],
+  id: &str,
+) -> Result<ConfigSnapshot, SnapshotError> {
+  let path = snapshot_path(dir, id)?;
+  let snapshot = read_snapshot(&path)?;
+  for (name, live_path) in live {
+    if snapshot.files.iter().any(|f| f == name) {
+      let contents = fs::read(path.join(name))?;
+      write_atomically(live_path, &contents)?;
+    }
+  }
+  Ok(snapshot)
+}
+
+fn read_snapshot(path: &Path) -> Result<ConfigSnapshot, SnapshotError> {
+  let id = path
+    .file_name()
+    .map(|n| n.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let raw = fs::read_to_string(path.join(METADATA))
+    .map_err(|_| SnapshotError::NotFound(id.clone()))?;
+  let mut snapshot: ConfigSnapshot =
+    toml::from_str(&raw).map_err(|_| SnapshotError::Corrupt(id.clone()))?;
+  snapshot.id = id;
+  Ok(snapshot)
+}
+
+fn list_in(dir: &Path) -> Result<Vec<ConfigSnapshot>, SnapshotError> {
+  if !dir.exists() {
+    return Ok(Vec::new());
+  }
+  let mut result = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    let hidden = path
+      .file_name()
+      .map(|n| n.to_string_lossy().starts_with('.'))
+      .unwrap_or(true);
+    if path.is_dir() && !hidden {
+      match read_snapshot(&path) {
+        Ok(snapshot) => result.push(snapshot),
+        Err(e) => warn!("Skipping {}: {e}", path.display()),
+      }
+    }
+  }
+  // Newest first. Snapshots taken in the same second are numbered -2,
+  // -3 and so on, so a longer ID is the later one.
+  result.sort_by(|a, b| {
+    (b.timestamp, b.id.len(), &b.id).cmp(&(a.timestamp, a.id.len(), &a.id))
+  });
+  Ok(result)
+}
+
+/// Removes all but the newest `retain` snapshots.
+fn prune(dir: &Path, retain: usize) -> Result<(), SnapshotError> {
+  for old in list_in(dir)?.iter().skip(retain) {
+    info!("Removing configuration snapshot {}", old.id);
+    fs::remove_dir_all(dir.join(&old.id))?;
+  }
+  Ok(())
+}
+
+fn diff_files(
+  from: &str,
+  from_files: &[(&'static str, Vec<u8>)],
+  to: &str,
+  to_files: &[(&'static str, Vec<u8>)],
+) -> String {
+  let text = |files: &[(&'static str, Vec<u8>)], name: &str| {
+    files
+      .iter()
+      .find(|(n, _)| *n == name)
+      .map(|(_, data)| String::from_utf8_lossy(data).to_string())
+      .unwrap_or_default()
+  };
+  let mut result = String::new();
+  for name in SNAPSHOT_FILES {
+    let (old, new) = (text(from_files, name), text(to_files, name));
+    if old != new {
+      result += &TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .header(&format!("{from}/{name}"), &format!("{to}/{name}"))
+        .to_string();
+    }
+  }
+  result
+}
+
+/// Formats a Unix time as `YYYYMMDD-HHMMSS`, in UTC.
+fn utc_id(unix_time: u64) -> String {
+  let days = (unix_time / 86400) as i64;
+  let secs = unix_time % 86400;
+  // Howard Hinnant's days-to-civil algorithm
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  format!(
+    "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+    secs / 3600,
+    (secs % 3600) / 60,
+    secs % 60
+  )
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+  #[error("Unable to load /etc/lqos.conf")]
+  ConfigLoadError,
+  #[error("Unable to access the snapshot directory or configuration files")]
+  Io(#[from] std::io::Error),
+  #[error("No snapshot named {0}")]
+  NotFound(String),
+  #[error("Snapshot {0} is damaged")]
+  Corrupt(String),
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn test_directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("lqos_snapshots_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("live")).unwrap();
+    dir
+  }
+
+  fn live(dir: &Path) -> Vec<(&'static str, PathBuf)> {
+    SNAPSHOT_FILES.map(|name| (name, dir.join("live").join(name))).to_vec()
+  }
+
+  #[test]
+  fn formats_utc_ids() {
+    assert_eq!(utc_id(0), "19700101-000000");
+    assert_eq!(utc_id(951782400 + 3723), "20000229-010203");
+    assert_eq!(utc_id(1700000000), "20231114-221320");
+  }
+
+  #[test]
+  fn takes_only_changed_snapshots() {
+    let dir = test_directory("take");
+    let snapshots = dir.join("snapshots");
+    let live = live(&dir);
+    fs::write(&live[1].1, "a\n").unwrap();
+
+    let first = take_in(&snapshots, &live, "one", 100).unwrap().unwrap();
+    assert_eq!(first.files, vec!["ShapedDevices.csv"]);
+    assert!(take_in(&snapshots, &live, "same", 101).unwrap().is_none());
+
+    fs::write(&live[2].1, "{}\n").unwrap();
+    let second = take_in(&snapshots, &live, "two", 100).unwrap().unwrap();
+    assert_eq!(second.id, format!("{}-2", first.id));
+
+    let listed = list_in(&snapshots).unwrap();
+    assert_eq!(listed, vec![second.clone(), first.clone()]);
+
+    prune(&snapshots, 1).unwrap();
+    assert_eq!(list_in(&snapshots).unwrap(), vec![second]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn restores_snapshot_files() {
+    let dir = test_directory("restore");
+    let snapshots = dir.join("snapshots");
+    let live = live(&dir);
+    fs::write(&live[1].1, "a\n").unwrap();
+    fs::write(&live[2].1, "{}\n").unwrap();
+    let first = take_in(&snapshots, &live, "one", 100).unwrap().unwrap();
+
+    fs::write(&live[1].1, "b\n").unwrap();
+    fs::write(&live[2].1, "{\"x\": 1}\n").unwrap();
+    restore_in(&snapshots, &live, &first.id).unwrap();
+    assert_eq!(fs::read_to_string(&live[1].1).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(&live[2].1).unwrap(), "{}\n");
+    // lqos.conf wasn't in the snapshot, so isn't created
+    assert!(!live[0].1.exists());
+
+    assert!(matches!(
+      restore_in(&snapshots, &live, "../live"),
+      Err(SnapshotError::NotFound(_))
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn diffs_changed_files() {
+    let old = vec![("ShapedDevices.csv", b"a\nb\n".to_vec())];
+    let new = vec![
+      ("ShapedDevices.csv", b"a\nc\n".to_vec()),
+      ("network.json", b"{}\n".to_vec()),
+    ];
+    let diff = diff_files("x", &old, "y", &new);
+    assert!(diff.contains("--- x/ShapedDevices.csv\n+++ y/ShapedDevices.csv"));
+    assert!(diff.contains("-b\n+c\n"));
+    assert!(diff.contains("+++ y/network.json"));
+    assert!(!diff.contains("lqos.conf"));
+  }
+}
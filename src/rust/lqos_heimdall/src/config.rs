@@ -16,4 +16,9 @@ pub enum HeimdallMode {
 pub struct HeimdalConfig {
   /// Current operation mode
   pub mode: u32,
+  /// Number of packet octets to capture in Analysis mode
+  pub snaplen: u32,
+  /// If non-zero, track flows for all shaped hosts, counting 1 in
+  /// `sample_rate` packets
+  pub sample_rate: u32,
 }
\ No newline at end of file
@@ -0,0 +1,168 @@
+//! IPFIX (RFC 7011) encoding.
+
+use super::{
+  enterprise_field, field, write_record, DatagramBuilder, ExportClock, Field,
+  FlowRecord, Value, FIELD_CIRCUIT_ID, FIELD_ECN, TEMPLATE_IPV4,
+  TEMPLATE_IPV6, VARIABLE_LENGTH,
+};
+
+const VERSION: u16 = 10;
+const HEADER_LEN: usize = 16;
+const TEMPLATE_SET_ID: u16 = 2;
+
+const IPV4_FIELDS: &[Field] = &[
+  field(8, 4, Value::SrcAddr),  // sourceIPv4Address
+  field(12, 4, Value::DstAddr), // destinationIPv4Address
+  field(4, 1, Value::Proto),    // protocolIdentifier
+  field(7, 2, Value::SrcPort),  // sourceTransportPort
+  field(11, 2, Value::DstPort), // destinationTransportPort
+  field(1, 8, Value::Bytes),    // octetDeltaCount
+  field(2, 8, Value::Packets),  // packetDeltaCount
+  field(5, 1, Value::Tos),      // ipClassOfService
+  field(195, 1, Value::Dscp),   // ipDiffServCodePoint
+  field(152, 8, Value::StartUnixMs), // flowStartMilliseconds
+  field(153, 8, Value::EndUnixMs), // flowEndMilliseconds
+  field(136, 1, Value::EndReason), // flowEndReason
+  enterprise_field(FIELD_ECN, 1, Value::Ecn),
+  enterprise_field(FIELD_CIRCUIT_ID, VARIABLE_LENGTH, Value::CircuitId),
+];
+
+const IPV6_FIELDS: &[Field] = &[
+  field(27, 16, Value::SrcAddr), // sourceIPv6Address
+  field(28, 16, Value::DstAddr), // destinationIPv6Address
+  field(4, 1, Value::Proto),
+  field(7, 2, Value::SrcPort),
+  field(11, 2, Value::DstPort),
+  field(1, 8, Value::Bytes),
+  field(2, 8, Value::Packets),
+  field(5, 1, Value::Tos),
+  field(195, 1, Value::Dscp),
+  field(152, 8, Value::StartUnixMs),
+  field(153, 8, Value::EndUnixMs),
+  field(136, 1, Value::EndReason),
+  enterprise_field(FIELD_ECN, 1, Value::Ecn),
+  enterprise_field(FIELD_CIRCUIT_ID, VARIABLE_LENGTH, Value::CircuitId),
+];
+
+fn template_record(id: u16, fields: &[Field], enterprise_id: u32) -> Vec<u8> {
+  let mut out = Vec::new();
+  out.extend_from_slice(&id.to_be_bytes());
+  out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+  for f in fields.iter() {
+    if f.enterprise {
+      out.extend_from_slice(&(f.id | 0x8000).to_be_bytes());
+      out.extend_from_slice(&f.len.to_be_bytes());
+      out.extend_from_slice(&enterprise_id.to_be_bytes());
+    } else {
+      out.extend_from_slice(&f.id.to_be_bytes());
+      out.extend_from_slice(&f.len.to_be_bytes());
+    }
+  }
+  out
+}
+
+/// Encodes `records` into IPFIX messages, preceded by the templates if
+/// `send_templates` is set. `sequence` counts the data records sent so
+/// far, as RFC 7011 requires.
+pub(super) fn encode(
+  records: &[FlowRecord],
+  send_templates: bool,
+  enterprise_id: u32,
+  sequence: &mut u32,
+  clock: &ExportClock,
+) -> Vec<Vec<u8>> {
+  let mut builder = DatagramBuilder::new(HEADER_LEN, false);
+  if send_templates {
+    let v4 = template_record(TEMPLATE_IPV4, IPV4_FIELDS, enterprise_id);
+    let v6 = template_record(TEMPLATE_IPV6, IPV6_FIELDS, enterprise_id);
+    builder.add(TEMPLATE_SET_ID, &v4, false);
+    builder.add(TEMPLATE_SET_ID, &v6, false);
+  }
+  let mut buffer = Vec::new();
+  for record in records.iter() {
+    let template = record.template_id();
+    let fields =
+      if template == TEMPLATE_IPV4 { IPV4_FIELDS } else { IPV6_FIELDS };
+    buffer.clear();
+    write_record(record, fields, clock, &mut buffer);
+    builder.add(template, &buffer, true);
+  }
+
+  builder
+    .finish()
+    .into_iter()
+    .map(|mut datagram| {
+      let len = datagram.body.len() as u16;
+      let header = &mut datagram.body[..HEADER_LEN];
+      header[0..2].copy_from_slice(&VERSION.to_be_bytes());
+      header[2..4].copy_from_slice(&len.to_be_bytes());
+      header[4..8].copy_from_slice(&clock.unix_secs().to_be_bytes());
+      header[8..12].copy_from_slice(&sequence.to_be_bytes());
+      // Observation domain ID (bytes 12..16) is left as 0
+      *sequence = sequence.wrapping_add(datagram.data_records);
+      datagram.body
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::export::test_support::{sets, test_clock, test_records};
+  use crate::export::MAX_DATAGRAM;
+
+  /// An IPv4 record with a 9 byte circuit ID: fixed fields, a 1 byte
+  /// length and the ID.
+  const RECORD_LEN: usize = 49 + 1 + 9;
+
+  #[test]
+  fn encodes_templates() {
+    let mut sequence = 0;
+    let datagrams = encode(&[], true, 12345, &mut sequence, &test_clock());
+    assert_eq!(datagrams.len(), 1);
+    let sets = sets(&datagrams[0], HEADER_LEN);
+    assert_eq!(sets.len(), 1);
+    let (id, templates) = sets[0];
+    assert_eq!(id, TEMPLATE_SET_ID);
+    assert_eq!(&templates[0..2], &TEMPLATE_IPV4.to_be_bytes());
+    assert_eq!(&templates[2..4], &14u16.to_be_bytes());
+    // 12 standard fields, then the two enterprise fields
+    let enterprise = &templates[4 + 12 * 4..];
+    assert_eq!(&enterprise[0..2], &(FIELD_ECN | 0x8000).to_be_bytes());
+    assert_eq!(&enterprise[4..8], &12345u32.to_be_bytes());
+    assert_eq!(&enterprise[8..10], &(FIELD_CIRCUIT_ID | 0x8000).to_be_bytes());
+    assert_eq!(&enterprise[10..12], &VARIABLE_LENGTH.to_be_bytes());
+    let v6 = &templates[4 + 12 * 4 + 2 * 8..];
+    assert_eq!(&v6[0..2], &TEMPLATE_IPV6.to_be_bytes());
+    // Templates aren't data, so don't advance the sequence
+    assert_eq!(sequence, 0);
+  }
+
+  #[test]
+  fn splits_records_across_messages() {
+    let mut sequence = 5;
+    let datagrams =
+      encode(&test_records(100), true, 0, &mut sequence, &test_clock());
+    assert!(datagrams.len() > 1);
+    let mut expected_sequence = 5u32;
+    for (i, datagram) in datagrams.iter().enumerate() {
+      assert!(datagram.len() <= MAX_DATAGRAM);
+      assert_eq!(&datagram[0..2], &VERSION.to_be_bytes());
+      assert_eq!(
+        u16::from_be_bytes([datagram[2], datagram[3]]) as usize,
+        datagram.len()
+      );
+      assert_eq!(&datagram[4..8], &1_700_000_000u32.to_be_bytes());
+      assert_eq!(&datagram[8..12], &expected_sequence.to_be_bytes());
+
+      let sets = sets(datagram, HEADER_LEN);
+      assert_eq!(sets[0].0 == TEMPLATE_SET_ID, i == 0);
+      let (id, data) = sets.last().unwrap();
+      assert_eq!(*id, TEMPLATE_IPV4);
+      assert_eq!(data.len() % RECORD_LEN, 0);
+      expected_sequence += (data.len() / RECORD_LEN) as u32;
+    }
+    assert_eq!(expected_sequence, 105);
+    assert_eq!(sequence, 105);
+  }
+}
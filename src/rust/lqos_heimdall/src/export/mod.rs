@@ -0,0 +1,598 @@
+//! Exports Heimdall's flow records to NetFlow v9 and IPFIX collectors.
+//!
+//! Flows are exported when they expire from `FLOW_DATA`, and every
+//! `active_timeout_secs` while they remain active. Each export only
+//! carries the bytes and packets seen since the previous export.
+
+mod ipfix;
+mod netflow9;
+use crate::flows::{for_each_flow_mut, FlowData, FlowKey};
+use lqos_bus::tos_parser;
+use lqos_config::{FlowExportConfig, FlowExportProtocol};
+use lqos_utils::{unix_time::time_since_boot, XdpIpAddress};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{
+  net::{IpAddr, SocketAddr, UdpSocket},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+  },
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Largest datagram we will send, keeping clear of fragmentation on a
+/// 1,500 byte MTU path.
+const MAX_DATAGRAM: usize = 1400;
+
+/// How often templates are re-sent. Collectors that start (or restart)
+/// after us can't decode anything until they see one.
+const TEMPLATE_REFRESH_SECS: u64 = 60;
+
+/// Template ID for flows between IPv4 addresses.
+const TEMPLATE_IPV4: u16 = 256;
+
+/// Template ID for flows between IPv6 addresses.
+const TEMPLATE_IPV6: u16 = 257;
+
+/// LibreQoS-specific field: the circuit ID from `ShapedDevices.csv`.
+const FIELD_CIRCUIT_ID: u16 = 1;
+
+/// LibreQoS-specific field: the 2-bit ECN code (there's no standard
+/// information element for ECN on its own).
+const FIELD_ECN: u16 = 2;
+
+/// Field length signifying a variable-length (IPFIX) field.
+const VARIABLE_LENGTH: u16 = 0xFFFF;
+
+static EXPORT_ENABLED: AtomicBool = AtomicBool::new(false);
+static EXPORTER: Lazy<Mutex<Option<FlowExporter>>> =
+  Lazy::new(|| Mutex::new(None));
+static CIRCUIT_LOOKUP: OnceCell<fn(&XdpIpAddress) -> Option<String>> =
+  OnceCell::new();
+
+/// Provides the function used to find the circuit ID for an IP address,
+/// which is included in exported flow records. Heimdall doesn't know
+/// about `ShapedDevices.csv`, so `lqosd` supplies this.
+pub fn set_circuit_lookup(lookup: fn(&XdpIpAddress) -> Option<String>) {
+  let _ = CIRCUIT_LOOKUP.set(lookup);
+}
+
+fn circuit_id(key: &FlowKey) -> Option<String> {
+  CIRCUIT_LOOKUP
+    .get()
+    .and_then(|lookup| lookup(&key.src).or_else(|| lookup(&key.dst)))
+}
+
+pub(crate) fn flow_export_enabled() -> bool {
+  EXPORT_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Apply the `[flow_export]` section of `/etc/lqos.conf`.
+pub(crate) fn configure_flow_export(config: Option<&FlowExportConfig>) {
+  let exporter = config.and_then(FlowExporter::new);
+  EXPORT_ENABLED.store(exporter.is_some(), Ordering::Relaxed);
+  *EXPORTER.lock().unwrap() = exporter;
+}
+
+/// Exports the part of each active flow that hasn't been exported in
+/// the last `active_timeout_secs`.
+pub(crate) fn export_active_flows() {
+  if !flow_export_enabled() {
+    return;
+  }
+  if let Some(clock) = ExportClock::now() {
+    let mut lock = EXPORTER.lock().unwrap();
+    if let Some(exporter) = lock.as_mut() {
+      let timeout = exporter.active_timeout_ns;
+      let mut records = Vec::new();
+      for_each_flow_mut(|key, data| {
+        if clock.boot_ns.saturating_sub(data.exported_at) >= timeout
+          && data.bytes > data.exported_bytes
+        {
+          records.push(FlowRecord::new(key, data, EndReason::ActiveTimeout));
+          data.exported_bytes = data.bytes;
+          data.exported_packets = data.packets;
+          data.exported_at = data.last_seen;
+        }
+      });
+      exporter.send(&records, &clock);
+    }
+  }
+}
+
+/// Exports flows that have just been expired from `FLOW_DATA`.
+pub(crate) fn export_expired_flows(flows: &[(FlowKey, FlowData)]) {
+  let records: Vec<FlowRecord> = flows
+    .iter()
+    .filter(|(_, data)| data.bytes > data.exported_bytes)
+    .map(|(key, data)| FlowRecord::new(key, data, EndReason::IdleTimeout))
+    .collect();
+  if records.is_empty() {
+    return;
+  }
+  if let Some(clock) = ExportClock::now() {
+    if let Some(exporter) = EXPORTER.lock().unwrap().as_mut() {
+      exporter.send(&records, &clock);
+    }
+  }
+}
+
+struct FlowExporter {
+  collectors: Vec<(SocketAddr, UdpSocket)>,
+  protocol: FlowExportProtocol,
+  active_timeout_ns: u64,
+  enterprise_id: u32,
+  sequence: u32,
+  last_template: Option<Instant>,
+}
+
+impl FlowExporter {
+  fn new(config: &FlowExportConfig) -> Option<Self> {
+    let collectors: Vec<(SocketAddr, UdpSocket)> = config
+      .collectors
+      .iter()
+      .filter_map(|collector| match collector.parse::<SocketAddr>() {
+        Ok(addr) => match connect_collector(addr) {
+          Ok(socket) => Some((addr, socket)),
+          Err(e) => {
+            log::warn!("Unable to open a socket to flow collector {addr}: {e:?}");
+            None
+          }
+        },
+        Err(_) => {
+          log::warn!("Ignoring invalid flow collector address: {collector}");
+          None
+        }
+      })
+      .collect();
+    if collectors.is_empty() {
+      log::warn!("Flow export is configured, but there are no usable collectors.");
+      return None;
+    }
+    log::info!(
+      "Exporting {:?} flow records to {} collector(s)",
+      config.protocol,
+      collectors.len()
+    );
+    if !crate::tracking_all_flows() {
+      log::warn!("Flow export only covers watched hosts. Enable track_all_hosts in the [flows] section to export every shaped host.");
+    }
+    Some(Self {
+      collectors,
+      protocol: config.protocol,
+      active_timeout_ns: Duration::from_secs(u64::max(config.active_timeout_secs, 1)).as_nanos() as u64,
+      enterprise_id: config.enterprise_id,
+      sequence: 0,
+      last_template: None,
+    })
+  }
+
+  fn send(&mut self, records: &[FlowRecord], clock: &ExportClock) {
+    let send_templates = self
+      .last_template
+      .map(|t| t.elapsed().as_secs() >= TEMPLATE_REFRESH_SECS)
+      .unwrap_or(true);
+    if records.is_empty() && !send_templates {
+      return;
+    }
+    let datagrams = match self.protocol {
+      FlowExportProtocol::Ipfix => ipfix::encode(
+        records,
+        send_templates,
+        self.enterprise_id,
+        &mut self.sequence,
+        clock,
+      ),
+      FlowExportProtocol::Netflow9 => {
+        netflow9::encode(records, send_templates, &mut self.sequence, clock)
+      }
+    };
+    if send_templates {
+      self.last_template = Some(Instant::now());
+    }
+    for datagram in datagrams.iter() {
+      for (addr, socket) in self.collectors.iter() {
+        if let Err(e) = socket.send(datagram) {
+          log::warn!("Unable to send flow records to {addr}: {e:?}");
+        }
+      }
+    }
+  }
+}
+
+fn connect_collector(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+  let socket = if addr.is_ipv4() {
+    UdpSocket::bind("0.0.0.0:0")?
+  } else {
+    UdpSocket::bind("[::]:0")?
+  };
+  socket.connect(addr)?;
+  Ok(socket)
+}
+
+/// Why a flow record was exported. Values match IPFIX's
+/// `flowEndReason` information element.
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum EndReason {
+  IdleTimeout = 1,
+  ActiveTimeout = 2,
+}
+
+/// A flow (or part of one) ready to be exported.
+struct FlowRecord {
+  src: IpAddr,
+  dst: IpAddr,
+  proto: u8,
+  src_port: u16,
+  dst_port: u16,
+  bytes: u64,
+  packets: u64,
+  tos: u8,
+  /// Nanoseconds since boot
+  start: u64,
+  /// Nanoseconds since boot
+  end: u64,
+  end_reason: EndReason,
+  circuit_id: Option<String>,
+}
+
+impl FlowRecord {
+  fn new(key: &FlowKey, data: &FlowData, end_reason: EndReason) -> Self {
+    Self {
+      src: key.src.as_ip(),
+      dst: key.dst.as_ip(),
+      proto: key.proto,
+      src_port: key.src_port,
+      dst_port: key.dst_port,
+      bytes: data.bytes - data.exported_bytes,
+      packets: data.packets.saturating_sub(data.exported_packets),
+      tos: data.tos,
+      start: data.exported_at,
+      end: data.last_seen,
+      end_reason,
+      circuit_id: circuit_id(key),
+    }
+  }
+
+  fn template_id(&self) -> u16 {
+    if self.src.is_ipv4() && self.dst.is_ipv4() {
+      TEMPLATE_IPV4
+    } else {
+      TEMPLATE_IPV6
+    }
+  }
+}
+
+/// Converts Heimdall's "nanoseconds since boot" timestamps into the
+/// forms the export protocols want.
+struct ExportClock {
+  boot_ns: u64,
+  unix_ms: u64,
+}
+
+impl ExportClock {
+  fn now() -> Option<Self> {
+    let boot_ns = Duration::from(time_since_boot().ok()?).as_nanos() as u64;
+    let unix_ms =
+      SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+    Some(Self { boot_ns, unix_ms })
+  }
+
+  fn unix_secs(&self) -> u32 {
+    (self.unix_ms / 1000) as u32
+  }
+
+  /// Milliseconds since the UNIX epoch
+  fn unix_ms_at(&self, boot_ns: u64) -> u64 {
+    self.unix_ms - (self.boot_ns.saturating_sub(boot_ns) / 1_000_000)
+  }
+
+  /// Milliseconds since boot, wrapping as NetFlow's `sysUptime` does.
+  fn uptime_ms_at(boot_ns: u64) -> u32 {
+    (boot_ns / 1_000_000) as u32
+  }
+}
+
+/// What a template field holds.
+#[derive(Clone, Copy)]
+enum Value {
+  SrcAddr,
+  DstAddr,
+  Proto,
+  SrcPort,
+  DstPort,
+  Bytes,
+  Packets,
+  Tos,
+  Dscp,
+  Ecn,
+  StartUnixMs,
+  EndUnixMs,
+  StartUptimeMs,
+  EndUptimeMs,
+  EndReason,
+  CircuitId,
+}
+
+/// A field in a template.
+struct Field {
+  id: u16,
+  len: u16,
+  /// LibreQoS-specific, rather than IANA defined?
+  enterprise: bool,
+  value: Value,
+}
+
+const fn field(id: u16, len: u16, value: Value) -> Field {
+  Field { id, len, enterprise: false, value }
+}
+
+const fn enterprise_field(id: u16, len: u16, value: Value) -> Field {
+  Field { id, len, enterprise: true, value }
+}
+
+fn ip_octets(ip: IpAddr) -> Vec<u8> {
+  match ip {
+    IpAddr::V4(ip) => ip.octets().to_vec(),
+    IpAddr::V6(ip) => ip.octets().to_vec(),
+  }
+}
+
+/// Writes a data record laid out according to `fields`.
+fn write_record(
+  record: &FlowRecord,
+  fields: &[Field],
+  clock: &ExportClock,
+  out: &mut Vec<u8>,
+) {
+  let (dscp, ecn) = tos_parser(record.tos);
+  for field in fields.iter() {
+    match field.value {
+      Value::SrcAddr => out.extend_from_slice(&ip_octets(record.src)),
+      Value::DstAddr => out.extend_from_slice(&ip_octets(record.dst)),
+      Value::Proto => out.push(record.proto),
+      Value::SrcPort => out.extend_from_slice(&record.src_port.to_be_bytes()),
+      Value::DstPort => out.extend_from_slice(&record.dst_port.to_be_bytes()),
+      Value::Bytes => out.extend_from_slice(&record.bytes.to_be_bytes()),
+      Value::Packets => out.extend_from_slice(&record.packets.to_be_bytes()),
+      Value::Tos => out.push(record.tos),
+      Value::Dscp => out.push(dscp),
+      Value::Ecn => out.push(ecn),
+      Value::StartUnixMs => {
+        out.extend_from_slice(&clock.unix_ms_at(record.start).to_be_bytes())
+      }
+      Value::EndUnixMs => {
+        out.extend_from_slice(&clock.unix_ms_at(record.end).to_be_bytes())
+      }
+      Value::StartUptimeMs => out.extend_from_slice(
+        &ExportClock::uptime_ms_at(record.start).to_be_bytes(),
+      ),
+      Value::EndUptimeMs => out.extend_from_slice(
+        &ExportClock::uptime_ms_at(record.end).to_be_bytes(),
+      ),
+      Value::EndReason => out.push(record.end_reason as u8),
+      Value::CircuitId => {
+        let id = record.circuit_id.as_deref().unwrap_or("").as_bytes();
+        if field.len == VARIABLE_LENGTH {
+          let id = &id[..usize::min(id.len(), 254)];
+          out.push(id.len() as u8);
+          out.extend_from_slice(id);
+        } else {
+          let mut fixed = vec![0u8; field.len as usize];
+          let n = usize::min(id.len(), fixed.len());
+          fixed[..n].copy_from_slice(&id[..n]);
+          out.extend_from_slice(&fixed);
+        }
+      }
+    }
+  }
+}
+
+/// An encoded export packet.
+struct Datagram {
+  body: Vec<u8>,
+  /// Total records (templates and data) in the packet.
+  records: u16,
+  /// Data records in the packet.
+  data_records: u32,
+}
+
+/// Packs template and data records into datagrams no larger than
+/// `MAX_DATAGRAM`, grouping consecutive records with the same set ID
+/// into a single set. The protocol header is left zeroed, for the
+/// caller to fill in.
+struct DatagramBuilder {
+  header_len: usize,
+  pad_sets: bool,
+  current: Vec<u8>,
+  set: Option<(usize, u16)>,
+  records: u16,
+  data_records: u32,
+  done: Vec<Datagram>,
+}
+
+impl DatagramBuilder {
+  fn new(header_len: usize, pad_sets: bool) -> Self {
+    Self {
+      header_len,
+      pad_sets,
+      current: vec![0; header_len],
+      set: None,
+      records: 0,
+      data_records: 0,
+      done: Vec::new(),
+    }
+  }
+
+  fn add(&mut self, set_id: u16, record: &[u8], is_data: bool) {
+    let mut new_set = self.set.map(|(_, id)| id != set_id).unwrap_or(true);
+    // Allow for a set header and up to 3 bytes of padding
+    if self.records > 0 && self.current.len() + record.len() + 7 > MAX_DATAGRAM {
+      self.flush();
+      new_set = true;
+    }
+    if new_set {
+      self.close_set();
+      self.set = Some((self.current.len(), set_id));
+      self.current.extend_from_slice(&set_id.to_be_bytes());
+      self.current.extend_from_slice(&[0, 0]);
+    }
+    self.current.extend_from_slice(record);
+    self.records += 1;
+    if is_data {
+      self.data_records += 1;
+    }
+  }
+
+  fn close_set(&mut self) {
+    if let Some((start, _)) = self.set.take() {
+      if self.pad_sets {
+        let padding = (4 - (self.current.len() - start) % 4) % 4;
+        self.current.resize(self.current.len() + padding, 0);
+      }
+      let len = (self.current.len() - start) as u16;
+      self.current[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+  }
+
+  fn flush(&mut self) {
+    self.close_set();
+    if self.records > 0 {
+      let body =
+        std::mem::replace(&mut self.current, vec![0; self.header_len]);
+      self.done.push(Datagram {
+        body,
+        records: self.records,
+        data_records: self.data_records,
+      });
+    }
+    self.records = 0;
+    self.data_records = 0;
+  }
+
+  fn finish(mut self) -> Vec<Datagram> {
+    self.flush();
+    self.done
+  }
+}
+
+/// Helpers shared by the exporter tests.
+#[cfg(test)]
+pub(crate) mod test_support {
+  use super::*;
+
+  /// Splits the sets (or NetFlow flowsets) out of a datagram, returning
+  /// each set's ID and contents.
+  pub(super) fn sets(datagram: &[u8], header_len: usize) -> Vec<(u16, &[u8])> {
+    let mut result = Vec::new();
+    let mut at = header_len;
+    while at < datagram.len() {
+      let id = u16::from_be_bytes([datagram[at], datagram[at + 1]]);
+      let len =
+        u16::from_be_bytes([datagram[at + 2], datagram[at + 3]]) as usize;
+      assert!(len >= 4 && at + len <= datagram.len(), "set overruns datagram");
+      result.push((id, &datagram[at + 4..at + len]));
+      at += len;
+    }
+    result
+  }
+
+  pub(super) fn test_clock() -> ExportClock {
+    ExportClock { boot_ns: 100_000_000_000, unix_ms: 1_700_000_000_000 }
+  }
+
+  /// `n` IPv4 flow records, each with a 9 byte circuit ID.
+  pub(super) fn test_records(n: usize) -> Vec<FlowRecord> {
+    (0..n)
+      .map(|i| FlowRecord {
+        src: IpAddr::from([100, 64, 0, (i % 250) as u8]),
+        dst: IpAddr::from([1, 1, 1, 1]),
+        proto: 6,
+        src_port: 40000 + i as u16,
+        dst_port: 443,
+        bytes: 1500 * (i as u64 + 1),
+        packets: i as u64 + 1,
+        tos: 0x2e << 2,
+        start: 90_000_000_000,
+        end: 99_000_000_000,
+        end_reason: EndReason::IdleTimeout,
+        circuit_id: Some("circuit-1".to_string()),
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::test_support::*;
+  use super::*;
+
+  #[test]
+  fn builder_splits_sets_across_datagrams() {
+    let mut builder = DatagramBuilder::new(16, false);
+    builder.add(2, &[1; 40], false);
+    for _ in 0..100 {
+      builder.add(256, &[7; 60], true);
+    }
+    let datagrams = builder.finish();
+    assert!(datagrams.len() > 1);
+    let mut data_records = 0;
+    for (i, datagram) in datagrams.iter().enumerate() {
+      assert!(datagram.body.len() <= MAX_DATAGRAM);
+      let sets = sets(&datagram.body, 16);
+      // The template set only starts the first datagram
+      let ids: Vec<u16> = sets.iter().map(|(id, _)| *id).collect();
+      assert_eq!(ids, if i == 0 { vec![2, 256] } else { vec![256] });
+      let records = sets.last().unwrap().1.len() / 60;
+      assert_eq!(datagram.data_records as usize, records);
+      data_records += records;
+    }
+    assert_eq!(data_records, 100);
+  }
+
+  #[test]
+  fn builder_pads_netflow_flowsets() {
+    let mut builder = DatagramBuilder::new(20, true);
+    builder.add(0, &[1; 6], false);
+    builder.add(256, &[2; 5], true);
+    let datagrams = builder.finish();
+    assert_eq!(datagrams.len(), 1);
+    assert_eq!(datagrams[0].records, 2);
+    let sets = sets(&datagrams[0].body, 20);
+    assert_eq!(sets[0].1.len(), 8);
+    assert_eq!(sets[1].1.len(), 8);
+  }
+
+  #[test]
+  fn sends_records_to_collectors() {
+    let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+    collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let config = FlowExportConfig {
+      protocol: FlowExportProtocol::Ipfix,
+      collectors: vec![collector.local_addr().unwrap().to_string()],
+      active_timeout_secs: 60,
+      enterprise_id: 12345,
+    };
+    let mut exporter = FlowExporter::new(&config).unwrap();
+    let clock = test_clock();
+    let mut buffer = [0u8; 2048];
+
+    // The first export carries the templates
+    exporter.send(&test_records(2), &clock);
+    let len = collector.recv(&mut buffer).unwrap();
+    let datagram = &buffer[..len];
+    assert_eq!(&datagram[0..2], &10u16.to_be_bytes());
+    assert_eq!(u16::from_be_bytes([datagram[2], datagram[3]]) as usize, len);
+    let ids: Vec<u16> = sets(datagram, 16).iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![2, TEMPLATE_IPV4]);
+
+    // Later ones don't, and continue the sequence
+    exporter.send(&test_records(1), &clock);
+    let len = collector.recv(&mut buffer).unwrap();
+    let datagram = &buffer[..len];
+    assert_eq!(&datagram[8..12], &2u32.to_be_bytes());
+    let ids: Vec<u16> = sets(datagram, 16).iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![TEMPLATE_IPV4]);
+  }
+}
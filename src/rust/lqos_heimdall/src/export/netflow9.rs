@@ -0,0 +1,159 @@
+//! NetFlow version 9 (RFC 3954) encoding.
+//!
+//! NetFlow v9 has no enterprise numbers, so LibreQoS-specific fields are
+//! sent with the high bit of the field type set (as IPFIX does), and the
+//! circuit ID is a fixed-length, zero-padded string.
+
+use super::{
+  field, write_record, DatagramBuilder, ExportClock, Field, FlowRecord,
+  Value, FIELD_CIRCUIT_ID, FIELD_ECN, TEMPLATE_IPV4, TEMPLATE_IPV6,
+};
+
+const VERSION: u16 = 9;
+const HEADER_LEN: usize = 20;
+const TEMPLATE_FLOWSET_ID: u16 = 0;
+
+/// Circuit IDs longer than this are truncated.
+const CIRCUIT_ID_LEN: u16 = 64;
+
+const IPV4_FIELDS: &[Field] = &[
+  field(8, 4, Value::SrcAddr),  // IPV4_SRC_ADDR
+  field(12, 4, Value::DstAddr), // IPV4_DST_ADDR
+  field(4, 1, Value::Proto),    // PROTOCOL
+  field(7, 2, Value::SrcPort),  // L4_SRC_PORT
+  field(11, 2, Value::DstPort), // L4_DST_PORT
+  field(1, 8, Value::Bytes),    // IN_BYTES
+  field(2, 8, Value::Packets),  // IN_PKTS
+  field(5, 1, Value::Tos),      // SRC_TOS
+  field(195, 1, Value::Dscp),   // IPFIX ipDiffServCodePoint
+  field(22, 4, Value::StartUptimeMs), // FIRST_SWITCHED
+  field(21, 4, Value::EndUptimeMs), // LAST_SWITCHED
+  field(136, 1, Value::EndReason), // IPFIX flowEndReason
+  field(FIELD_ECN | 0x8000, 1, Value::Ecn),
+  field(FIELD_CIRCUIT_ID | 0x8000, CIRCUIT_ID_LEN, Value::CircuitId),
+];
+
+const IPV6_FIELDS: &[Field] = &[
+  field(27, 16, Value::SrcAddr), // IPV6_SRC_ADDR
+  field(28, 16, Value::DstAddr), // IPV6_DST_ADDR
+  field(4, 1, Value::Proto),
+  field(7, 2, Value::SrcPort),
+  field(11, 2, Value::DstPort),
+  field(1, 8, Value::Bytes),
+  field(2, 8, Value::Packets),
+  field(5, 1, Value::Tos),
+  field(195, 1, Value::Dscp),
+  field(22, 4, Value::StartUptimeMs),
+  field(21, 4, Value::EndUptimeMs),
+  field(136, 1, Value::EndReason),
+  field(FIELD_ECN | 0x8000, 1, Value::Ecn),
+  field(FIELD_CIRCUIT_ID | 0x8000, CIRCUIT_ID_LEN, Value::CircuitId),
+];
+
+fn template_record(id: u16, fields: &[Field]) -> Vec<u8> {
+  let mut out = Vec::new();
+  out.extend_from_slice(&id.to_be_bytes());
+  out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+  for f in fields.iter() {
+    out.extend_from_slice(&f.id.to_be_bytes());
+    out.extend_from_slice(&f.len.to_be_bytes());
+  }
+  out
+}
+
+/// Encodes `records` into NetFlow v9 export packets, preceded by the
+/// templates if `send_templates` is set. `sequence` counts the export
+/// packets sent so far.
+pub(super) fn encode(
+  records: &[FlowRecord],
+  send_templates: bool,
+  sequence: &mut u32,
+  clock: &ExportClock,
+) -> Vec<Vec<u8>> {
+  let mut builder = DatagramBuilder::new(HEADER_LEN, true);
+  if send_templates {
+    let v4 = template_record(TEMPLATE_IPV4, IPV4_FIELDS);
+    let v6 = template_record(TEMPLATE_IPV6, IPV6_FIELDS);
+    builder.add(TEMPLATE_FLOWSET_ID, &v4, false);
+    builder.add(TEMPLATE_FLOWSET_ID, &v6, false);
+  }
+  let mut buffer = Vec::new();
+  for record in records.iter() {
+    let template = record.template_id();
+    let fields =
+      if template == TEMPLATE_IPV4 { IPV4_FIELDS } else { IPV6_FIELDS };
+    buffer.clear();
+    write_record(record, fields, clock, &mut buffer);
+    builder.add(template, &buffer, true);
+  }
+
+  builder
+    .finish()
+    .into_iter()
+    .map(|mut datagram| {
+      let header = &mut datagram.body[..HEADER_LEN];
+      header[0..2].copy_from_slice(&VERSION.to_be_bytes());
+      header[2..4].copy_from_slice(&datagram.records.to_be_bytes());
+      header[4..8].copy_from_slice(
+        &ExportClock::uptime_ms_at(clock.boot_ns).to_be_bytes(),
+      );
+      header[8..12].copy_from_slice(&clock.unix_secs().to_be_bytes());
+      header[12..16].copy_from_slice(&sequence.to_be_bytes());
+      // Source ID (bytes 16..20) is left as 0
+      *sequence = sequence.wrapping_add(1);
+      datagram.body
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::export::test_support::{sets, test_clock, test_records};
+  use crate::export::MAX_DATAGRAM;
+
+  #[test]
+  fn encodes_templates() {
+    let mut sequence = 0;
+    let datagrams = encode(&[], true, &mut sequence, &test_clock());
+    assert_eq!(datagrams.len(), 1);
+    // Both templates count as records
+    assert_eq!(&datagrams[0][2..4], &2u16.to_be_bytes());
+    let sets = sets(&datagrams[0], HEADER_LEN);
+    assert_eq!(sets.len(), 1);
+    let (id, templates) = sets[0];
+    assert_eq!(id, TEMPLATE_FLOWSET_ID);
+    assert_eq!(&templates[0..2], &TEMPLATE_IPV4.to_be_bytes());
+    let fields = u16::from_be_bytes([templates[2], templates[3]]) as usize;
+    let v6 = &templates[4 + fields * 4..];
+    assert_eq!(&v6[0..2], &TEMPLATE_IPV6.to_be_bytes());
+    assert_eq!(sequence, 1);
+  }
+
+  #[test]
+  fn splits_records_across_packets() {
+    let mut sequence = 7;
+    let datagrams =
+      encode(&test_records(100), false, &mut sequence, &test_clock());
+    assert!(datagrams.len() > 1);
+    let mut records = 0;
+    for (i, datagram) in datagrams.iter().enumerate() {
+      assert!(datagram.len() <= MAX_DATAGRAM);
+      assert_eq!(&datagram[0..2], &VERSION.to_be_bytes());
+      assert_eq!(&datagram[8..12], &1_700_000_000u32.to_be_bytes());
+      assert_eq!(&datagram[12..16], &(7 + i as u32).to_be_bytes());
+
+      let count = u16::from_be_bytes([datagram[2], datagram[3]]) as usize;
+      let sets = sets(datagram, HEADER_LEN);
+      assert_eq!(sets.len(), 1);
+      let (id, data) = sets[0];
+      assert_eq!(id, TEMPLATE_IPV4);
+      // Flowsets are padded to a multiple of 4 bytes
+      assert_eq!((data.len() + 4) % 4, 0);
+      assert!(data.len() >= count);
+      records += count;
+    }
+    assert_eq!(records, 100);
+    assert_eq!(sequence, 7 + datagrams.len() as u32);
+  }
+}
@@ -1,10 +1,19 @@
-use crate::{timeline::expire_timeline, FLOW_EXPIRE_SECS};
+use crate::{
+  export::{export_expired_flows, flow_export_enabled},
+  timeline::expire_timeline,
+  FLOW_EXPIRE_SECS,
+};
 use dashmap::DashMap;
-use lqos_bus::{tos_parser, BusResponse, FlowTransport};
-use lqos_sys::bpf_per_cpu_map::BpfPerCpuMap;
+use lqos_bus::{tos_parser, BusResponse, FlowTransport, TcpFlowStats};
+use lqos_config::FlowConfig;
+use lqos_sys::{bpf_map::BpfMap, bpf_per_cpu_map::BpfPerCpuMap};
 use lqos_utils::{unix_time::time_since_boot, XdpIpAddress};
 use once_cell::sync::Lazy;
-use std::{collections::HashSet, time::Duration};
+use std::{
+  collections::HashSet,
+  sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+  time::Duration,
+};
 
 /// Representation of the eBPF `heimdall_key` type.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
@@ -38,21 +47,96 @@ pub struct HeimdallData {
   pub reserved: [u8; 3],
 }
 
+/// Number of recent RTT samples kept per flow by the kernel.
+const HEIMDALL_RTT_SAMPLES: usize = 16;
+
+/// Mapped representation of the eBPF `heimdall_tcp_data` type.
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct HeimdallTcpData {
+  /// When the last TSval was sent, in nanoseconds since boot (0 once
+  /// echoed)
+  pub tsval_time: u64,
+  /// When the highest sequence number last advanced
+  pub next_seq_time: u64,
+  /// Most recent TSval sent on this flow
+  pub tsval: u32,
+  /// Sequence number following the highest one seen
+  pub next_seq: u32,
+  /// Smallest RTT seen, in microseconds
+  pub rtt_min_us: u32,
+  /// Largest RTT seen, in microseconds
+  pub rtt_max_us: u32,
+  /// Ring buffer of recent RTT samples, in microseconds
+  pub rtt_samples_us: [u32; HEIMDALL_RTT_SAMPLES],
+  /// Total number of RTT samples taken
+  pub rtt_count: u32,
+  /// Retransmitted data segments
+  pub retransmits: u32,
+  /// Out-of-order data segments
+  pub out_of_order: u32,
+  /// Zero-window advertisements
+  pub zero_windows: u32,
+}
+
+impl From<&HeimdallTcpData> for TcpFlowStats {
+  fn from(tcp: &HeimdallTcpData) -> Self {
+    let n = usize::min(tcp.rtt_count as usize, HEIMDALL_RTT_SAMPLES);
+    let mut samples = tcp.rtt_samples_us[..n].to_vec();
+    samples.sort_unstable();
+    let median = if n == 0 { 0 } else { samples[n / 2] };
+    Self {
+      rtt_min_ms: tcp.rtt_min_us as f32 / 1000.0,
+      rtt_median_ms: median as f32 / 1000.0,
+      rtt_max_ms: tcp.rtt_max_us as f32 / 1000.0,
+      rtt_samples: tcp.rtt_count,
+      retransmits: tcp.retransmits,
+      out_of_order: tcp.out_of_order,
+      zero_windows: tcp.zero_windows,
+    }
+  }
+}
+
+/// Identifies a single (one-directional) flow tracked by Heimdall.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct FlowKey {
-  src: XdpIpAddress,
-  dst: XdpIpAddress,
-  proto: u8,
-  src_port: u16,
-  dst_port: u16,
+pub struct FlowKey {
+  /// Source IP address
+  pub src: XdpIpAddress,
+  /// Destination IP address
+  pub dst: XdpIpAddress,
+  /// IP protocol (see the Linux kernel!)
+  pub proto: u8,
+  /// Source port number, or ICMP type.
+  pub src_port: u16,
+  /// Destination port number.
+  pub dst_port: u16,
 }
 
+/// Counters for a flow tracked by Heimdall.
 #[derive(Clone, Debug, Default)]
-struct FlowData {
-  last_seen: u64,
-  bytes: u64,
-  packets: u64,
-  tos: u8,
+pub struct FlowData {
+  /// Last seen, in nanoseconds (since boot time).
+  pub last_seen: u64,
+  /// Number of bytes since the flow started being tracked
+  pub bytes: u64,
+  /// Number of packets since the flow started being tracked
+  pub packets: u64,
+  /// IP header TOS value
+  pub tos: u8,
+  /// Bytes seen during the last read cycle (one second)
+  pub bytes_per_second: u64,
+  /// When Heimdall started tracking the flow, in nanoseconds (since
+  /// boot time).
+  pub first_seen: u64,
+  /// Byte count at the last flow export.
+  pub exported_bytes: u64,
+  /// Packet count at the last flow export.
+  pub exported_packets: u64,
+  /// When the flow was last exported (or first seen), in nanoseconds
+  /// (since boot time).
+  pub exported_at: u64,
+  /// TCP analytics, if any have been gathered for the flow.
+  pub tcp: Option<TcpFlowStats>,
 }
 
 impl From<&HeimdallKey> for FlowKey {
@@ -67,8 +151,59 @@ impl From<&HeimdallKey> for FlowKey {
   }
 }
 
+impl From<&FlowKey> for HeimdallKey {
+  fn from(value: &FlowKey) -> Self {
+    // The kernel zeroes its keys - padding included - and compares them
+    // byte for byte, so the padding after `ip_protocol` must be zero.
+    let mut key: HeimdallKey = unsafe { std::mem::zeroed() };
+    key.src_ip = value.src;
+    key.dst_ip = value.dst;
+    key.ip_protocol = value.proto;
+    key.src_port = value.src_port;
+    key.dst_port = value.dst_port;
+    key
+  }
+}
+
 static FLOW_DATA: Lazy<DashMap<FlowKey, FlowData>> = Lazy::new(DashMap::new);
 
+/// "1 in N" sampling rate for tracking all shaped hosts. 0 = only
+/// track watched hosts.
+static FLOW_SAMPLE_RATE: AtomicU32 = AtomicU32::new(0);
+
+/// Upper bound on the number of flows held in `FLOW_DATA`.
+static MAX_FLOWS: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Apply the `[flows]` section of `/etc/lqos.conf`. Call this before
+/// setting the Heimdall mode, which passes the sample rate to the kernel.
+pub(crate) fn configure_flow_tracking(config: Option<&FlowConfig>) {
+  match config {
+    Some(cfg) if cfg.track_all_hosts => {
+      let sample_rate = u32::max(cfg.sample_rate, 1);
+      log::info!(
+        "Heimdall is tracking flows for all shaped hosts, sampling 1 in {sample_rate} packets, up to {} flows.",
+        cfg.max_flows
+      );
+      FLOW_SAMPLE_RATE.store(sample_rate, Ordering::Relaxed);
+      MAX_FLOWS.store(cfg.max_flows, Ordering::Relaxed);
+    }
+    _ => {
+      FLOW_SAMPLE_RATE.store(0, Ordering::Relaxed);
+      MAX_FLOWS.store(usize::MAX, Ordering::Relaxed);
+    }
+  }
+}
+
+pub(crate) fn flow_sample_rate() -> u32 {
+  FLOW_SAMPLE_RATE.load(Ordering::Relaxed)
+}
+
+/// Returns `true` if Heimdall is tracking flows for all shaped hosts,
+/// rather than just the watched ones.
+pub fn tracking_all_flows() -> bool {
+  flow_sample_rate() > 0
+}
+
 /*pub(crate) fn record_flow(event: &HeimdallEvent) {
   let key: FlowKey = event.into();
   if let Some(mut data) = FLOW_DATA.get_mut(&key) {
@@ -119,37 +254,114 @@ fn combine_flows(values: &[HeimdallData]) -> FlowData {
 }
 
 pub fn read_flows() {
+  let max_flows = MAX_FLOWS.load(Ordering::Relaxed);
   heimdall_for_each(&mut |key, value| {
     let flow_key = key.into();
     let combined = combine_flows(value);
     if let Some(mut flow) = FLOW_DATA.get_mut(&flow_key) {
+      flow.bytes_per_second = combined.bytes.saturating_sub(flow.bytes);
       flow.last_seen = combined.last_seen;
       flow.bytes = combined.bytes;
       flow.packets = combined.packets;
       flow.tos = combined.tos;
-    } else {
-      FLOW_DATA.insert(flow_key, combined);
+    } else if FLOW_DATA.len() < max_flows {
+      FLOW_DATA.insert(
+        flow_key,
+        FlowData {
+          first_seen: combined.last_seen,
+          exported_at: combined.last_seen,
+          ..combined
+        },
+      );
     }
   });
+  read_tcp_stats();
+}
+
+fn read_tcp_stats() {
+  if let Ok(tcp) = BpfMap::<HeimdallKey, HeimdallTcpData>::from_path(
+    "/sys/fs/bpf/heimdall_tcp",
+  ) {
+    tcp.for_each(&mut |key, value| {
+      if let Some(mut flow) = FLOW_DATA.get_mut(&key.into()) {
+        flow.tcp = Some(value.into());
+      }
+    });
+  }
+}
+
+/// Calls `callback` for every flow Heimdall is currently tracking,
+/// allowing it to modify the flow's data.
+pub(crate) fn for_each_flow_mut(mut callback: impl FnMut(&FlowKey, &mut FlowData)) {
+  FLOW_DATA.iter_mut().for_each(|mut f| {
+    let (key, value) = f.pair_mut();
+    callback(key, value)
+  });
+}
+
+/// Calls `callback` for every flow Heimdall is currently tracking.
+pub fn for_each_flow(mut callback: impl FnMut(&FlowKey, &FlowData)) {
+  FLOW_DATA.iter().for_each(|f| callback(f.key(), f.value()));
 }
 
 pub fn expire_heimdall_flows() {
   if let Ok(now) = time_since_boot() {
     let since_boot = Duration::from(now);
     let expire = (since_boot - Duration::from_secs(FLOW_EXPIRE_SECS)).as_nanos() as u64;
-    FLOW_DATA.retain(|_k, v| v.last_seen > expire);
+    let mut expired = Vec::new();
+    FLOW_DATA.retain(|k, v| {
+      let keep = v.last_seen > expire;
+      if !keep {
+        expired.push((k.clone(), v.clone()));
+      }
+      keep
+    });
+    if flow_export_enabled() {
+      export_expired_flows(&expired);
+    }
+    delete_kernel_flows(expired.iter().map(|(k, _)| k));
     expire_timeline();
   }
 }
 
+/// Removes expired flows from the kernel's maps, too. Otherwise
+/// `read_flows` would find them again on its next pass, and they would
+/// be tracked - and exported - all over again, counters and all.
+fn delete_kernel_flows<'a>(expired: impl Iterator<Item = &'a FlowKey>) {
+  let mut keys: Vec<HeimdallKey> = expired.map(HeimdallKey::from).collect();
+  if keys.is_empty() {
+    return;
+  }
+  if let Ok(mut heimdall) = BpfPerCpuMap::<HeimdallKey, HeimdallData>::from_path(
+    "/sys/fs/bpf/heimdall",
+  ) {
+    keys.iter_mut().for_each(|key| {
+      let _ = heimdall.delete(key);
+    });
+  }
+  if let Ok(mut tcp) = BpfMap::<HeimdallKey, HeimdallTcpData>::from_path(
+    "/sys/fs/bpf/heimdall_tcp",
+  ) {
+    keys.iter_mut().for_each(|key| {
+      let _ = tcp.delete(key);
+    });
+  }
+}
+
 pub fn get_flow_stats(ip: XdpIpAddress) -> BusResponse {
+  get_flow_stats_matching(|key| key.src == ip || key.dst == ip)
+}
+
+/// Returns all tracked flows for which `filter` returns `true`, paired
+/// up with their reciprocal flows (if any) and sorted by bytes.
+pub fn get_flow_stats_matching(filter: impl Fn(&FlowKey) -> bool) -> BusResponse {
   let mut result = Vec::new();
 
   // Obtain all the flows
   let mut all_flows = Vec::new();
   for value in FLOW_DATA.iter() {
     let key = value.key();
-    if key.src == ip || key.dst == ip {
+    if filter(key) {
       let (dscp, ecn) = tos_parser(value.tos);
       all_flows.push(FlowTransport {
         src: key.src.as_ip().to_string(),
@@ -165,6 +377,7 @@ pub fn get_flow_stats(ip: XdpIpAddress) -> BusResponse {
         packets: value.packets,
         dscp,
         ecn,
+        tcp: value.tcp.clone(),
       });
     }
   }
@@ -192,3 +405,57 @@ pub fn get_flow_stats(ip: XdpIpAddress) -> BusResponse {
 
   BusResponse::FlowData(result)
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn tcp_data(samples: &[u32], rtt_count: u32) -> HeimdallTcpData {
+    let mut tcp = HeimdallTcpData {
+      rtt_min_us: 1500,
+      rtt_max_us: 90_000,
+      rtt_count,
+      retransmits: 3,
+      out_of_order: 2,
+      zero_windows: 1,
+      ..Default::default()
+    };
+    tcp.rtt_samples_us[..samples.len()].copy_from_slice(samples);
+    tcp
+  }
+
+  #[test]
+  fn median_of_recent_samples() {
+    let stats = TcpFlowStats::from(&tcp_data(&[9000, 1500, 4000], 3));
+    assert_eq!(stats.rtt_median_ms, 4.0);
+    assert_eq!(stats.rtt_min_ms, 1.5);
+    assert_eq!(stats.rtt_max_ms, 90.0);
+    assert_eq!(stats.rtt_samples, 3);
+
+    // Slots past rtt_count haven't been filled yet, and are ignored
+    let stats = TcpFlowStats::from(&tcp_data(&[2000, 6000], 2));
+    assert_eq!(stats.rtt_median_ms, 6.0);
+
+    let stats = TcpFlowStats::from(&tcp_data(&[], 0));
+    assert_eq!(stats.rtt_median_ms, 0.0);
+  }
+
+  #[test]
+  fn median_once_the_ring_has_wrapped() {
+    let samples: Vec<u32> = (1..=HEIMDALL_RTT_SAMPLES as u32)
+      .rev()
+      .map(|ms| ms * 1000)
+      .collect();
+    let stats = TcpFlowStats::from(&tcp_data(&samples, 1000));
+    assert_eq!(stats.rtt_median_ms, (HEIMDALL_RTT_SAMPLES / 2 + 1) as f32);
+    assert_eq!(stats.rtt_samples, 1000);
+  }
+
+  #[test]
+  fn counts_tcp_problems() {
+    let stats = TcpFlowStats::from(&tcp_data(&[1000], 1));
+    assert_eq!(stats.retransmits, 3);
+    assert_eq!(stats.out_of_order, 2);
+    assert_eq!(stats.zero_windows, 1);
+  }
+}
@@ -7,15 +7,27 @@ pub mod perf_interface;
 pub mod stats;
 pub use config::{HeimdalConfig, HeimdallMode};
 mod flows;
-pub use flows::{expire_heimdall_flows, get_flow_stats};
+pub use flows::{
+  expire_heimdall_flows, for_each_flow, get_flow_stats,
+  get_flow_stats_matching, tracking_all_flows, FlowData, FlowKey,
+};
+mod export;
+pub use export::set_circuit_lookup;
 mod timeline;
 pub use timeline::{n_second_packet_dump, n_second_pcap, hyperfocus_on_target};
 mod pcap;
+mod stream;
+pub use stream::stream_packets;
 mod watchlist;
 use lqos_utils::fdtimer::periodic;
 pub use watchlist::{heimdall_expire, heimdall_watch_ip, set_heimdall_mode};
 
-use crate::flows::read_flows;
+use crate::{
+  export::{configure_flow_export, export_active_flows},
+  flows::{configure_flow_tracking, read_flows},
+  perf_interface::DEFAULT_SNAPLEN,
+};
+use lqos_config::EtcLqos;
 
 /// How long should Heimdall keep watching a flow after being requested
 /// to do so? Setting this to a long period increases CPU load after the
@@ -35,7 +47,11 @@ const SESSION_EXPIRE_SECONDS: u64 = 600;
 /// Interface to running Heimdall (start this when lqosd starts)
 /// This is async to match the other spawning systems.
 pub async fn start_heimdall() {
-  if set_heimdall_mode(HeimdallMode::WatchOnly).is_err() {
+  if let Ok(cfg) = EtcLqos::load() {
+    configure_flow_tracking(cfg.flows.as_ref());
+    configure_flow_export(cfg.flow_export.as_ref());
+  }
+  if set_heimdall_mode(HeimdallMode::WatchOnly, DEFAULT_SNAPLEN).is_err() {
     log::error!(
       "Unable to set Heimdall Mode. Packet watching will be unavailable."
     );
@@ -48,6 +64,7 @@ pub async fn start_heimdall() {
   std::thread::spawn(move || {
     periodic(interval_ms, "Heimdall Packet Watcher", &mut || {
       read_flows();
+      export_active_flows();
       expire_heimdall_flows();
       heimdall_expire();
     });
@@ -1,6 +1,6 @@
 use std::time::Duration;
 use zerocopy::AsBytes;
-use crate::perf_interface::{HeimdallEvent, PACKET_OCTET_SIZE};
+use crate::perf_interface::HeimdallEvent;
 
 #[derive(AsBytes)]
 #[repr(C)]
@@ -15,14 +15,14 @@ pub(crate) struct PcapFileHeader {
 }
 
 impl PcapFileHeader {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(snaplen: u32) -> Self {
         Self {
             magic: 0xa1b2c3d4,
             version_major: 2,
             version_minor: 4,
             thiszone: 0,
             sigfigs: 0,
-            snaplen: PACKET_OCTET_SIZE as u32,
+            snaplen,
             link_type: 1,
         }
     }
@@ -43,8 +43,19 @@ impl PcapPacketHeader {
         Self {
             ts_sec: timestamp_nanos.as_secs() as u32,
             ts_usec: timestamp_nanos.subsec_micros(),
-            inc_len: u32::min(PACKET_OCTET_SIZE as u32, event.size),
+            inc_len: u32::min(event.packet_data.len() as u32, event.size),
             orig_len: event.size
         }
     }
-}
\ No newline at end of file
+}
+
+/// Builds a pcap record (packet header, followed by the captured octets)
+/// for a Heimdall event.
+pub(crate) fn pcap_record(event: &HeimdallEvent) -> Vec<u8> {
+    let header = PcapPacketHeader::from_heimdall(event);
+    let len = usize::min(event.packet_data.len(), event.size as usize);
+    let mut record = Vec::with_capacity(std::mem::size_of::<PcapPacketHeader>() + len);
+    record.extend_from_slice(header.as_bytes());
+    record.extend_from_slice(&event.packet_data[0 .. len]);
+    record
+}
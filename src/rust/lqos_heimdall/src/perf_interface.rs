@@ -3,11 +3,39 @@ use lqos_utils::XdpIpAddress;
 use zerocopy::FromBytes;
 use crate::timeline::store_on_timeline;
 
-/// This constant MUST exactly match PACKET_OCTET_STATE in heimdall.h
-pub(crate) const PACKET_OCTET_SIZE: usize = 128;
+/// This constant MUST exactly match PACKET_OCTET_SIZE in heimdall.h.
+/// It is the largest snap length a capture session may request.
+pub const PACKET_OCTET_SIZE: usize = 1522;
 
-#[derive(FromBytes, Debug, Clone, PartialEq, Eq, Hash)]
+/// Snap length used when a capture session doesn't ask for one.
+pub const DEFAULT_SNAPLEN: u32 = 128;
+
+/// The smallest snap length a capture session may use: enough for an
+/// Ethernet header, an IPv6 header and a TCP header without options.
+/// Anything shorter can't show which flow a packet belongs to.
+pub const MIN_SNAPLEN: u32 = 14 + 40 + 20;
+
+/// The fixed-size portion of the eBPF `heimdall_event` type. The kernel
+/// only sends as many `dump` octets as the session's snap length, so
+/// the packet data is read separately.
+#[derive(FromBytes, Debug, Clone)]
 #[repr(C)]
+struct HeimdallEventHeader {
+  timestamp: u64,
+  src: XdpIpAddress,
+  dst: XdpIpAddress,
+  src_port : u16,
+  dst_port: u16,
+  ip_protocol: u8,
+  tos: u8,
+  size: u32,
+  tcp_flags: u8,
+  tcp_window: u16,
+  tcp_tsval: u32,
+  tcp_tsecr: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct HeimdallEvent {
   pub timestamp: u64,
   pub src: XdpIpAddress,
@@ -21,7 +49,28 @@ pub struct HeimdallEvent {
   pub tcp_window: u16,
   pub tcp_tsval: u32,
   pub tcp_tsecr: u32,
-  pub packet_data: [u8; PACKET_OCTET_SIZE],
+  /// Captured packet octets, up to the session's snap length.
+  pub packet_data: Vec<u8>,
+}
+
+impl HeimdallEvent {
+  fn new(header: HeimdallEventHeader, packet_data: &[u8]) -> Self {
+    Self {
+      timestamp: header.timestamp,
+      src: header.src,
+      dst: header.dst,
+      src_port: header.src_port,
+      dst_port: header.dst_port,
+      ip_protocol: header.ip_protocol,
+      tos: header.tos,
+      size: header.size,
+      tcp_flags: header.tcp_flags,
+      tcp_window: header.tcp_window,
+      tcp_tsval: header.tcp_tsval,
+      tcp_tsecr: header.tcp_tsecr,
+      packet_data: packet_data.to_vec(),
+    }
+  }
 }
 
 /*
@@ -49,18 +98,19 @@ pub unsafe extern "C" fn heimdall_handle_events(
   data: *mut c_void,
   data_size: usize,
 ) -> i32 {
-  const EVENT_SIZE: usize = std::mem::size_of::<HeimdallEvent>();
-  if data_size < EVENT_SIZE {
+  const HEADER_SIZE: usize = std::mem::size_of::<HeimdallEventHeader>();
+  if data_size < HEADER_SIZE {
     log::warn!("Warning: incoming data too small in Heimdall buffer");
     return 0;
   }
 
   //COLLECTED_EVENTS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
   let data_u8 = data as *const u8;
-  let data_slice : &[u8] = slice::from_raw_parts(data_u8, EVENT_SIZE);
+  let data_size = usize::min(data_size, HEADER_SIZE + PACKET_OCTET_SIZE);
+  let data_slice : &[u8] = slice::from_raw_parts(data_u8, data_size);
 
-  if let Some(incoming) = HeimdallEvent::read_from(data_slice) {
-    store_on_timeline(incoming);
+  if let Some(header) = HeimdallEventHeader::read_from(&data_slice[..HEADER_SIZE]) {
+    store_on_timeline(HeimdallEvent::new(header, &data_slice[HEADER_SIZE..]));
   } else {
     println!("Failed to decode");
   }
@@ -0,0 +1,103 @@
+//! Live packet streaming. Rather than collecting packets on the
+//! timeline and handing them back as a pcap file once the session ends,
+//! a stream forwards each packet to a bus client as soon as Heimdall
+//! receives it from the kernel.
+
+use crate::{
+  heimdall_watch_ip,
+  pcap::{pcap_record, PcapFileHeader},
+  perf_interface::{HeimdallEvent, DEFAULT_SNAPLEN},
+  set_heimdall_mode,
+  timeline::{capture_settings, FOCUS_SESSION_ID, HYPERFOCUSED},
+  HeimdallMode,
+};
+use dashmap::DashMap;
+use lqos_bus::{BusResponse, BusStreamReceiver, BusStreamSender};
+use lqos_utils::XdpIpAddress;
+use once_cell::sync::Lazy;
+use std::{sync::atomic::Ordering, time::Duration};
+use zerocopy::AsBytes;
+
+/// How many packets may be queued for a stream client before further
+/// packets are dropped. Slow clients lose packets rather than slowing
+/// down Heimdall.
+const STREAM_BUFFER_PACKETS: usize = 4096;
+
+struct LiveStream {
+  target: XdpIpAddress,
+  tx: BusStreamSender,
+}
+
+static LIVE_STREAMS: Lazy<DashMap<usize, LiveStream>> =
+  Lazy::new(DashMap::new);
+
+/// Start streaming packets for an IP address. Heimdall stays in Analysis
+/// mode until the returned receiver is dropped (which the bus server does
+/// when the client disconnects).
+///
+/// This shares the capture slot with `hyperfocus_on_target`, so only one
+/// capture or stream may run at a time.
+///
+/// ## Arguments
+///
+/// * `ip` - the IP address to watch.
+/// * `snaplen` - how many octets of each packet to capture. See
+///   `hyperfocus_on_target` for defaults.
+///
+/// ## Returns
+///
+/// * `None` if Heimdall is busy, or...
+/// * A receiver of `BusResponse::PcapStream` messages. The first holds
+///   the pcap file header, each following message holds one packet.
+pub fn stream_packets(
+  ip: XdpIpAddress,
+  snaplen: Option<u32>,
+) -> Option<BusStreamReceiver> {
+  if HYPERFOCUSED.compare_exchange(
+    false,
+    true,
+    Ordering::Relaxed,
+    Ordering::Relaxed,
+  ) != Ok(false)
+  {
+    log::warn!("Heimdall was busy and won't start a live stream.");
+    return None;
+  }
+
+  let (_, snaplen) = capture_settings(snaplen);
+  let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_PACKETS);
+  let header = PcapFileHeader::new(snaplen);
+  let _ = tx.try_send(BusResponse::PcapStream(header.as_bytes().to_vec()));
+
+  let id = FOCUS_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+  LIVE_STREAMS.insert(id, LiveStream { target: ip, tx });
+  log::info!("Heimdall is streaming packets for {}", ip.as_ip().to_string());
+
+  std::thread::spawn(move || {
+    while LIVE_STREAMS.get(&id).map(|s| !s.tx.is_closed()).unwrap_or(false) {
+      let _ = set_heimdall_mode(HeimdallMode::Analysis, snaplen);
+      heimdall_watch_ip(ip);
+      std::thread::sleep(Duration::from_secs(1));
+    }
+    LIVE_STREAMS.remove(&id);
+    let _ = set_heimdall_mode(HeimdallMode::WatchOnly, DEFAULT_SNAPLEN);
+    log::info!("Heimdall stopped streaming packets for {}", ip.as_ip().to_string());
+    HYPERFOCUSED.store(false, Ordering::Relaxed);
+  });
+
+  Some(rx)
+}
+
+/// Forward a freshly received event to any live streams watching
+/// either end of it.
+pub(crate) fn publish_to_streams(event: &HeimdallEvent) {
+  if LIVE_STREAMS.is_empty() {
+    return;
+  }
+  for stream in LIVE_STREAMS.iter() {
+    if stream.target == event.src || stream.target == event.dst {
+      // A full buffer means the client isn't keeping up; drop the packet.
+      let _ = stream.tx.try_send(BusResponse::PcapStream(pcap_record(event)));
+    }
+  }
+}
@@ -1,9 +1,11 @@
 use crate::{
   heimdall_watch_ip,
-  pcap::{PcapFileHeader, PcapPacketHeader},
-  perf_interface::{HeimdallEvent, PACKET_OCTET_SIZE},
-  set_heimdall_mode, HeimdallMode, SESSION_EXPIRE_SECONDS,
-  TIMELINE_EXPIRE_SECS,
+  pcap::{pcap_record, PcapFileHeader},
+  perf_interface::{
+    HeimdallEvent, DEFAULT_SNAPLEN, MIN_SNAPLEN, PACKET_OCTET_SIZE,
+  },
+  set_heimdall_mode, stream::publish_to_streams, HeimdallMode,
+  SESSION_EXPIRE_SECONDS, TIMELINE_EXPIRE_SECS,
 };
 use dashmap::{DashMap, DashSet};
 use lqos_bus::{tos_parser, PacketHeader};
@@ -53,6 +55,7 @@ impl Timeline {
 static TIMELINE: Lazy<Timeline> = Lazy::new(Timeline::new);
 
 pub(crate) fn store_on_timeline(event: HeimdallEvent) {
+  publish_to_streams(&event);
   TIMELINE.data.insert(event); // We're moving here deliberately
 }
 
@@ -66,8 +69,29 @@ pub(crate) fn expire_timeline() {
   }
 }
 
+/// Determines the capture time (in seconds) and snap length for a
+/// capture session. If explicitly set in `/etc/lqos.conf`, those values
+/// are used. Otherwise, default to a reasonable 10 seconds and 128 octets.
+/// A snap length requested by the caller takes precedence. Either way, it
+/// is clamped between `MIN_SNAPLEN` and `PACKET_OCTET_SIZE`.
+pub(crate) fn capture_settings(snaplen: Option<u32>) -> (usize, u32) {
+  let (capture_time, default_snaplen) = if let Ok(cfg) = EtcLqos::load() {
+    (
+      cfg.packet_capture_time.unwrap_or(10),
+      cfg.packet_capture_snaplen.unwrap_or(DEFAULT_SNAPLEN),
+    )
+  } else {
+    (10, DEFAULT_SNAPLEN)
+  };
+  let snaplen = snaplen
+    .unwrap_or(default_snaplen)
+    .clamp(MIN_SNAPLEN, PACKET_OCTET_SIZE as u32);
+  (capture_time, snaplen)
+}
+
 struct FocusSession {
   expire: u64,
+  snaplen: u32,
   data: DashSet<HeimdallEvent>,
   dump_filename: Option<String>,
 }
@@ -83,8 +107,10 @@ impl Drop for FocusSession {
   }
 }
 
-static HYPERFOCUSED: AtomicBool = AtomicBool::new(false);
-static FOCUS_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
+/// Set while a capture session (or live stream) has Heimdall in Analysis
+/// mode. Only one may run at a time.
+pub(crate) static HYPERFOCUSED: AtomicBool = AtomicBool::new(false);
+pub(crate) static FOCUS_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
 static FOCUS_SESSIONS: Lazy<DashMap<usize, FocusSession>> =
   Lazy::new(DashMap::new);
 
@@ -96,11 +122,22 @@ static FOCUS_SESSIONS: Lazy<DashMap<usize, FocusSession>> =
 ///
 /// You can only do this on one target at a time.
 ///
+/// ## Arguments
+///
+/// * `ip` - the IP address to watch.
+/// * `snaplen` - how many octets of each packet to capture. If `None`,
+///   the `packet_capture_snaplen` setting from `/etc/lqos.conf` is used,
+///   falling back to `DEFAULT_SNAPLEN`. Clamped between `MIN_SNAPLEN`
+///   and `PACKET_OCTET_SIZE`.
+///
 /// ## Returns
 ///
 /// * Either `None` or...
 /// * The id number of the collection session for analysis.
-pub fn hyperfocus_on_target(ip: XdpIpAddress) -> Option<(usize, usize)> {
+pub fn hyperfocus_on_target(
+  ip: XdpIpAddress,
+  snaplen: Option<u32>,
+) -> Option<(usize, usize)> {
   if HYPERFOCUSED.compare_exchange(
     false,
     true,
@@ -108,22 +145,16 @@ pub fn hyperfocus_on_target(ip: XdpIpAddress) -> Option<(usize, usize)> {
     std::sync::atomic::Ordering::Relaxed,
   ) == Ok(false)
   {
-    // If explicitly set, obtain the capture time. Otherwise, default to
-    // a reasonable 10 seconds.
-    let capture_time = if let Ok(cfg) = EtcLqos::load() {
-      cfg.packet_capture_time.unwrap_or(10)
-    } else {
-      10
-    };
+    let (capture_time, snaplen) = capture_settings(snaplen);
     let new_id =
       FOCUS_SESSION_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
     std::thread::spawn(move || {
       for _ in 0..capture_time {
-        let _ = set_heimdall_mode(HeimdallMode::Analysis);
+        let _ = set_heimdall_mode(HeimdallMode::Analysis, snaplen);
         heimdall_watch_ip(ip);
         std::thread::sleep(Duration::from_secs(1));
       }
-      let _ = set_heimdall_mode(HeimdallMode::WatchOnly);
+      let _ = set_heimdall_mode(HeimdallMode::WatchOnly, DEFAULT_SNAPLEN);
 
       if let Ok(now) = time_since_boot() {
         let since_boot = Duration::from(now);
@@ -133,6 +164,7 @@ pub fn hyperfocus_on_target(ip: XdpIpAddress) -> Option<(usize, usize)> {
           new_id,
           FocusSession {
             expire,
+            snaplen,
             data: TIMELINE.data.clone(),
             dump_filename: None,
           },
@@ -165,21 +197,15 @@ pub fn n_second_pcap(session_id: usize) -> Option<String> {
     let path = Path::new(&filename);
     let mut out = File::create(path).expect("Unable to create {filename}");
     out
-      .write_all(PcapFileHeader::new().as_bytes())
+      .write_all(PcapFileHeader::new(session.snaplen).as_bytes())
       .expect("Unable to write to {filename}");
 
     session
     .data
     .iter()
-    .map(|e| (e.packet_data, e.size, PcapPacketHeader::from_heimdall(&e)))
     .for_each(
-      |(data, size, p)| {
-        out.write_all(p.as_bytes()).expect("Unable to write to {filename}");
-        if size < PACKET_OCTET_SIZE as u32 {
-          out.write_all(&data[0 .. size as usize]).unwrap();
-        } else {
-          out.write_all(&data).unwrap();
-        }
+      |e| {
+        out.write_all(&pcap_record(&e)).expect("Unable to write to {filename}");
       },
     );
 
@@ -188,3 +214,16 @@ pub fn n_second_pcap(session_id: usize) -> Option<String> {
     None
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn snaplen_is_clamped() {
+    assert_eq!(capture_settings(Some(0)).1, MIN_SNAPLEN);
+    assert_eq!(capture_settings(Some(MIN_SNAPLEN - 1)).1, MIN_SNAPLEN);
+    assert_eq!(capture_settings(Some(256)).1, 256);
+    assert_eq!(capture_settings(Some(9000)).1, PACKET_OCTET_SIZE as u32);
+  }
+}
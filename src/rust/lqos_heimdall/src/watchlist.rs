@@ -1,4 +1,7 @@
-use crate::{HeimdalConfig, HeimdallMode, EXPIRE_WATCHES_SECS};
+use crate::{
+  flows::flow_sample_rate, perf_interface::PACKET_OCTET_SIZE, HeimdalConfig,
+  HeimdallMode, EXPIRE_WATCHES_SECS,
+};
 use dashmap::DashMap;
 use lqos_sys::bpf_map::BpfMap;
 use lqos_utils::{unix_time::time_since_boot, XdpIpAddress};
@@ -9,9 +12,20 @@ const HEIMDALL_CFG_PATH: &str = "/sys/fs/bpf/heimdall_config";
 const HEIMDALL_WATCH_PATH: &str = "/sys/fs/bpf/heimdall_watching";
 
 /// Change the eBPF Heimdall System mode.
-pub fn set_heimdall_mode(mode: HeimdallMode) -> anyhow::Result<()> {
+///
+/// ## Arguments
+///
+/// * `mode` - the new operation mode.
+/// * `snaplen` - how many octets of each packet to capture in
+///   `Analysis` mode. Clamped to `PACKET_OCTET_SIZE`.
+pub fn set_heimdall_mode(mode: HeimdallMode, snaplen: u32) -> anyhow::Result<()> {
   let mut map = BpfMap::<u32, HeimdalConfig>::from_path(HEIMDALL_CFG_PATH)?;
-  map.insert_or_update(&mut 0, &mut HeimdalConfig { mode: mode as u32 })?;
+  let snaplen = u32::min(snaplen, PACKET_OCTET_SIZE as u32);
+  let sample_rate = flow_sample_rate();
+  map.insert_or_update(
+    &mut 0,
+    &mut HeimdalConfig { mode: mode as u32, snaplen, sample_rate },
+  )?;
   Ok(())
 }
 
@@ -1,11 +1,11 @@
-use std::sync::Mutex;
+use std::{net::IpAddr, sync::Mutex};
 
 use anyhow::Error;
-use lqos_config::{UserRole, WebUsers};
+use lqos_config::{ApiScope, AuthenticationError, UserRole, WebUsers};
 use once_cell::sync::Lazy;
 use rocket::serde::{json::Json, Deserialize, Serialize};
 use rocket::{
-  http::{Cookie, CookieJar, Status},
+  http::{Cookie, CookieJar, SameSite, Status},
   request::{FromRequest, Outcome},
   Request,
 };
@@ -18,6 +18,51 @@ pub enum AuthGuard {
   Admin,
   ReadOnly,
   FirstUse,
+  /// An API key with the scope the route needs. `reload` is set if the
+  /// key may also reload LibreQoS.
+  ApiKey { reload: bool },
+}
+
+/// The scope an API key needs to use each route, by handler name.
+/// `None` means API keys can't use the route at all, which is the case
+/// for any route not listed here - new routes have to be opted in.
+fn scope_for_route(name: &str) -> Option<ApiScope> {
+  match name {
+    "current_throughput"
+    | "cpu_usage"
+    | "ram_usage"
+    | "top_10_downloaders"
+    | "worst_10_rtt"
+    | "rtt_histogram"
+    | "host_counts"
+    | "all_shaped_devices"
+    | "shaped_devices_count"
+    | "shaped_devices_range"
+    | "shaped_devices_search"
+    | "shaped_devices_validation"
+    | "all_unknown_devices"
+    | "unknown_devices_count"
+    | "unknown_devices_range"
+    | "unknown_devices_csv"
+    | "raw_queue_by_circuit"
+    | "circuit_info"
+    | "current_circuit_throughput"
+    | "watch_circuit"
+    | "flow_stats"
+    | "queue_history_json"
+    | "queue_history_csv"
+    | "circuit_flows"
+    | "top_applications"
+    | "top_destinations" => Some(ApiScope::ReadStats),
+    "shaped_devices_change" | "network_json_change" => {
+      Some(ApiScope::ManageDevices)
+    }
+    "reload_libreqos" => Some(ApiScope::Reload),
+    "request_analysis" | "packet_dump" | "pcap" => {
+      Some(ApiScope::CapturePackets)
+    }
+    _ => None,
+  }
 }
 
 #[rocket::async_trait]
@@ -38,7 +83,36 @@ impl<'r> FromRequest<'r> for AuthGuard {
       }
     }
 
-    if let Some(users) = &*lock {
+    if let Some(users) = &mut *lock {
+      // If lqusers.toml can't be re-read, keep using the copy we have.
+      let _ = users.reload_if_changed();
+      if let Some(key) = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|h| h.strip_prefix("Bearer "))
+      {
+        let scopes = match users.get_api_key_scopes(key.trim()) {
+          Ok(scopes) => scopes,
+          Err(_) => {
+            return Outcome::Failure((
+              Status::Unauthorized,
+              Error::msg("Invalid API key"),
+            ))
+          }
+        };
+        let route = request.route().and_then(|r| r.name.as_deref());
+        return match route.and_then(scope_for_route) {
+          Some(scope) if scopes.contains(&scope) => {
+            Outcome::Success(AuthGuard::ApiKey {
+              reload: scopes.contains(&ApiScope::Reload),
+            })
+          }
+          _ => Outcome::Failure((
+            Status::Forbidden,
+            Error::msg("API key lacks the scope for this route"),
+          )),
+        };
+      }
       if let Some(token) = request.cookies().get("User-Token") {
         match users.get_role_from_token(token.value()) {
           Ok(UserRole::Admin) => return Outcome::Success(AuthGuard::Admin),
@@ -64,7 +138,28 @@ impl<'r> FromRequest<'r> for AuthGuard {
   }
 }
 
-impl AuthGuard {}
+impl AuthGuard {
+  /// May the caller change devices or the network tree? Admins may,
+  /// and so may API keys that made it past the route's scope check.
+  pub fn can_change(&self) -> bool {
+    matches!(self, AuthGuard::Admin | AuthGuard::ApiKey { .. })
+  }
+
+  /// May the caller reload LibreQoS?
+  pub fn can_reload(&self) -> bool {
+    matches!(self, AuthGuard::Admin | AuthGuard::ApiKey { reload: true })
+  }
+}
+
+/// The session cookie. It can't be read by scripts, and is only sent
+/// with requests from the node manager's own pages.
+fn session_cookie(token: String) -> Cookie<'static> {
+  Cookie::build("User-Token", token)
+    .http_only(true)
+    .same_site(SameSite::Strict)
+    .path("/")
+    .finish()
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
@@ -85,10 +180,11 @@ pub fn create_first_user(
   let mut lock = WEB_USERS.lock().unwrap();
   let mut users = WebUsers::load_or_create().unwrap();
   users.allow_anonymous(info.allow_anonymous).unwrap();
-  let token = users
+  users
     .add_or_update_user(&info.username, &info.password, UserRole::Admin)
     .unwrap();
-  cookies.add(Cookie::new("User-Token", token));
+  let token = users.start_session(&info.username);
+  cookies.add(session_cookie(token));
   *lock = Some(users);
   Json("OK".to_string())
 }
@@ -101,15 +197,74 @@ pub struct LoginAttempt {
 }
 
 #[post("/api/login", data = "<info>")]
-pub fn login(cookies: &CookieJar, info: Json<LoginAttempt>) -> Json<String> {
-  let mut lock = WEB_USERS.lock().unwrap();
-  if lock.is_none() && WebUsers::does_users_file_exist().unwrap() {
-    *lock = Some(WebUsers::load_or_create().unwrap());
+pub async fn login(
+  cookies: &CookieJar<'_>,
+  client: Option<IpAddr>,
+  info: Json<LoginAttempt>,
+) -> Json<String> {
+  // Checking a password is slow, and may mean asking an LDAP or RADIUS
+  // server, so it happens on a blocking thread. WEB_USERS isn't held
+  // while an external server is asked, so a slow one can't stall every
+  // other request.
+  let client = client.map(|ip| ip.to_string()).unwrap_or_default();
+  let result = rocket::tokio::task::spawn_blocking(move || {
+    {
+      let mut lock = WEB_USERS.lock().unwrap();
+      if lock.is_none() && WebUsers::does_users_file_exist().unwrap() {
+        *lock = Some(WebUsers::load_or_create().unwrap());
+      }
+      match lock.as_ref() {
+        Some(users) => users.check_login_allowed(&info.username, &client)?,
+        None => return Err(AuthenticationError::InvalidLogin),
+      }
+    }
+    let external_role =
+      WebUsers::authenticate_externally(&info.username, &info.password);
+    let mut lock = WEB_USERS.lock().unwrap();
+    match lock.as_mut() {
+      Some(users) => users.finish_login(
+        &info.username,
+        &info.password,
+        &client,
+        external_role,
+      ),
+      None => Err(AuthenticationError::InvalidLogin),
+    }
+  })
+  .await;
+  match result {
+    Ok(Ok(token)) => {
+      cookies.add(session_cookie(token));
+      Json("OK".to_string())
+    }
+    Ok(Err(AuthenticationError::TooManyAttempts)) => {
+      Json("LOCKED".to_string())
+    }
+    _ => Json("ERROR".to_string()),
   }
-  if let Some(users) = &*lock {
-    if let Ok(token) = users.login(&info.username, &info.password) {
-      cookies.add(Cookie::new("User-Token", token));
-      return Json("OK".to_string());
+}
+
+#[post("/api/logout")]
+pub fn logout(cookies: &CookieJar) -> Json<String> {
+  if let Some(token) = cookies.get("User-Token") {
+    if let Some(users) = &mut *WEB_USERS.lock().unwrap() {
+      users.logout(token.value());
+    }
+  }
+  cookies.remove(Cookie::named("User-Token"));
+  Json("OK".to_string())
+}
+
+/// Logs the current user out everywhere, including this browser.
+#[post("/api/revoke_sessions")]
+pub fn revoke_sessions(_auth: AuthGuard, cookies: &CookieJar) -> Json<String> {
+  if let Some(token) = cookies.get("User-Token") {
+    if let Some(users) = &mut *WEB_USERS.lock().unwrap() {
+      let username = users.get_username(token.value());
+      if users.revoke_sessions(&username).is_ok() {
+        cookies.remove(Cookie::named("User-Token"));
+        return Json("OK".to_string());
+      }
     }
   }
   Json("ERROR".to_string())
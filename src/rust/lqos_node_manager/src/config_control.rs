@@ -53,7 +53,9 @@ pub async fn update_python_config(
   _auth: AuthGuard,
   config: Json<LibreQoSConfig>,
 ) -> Json<String> {
-  config.save().unwrap();
+  if config.save().is_err() {
+    return Json("ERROR".to_string());
+  }
   Json("OK".to_string())
 }
 
@@ -59,6 +59,8 @@ fn rocket() -> _ {
         shaped_devices::shaped_devices_search,
         shaped_devices::reload_required,
         shaped_devices::reload_libreqos,
+        shaped_devices::shaped_devices_validation,
+        shaped_devices::shaped_devices_change,
         unknown_devices::all_unknown_devices,
         unknown_devices::unknown_devices_count,
         unknown_devices::unknown_devices_range,
@@ -69,6 +71,11 @@ fn rocket() -> _ {
         queue_info::current_circuit_throughput,
         queue_info::watch_circuit,
         queue_info::flow_stats,
+        queue_info::queue_history_json,
+        queue_info::queue_history_csv,
+        queue_info::circuit_flows,
+        queue_info::top_applications,
+        queue_info::top_destinations,
         queue_info::packet_dump,
         queue_info::pcap,
         queue_info::request_analysis,
@@ -79,13 +86,18 @@ fn rocket() -> _ {
         config_control::update_lqos_tuning,
         auth_guard::create_first_user,
         auth_guard::login,
+        auth_guard::logout,
+        auth_guard::revoke_sessions,
         auth_guard::admin_check,
         static_pages::login_page,
         auth_guard::username,
         network_tree::tree_entry,
+        network_tree::tree_entry_by_id,
         network_tree::tree_clients,
         network_tree::network_tree_summary,
         network_tree::node_names,
+        network_tree::node_indices,
+        network_tree::network_json_change,
         network_tree::funnel_for_queue,
         config_control::stats,
         // Supporting files
@@ -1,13 +1,15 @@
 use std::net::IpAddr;
 
 use lqos_bus::{bus_request, BusRequest, BusResponse};
-use lqos_config::NetworkJsonTransport;
+use lqos_config::{NetworkJsonChange, NetworkJsonTransport};
 use rocket::{
   fs::NamedFile,
-  serde::{json::Json, Serialize, msgpack::MsgPack},
+  serde::{json::Json, Deserialize, Serialize, msgpack::MsgPack},
 };
 
-use crate::{cache_control::NoCache, tracker::SHAPED_DEVICES};
+use crate::{
+  auth_guard::AuthGuard, cache_control::NoCache, tracker::SHAPED_DEVICES,
+};
 
 // Note that NoCache can be replaced with a cache option
 // once the design work is complete.
@@ -30,6 +32,20 @@ pub async fn tree_entry(
   NoCache::new(MsgPack(result))
 }
 
+#[get("/api/network_tree_by_id/<id>")]
+pub async fn tree_entry_by_id(
+  id: String,
+) -> NoCache<MsgPack<Vec<(usize, NetworkJsonTransport)>>> {
+  let responses =
+    bus_request(vec![BusRequest::GetNetworkMapById { id }]).await.unwrap();
+  let result = match &responses[0] {
+    BusResponse::NetworkMap(nodes) => nodes.to_owned(),
+    _ => Vec::new(),
+  };
+
+  NoCache::new(MsgPack(result))
+}
+
 #[get("/api/network_tree_summary")]
 pub async fn network_tree_summary(
 ) -> NoCache<MsgPack<Vec<(usize, NetworkJsonTransport)>>> {
@@ -103,6 +119,54 @@ pub async fn node_names(
   NoCache::new(Json(result))
 }
 
+#[post("/api/node_indices", data = "<ids>")]
+pub async fn node_indices(
+  ids: Json<Vec<String>>,
+) -> NoCache<Json<Vec<(String, usize)>>> {
+  let mut result = Vec::new();
+  for msg in bus_request(vec![BusRequest::GetNodeIndicesFromIds(ids.0)])
+    .await
+    .unwrap()
+    .iter()
+  {
+    if let BusResponse::NodeIndices(map) = msg {
+      result.extend_from_slice(map);
+    }
+  }
+
+  NoCache::new(Json(result))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NetworkJsonChangeRequest {
+  pub changes: Vec<NetworkJsonChange>,
+  pub reload: bool,
+}
+
+#[post("/api/network_json_change", data = "<request>")]
+pub async fn network_json_change(
+  auth: AuthGuard,
+  request: Json<NetworkJsonChangeRequest>,
+) -> NoCache<Json<Result<String, String>>> {
+  if !auth.can_change() || (request.reload && !auth.can_reload()) {
+    return NoCache::new(Json(Err("Not authorized".to_string())));
+  }
+  let responses = bus_request(vec![BusRequest::ChangeNetworkJson {
+    changes: request.changes.clone(),
+    reload: request.reload,
+  }])
+  .await
+  .unwrap();
+  let result = match &responses[0] {
+    BusResponse::Ack => Ok("Saved".to_string()),
+    BusResponse::ReloadLibreQoS(msg) => Ok(msg.clone()),
+    BusResponse::Fail(msg) => Err(msg.clone()),
+    _ => Err("Unable to change network.json".to_string()),
+  };
+  NoCache::new(Json(result))
+}
+
 #[get("/api/funnel_for_queue/<circuit_id>")]
 pub async fn funnel_for_queue(
   circuit_id: String,
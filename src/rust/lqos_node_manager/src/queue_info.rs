@@ -1,7 +1,7 @@
 use crate::auth_guard::AuthGuard;
 use crate::cache_control::NoCache;
 use crate::tracker::SHAPED_DEVICES;
-use lqos_bus::{bus_request, BusRequest, BusResponse, FlowTransport, PacketHeader, QueueStoreTransit};
+use lqos_bus::{bus_request, BusRequest, BusResponse, FlowSummary, FlowTransport, PacketHeader, QueueHistoryRecord, QueueStoreTransit};
 use rocket::fs::NamedFile;
 use rocket::http::Status;
 use rocket::response::content::RawJson;
@@ -119,6 +119,72 @@ pub async fn flow_stats(ip_list: String, _auth: AuthGuard) -> NoCache<MsgPack<Ve
   NoCache::new(MsgPack(result))
 }
 
+#[get("/api/circuit_flows/<circuit_id>")]
+pub async fn circuit_flows(circuit_id: String, _auth: AuthGuard) -> NoCache<MsgPack<Vec<(FlowTransport, Option<FlowTransport>)>>> {
+  if let Ok(responses) = bus_request(vec![BusRequest::GetCircuitFlows(circuit_id)]).await {
+    if let Some(BusResponse::FlowData(flows)) = responses.first() {
+      return NoCache::new(MsgPack(flows.clone()));
+    }
+  }
+  NoCache::new(MsgPack(Vec::new()))
+}
+
+#[get("/api/top_applications")]
+pub async fn top_applications(_auth: AuthGuard) -> NoCache<MsgPack<Vec<FlowSummary>>> {
+  if let Ok(responses) = bus_request(vec![BusRequest::TopFlowApplications(10)]).await {
+    if let Some(BusResponse::TopFlowApplications(apps)) = responses.first() {
+      return NoCache::new(MsgPack(apps.clone()));
+    }
+  }
+  NoCache::new(MsgPack(Vec::new()))
+}
+
+#[get("/api/top_destinations")]
+pub async fn top_destinations(_auth: AuthGuard) -> NoCache<MsgPack<Vec<FlowSummary>>> {
+  if let Ok(responses) = bus_request(vec![BusRequest::TopFlowDestinations(10)]).await {
+    if let Some(BusResponse::TopFlowDestinations(dests)) = responses.first() {
+      return NoCache::new(MsgPack(dests.clone()));
+    }
+  }
+  NoCache::new(MsgPack(Vec::new()))
+}
+
+/// Errors from `lqosd` - such as history not being enabled, or too long
+/// a range - are passed on rather than looking like an empty history.
+async fn queue_history(circuit_id: String, start: u64, end: u64) -> Result<Vec<QueueHistoryRecord>, (Status, String)> {
+  let responses = bus_request(vec![BusRequest::GetQueueHistory{ circuit_id, start, end }])
+    .await
+    .map_err(|_| (Status::ServiceUnavailable, "Unable to contact lqosd".to_string()))?;
+  match responses.first() {
+    Some(BusResponse::QueueHistory(records)) => Ok(records.clone()),
+    Some(BusResponse::Fail(msg)) => Err((Status::BadRequest, msg.clone())),
+    _ => Err((Status::InternalServerError, "Unable to read queue history".to_string())),
+  }
+}
+
+#[get("/api/queue_history/<circuit_id>/<start>/<end>")]
+pub async fn queue_history_json(circuit_id: String, start: u64, end: u64, _auth: AuthGuard) -> Result<NoCache<Json<Vec<QueueHistoryRecord>>>, (Status, String)> {
+  Ok(NoCache::new(Json(queue_history(circuit_id, start, end).await?)))
+}
+
+/// One row per sample. Drops and marks are summed across tins, and the
+/// delay is the worst tin's average.
+#[get("/api/queue_history_csv/<circuit_id>/<start>/<end>")]
+pub async fn queue_history_csv(circuit_id: String, start: u64, end: u64, _auth: AuthGuard) -> Result<NoCache<String>, (Status, String)> {
+  let mut result = String::from("unix_time,down_bytes,down_packets,down_qlen,down_drops,down_marks,down_delay_us,up_bytes,up_packets,up_qlen,up_drops,up_marks,up_delay_us\n");
+  for record in queue_history(circuit_id, start, end).await?.iter() {
+    result += &record.unix_time.to_string();
+    for diff in [&record.download, &record.upload] {
+      let drops: u64 = diff.tins.iter().map(|t| t.drops as u64).sum();
+      let marks: u64 = diff.tins.iter().map(|t| t.marks as u64).sum();
+      let delay = diff.tins.iter().map(|t| t.avg_delay_us).max().unwrap_or(0);
+      result += &format!(",{},{},{},{drops},{marks},{delay}", diff.bytes, diff.packets, diff.qlen);
+    }
+    result += "\n";
+  }
+  Ok(NoCache::new(result))
+}
+
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub enum RequestAnalysisResult {
@@ -126,9 +192,9 @@ pub enum RequestAnalysisResult {
   Ok{ session_id: usize, countdown: usize }
 }
 
-#[get("/api/request_analysis/<ip>")]
-pub async fn request_analysis(ip: String) -> NoCache<Json<RequestAnalysisResult>> {
-  for r in bus_request(vec![BusRequest::GatherPacketData(ip)]).await.unwrap() {
+#[get("/api/request_analysis/<ip>?<snaplen>")]
+pub async fn request_analysis(ip: String, snaplen: Option<u32>, _auth: AuthGuard) -> NoCache<Json<RequestAnalysisResult>> {
+  for r in bus_request(vec![BusRequest::GatherPacketData{ ip, snaplen }]).await.unwrap() {
     if let BusResponse::PacketCollectionSession{session_id, countdown} = r {
       return NoCache::new(Json(RequestAnalysisResult::Ok{session_id, countdown}));
     }
@@ -150,7 +216,7 @@ pub async fn packet_dump(id: usize, _auth: AuthGuard) -> NoCache<Json<Vec<Packet
 
 #[allow(unused_variables)]
 #[get("/api/pcap/<id>/<filename>")]
-pub async fn pcap(id: usize, filename: String) -> Result<NoCache<NamedFile>, Status> {
+pub async fn pcap(id: usize, filename: String, _auth: AuthGuard) -> Result<NoCache<NamedFile>, Status> {
   // The unusued _filename parameter is there to allow the changing of the
   // filename on the client side. See Github issue 291.
   for r in bus_request(vec![BusRequest::GetPcapDump(id)]).await.unwrap() {
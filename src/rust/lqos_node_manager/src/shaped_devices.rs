@@ -4,8 +4,8 @@ use crate::auth_guard::AuthGuard;
 use crate::cache_control::NoCache;
 use crate::tracker::SHAPED_DEVICES;
 use lqos_bus::{bus_request, BusRequest, BusResponse};
-use lqos_config::ShapedDevice;
-use rocket::serde::json::Json;
+use lqos_config::{ShapedDevice, ShapedDevicesChange, ShapedDevicesReport};
+use rocket::serde::{json::Json, Deserialize};
 
 static RELOAD_REQUIRED: AtomicBool = AtomicBool::new(false);
 
@@ -61,7 +61,7 @@ pub fn reload_required() -> NoCache<Json<bool>> {
 
 #[get("/api/reload_libreqos")]
 pub async fn reload_libreqos(auth: AuthGuard) -> NoCache<Json<String>> {
-  if auth != AuthGuard::Admin {
+  if !auth.can_reload() {
     return NoCache::new(Json("Not authorized".to_string()));
   }
   // Send request to lqosd
@@ -74,3 +74,50 @@ pub async fn reload_libreqos(auth: AuthGuard) -> NoCache<Json<String>> {
   RELOAD_REQUIRED.store(false, std::sync::atomic::Ordering::Relaxed);
   NoCache::new(Json(result))
 }
+
+#[get("/api/shaped_devices_validation")]
+pub async fn shaped_devices_validation(
+  _auth: AuthGuard,
+) -> NoCache<Json<Result<ShapedDevicesReport, String>>> {
+  let responses =
+    bus_request(vec![BusRequest::ValidateShapedDevicesCsv]).await.unwrap();
+  let result = match &responses[0] {
+    BusResponse::ShapedDevicesValidation(report) => Ok(*report.clone()),
+    BusResponse::Fail(msg) => Err(msg.clone()),
+    _ => Err("Unable to validate ShapedDevices.csv".to_string()),
+  };
+  NoCache::new(Json(result))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ShapedDevicesChangeRequest {
+  pub changes: Vec<ShapedDevicesChange>,
+  pub reload: bool,
+}
+
+#[post("/api/shaped_devices_change", data = "<request>")]
+pub async fn shaped_devices_change(
+  auth: AuthGuard,
+  request: Json<ShapedDevicesChangeRequest>,
+) -> NoCache<Json<Result<String, String>>> {
+  if !auth.can_change() || (request.reload && !auth.can_reload()) {
+    return NoCache::new(Json(Err("Not authorized".to_string())));
+  }
+  let responses = bus_request(vec![BusRequest::ChangeShapedDevices {
+    changes: request.changes.clone(),
+    reload: request.reload,
+  }])
+  .await
+  .unwrap();
+  let result = match &responses[0] {
+    BusResponse::Ack => {
+      RELOAD_REQUIRED.store(true, std::sync::atomic::Ordering::Relaxed);
+      Ok("Saved".to_string())
+    }
+    BusResponse::ReloadLibreQoS(msg) => Ok(msg.clone()),
+    BusResponse::Fail(msg) => Err(msg.clone()),
+    _ => Err("Unable to change ShapedDevices.csv".to_string()),
+  };
+  NoCache::new(Json(result))
+}
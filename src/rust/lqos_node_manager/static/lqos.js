@@ -16,7 +16,10 @@ const NetTrans = {
     "current_throughput": 2,
     "rtts": 3,
     "parents": 4,
-    "immediate_parent": 5
+    "immediate_parent": 5,
+    "id": 6,
+    "node_type": 7,
+    "tags": 8
 }
 
 const Circuit = {
@@ -45,7 +48,30 @@ const FlowTrans = {
     "bytes": 5,
     "packets": 6,
     "dscp": 7,
-    "ecn": 8
+    "ecn": 8,
+    "tcp": 9
+}
+
+const TcpStats = {
+    "rtt_min_ms": 0,
+    "rtt_median_ms": 1,
+    "rtt_max_ms": 2,
+    "rtt_samples": 3,
+    "retransmits": 4,
+    "out_of_order": 5,
+    "zero_windows": 6,
+}
+
+function tcpRtt(tcp) {
+    if (tcp == null || tcp[TcpStats.rtt_samples] == 0) return "-";
+    return tcp[TcpStats.rtt_median_ms].toFixed(1) + " ms <small>(" +
+        tcp[TcpStats.rtt_min_ms].toFixed(1) + "-" +
+        tcp[TcpStats.rtt_max_ms].toFixed(1) + ")</small>";
+}
+
+function tcpIssues(tcp) {
+    if (tcp == null) return "-";
+    return tcp[TcpStats.retransmits] + " / " + tcp[TcpStats.out_of_order] + " / " + tcp[TcpStats.zero_windows];
 }
 
 const CircuitInfo = {
@@ -62,6 +88,7 @@ const QD = { // Queue data
 
 const CT = { // Cake transit
     "memory_used": 0,
+    "kind": 1,
 }
 
 const CDT = { // Cake Diff Transit
@@ -128,6 +155,12 @@ function deleteAllCookies() {
     window.location.reload();
 }
 
+function logout() {
+    $.post("/api/logout", () => {
+        window.location.href = "/login";
+    });
+}
+
 function cssrules() {
     var rules = {};
     for (var i = 0; i < document.styleSheets.length; ++i) {
@@ -156,7 +189,7 @@ function updateHostCounts() {
         if (un == "Anonymous") {
             html = "<a class='nav-link' href='/login'><i class='fa fa-user'></i> Login</a>";
         } else {
-            html = "<a class='nav-link' onclick='deleteAllCookies();'><i class='fa fa-user'></i> Logout " + un + "</a>";
+            html = "<a class='nav-link' href='#' onclick='logout();'><i class='fa fa-user'></i> Logout " + un + "</a>";
         }
         $("#currentLogin").html(html);
     });
@@ -32,6 +32,7 @@ fn liblqos_python(_py: Python, m: &PyModule) -> PyResult<()> {
   m.add_wrapped(wrap_pyfunction!(is_libre_already_running))?;
   m.add_wrapped(wrap_pyfunction!(create_lock_file))?;
   m.add_wrapped(wrap_pyfunction!(free_lock_file))?;
+  m.add_wrapped(wrap_pyfunction!(isp_config_json))?;
   Ok(())
 }
 
@@ -203,8 +204,13 @@ fn validate_shaped_devices() -> PyResult<String> {
   let result = run_query(vec![BusRequest::ValidateShapedDevicesCsv]).unwrap();
   for response in result.iter() {
     match response {
-      BusResponse::Ack => return Ok("OK".to_string()),
-      BusResponse::ShapedDevicesValidation(error) => return Ok(error.clone()),
+      BusResponse::ShapedDevicesValidation(report) => {
+        if report.is_valid() {
+          return Ok("OK".to_string());
+        }
+        return Ok(report.to_string());
+      }
+      BusResponse::Fail(error) => return Ok(error.clone()),
       _ => {}
     }
   }
@@ -254,3 +260,14 @@ fn free_lock_file() -> PyResult<()> {
   let _ = remove_file(LOCK_FILE); // Ignore result
   Ok(())
 }
+
+/// Returns the settings that used to live in `ispConfig.py`, read from
+/// `/etc/lqos.conf`, as a JSON object keyed by their `ispConfig.py` names.
+/// The `ispConfig.py` shim loads its globals from this.
+#[pyfunction]
+fn isp_config_json() -> PyResult<String> {
+  let cfg = lqos_config::EtcLqos::load()
+    .map_err(|_| PyOSError::new_err("Unable to read /etc/lqos.conf"))?;
+  serde_json::to_string(&lqos_config::python_values(&cfg))
+    .map_err(|e| PyOSError::new_err(e.to_string()))
+}
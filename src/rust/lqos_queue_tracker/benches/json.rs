@@ -5,6 +5,7 @@
 //! dummy interface. FIXME.
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lqos_bus::TcHandle;
 use lqos_queue_tracker::*;
 use std::process::{id, Command};
 
@@ -146,6 +147,26 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         black_box(json);
       });
     });
+
+    // Compare the two queue reader backends, reading the first client's
+    // CAKE queue as the queue tracker would.
+    let first_client = TcHandle::from_string("0x1:2").unwrap();
+    let tc_reader =
+      format!("{queue_count} queues: read one circuit with tc backend");
+    let netlink_reader =
+      format!("{queue_count} queues: read one circuit with netlink backend");
+
+    c.bench_function(&tc_reader, |b| {
+      b.iter(|| {
+        black_box(read_named_queue_tc(interface, first_client).unwrap());
+      });
+    });
+
+    c.bench_function(&netlink_reader, |b| {
+      b.iter(|| {
+        black_box(read_named_queue_netlink(interface, first_client).unwrap());
+      });
+    });
   }
 }
 
@@ -1,7 +1,14 @@
 use crate::{
-  circuit_to_queue::CIRCUIT_TO_QUEUE, queue_store::QueueStore, still_watching,
+  circuit_to_queue::CIRCUIT_TO_QUEUE,
+  htb_class_store::HTB_CLASSES,
+  queue_store::QueueStore,
+  queue_structure::{
+    circuit_queue_info, circuits_on_cpu, circuits_under_class, cpu_totals,
+    QueueNode, QUEUE_STRUCTURE,
+  },
+  still_watching,
 };
-use lqos_bus::BusResponse;
+use lqos_bus::{BusResponse, TcHandle};
 
 pub fn get_raw_circuit_data(circuit_id: &str) -> BusResponse {
   still_watching(circuit_id);
@@ -13,3 +20,54 @@ pub fn get_raw_circuit_data(circuit_id: &str) -> BusResponse {
     BusResponse::RawQueueData(None)
   }
 }
+
+/// Retrieves HTB class history for a circuit, or a network node, by
+/// circuit ID or node name.
+pub fn get_htb_class_data(id: &str) -> BusResponse {
+  let class_id =
+    if let Some(queues) = &QUEUE_STRUCTURE.read().unwrap().maybe_queues {
+      queues
+        .iter()
+        .find(|q| {
+          q.circuit_id.as_deref() == Some(id) || q.name.as_deref() == Some(id)
+        })
+        .map(|q| q.class_id)
+    } else {
+      None
+    };
+  BusResponse::HtbClassData(
+    class_id
+      .and_then(|class_id| HTB_CLASSES.get(&class_id))
+      .map(|store| Box::new(store.value().clone().into())),
+  )
+}
+
+fn with_queues<T: Default>(f: impl FnOnce(&[QueueNode]) -> T) -> T {
+  if let Some(queues) = &QUEUE_STRUCTURE.read().unwrap().maybe_queues {
+    f(queues)
+  } else {
+    T::default()
+  }
+}
+
+/// Looks up a circuit's classes, CPU and the nodes above it.
+pub fn get_circuit_queue_info(circuit_id: &str) -> BusResponse {
+  BusResponse::CircuitQueueInfo(
+    with_queues(|q| circuit_queue_info(q, circuit_id)).map(Box::new),
+  )
+}
+
+/// Lists the circuits whose download queue is on `cpu`.
+pub fn get_circuits_on_cpu(cpu: u32) -> BusResponse {
+  BusResponse::QueueNodes(with_queues(|q| circuits_on_cpu(q, cpu)))
+}
+
+/// Lists the circuits anywhere below `class_id`.
+pub fn get_circuits_under_class(class_id: TcHandle) -> BusResponse {
+  BusResponse::QueueNodes(with_queues(|q| circuits_under_class(q, class_id)))
+}
+
+/// Per-CPU circuit counts and plan totals.
+pub fn get_queue_cpu_totals() -> BusResponse {
+  BusResponse::QueueCpuTotals(with_queues(cpu_totals))
+}
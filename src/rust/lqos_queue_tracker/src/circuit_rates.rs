@@ -0,0 +1,503 @@
+use crate::queue_structure::{QueueNetwork, QueueNode, QUEUE_STRUCTURE};
+use log::{error, info};
+use lqos_bus::{BusResponse, TcHandle};
+use lqos_config::LibreQoSConfig;
+use serde_json::Value;
+use std::process::Command;
+use thiserror::Error;
+
+const TC: &str = "/sbin/tc";
+
+/// New rates for a circuit, in Mbps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitRates {
+  pub download_min: u64,
+  pub download_max: u64,
+  pub upload_min: u64,
+  pub upload_max: u64,
+}
+
+impl CircuitRates {
+  fn is_valid(&self) -> bool {
+    self.download_min > 0
+      && self.upload_min > 0
+      && self.download_min <= self.download_max
+      && self.upload_min <= self.upload_max
+  }
+
+  /// The rates a circuit's classes are built with, for these plan rates:
+  /// each is multiplied by `factor` and rounded the way LibreQoS.py
+  /// rounds it, so that both build the same classes.
+  fn with_overhead(&self, factor: f64) -> Self {
+    let shape = |mbps: u64| python_round(mbps as f64 * factor);
+    Self {
+      download_min: shape(self.download_min),
+      download_max: shape(self.download_max),
+      upload_min: shape(self.upload_min),
+      upload_max: shape(self.upload_max),
+    }
+  }
+}
+
+/// Python's `round()`, which rounds halves to the nearest even number.
+fn python_round(value: f64) -> u64 {
+  let rounded = value.round();
+  if (rounded - value).abs() == 0.5 && rounded % 2.0 != 0.0 {
+    (rounded - 1.0) as u64
+  } else {
+    rounded as u64
+  }
+}
+
+/// Changes one circuit's HTB classes in place, and updates the stored
+/// queue structure to match. `rates` are plan rates; the configured TCP
+/// overhead factor is applied to them, as LibreQoS.py does.
+pub fn update_circuit_rates(
+  circuit_id: &str,
+  rates: CircuitRates,
+) -> BusResponse {
+  match apply_circuit_rates(circuit_id, rates) {
+    Ok(()) => {
+      info!("Changed circuit {circuit_id} rates to {rates:?}");
+      BusResponse::Ack
+    }
+    Err(e) => {
+      error!("Unable to change circuit {circuit_id} rates: {e}");
+      BusResponse::Fail(e.to_string())
+    }
+  }
+}
+
+/// The classes a circuit was built with, and their current rates, from
+/// the queue structure.
+#[derive(Debug, PartialEq, Eq)]
+struct CircuitClasses {
+  class_id: TcHandle,
+  parent_class_id: TcHandle,
+  up_class_id: TcHandle,
+  up_parent_class_id: TcHandle,
+  rates: CircuitRates,
+}
+
+fn apply_circuit_rates(
+  circuit_id: &str,
+  rates: CircuitRates,
+) -> Result<(), CircuitRateError> {
+  let config =
+    LibreQoSConfig::load().map_err(|_| CircuitRateError::LqosConfig)?;
+  let rates = rates.with_overhead(config.tcp_overhead_factor);
+  if !rates.is_valid() {
+    return Err(CircuitRateError::InvalidRates);
+  }
+  let circuit = find_circuit(circuit_id)?;
+
+  let mut applied = 0;
+  let result = tc_commands(&config, &circuit, rates)
+    .iter()
+    .try_for_each(|args| {
+      run_tc(args)?;
+      applied += 1;
+      Ok(())
+    })
+    .and_then(|_| update_queue_structure_file(circuit_id, rates));
+  if let Err(e) = result {
+    // Undo whatever did change, so that the kernel still matches the
+    // queue structure.
+    let restored = tc_commands(&config, &circuit, circuit.rates)[..applied]
+      .iter()
+      .try_for_each(|args| run_tc(args));
+    return Err(match restored {
+      Ok(()) => e,
+      Err(restore_error) => CircuitRateError::PartlyChanged(
+        circuit_id.to_string(),
+        format!("{e}, then {restore_error}"),
+      ),
+    });
+  }
+  update_queue_structure(circuit_id, rates);
+  Ok(())
+}
+
+/// The `tc` commands that give a circuit's classes `rates`.
+fn tc_commands(
+  config: &LibreQoSConfig,
+  circuit: &CircuitClasses,
+  rates: CircuitRates,
+) -> Vec<Vec<String>> {
+  let (download_interface, upload_interface) = if config.on_a_stick_mode {
+    (&config.internet_interface, &config.internet_interface)
+  } else {
+    (&config.isp_interface, &config.internet_interface)
+  };
+  let mut commands = vec![
+    class_change_args(
+      download_interface,
+      circuit.parent_class_id,
+      circuit.class_id,
+      rates.download_min,
+      rates.download_max,
+    ),
+    class_change_args(
+      upload_interface,
+      circuit.up_parent_class_id,
+      circuit.up_class_id,
+      rates.upload_min,
+      rates.upload_max,
+    ),
+  ];
+
+  // Re-apply the configured qdisc, so that any rate-dependent options
+  // in `sqm` follow the new plan. `change` keeps the qdisc's statistics.
+  if !config.monitor_mode && !config.sqm.is_empty() {
+    commands.push(qdisc_change_args(
+      download_interface,
+      circuit.class_id,
+      &config.sqm,
+    ));
+    commands.push(qdisc_change_args(
+      upload_interface,
+      circuit.up_class_id,
+      &config.sqm,
+    ));
+  }
+  commands
+}
+
+fn find_circuit(circuit_id: &str) -> Result<CircuitClasses, CircuitRateError> {
+  QUEUE_STRUCTURE
+    .read()
+    .unwrap()
+    .maybe_queues
+    .as_ref()
+    .and_then(|queues| circuit_classes(queues, circuit_id))
+    .ok_or_else(|| CircuitRateError::UnknownCircuit(circuit_id.to_string()))
+}
+
+fn circuit_classes(
+  queues: &[QueueNode],
+  circuit_id: &str,
+) -> Option<CircuitClasses> {
+  queues.iter().find(|q| q.circuit_id.as_deref() == Some(circuit_id)).map(
+    |q| CircuitClasses {
+      class_id: q.class_id,
+      parent_class_id: q.parent_class_id,
+      up_class_id: q.up_class_id,
+      up_parent_class_id: q.up_parent_class_id,
+      rates: CircuitRates {
+        download_min: q.download_bandwidth_mbps_min,
+        download_max: q.download_bandwidth_mbps,
+        upload_min: q.upload_bandwidth_mbps_min,
+        upload_max: q.upload_bandwidth_mbps,
+      },
+    },
+  )
+}
+
+/// Arguments for `tc`, matching the way LibreQoS.py builds circuit
+/// classes.
+fn class_change_args(
+  interface: &str,
+  parent: TcHandle,
+  class_id: TcHandle,
+  min_mbps: u64,
+  max_mbps: u64,
+) -> Vec<String> {
+  vec![
+    "class".to_string(),
+    "change".to_string(),
+    "dev".to_string(),
+    interface.to_string(),
+    "parent".to_string(),
+    parent.to_string(),
+    "classid".to_string(),
+    class_id.to_string(),
+    "htb".to_string(),
+    "rate".to_string(),
+    format!("{min_mbps}mbit"),
+    "ceil".to_string(),
+    format!("{max_mbps}mbit"),
+    "prio".to_string(),
+    "3".to_string(),
+  ]
+}
+
+fn qdisc_change_args(
+  interface: &str,
+  class_id: TcHandle,
+  sqm: &str,
+) -> Vec<String> {
+  let mut args = vec![
+    "qdisc".to_string(),
+    "change".to_string(),
+    "dev".to_string(),
+    interface.to_string(),
+    "parent".to_string(),
+    class_id.to_string(),
+  ];
+  args.extend(sqm.split_whitespace().map(|s| s.to_string()));
+  args
+}
+
+fn run_tc(args: &[String]) -> Result<(), CircuitRateError> {
+  let output = Command::new(TC)
+    .args(args)
+    .output()
+    .map_err(|e| CircuitRateError::Tc(e.to_string()))?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(CircuitRateError::Tc(format!(
+      "tc {} failed: {}",
+      args.join(" "),
+      String::from_utf8_lossy(&output.stderr).trim()
+    )))
+  }
+}
+
+/// Updates the in-memory queue structure, so that readers see the new
+/// rates before the file watcher reloads `queuingStructure.json`.
+fn update_queue_structure(circuit_id: &str, rates: CircuitRates) {
+  let mut structure = QUEUE_STRUCTURE.write().unwrap();
+  if let Some(queues) = structure.maybe_queues.as_mut() {
+    for q in
+      queues.iter_mut().filter(|q| q.circuit_id.as_deref() == Some(circuit_id))
+    {
+      q.download_bandwidth_mbps_min = rates.download_min;
+      q.download_bandwidth_mbps = rates.download_max;
+      q.upload_bandwidth_mbps_min = rates.upload_min;
+      q.upload_bandwidth_mbps = rates.upload_max;
+    }
+  }
+}
+
+/// Rewrites `queuingStructure.json` with the circuit's new rates. The
+/// new file is written alongside and renamed into place, so that a
+/// reader never sees half a file.
+fn update_queue_structure_file(
+  circuit_id: &str,
+  rates: CircuitRates,
+) -> Result<(), CircuitRateError> {
+  let path =
+    QueueNetwork::path().map_err(|_| CircuitRateError::QueueStructureFile)?;
+  let raw = std::fs::read_to_string(&path)
+    .map_err(|_| CircuitRateError::QueueStructureFile)?;
+  let mut json: Value = serde_json::from_str(&raw)
+    .map_err(|_| CircuitRateError::QueueStructureFile)?;
+  let found = json
+    .get_mut("Network")
+    .map(|network| set_circuit_rates(network, circuit_id, rates))
+    .unwrap_or(false);
+  if !found {
+    return Err(CircuitRateError::UnknownCircuit(circuit_id.to_string()));
+  }
+  let new_raw = serde_json::to_string_pretty(&json)
+    .map_err(|_| CircuitRateError::QueueStructureFile)?;
+  let tmp_path = path.with_extension("json.tmp");
+  std::fs::write(&tmp_path, new_raw)
+    .and_then(|_| std::fs::rename(&tmp_path, &path))
+    .map_err(|_| {
+      let _ = std::fs::remove_file(&tmp_path);
+      CircuitRateError::QueueStructureFile
+    })
+}
+
+/// Walks the `Network` tree of `queuingStructure.json`, and sets the
+/// rates of the matching circuit. Returns true if it was found.
+fn set_circuit_rates(
+  nodes: &mut Value,
+  circuit_id: &str,
+  rates: CircuitRates,
+) -> bool {
+  let mut found = false;
+  if let Value::Object(map) = nodes {
+    for node in map.values_mut() {
+      if let Some(Value::Array(circuits)) = node.get_mut("circuits") {
+        for circuit in circuits.iter_mut() {
+          if circuit.get("circuitID").and_then(|id| id.as_str())
+            == Some(circuit_id)
+          {
+            circuit["minDownload"] = rates.download_min.into();
+            circuit["maxDownload"] = rates.download_max.into();
+            circuit["minUpload"] = rates.upload_min.into();
+            circuit["maxUpload"] = rates.upload_max.into();
+            found = true;
+          }
+        }
+      }
+      if let Some(children) = node.get_mut("children") {
+        found |= set_circuit_rates(children, circuit_id, rates);
+      }
+    }
+  }
+  found
+}
+
+#[derive(Error, Debug)]
+pub enum CircuitRateError {
+  #[error("Minimum rates must be non-zero, and no more than the maximum")]
+  InvalidRates,
+  #[error("Unable to read LibreQoS config")]
+  LqosConfig,
+  #[error("Circuit {0} is not in the queue structure")]
+  UnknownCircuit(String),
+  #[error("{0}")]
+  Tc(String),
+  #[error("Unable to update queuingStructure.json")]
+  QueueStructureFile,
+  #[error("Circuit {0} may be left with a mix of old and new rates: {1}")]
+  PartlyChanged(String, String),
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN: &str =
+    include_str!("./queue_structure/example_queue_with_children.test.json");
+
+  const RATES: CircuitRates = CircuitRates {
+    download_min: 50,
+    download_max: 500,
+    upload_min: 10,
+    upload_max: 100,
+  };
+
+  fn find_circuit<'a>(nodes: &'a Value, id: &str) -> Option<&'a Value> {
+    for node in nodes.as_object()?.values() {
+      if let Some(Value::Array(circuits)) = node.get("circuits") {
+        if let Some(c) =
+          circuits.iter().find(|c| c["circuitID"].as_str() == Some(id))
+        {
+          return Some(c);
+        }
+      }
+      if let Some(c) = node.get("children").and_then(|c| find_circuit(c, id)) {
+        return Some(c);
+      }
+    }
+    None
+  }
+
+  #[test]
+  fn circuits_change_under_their_parent_class() {
+    let queues =
+      QueueNetwork::from_json_str(EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN)
+        .unwrap()
+        .to_flat();
+    let handle = |h| TcHandle::from_string(h).unwrap();
+    assert_eq!(
+      circuit_classes(&queues, "9999"),
+      Some(CircuitClasses {
+        class_id: handle("2:6"),
+        parent_class_id: handle("2:5"),
+        up_class_id: handle("2:6"),
+        up_parent_class_id: handle("2:5"),
+        rates: CircuitRates {
+          download_min: 27,
+          download_max: 200,
+          upload_min: 5,
+          upload_max: 200,
+        },
+      })
+    );
+    assert_eq!(circuit_classes(&queues, "not-a-circuit"), None);
+  }
+
+  #[test]
+  fn sets_nested_circuit_rates() {
+    let mut json: Value =
+      serde_json::from_str(EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN).unwrap();
+    assert!(set_circuit_rates(&mut json["Network"], "9999", RATES));
+    let circuit = find_circuit(&json["Network"], "9999").unwrap();
+    assert_eq!(circuit["minDownload"], 50);
+    assert_eq!(circuit["maxDownload"], 500);
+    assert_eq!(circuit["minUpload"], 10);
+    assert_eq!(circuit["maxUpload"], 100);
+  }
+
+  #[test]
+  fn unknown_circuit_is_not_found() {
+    let mut json: Value =
+      serde_json::from_str(EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN).unwrap();
+    assert!(!set_circuit_rates(&mut json["Network"], "nope", RATES));
+  }
+
+  #[test]
+  fn builds_tc_arguments() {
+    let args = class_change_args(
+      "eth1",
+      TcHandle::from_string("1:3").unwrap(),
+      TcHandle::from_string("1:6").unwrap(),
+      50,
+      500,
+    );
+    assert_eq!(
+      args.join(" "),
+      "class change dev eth1 parent 1:3 classid 1:6 htb rate 50mbit ceil 500mbit prio 3"
+    );
+    let args = qdisc_change_args(
+      "eth1",
+      TcHandle::from_string("1:6").unwrap(),
+      "cake diffserv4",
+    );
+    assert_eq!(
+      args.join(" "),
+      "qdisc change dev eth1 parent 1:6 cake diffserv4"
+    );
+  }
+
+  #[test]
+  fn adds_overhead_like_libreqos_py() {
+    // round(rate * 1.09) in Python, which rounds 272.5 down to 272
+    let rates = CircuitRates {
+      download_min: 50,
+      download_max: 100,
+      upload_min: 250,
+      upload_max: 1000,
+    };
+    assert_eq!(
+      rates.with_overhead(1.09),
+      CircuitRates {
+        download_min: 55,
+        download_max: 109,
+        upload_min: 272,
+        upload_max: 1090,
+      }
+    );
+    assert_eq!(rates.with_overhead(1.0), rates);
+  }
+
+  #[test]
+  fn restores_with_the_same_commands() {
+    let queues =
+      QueueNetwork::from_json_str(EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN)
+        .unwrap()
+        .to_flat();
+    let circuit = circuit_classes(&queues, "9999").unwrap();
+    let config = LibreQoSConfig::default();
+    let change = tc_commands(&config, &circuit, RATES);
+    let restore = tc_commands(&config, &circuit, circuit.rates);
+    assert_eq!(change.len(), 4);
+    assert_eq!(
+      change[0].join(" "),
+      "class change dev eth1 parent 2:5 classid 2:6 htb rate 50mbit ceil 500mbit prio 3"
+    );
+    assert_eq!(
+      restore[0].join(" "),
+      "class change dev eth1 parent 2:5 classid 2:6 htb rate 27mbit ceil 200mbit prio 3"
+    );
+    assert_eq!(
+      restore[1].join(" "),
+      "class change dev eth2 parent 2:5 classid 2:6 htb rate 5mbit ceil 200mbit prio 3"
+    );
+  }
+
+  #[test]
+  fn rejects_inverted_rates() {
+    assert!(RATES.is_valid());
+    let rates = CircuitRates { download_min: 600, ..RATES };
+    assert!(!rates.is_valid());
+    let rates = CircuitRates { upload_min: 0, ..RATES };
+    assert!(!rates.is_valid());
+  }
+}
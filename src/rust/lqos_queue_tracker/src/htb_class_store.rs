@@ -0,0 +1,147 @@
+use crate::{
+  queue_diff::{make_htb_class_diff, HtbClassDiff},
+  queue_types::TcHtbClass,
+  NUM_HTB_HISTORY,
+};
+use dashmap::DashMap;
+use lqos_bus::{
+  HtbClassDiffTransit, HtbClassStoreTransit, HtbClassTransit, TcHandle,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// HTB class history, keyed by the node's download `class_id`.
+pub(crate) static HTB_CLASSES: Lazy<DashMap<TcHandle, HtbClassStore>> =
+  Lazy::new(DashMap::new);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HtbClassStore {
+  history: Vec<(HtbClassDiff, HtbClassDiff)>,
+  history_head: usize,
+  current_download: TcHtbClass,
+  current_upload: TcHtbClass,
+}
+
+impl HtbClassStore {
+  pub(crate) fn new(download: TcHtbClass, upload: TcHtbClass) -> Self {
+    Self {
+      history: vec![
+        (HtbClassDiff::default(), HtbClassDiff::default());
+        NUM_HTB_HISTORY
+      ],
+      history_head: 0,
+      current_download: download,
+      current_upload: upload,
+    }
+  }
+
+  pub(crate) fn update(&mut self, download: &TcHtbClass, upload: &TcHtbClass) {
+    let new_diff_dn = make_htb_class_diff(&self.current_download, download);
+    let new_diff_up = make_htb_class_diff(&self.current_upload, upload);
+    self.current_download = download.clone();
+    self.current_upload = upload.clone();
+    self.history[self.history_head] = (new_diff_dn, new_diff_up);
+    self.history_head += 1;
+    if self.history_head >= NUM_HTB_HISTORY {
+      self.history_head = 0;
+    }
+  }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<HtbClassStoreTransit> for HtbClassStore {
+  fn into(self) -> HtbClassStoreTransit {
+    HtbClassStoreTransit {
+      history: self
+        .history
+        .iter()
+        .cloned()
+        .map(|(a, b)| (a.into(), b.into()))
+        .collect(),
+      history_head: self.history_head,
+      current_download: self.current_download.into(),
+      current_upload: self.current_upload.into(),
+    }
+  }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<HtbClassDiffTransit> for HtbClassDiff {
+  fn into(self) -> HtbClassDiffTransit {
+    HtbClassDiffTransit {
+      bytes: self.bytes,
+      packets: self.packets,
+      drops: self.drops,
+      overlimits: self.overlimits,
+      borrows: self.borrows,
+      lends: self.lends,
+      tokens: self.tokens,
+      ctokens: self.ctokens,
+    }
+  }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<HtbClassTransit> for TcHtbClass {
+  fn into(self) -> HtbClassTransit {
+    HtbClassTransit {
+      rate: self.rate,
+      ceil: self.ceil,
+      bytes: self.bytes,
+      drops: self.drops,
+      overlimits: self.overlimits,
+      borrows: self.borrows,
+      lends: self.lends,
+      tokens: self.tokens,
+      ctokens: self.ctokens,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::NetlinkQdisc;
+
+  fn class(bytes: u64, borrows: u32, tokens: i32) -> TcHtbClass {
+    // struct tc_htb_xstats: lends, borrows, giants, tokens, ctokens
+    let mut xstats = vec![0u8; 20];
+    xstats[4..8].copy_from_slice(&borrows.to_ne_bytes());
+    xstats[12..16].copy_from_slice(&tokens.to_ne_bytes());
+    TcHtbClass::from_netlink(&NetlinkQdisc {
+      kind: "htb".to_string(),
+      bytes,
+      xstats,
+      ..Default::default()
+    })
+  }
+
+  #[test]
+  fn update_records_per_period_diffs() {
+    let mut store = HtbClassStore::new(class(1000, 1, 500), class(0, 0, 0));
+    store.update(&class(4000, 3, -20), &class(100, 0, 10));
+    assert_eq!(store.history_head, 1);
+    let (down, up) = &store.history[0];
+    assert_eq!(down.bytes, 3000);
+    assert_eq!(down.borrows, 2);
+    assert_eq!(down.tokens, -20);
+    assert_eq!(up.bytes, 100);
+  }
+
+  #[test]
+  fn history_wraps_around() {
+    let mut store = HtbClassStore::new(class(0, 0, 0), class(0, 0, 0));
+    for i in 1..=NUM_HTB_HISTORY as u64 + 1 {
+      store.update(&class(i, 0, 0), &class(i, 0, 0));
+    }
+    assert_eq!(store.history_head, 1);
+  }
+
+  #[test]
+  fn counter_reset_does_not_underflow() {
+    let mut store = HtbClassStore::new(class(5000, 9, 0), class(0, 0, 0));
+    store.update(&class(100, 1, 0), &class(0, 0, 0));
+    assert_eq!(store.history[0].0.bytes, 0);
+    assert_eq!(store.history[0].0.borrows, 0);
+  }
+}
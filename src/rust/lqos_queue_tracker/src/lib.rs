@@ -1,7 +1,11 @@
 mod bus;
+mod circuit_rates;
 mod circuit_to_queue;
+mod htb_class_store;
 mod interval;
+mod netlink;
 mod queue_diff;
+mod queue_history;
 mod queue_store;
 mod queue_structure;
 mod queue_types;
@@ -10,9 +14,25 @@ mod tracking;
 /// How many history items do we store?
 const NUM_QUEUE_HISTORY: usize = 600;
 
-pub use bus::get_raw_circuit_data;
+/// How many HTB class history items do we store?
+const NUM_HTB_HISTORY: usize = 60;
+
+pub use bus::{
+  get_circuit_queue_info, get_circuits_on_cpu, get_circuits_under_class,
+  get_htb_class_data, get_queue_cpu_totals, get_raw_circuit_data,
+};
+pub use circuit_rates::{update_circuit_rates, CircuitRates};
 pub use interval::set_queue_refresh_interval;
+pub use queue_history::get_queue_history;
 pub use queue_structure::spawn_queue_structure_monitor;
 pub use queue_types::deserialize_tc_tree; // Exported for the benchmarker
 pub use tracking::spawn_queue_monitor;
+pub use tracking::{
+  read_named_queue_from_interface, read_named_queue_netlink,
+  read_named_queue_tc, set_queue_stats_backend, QueueReaderError,
+};
 pub use tracking::{add_watched_queue, still_watching};
+pub use tracking::{
+  all_circuit_queue_summaries, audit_queue_tree, bufferbloat_distribution,
+  get_circuit_bufferbloat, get_circuit_queue_summary,
+};
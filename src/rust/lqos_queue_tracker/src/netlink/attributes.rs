@@ -0,0 +1,141 @@
+/// Attribute type bits that are flags, rather than part of the type.
+const NLA_TYPE_MASK: u16 = 0x3FFF;
+
+/// A single netlink attribute (`struct rtattr`).
+pub(crate) struct Attribute<'a> {
+  pub(crate) kind: u16,
+  pub(crate) payload: &'a [u8],
+}
+
+impl<'a> Attribute<'a> {
+  pub(crate) fn as_u32(&self) -> u32 {
+    read_u32(self.payload, 0)
+  }
+
+  pub(crate) fn as_i32(&self) -> i32 {
+    read_u32(self.payload, 0) as i32
+  }
+
+  pub(crate) fn as_u64(&self) -> u64 {
+    read_u64(self.payload, 0)
+  }
+
+  /// Reads a NUL-terminated string attribute.
+  pub(crate) fn as_string(&self) -> String {
+    let end =
+      self.payload.iter().position(|b| *b == 0).unwrap_or(self.payload.len());
+    String::from_utf8_lossy(&self.payload[..end]).to_string()
+  }
+
+  /// Iterates the attributes nested inside this one.
+  pub(crate) fn nested(&self) -> AttributeIter<'a> {
+    attributes(self.payload)
+  }
+}
+
+/// Iterates the attributes packed into `buffer`, stopping at the first
+/// malformed one.
+pub(crate) fn attributes(buffer: &[u8]) -> AttributeIter<'_> {
+  AttributeIter { buffer }
+}
+
+pub(crate) struct AttributeIter<'a> {
+  buffer: &'a [u8],
+}
+
+impl<'a> Iterator for AttributeIter<'a> {
+  type Item = Attribute<'a>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.buffer.len() < 4 {
+      return None;
+    }
+    let len = read_u16(self.buffer, 0) as usize;
+    if len < 4 || len > self.buffer.len() {
+      return None;
+    }
+    let attribute = Attribute {
+      kind: read_u16(self.buffer, 2) & NLA_TYPE_MASK,
+      payload: &self.buffer[4..len],
+    };
+    let aligned = usize::min(align4(len), self.buffer.len());
+    self.buffer = &self.buffer[aligned..];
+    Some(attribute)
+  }
+}
+
+/// Netlink messages and attributes are padded to 4 byte boundaries.
+pub(crate) fn align4(len: usize) -> usize {
+  (len + 3) & !3
+}
+
+// The reads below return 0 for short buffers, in keeping with the JSON
+// decoders' `unwrap_or(0)` handling of missing values.
+
+pub(crate) fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+  buffer
+    .get(offset..offset + 2)
+    .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+    .unwrap_or(0)
+}
+
+pub(crate) fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+  buffer
+    .get(offset..offset + 4)
+    .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+    .unwrap_or(0)
+}
+
+pub(crate) fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+  buffer
+    .get(offset..offset + 8)
+    .map(|b| {
+      u64::from_ne_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+    })
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn attribute(kind: u16, payload: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    result.extend_from_slice(&((payload.len() + 4) as u16).to_ne_bytes());
+    result.extend_from_slice(&kind.to_ne_bytes());
+    result.extend_from_slice(payload);
+    result.resize(align4(result.len()), 0);
+    result
+  }
+
+  #[test]
+  fn parse_flat_attributes() {
+    let mut buffer = attribute(1, b"cake\0");
+    buffer.extend(attribute(2, &42u32.to_ne_bytes()));
+    let parsed: Vec<Attribute> = attributes(&buffer).collect();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].kind, 1);
+    assert_eq!(parsed[0].as_string(), "cake");
+    assert_eq!(parsed[1].as_u32(), 42);
+  }
+
+  #[test]
+  fn parse_nested_attributes() {
+    let inner = attribute(3, &7u64.to_ne_bytes());
+    // Nested attributes have the NLA_F_NESTED bit set
+    let buffer = attribute(0x8000 | 10, &inner);
+    let outer: Vec<Attribute> = attributes(&buffer).collect();
+    assert_eq!(outer.len(), 1);
+    assert_eq!(outer[0].kind, 10);
+    let inner: Vec<Attribute> = outer[0].nested().collect();
+    assert_eq!(inner[0].kind, 3);
+    assert_eq!(inner[0].as_u64(), 7);
+  }
+
+  #[test]
+  fn stop_at_malformed_attribute() {
+    let mut buffer = attribute(1, &1u32.to_ne_bytes());
+    buffer.extend_from_slice(&[200, 0, 2, 0]);
+    assert_eq!(attributes(&buffer).count(), 1);
+  }
+}
@@ -0,0 +1,267 @@
+//! A minimal rtnetlink client, used to read qdisc statistics directly
+//! from the kernel instead of forking `tc` and parsing its JSON.
+
+mod attributes;
+pub(crate) use attributes::{attributes, read_u32, read_u64, Attribute};
+use attributes::align4;
+use std::ffi::CString;
+use thiserror::Error;
+
+const RTM_NEWQDISC: u16 = 36;
+const RTM_GETQDISC: u16 = 38;
+const RTM_NEWTCLASS: u16 = 40;
+const RTM_GETTCLASS: u16 = 42;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_DUMP: u16 = 0x300;
+const NLMSG_HDRLEN: usize = 16;
+const TCMSG_LEN: usize = 20;
+
+const TCA_KIND: u16 = 1;
+const TCA_OPTIONS: u16 = 2;
+const TCA_XSTATS: u16 = 4;
+const TCA_STATS2: u16 = 7;
+const TCA_STATS_BASIC: u16 = 1;
+const TCA_STATS_QUEUE: u16 = 3;
+const TCA_STATS_APP: u16 = 4;
+const TCA_STATS_PKT64: u16 = 8;
+
+/// Dump replies are never larger than 32k per `recv`.
+const RECV_BUFFER_SIZE: usize = 65536;
+
+/// A qdisc, as reported by an `RTM_GETQDISC` dump. Classes, from an
+/// `RTM_GETTCLASS` dump, are reported in the same shape.
+#[derive(Default, Debug)]
+pub(crate) struct NetlinkQdisc {
+  pub(crate) kind: String,
+  pub(crate) handle: u32,
+  pub(crate) parent: u32,
+  /// Raw `TCA_OPTIONS` attributes, decoded by each qdisc type.
+  pub(crate) options: Vec<u8>,
+  /// Raw qdisc-specific statistics (`TCA_STATS_APP`).
+  pub(crate) xstats: Vec<u8>,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u64,
+  pub(crate) qlen: u32,
+  pub(crate) backlog: u32,
+  pub(crate) drops: u32,
+  pub(crate) requeues: u32,
+  pub(crate) overlimits: u32,
+}
+
+/// The kernel reports some times in scheduler ticks of 64ns. `tc`
+/// converts them to whole microseconds (truncating), as does this.
+pub(crate) fn ticks_to_us(ticks: u32) -> u64 {
+  (ticks as f64 / 15.625) as u64
+}
+
+/// Reads every qdisc attached to `interface`, with statistics.
+pub(crate) fn dump_qdiscs(
+  interface: &str,
+) -> Result<Vec<NetlinkQdisc>, NetlinkError> {
+  dump(interface, RTM_GETQDISC, RTM_NEWQDISC)
+}
+
+/// Reads every class attached to `interface`, with statistics. `tc`
+/// doesn't offer JSON output for classes, so this is the only way that
+/// class statistics are read.
+pub(crate) fn dump_classes(
+  interface: &str,
+) -> Result<Vec<NetlinkQdisc>, NetlinkError> {
+  dump(interface, RTM_GETTCLASS, RTM_NEWTCLASS)
+}
+
+/// Sends a `request_type` dump request for `interface`, and collects the
+/// `reply_type` messages that come back.
+fn dump(
+  interface: &str,
+  request_type: u16,
+  reply_type: u16,
+) -> Result<Vec<NetlinkQdisc>, NetlinkError> {
+  let name = CString::new(interface)
+    .map_err(|_| NetlinkError::NoSuchInterface(interface.to_string()))?;
+  let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+  if ifindex == 0 {
+    return Err(NetlinkError::NoSuchInterface(interface.to_string()));
+  }
+
+  let socket = NetlinkSocket::new()?;
+  socket.send(&dump_request(request_type, ifindex))?;
+
+  let mut result = Vec::new();
+  let mut buffer = vec![0u8; RECV_BUFFER_SIZE];
+  loop {
+    let len = socket.recv(&mut buffer)?;
+    let mut offset = 0;
+    while offset + NLMSG_HDRLEN <= len {
+      let msg_len = read_u32(&buffer, offset) as usize;
+      let msg_type = attributes::read_u16(&buffer, offset + 4);
+      if msg_len < NLMSG_HDRLEN || offset + msg_len > len {
+        return Err(NetlinkError::Truncated);
+      }
+      let payload = &buffer[offset + NLMSG_HDRLEN..offset + msg_len];
+      match msg_type {
+        NLMSG_DONE => return Ok(result),
+        NLMSG_ERROR => {
+          let code = read_u32(payload, 0) as i32;
+          if code != 0 {
+            return Err(NetlinkError::Kernel(
+              std::io::Error::from_raw_os_error(-code),
+            ));
+          }
+        }
+        t if t == reply_type => {
+          if let Some(qdisc) = parse_qdisc(payload) {
+            result.push(qdisc);
+          }
+        }
+        _ => {}
+      }
+      offset += align4(msg_len);
+    }
+  }
+}
+
+/// Builds a dump request (`RTM_GETQDISC` or `RTM_GETTCLASS`) for one
+/// interface.
+fn dump_request(request_type: u16, ifindex: u32) -> Vec<u8> {
+  let len = NLMSG_HDRLEN + TCMSG_LEN;
+  let mut request = Vec::with_capacity(len);
+  // struct nlmsghdr
+  request.extend_from_slice(&(len as u32).to_ne_bytes());
+  request.extend_from_slice(&request_type.to_ne_bytes());
+  request.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+  request.extend_from_slice(&1u32.to_ne_bytes()); // Sequence
+  request.extend_from_slice(&0u32.to_ne_bytes()); // Port ID
+  // struct tcmsg
+  request.extend_from_slice(&[libc::AF_UNSPEC as u8, 0, 0, 0]);
+  request.extend_from_slice(&ifindex.to_ne_bytes());
+  request.extend_from_slice(&0u32.to_ne_bytes()); // Handle
+  request.extend_from_slice(&0u32.to_ne_bytes()); // Parent
+  request.extend_from_slice(&0u32.to_ne_bytes()); // Info
+  request
+}
+
+pub(crate) fn parse_qdisc(payload: &[u8]) -> Option<NetlinkQdisc> {
+  if payload.len() < TCMSG_LEN {
+    return None;
+  }
+  let mut qdisc = NetlinkQdisc {
+    handle: read_u32(payload, 8),
+    parent: read_u32(payload, 12),
+    ..Default::default()
+  };
+  for attribute in attributes(&payload[TCMSG_LEN..]) {
+    match attribute.kind {
+      TCA_KIND => qdisc.kind = attribute.as_string(),
+      TCA_OPTIONS => qdisc.options = attribute.payload.to_vec(),
+      TCA_XSTATS if qdisc.xstats.is_empty() => {
+        qdisc.xstats = attribute.payload.to_vec()
+      }
+      TCA_STATS2 => parse_stats(&attribute, &mut qdisc),
+      _ => {}
+    }
+  }
+  Some(qdisc)
+}
+
+fn parse_stats(stats: &Attribute, qdisc: &mut NetlinkQdisc) {
+  for attribute in stats.nested() {
+    match attribute.kind {
+      TCA_STATS_BASIC => {
+        // struct gnet_stats_basic
+        qdisc.bytes = attribute.as_u64();
+        if qdisc.packets == 0 {
+          qdisc.packets = read_u32(attribute.payload, 8) as u64;
+        }
+      }
+      TCA_STATS_PKT64 => qdisc.packets = attribute.as_u64(),
+      TCA_STATS_QUEUE => {
+        // struct gnet_stats_queue
+        let p = attribute.payload;
+        qdisc.qlen = read_u32(p, 0);
+        qdisc.backlog = read_u32(p, 4);
+        qdisc.drops = read_u32(p, 8);
+        qdisc.requeues = read_u32(p, 12);
+        qdisc.overlimits = read_u32(p, 16);
+      }
+      TCA_STATS_APP => qdisc.xstats = attribute.payload.to_vec(),
+      _ => {}
+    }
+  }
+}
+
+/// A `NETLINK_ROUTE` socket, closed on drop.
+struct NetlinkSocket {
+  fd: i32,
+}
+
+impl NetlinkSocket {
+  fn new() -> Result<Self, NetlinkError> {
+    let fd = unsafe {
+      libc::socket(
+        libc::AF_NETLINK,
+        libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+        libc::NETLINK_ROUTE,
+      )
+    };
+    if fd < 0 {
+      return Err(NetlinkError::Socket(std::io::Error::last_os_error()));
+    }
+    let socket = Self { fd };
+    let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as u16;
+    let bound = unsafe {
+      libc::bind(
+        fd,
+        &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+        std::mem::size_of::<libc::sockaddr_nl>() as u32,
+      )
+    };
+    if bound < 0 {
+      return Err(NetlinkError::Socket(std::io::Error::last_os_error()));
+    }
+    Ok(socket)
+  }
+
+  fn send(&self, message: &[u8]) -> Result<(), NetlinkError> {
+    let sent = unsafe {
+      libc::send(self.fd, message.as_ptr() as *const libc::c_void, message.len(), 0)
+    };
+    if sent < 0 {
+      return Err(NetlinkError::Socket(std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn recv(&self, buffer: &mut [u8]) -> Result<usize, NetlinkError> {
+    let received = unsafe {
+      libc::recv(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+    };
+    if received < 0 {
+      return Err(NetlinkError::Socket(std::io::Error::last_os_error()));
+    }
+    Ok(received as usize)
+  }
+}
+
+impl Drop for NetlinkSocket {
+  fn drop(&mut self) {
+    unsafe {
+      libc::close(self.fd);
+    }
+  }
+}
+
+#[derive(Error, Debug)]
+pub enum NetlinkError {
+  #[error("No such interface: {0}")]
+  NoSuchInterface(String),
+  #[error("Netlink socket error")]
+  Socket(std::io::Error),
+  #[error("The kernel returned an error")]
+  Kernel(std::io::Error),
+  #[error("Truncated netlink message")]
+  Truncated,
+}
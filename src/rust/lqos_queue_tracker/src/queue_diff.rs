@@ -1,4 +1,4 @@
-use crate::queue_types::QueueType;
+use crate::queue_types::{BasicCounters, QueueType, TcHtbClass};
 use log::error;
 use serde::Serialize;
 use thiserror::Error;
@@ -6,11 +6,18 @@ use thiserror::Error;
 #[derive(Debug, Clone, Serialize)]
 pub enum QueueDiff {
   None,
-  //    Mq,
-  //    Htb,
-  //    FqCodel,
+  Mq(BasicDiff),
+  Htb(BasicDiff),
+  FqCodel(BasicDiff),
   Cake(CakeDiff),
-  //    ClsAct,
+  Fq(BasicDiff),
+  FqPie(BasicDiff),
+  Sfq(BasicDiff),
+  Pfifo(BasicDiff),
+  Bfifo(BasicDiff),
+  PfifoFast(BasicDiff),
+  Tbf(BasicDiff),
+  Generic(BasicDiff),
 }
 
 pub(crate) fn make_queue_diff(
@@ -20,11 +27,19 @@ pub(crate) fn make_queue_diff(
   match previous {
     QueueType::Cake(..) => match current {
       QueueType::Cake(..) => Ok(cake_diff(previous, current)?),
-      _ => {
-        error!("Queue diffs are not implemented for Cake to {:?}", current);
-        Err(QueueDiffError::NotImplemented)
-      }
+      _ => Err(QueueDiffError::KindChanged),
     },
+    QueueType::Mq(..)
+    | QueueType::Htb(..)
+    | QueueType::FqCodel(..)
+    | QueueType::Fq(..)
+    | QueueType::FqPie(..)
+    | QueueType::Sfq(..)
+    | QueueType::Pfifo(..)
+    | QueueType::Bfifo(..)
+    | QueueType::PfifoFast(..)
+    | QueueType::Tbf(..)
+    | QueueType::Generic(..) => basic_diff(previous, current),
     _ => {
       error!("Queue diffs are not implemented for {:?}", current);
       Err(QueueDiffError::NotImplemented)
@@ -79,8 +94,125 @@ fn cake_diff(
   Err(QueueDiffError::NotImplemented)
 }
 
+/// Diff of the statistics every qdisc reports. `marks` is only counted
+/// by the kinds that ECN mark (fq_codel, fq and fq_pie).
+#[derive(Serialize, Clone, Debug)]
+pub struct BasicDiff {
+  pub bytes: u64,
+  pub packets: u32,
+  pub drops: u32,
+  pub marks: u32,
+  pub backlog: u32,
+  pub qlen: u32,
+}
+
+fn basic_diff(
+  previous: &QueueType,
+  current: &QueueType,
+) -> Result<QueueDiff, QueueDiffError> {
+  if previous.kind() != current.kind() {
+    return Err(QueueDiffError::KindChanged);
+  }
+  let (prev, new): (BasicCounters, BasicCounters) =
+    match (previous.basic_counters(), current.basic_counters()) {
+      (Some(prev), Some(new)) => (prev, new),
+      _ => return Err(QueueDiffError::NotImplemented),
+    };
+  let diff = BasicDiff {
+    bytes: new.bytes.saturating_sub(prev.bytes),
+    packets: new.packets.saturating_sub(prev.packets),
+    drops: new.drops.saturating_sub(prev.drops),
+    marks: new.marks.saturating_sub(prev.marks),
+    backlog: new.backlog,
+    qlen: new.qlen,
+  };
+  Ok(match current {
+    QueueType::Mq(..) => QueueDiff::Mq(diff),
+    QueueType::Htb(..) => QueueDiff::Htb(diff),
+    QueueType::FqCodel(..) => QueueDiff::FqCodel(diff),
+    QueueType::Fq(..) => QueueDiff::Fq(diff),
+    QueueType::FqPie(..) => QueueDiff::FqPie(diff),
+    QueueType::Sfq(..) => QueueDiff::Sfq(diff),
+    QueueType::Pfifo(..) => QueueDiff::Pfifo(diff),
+    QueueType::Bfifo(..) => QueueDiff::Bfifo(diff),
+    QueueType::PfifoFast(..) => QueueDiff::PfifoFast(diff),
+    QueueType::Tbf(..) => QueueDiff::Tbf(diff),
+    _ => QueueDiff::Generic(diff),
+  })
+}
+
+/// Diff of an HTB class. `tokens` and `ctokens` are the current values;
+/// when they go negative, the class is over its rate or ceiling.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct HtbClassDiff {
+  pub bytes: u64,
+  pub packets: u32,
+  pub drops: u32,
+  pub overlimits: u32,
+  pub borrows: u32,
+  pub lends: u32,
+  pub tokens: i32,
+  pub ctokens: i32,
+}
+
+pub(crate) fn make_htb_class_diff(
+  previous: &TcHtbClass,
+  current: &TcHtbClass,
+) -> HtbClassDiff {
+  HtbClassDiff {
+    bytes: current.bytes.saturating_sub(previous.bytes),
+    packets: current.packets.saturating_sub(previous.packets),
+    drops: current.drops.saturating_sub(previous.drops),
+    overlimits: current.overlimits.saturating_sub(previous.overlimits),
+    borrows: current.borrows.saturating_sub(previous.borrows),
+    lends: current.lends.saturating_sub(previous.lends),
+    tokens: current.tokens,
+    ctokens: current.ctokens,
+  }
+}
+
 #[derive(Debug, Error)]
 pub enum QueueDiffError {
   #[error("Not implemented")]
   NotImplemented,
+  #[error("The qdisc kind changed")]
+  KindChanged,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::deserialize_tc_tree;
+
+  fn sfq(bytes: u64, drops: u32) -> QueueType {
+    let json = format!(
+      r#"[{{"kind":"sfq","handle":"20:","parent":"1:2","options":{{"limit":127}},"bytes":{bytes},"packets":10,"drops":{drops},"overlimits":0,"requeues":0,"backlog":0,"qlen":3}}]"#
+    );
+    deserialize_tc_tree(&json).unwrap().remove(0)
+  }
+
+  #[test]
+  fn basic_diff_subtracts_counters() {
+    let diff = make_queue_diff(&sfq(1000, 2), &sfq(1500, 5)).unwrap();
+    if let QueueDiff::Sfq(diff) = diff {
+      assert_eq!(diff.bytes, 500);
+      assert_eq!(diff.drops, 3);
+      assert_eq!(diff.qlen, 3);
+    } else {
+      panic!("Expected an SFQ diff");
+    }
+  }
+
+  #[test]
+  fn basic_diff_rejects_kind_change() {
+    let pfifo = deserialize_tc_tree(
+      r#"[{"kind":"pfifo","handle":"20:","parent":"1:2","options":{"limit":100},"bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}]"#,
+    )
+    .unwrap()
+    .remove(0);
+    assert!(matches!(
+      make_queue_diff(&sfq(0, 0), &pfifo),
+      Err(QueueDiffError::KindChanged)
+    ));
+  }
 }
@@ -0,0 +1,275 @@
+//! On-disk queue history. The queue sweep's per-circuit diffs are
+//! appended to one JSON-lines file per hour, named after the Unix time at
+//! which the hour starts. Whole files are removed to enforce retention.
+
+use log::warn;
+use lqos_bus::{BusResponse, QueueHistoryRecord};
+use lqos_config::QueueHistoryConfig;
+use lqos_utils::unix_time::unix_now;
+use std::{
+  fs::{self, OpenOptions},
+  io::{BufRead, BufReader, Write},
+  path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const SECONDS_PER_HOUR: u64 = 3600;
+const FILE_PREFIX: &str = "queues-";
+const FILE_SUFFIX: &str = ".jsonl";
+
+/// The longest range that can be read at once. Reading happens on the
+/// bus, so a request for weeks of history mustn't tie it up.
+const MAX_RANGE_SECS: u64 = 24 * SECONDS_PER_HOUR;
+
+fn hour_of(unix_time: u64) -> u64 {
+  unix_time - (unix_time % SECONDS_PER_HOUR)
+}
+
+fn file_for_hour(directory: &Path, hour: u64) -> PathBuf {
+  directory.join(format!("{FILE_PREFIX}{hour}{FILE_SUFFIX}"))
+}
+
+/// Every history file in `directory`, as `(hour, path, size)`, oldest
+/// first.
+fn history_files(
+  directory: &Path,
+) -> Result<Vec<(u64, PathBuf, u64)>, QueueHistoryError> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(directory)? {
+    let entry = entry?;
+    let name = entry.file_name();
+    let hour = name
+      .to_str()
+      .and_then(|n| n.strip_prefix(FILE_PREFIX))
+      .and_then(|n| n.strip_suffix(FILE_SUFFIX))
+      .and_then(|n| n.parse::<u64>().ok());
+    if let Some(hour) = hour {
+      files.push((hour, entry.path(), entry.metadata()?.len()));
+    }
+  }
+  files.sort_by(|a, b| a.0.cmp(&b.0));
+  Ok(files)
+}
+
+/// Appends a sweep's worth of records to the file for their hour.
+pub(crate) fn append(
+  directory: &Path,
+  records: &[QueueHistoryRecord],
+) -> Result<(), QueueHistoryError> {
+  if records.is_empty() {
+    return Ok(());
+  }
+  fs::create_dir_all(directory)?;
+  // Records from one sweep share a timestamp, so they share a file.
+  let path = file_for_hour(directory, hour_of(records[0].unix_time));
+  let mut lines = String::new();
+  for record in records.iter() {
+    lines += &serde_json::to_string(record)?;
+    lines.push('\n');
+  }
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  file.write_all(lines.as_bytes())?;
+  Ok(())
+}
+
+/// Removes files that have aged out, then removes the oldest files until
+/// the history fits in `max_bytes`. The current hour's file is never
+/// removed.
+pub(crate) fn prune(
+  directory: &Path,
+  now: u64,
+  retention_hours: u64,
+  max_bytes: u64,
+) -> Result<(), QueueHistoryError> {
+  let current_hour = hour_of(now);
+  let oldest_kept =
+    current_hour.saturating_sub(retention_hours * SECONDS_PER_HOUR);
+  let files = history_files(directory)?;
+  let mut total: u64 = files.iter().map(|f| f.2).sum();
+  for (hour, path, size) in files.iter() {
+    if *hour >= current_hour {
+      break;
+    }
+    if *hour < oldest_kept || total > max_bytes {
+      fs::remove_file(path)?;
+      total -= size;
+    }
+  }
+  Ok(())
+}
+
+/// Reads a circuit's records between `start` and `end`, inclusive,
+/// oldest first.
+pub(crate) fn read_range(
+  directory: &Path,
+  circuit_id: &str,
+  start: u64,
+  end: u64,
+) -> Result<Vec<QueueHistoryRecord>, QueueHistoryError> {
+  // Other circuits' records are skipped without parsing them
+  let needle =
+    format!("\"circuit_id\":{}", serde_json::to_string(circuit_id)?);
+  let mut result = Vec::new();
+  for (hour, path, _) in history_files(directory)? {
+    if hour < hour_of(start) || hour > end {
+      continue;
+    }
+    let reader = BufReader::new(fs::File::open(path)?);
+    for line in reader.lines() {
+      let line = line?;
+      if !line.contains(&needle) {
+        continue;
+      }
+      // A partially written final line (from a crash or a full disk) is
+      // skipped rather than failing the whole export.
+      if let Ok(record) = serde_json::from_str::<QueueHistoryRecord>(&line) {
+        if record.circuit_id == circuit_id
+          && record.unix_time >= start
+          && record.unix_time <= end
+        {
+          result.push(record);
+        }
+      }
+    }
+  }
+  Ok(result)
+}
+
+/// Writes a sweep's records and enforces the retention limits.
+pub(crate) fn store_sweep(
+  config: &QueueHistoryConfig,
+  records: &[QueueHistoryRecord],
+) {
+  let directory = Path::new(&config.directory);
+  if let Err(e) = append(directory, records) {
+    warn!("Unable to write queue history to {}: {e:?}", config.directory);
+    return;
+  }
+  if let Ok(now) = unix_now() {
+    if let Err(e) = prune(
+      directory,
+      now,
+      config.retention_hours,
+      config.max_megabytes * 1024 * 1024,
+    ) {
+      warn!("Unable to prune queue history: {e:?}");
+    }
+  }
+}
+
+/// The history settings from `/etc/lqos.conf`, if history is enabled.
+pub(crate) fn history_config() -> Option<QueueHistoryConfig> {
+  lqos_config::EtcLqos::load()
+    .ok()
+    .and_then(|cfg| cfg.queue_history)
+    .filter(|history| history.enabled)
+}
+
+/// Retrieves a circuit's on-disk queue history between two Unix times,
+/// at most `MAX_RANGE_SECS` apart.
+pub fn get_queue_history(
+  circuit_id: &str,
+  start: u64,
+  end: u64,
+) -> BusResponse {
+  if end < start || end - start > MAX_RANGE_SECS {
+    return BusResponse::Fail(format!(
+      "Queue history can be read {} hours at a time",
+      MAX_RANGE_SECS / SECONDS_PER_HOUR
+    ));
+  }
+  if let Some(config) = history_config() {
+    match read_range(Path::new(&config.directory), circuit_id, start, end) {
+      Ok(records) => BusResponse::QueueHistory(records),
+      Err(e) => {
+        warn!("Unable to read queue history: {e:?}");
+        BusResponse::Fail("Unable to read queue history".to_string())
+      }
+    }
+  } else {
+    BusResponse::Fail("Queue history is not enabled".to_string())
+  }
+}
+
+#[derive(Error, Debug)]
+pub enum QueueHistoryError {
+  #[error("Unable to access the queue history directory")]
+  Io(#[from] std::io::Error),
+  #[error("Unable to encode a queue history record")]
+  Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn test_directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("lqos_queue_history_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  fn record(unix_time: u64, circuit_id: &str) -> QueueHistoryRecord {
+    QueueHistoryRecord {
+      unix_time,
+      circuit_id: circuit_id.to_string(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn reads_back_a_range() {
+    let dir = test_directory("range");
+    for t in [3500, 3700, 7300, 10900] {
+      append(&dir, &[record(t, "a"), record(t, "b"), record(t, "ab")])
+        .unwrap();
+    }
+    let times: Vec<u64> = read_range(&dir, "a", 3600, 7300)
+      .unwrap()
+      .iter()
+      .map(|r| r.unix_time)
+      .collect();
+    assert_eq!(times, vec![3700, 7300]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn refuses_long_ranges() {
+    assert!(matches!(
+      get_queue_history("a", 0, MAX_RANGE_SECS + 1),
+      BusResponse::Fail(_)
+    ));
+    assert!(matches!(get_queue_history("a", 10, 5), BusResponse::Fail(_)));
+  }
+
+  #[test]
+  fn skips_partial_lines() {
+    let dir = test_directory("partial");
+    append(&dir, &[record(100, "a")]).unwrap();
+    let mut file =
+      OpenOptions::new().append(true).open(file_for_hour(&dir, 0)).unwrap();
+    file.write_all(b"{\"unix_time\":2").unwrap();
+    assert_eq!(read_range(&dir, "a", 0, 200).unwrap().len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn prunes_by_age_and_size() {
+    let dir = test_directory("prune");
+    for hour in 0..5 {
+      append(&dir, &[record(hour * SECONDS_PER_HOUR, "a")]).unwrap();
+    }
+    let now = 4 * SECONDS_PER_HOUR;
+    prune(&dir, now, 3, u64::MAX).unwrap();
+    let hours = |dir: &Path| -> Vec<u64> {
+      history_files(dir).unwrap().iter().map(|f| f.0).collect()
+    };
+    assert_eq!(hours(&dir), vec![3600, 7200, 10800, 14400]);
+
+    // Too big for anything but the current hour
+    prune(&dir, now, 3, 1).unwrap();
+    assert_eq!(hours(&dir), vec![14400]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
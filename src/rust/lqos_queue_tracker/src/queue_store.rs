@@ -1,5 +1,5 @@
 use crate::{
-  queue_diff::{make_queue_diff, CakeDiffTin, QueueDiff},
+  queue_diff::{make_queue_diff, BasicDiff, CakeDiffTin, QueueDiff},
   queue_types::{
     QueueType,
   },
@@ -33,6 +33,15 @@ impl QueueStore {
   }
 
   pub(crate) fn update(&mut self, download: &QueueType, upload: &QueueType) {
+    // If the circuit has been rebuilt with a different qdisc, the old
+    // history can't be compared with the new one. Start again.
+    if download.kind() != self.current_download.kind()
+      || upload.kind() != self.current_upload.kind()
+    {
+      *self = Self::new(download.clone(), upload.clone());
+      return;
+    }
+
     self.prev_upload = Some(self.current_upload.clone());
     self.prev_download = Some(self.current_download.clone());
     self.current_download = download.clone();
@@ -77,18 +86,48 @@ impl Into<QueueStoreTransit> for QueueStore {
   }
 }
 
+/// CAKE diffs are sent tin by tin. Every other kind is sent as a
+/// single tin, so that the web UI can plot any kind of queue.
 #[allow(clippy::from_over_into)]
 impl Into<CakeDiffTransit> for QueueDiff {
   fn into(self) -> CakeDiffTransit {
-    if let QueueDiff::Cake(c) = &self {
-      CakeDiffTransit {
+    match self {
+      QueueDiff::None => CakeDiffTransit::default(),
+      QueueDiff::Cake(c) => CakeDiffTransit {
         bytes: c.bytes,
         packets: c.packets,
         qlen: c.qlen,
         tins: c.tins.iter().cloned().map(|t| t.into()).collect(),
-      }
-    } else {
-      CakeDiffTransit::default()
+      },
+      QueueDiff::Mq(d)
+      | QueueDiff::Htb(d)
+      | QueueDiff::FqCodel(d)
+      | QueueDiff::Fq(d)
+      | QueueDiff::FqPie(d)
+      | QueueDiff::Sfq(d)
+      | QueueDiff::Pfifo(d)
+      | QueueDiff::Bfifo(d)
+      | QueueDiff::PfifoFast(d)
+      | QueueDiff::Tbf(d)
+      | QueueDiff::Generic(d) => d.into(),
+    }
+  }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<CakeDiffTransit> for BasicDiff {
+  fn into(self) -> CakeDiffTransit {
+    CakeDiffTransit {
+      bytes: self.bytes,
+      packets: self.packets,
+      qlen: self.qlen,
+      tins: vec![CakeDiffTinTransit {
+        sent_bytes: self.bytes,
+        backlog_bytes: self.backlog,
+        drops: self.drops,
+        marks: self.marks,
+        avg_delay_us: 0,
+      }],
     }
   }
 }
@@ -111,6 +150,7 @@ impl Into<CakeTransit> for QueueType {
   fn into(self) -> CakeTransit {
     if let QueueType::Cake(c) = self {
       CakeTransit {
+        kind: "cake".to_string(),
         //handle: c.handle,
         //parent: c.parent,
         //options: c.options.into(),
@@ -132,7 +172,7 @@ impl Into<CakeTransit> for QueueType {
         //drops: c.drops,
       }
     } else {
-      CakeTransit::default()
+      CakeTransit { kind: self.kind().to_string(), ..Default::default() }
     }
   }
 }
@@ -186,3 +226,41 @@ impl Into<CakeTinTransit> for TcCakeTin {
   }
 }
 */
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::deserialize_tc_tree;
+
+  fn queue(kind: &str, bytes: u64) -> QueueType {
+    let json = format!(
+      r#"[{{"kind":"{kind}","handle":"20:","parent":"1:2","options":{{}},"bytes":{bytes},"packets":10,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}}]"#
+    );
+    deserialize_tc_tree(&json).unwrap().remove(0)
+  }
+
+  #[test]
+  fn fq_codel_history_is_recorded() {
+    let mut store =
+      QueueStore::new(queue("fq_codel", 100), queue("fq_codel", 100));
+    store.update(&queue("fq_codel", 600), &queue("fq_codel", 300));
+    assert_eq!(store.history_head, 1);
+    let transit: QueueStoreTransit = store.into();
+    assert_eq!(transit.history[0].0.bytes, 500);
+    assert_eq!(transit.history[0].0.tins.len(), 1);
+    assert_eq!(transit.history[0].1.tins[0].sent_bytes, 200);
+    assert_eq!(transit.current_download.kind, "fq_codel");
+  }
+
+  #[test]
+  fn kind_change_resets_history() {
+    let mut store =
+      QueueStore::new(queue("fq_codel", 100), queue("fq_codel", 100));
+    store.update(&queue("fq_codel", 600), &queue("fq_codel", 300));
+    store.update(&queue("sfq", 50), &queue("sfq", 50));
+    assert_eq!(store.history_head, 0);
+    assert!(store.history.iter().all(|(d, _)| matches!(d, QueueDiff::None)));
+    store.update(&queue("sfq", 80), &queue("sfq", 60));
+    assert!(matches!(store.history[0].0, QueueDiff::Sfq(..)));
+  }
+}
@@ -1,11 +1,15 @@
 mod queing_structure_json_monitor;
 mod queue_network;
 mod queue_node;
+mod query;
 use log::error;
 pub use queing_structure_json_monitor::spawn_queue_structure_monitor;
 pub(crate) use queing_structure_json_monitor::QUEUE_STRUCTURE;
-use queue_network::QueueNetwork;
-use queue_node::QueueNode;
+pub(crate) use queue_network::QueueNetwork;
+pub(crate) use queue_node::QueueNode;
+pub(crate) use query::{
+  circuit_queue_info, circuits_on_cpu, circuits_under_class, cpu_totals,
+};
 use thiserror::Error;
 
 pub(crate) fn read_queueing_structure(
@@ -0,0 +1,182 @@
+use super::QueueNode;
+use lqos_bus::{CircuitQueueInfo, CpuQueueTotals, QueueNodeSummary, TcHandle};
+use std::collections::{BTreeMap, HashMap};
+
+impl From<&QueueNode> for QueueNodeSummary {
+  fn from(node: &QueueNode) -> Self {
+    Self {
+      name: if node.circuit_id.is_some() {
+        node.circuit_name.clone()
+      } else {
+        node.name.clone()
+      },
+      circuit_id: node.circuit_id.clone(),
+      class_id: node.class_id,
+      up_class_id: node.up_class_id,
+      parent_class_id: node.parent_class_id,
+      up_parent_class_id: node.up_parent_class_id,
+      cpu: node.cpu_num,
+      up_cpu: node.up_cpu_num,
+      download_mbps: (
+        node.download_bandwidth_mbps_min,
+        node.download_bandwidth_mbps,
+      ),
+      upload_mbps: (
+        node.upload_bandwidth_mbps_min,
+        node.upload_bandwidth_mbps,
+      ),
+    }
+  }
+}
+
+fn circuits(queues: &[QueueNode]) -> impl Iterator<Item = &QueueNode> {
+  queues.iter().filter(|q| q.circuit_id.is_some())
+}
+
+/// Network nodes by download class. Devices share their circuit's queue
+/// and have no class of their own, so they are left out.
+fn nodes_by_class(queues: &[QueueNode]) -> HashMap<TcHandle, &QueueNode> {
+  queues
+    .iter()
+    .filter(|q| q.circuit_id.is_none() && q.class_id.as_u32() != 0)
+    .map(|q| (q.class_id, q))
+    .collect()
+}
+
+/// The nodes above `node`, nearest first. Stops at the top of the tree,
+/// which is parented to the per-CPU root class LibreQoS creates outside of
+/// `queuingStructure.json`.
+fn parent_chain<'a>(
+  node: &QueueNode,
+  nodes: &HashMap<TcHandle, &'a QueueNode>,
+) -> Vec<&'a QueueNode> {
+  let mut chain = Vec::new();
+  let mut parent = node.parent_class_id;
+  while let Some(p) = nodes.get(&parent) {
+    // A malformed file could loop; no real tree is deeper than this.
+    if chain.len() > nodes.len() {
+      break;
+    }
+    chain.push(*p);
+    parent = p.parent_class_id;
+  }
+  chain
+}
+
+pub(crate) fn circuit_queue_info(
+  queues: &[QueueNode],
+  circuit_id: &str,
+) -> Option<CircuitQueueInfo> {
+  let circuit =
+    circuits(queues).find(|q| q.circuit_id.as_deref() == Some(circuit_id))?;
+  let nodes = nodes_by_class(queues);
+  Some(CircuitQueueInfo {
+    circuit: circuit.into(),
+    parents: parent_chain(circuit, &nodes)
+      .into_iter()
+      .map(QueueNodeSummary::from)
+      .collect(),
+  })
+}
+
+pub(crate) fn circuits_on_cpu(
+  queues: &[QueueNode],
+  cpu: u32,
+) -> Vec<QueueNodeSummary> {
+  circuits(queues).filter(|q| q.cpu_num == cpu).map(|q| q.into()).collect()
+}
+
+/// Circuits anywhere below `class_id`, not just its direct children.
+pub(crate) fn circuits_under_class(
+  queues: &[QueueNode],
+  class_id: TcHandle,
+) -> Vec<QueueNodeSummary> {
+  let nodes = nodes_by_class(queues);
+  circuits(queues)
+    .filter(|q| {
+      q.parent_class_id == class_id
+        || parent_chain(q, &nodes).iter().any(|p| p.class_id == class_id)
+    })
+    .map(|q| q.into())
+    .collect()
+}
+
+/// Circuit counts and plan totals per CPU, in CPU order. Circuits are
+/// counted against the CPU that handles their download queue.
+pub(crate) fn cpu_totals(queues: &[QueueNode]) -> Vec<CpuQueueTotals> {
+  let mut totals: BTreeMap<u32, CpuQueueTotals> = BTreeMap::new();
+  for circuit in circuits(queues) {
+    let entry = totals.entry(circuit.cpu_num).or_insert(CpuQueueTotals {
+      cpu: circuit.cpu_num,
+      ..Default::default()
+    });
+    entry.circuits += 1;
+    entry.download_mbps.0 += circuit.download_bandwidth_mbps_min;
+    entry.download_mbps.1 += circuit.download_bandwidth_mbps;
+    entry.upload_mbps.0 += circuit.upload_bandwidth_mbps_min;
+    entry.upload_mbps.1 += circuit.upload_bandwidth_mbps;
+  }
+  totals.into_values().collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::queue_structure::QueueNetwork;
+
+  const EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN: &str =
+    include_str!("./example_queue_with_children.test.json");
+
+  fn queues() -> Vec<QueueNode> {
+    QueueNetwork::from_json_str(EXAMPLE_QUEUE_STRUCTURE_WITH_CHILDREN)
+      .unwrap()
+      .to_flat()
+  }
+
+  #[test]
+  fn circuit_inherits_parent_and_cpu() {
+    let info = circuit_queue_info(&queues(), "9999").unwrap();
+    assert_eq!(info.circuit.class_id, TcHandle::from_string("2:6").unwrap());
+    assert_eq!(
+      info.circuit.parent_class_id,
+      TcHandle::from_string("2:5").unwrap()
+    );
+    assert_eq!(info.circuit.cpu, 1);
+    assert_eq!(info.circuit.download_mbps, (27, 200));
+    let parents: Vec<_> =
+      info.parents.iter().map(|p| p.name.clone().unwrap()).collect();
+    assert_eq!(parents, vec!["PoP_1", "Site_2"]);
+  }
+
+  #[test]
+  fn unknown_circuit_has_no_info() {
+    assert!(circuit_queue_info(&queues(), "not-a-circuit").is_none());
+  }
+
+  #[test]
+  fn finds_circuits_by_cpu_and_class() {
+    let queues = queues();
+    let on_cpu: Vec<_> = circuits_on_cpu(&queues, 1)
+      .into_iter()
+      .map(|c| c.circuit_id.unwrap())
+      .collect();
+    assert!(on_cpu.contains(&"9999".to_string()));
+    assert!(!on_cpu.contains(&"104".to_string()));
+
+    let site_2 = TcHandle::from_string("2:3").unwrap();
+    let under: Vec<_> = circuits_under_class(&queues, site_2)
+      .into_iter()
+      .map(|c| c.circuit_id.unwrap())
+      .collect();
+    assert_eq!(under, vec!["9999".to_string()]);
+  }
+
+  #[test]
+  fn totals_match_circuits() {
+    let queues = queues();
+    let totals = cpu_totals(&queues);
+    assert!(totals.windows(2).all(|w| w[0].cpu < w[1].cpu));
+    let count: u32 = totals.iter().map(|t| t.circuits).sum();
+    assert_eq!(count as usize, circuits(&queues).count());
+  }
+}
@@ -36,14 +36,22 @@ impl QueueNetwork {
     }
     let raw_string = std::fs::read_to_string(path)
       .map_err(|_| QueueStructureError::FileNotFound)?;
+    Self::from_json_str(&raw_string)
+  }
+
+  pub(crate) fn from_json_str(
+    raw_string: &str,
+  ) -> Result<Self, QueueStructureError> {
     let mut result = Self { cpu_node: Vec::new() };
-    let json: Value = serde_json::from_str(&raw_string)
+    let json: Value = serde_json::from_str(raw_string)
       .map_err(|_| QueueStructureError::FileNotFound)?;
     if let Value::Object(map) = &json {
       if let Some(network) = map.get("Network") {
         if let Value::Object(map) = network {
           for (key, value) in map.iter() {
-            result.cpu_node.push(QueueNode::from_json(key, value)?);
+            let mut node = QueueNode::from_json(key, value)?;
+            node.name = Some(key.to_string());
+            result.cpu_node.push(node);
           }
         } else {
           error!("Unable to parse JSON for queueStructure");
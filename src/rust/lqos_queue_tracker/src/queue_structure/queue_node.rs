@@ -29,6 +29,8 @@ pub struct QueueNode {
   pub device_name: Option<String>,
   pub mac: Option<String>,
   pub children: Vec<QueueNode>,
+  /// The node's key in `network.json`, for sites and access points.
+  pub name: Option<String>,
 }
 
 /// Provides a convenient wrapper that attempts to decode a u64 from a JSON
@@ -213,7 +215,9 @@ impl QueueNode {
                   error!("{:?}", n);
                   return Err(QueueStructureError::Children);
                 }
-                result.circuits.push(n.unwrap());
+                let mut n = n.unwrap();
+                n.name = Some(key.to_string());
+                result.circuits.push(n);
               }
             } else {
               log::warn!("Children was not an object");
@@ -226,6 +230,16 @@ impl QueueNode {
           _ => log::error!("I don't know how to parse key: [{key}]"),
         }
       }
+      // Circuits don't list their parent class or CPU: they are those of
+      // the node that the circuit is attached to.
+      for circuit in
+        result.circuits.iter_mut().filter(|c| c.circuit_id.is_some())
+      {
+        circuit.parent_class_id = result.class_id;
+        circuit.up_parent_class_id = result.up_class_id;
+        circuit.cpu_num = result.cpu_num;
+        circuit.up_cpu_num = result.up_cpu_num;
+      }
     } else {
       log::warn!("Unable to parse node structure for [{key}]");
     }
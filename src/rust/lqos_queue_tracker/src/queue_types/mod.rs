@@ -1,8 +1,18 @@
 pub(crate) mod tc_cake;
+mod tc_fifo;
+mod tc_fq;
 mod tc_fq_codel;
+mod tc_fq_pie;
+mod tc_generic;
 mod tc_htb;
+mod tc_htb_class;
 mod tc_mq;
+mod tc_sfq;
+mod tc_tbf;
+use crate::netlink::NetlinkQdisc;
+pub(crate) use tc_htb_class::TcHtbClass;
 use log::warn;
+use log_once::info_once;
 use serde::Serialize;
 use serde_json::Value;
 use thiserror::Error;
@@ -14,10 +24,44 @@ pub enum QueueType {
   FqCodel(tc_fq_codel::TcFqCodel),
   Cake(tc_cake::TcCake),
   ClsAct,
+  Fq(tc_fq::TcFq),
+  FqPie(tc_fq_pie::TcFqPie),
+  Sfq(tc_sfq::TcSfq),
+  Pfifo(tc_fifo::TcFifo),
+  Bfifo(tc_fifo::TcFifo),
+  PfifoFast(tc_fifo::TcPfifoFast),
+  Tbf(tc_tbf::TcTbf),
+  Generic(tc_generic::TcGeneric),
+}
+
+/// The statistics that every qdisc kind reports, plus ECN marks where
+/// the kind counts them.
+#[derive(Default, Clone, Copy, Debug)]
+pub(crate) struct BasicCounters {
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  pub(crate) marks: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+/// Builds `BasicCounters` from any qdisc struct with the usual fields.
+macro_rules! basic_counters {
+  ($q: expr, $marks: expr) => {
+    BasicCounters {
+      bytes: $q.bytes,
+      packets: $q.packets,
+      drops: $q.drops,
+      marks: $marks,
+      backlog: $q.backlog,
+      qlen: $q.qlen,
+    }
+  };
 }
 
 impl QueueType {
-  fn parse(
+  pub(crate) fn parse(
     kind: &str,
     map: &serde_json::Map<std::string::String, Value>,
   ) -> Result<QueueType, QDiscError> {
@@ -29,12 +73,87 @@ impl QueueType {
       }
       "cake" => Ok(QueueType::Cake(tc_cake::TcCake::from_json(map)?)),
       "clsact" => Ok(QueueType::ClsAct),
+      "fq" => Ok(QueueType::Fq(tc_fq::TcFq::from_json(map)?)),
+      "fq_pie" => Ok(QueueType::FqPie(tc_fq_pie::TcFqPie::from_json(map)?)),
+      "sfq" => Ok(QueueType::Sfq(tc_sfq::TcSfq::from_json(map)?)),
+      "pfifo" => Ok(QueueType::Pfifo(tc_fifo::TcFifo::from_json(map)?)),
+      "bfifo" => Ok(QueueType::Bfifo(tc_fifo::TcFifo::from_json(map)?)),
+      "pfifo_fast" => {
+        Ok(QueueType::PfifoFast(tc_fifo::TcPfifoFast::from_json(map)?))
+      }
+      "tbf" => Ok(QueueType::Tbf(tc_tbf::TcTbf::from_json(map)?)),
       _ => {
-        warn!("I don't know how to parse qdisc type {kind}");
-        Err(QDiscError::UnknownQdisc(format!("Unknown queue kind: {kind}")))
+        info_once!("No decoder for qdisc type {kind}, keeping basic stats.");
+        Ok(QueueType::Generic(tc_generic::TcGeneric::from_json(kind, map)))
       }
     }
   }
+
+  pub(crate) fn from_netlink(
+    qdisc: &NetlinkQdisc,
+  ) -> Result<QueueType, QDiscError> {
+    match qdisc.kind.as_str() {
+      "mq" => Ok(QueueType::Mq(tc_mq::TcMultiQueue::from_netlink(qdisc))),
+      "htb" => Ok(QueueType::Htb(tc_htb::TcHtb::from_netlink(qdisc)?)),
+      "fq_codel" => {
+        Ok(QueueType::FqCodel(tc_fq_codel::TcFqCodel::from_netlink(qdisc)?))
+      }
+      "cake" => Ok(QueueType::Cake(tc_cake::TcCake::from_netlink(qdisc)?)),
+      "clsact" => Ok(QueueType::ClsAct),
+      "fq" => Ok(QueueType::Fq(tc_fq::TcFq::from_netlink(qdisc)?)),
+      "fq_pie" => {
+        Ok(QueueType::FqPie(tc_fq_pie::TcFqPie::from_netlink(qdisc)?))
+      }
+      "sfq" => Ok(QueueType::Sfq(tc_sfq::TcSfq::from_netlink(qdisc)?)),
+      "pfifo" => Ok(QueueType::Pfifo(tc_fifo::TcFifo::from_netlink(qdisc))),
+      "bfifo" => Ok(QueueType::Bfifo(tc_fifo::TcFifo::from_netlink(qdisc))),
+      "pfifo_fast" => {
+        Ok(QueueType::PfifoFast(tc_fifo::TcPfifoFast::from_netlink(qdisc)))
+      }
+      "tbf" => Ok(QueueType::Tbf(tc_tbf::TcTbf::from_netlink(qdisc)?)),
+      kind => {
+        info_once!("No decoder for qdisc type {kind}, keeping basic stats.");
+        Ok(QueueType::Generic(tc_generic::TcGeneric::from_netlink(qdisc)))
+      }
+    }
+  }
+
+  /// The qdisc kind, as `tc` names it.
+  pub(crate) fn kind(&self) -> &str {
+    match self {
+      QueueType::Mq(..) => "mq",
+      QueueType::Htb(..) => "htb",
+      QueueType::FqCodel(..) => "fq_codel",
+      QueueType::Cake(..) => "cake",
+      QueueType::ClsAct => "clsact",
+      QueueType::Fq(..) => "fq",
+      QueueType::FqPie(..) => "fq_pie",
+      QueueType::Sfq(..) => "sfq",
+      QueueType::Pfifo(..) => "pfifo",
+      QueueType::Bfifo(..) => "bfifo",
+      QueueType::PfifoFast(..) => "pfifo_fast",
+      QueueType::Tbf(..) => "tbf",
+      QueueType::Generic(q) => &q.kind,
+    }
+  }
+
+  /// The basic counters for every kind except CAKE, which has its own
+  /// per-tin diff, and clsact, which has no statistics.
+  pub(crate) fn basic_counters(&self) -> Option<BasicCounters> {
+    match self {
+      QueueType::Mq(q) => Some(basic_counters!(q, 0)),
+      QueueType::Htb(q) => Some(basic_counters!(q, 0)),
+      QueueType::FqCodel(q) => Some(basic_counters!(q, q.ecn_mark)),
+      QueueType::Fq(q) => Some(basic_counters!(q, q.ce_mark as u32)),
+      QueueType::FqPie(q) => Some(basic_counters!(q, q.ecn_mark)),
+      QueueType::Sfq(q) => Some(basic_counters!(q, 0)),
+      QueueType::Pfifo(q) | QueueType::Bfifo(q) => Some(basic_counters!(q, 0)),
+      QueueType::PfifoFast(q) => Some(basic_counters!(q, 0)),
+      QueueType::Tbf(q) => Some(basic_counters!(q, 0)),
+      QueueType::Generic(q) => Some(basic_counters!(q, 0)),
+      _ => None,
+    }
+  }
 }
 
 /// Separated into a separate function for cleaner benchmark code
@@ -61,10 +180,36 @@ pub fn deserialize_tc_tree(json: &str) -> Result<Vec<QueueType>, QDiscError> {
   Ok(result)
 }
 
+/// Deserializes only the qdiscs whose kind appears in `kinds`, skipping
+/// everything else. Used by the queue sweep, which only wants the leaf
+/// qdiscs from a whole-interface dump.
+pub(crate) fn deserialize_tc_tree_kinds(
+  json: &str,
+  kinds: &[&str],
+) -> Result<Vec<QueueType>, QDiscError> {
+  let mut result = Vec::new();
+  let json: Value = serde_json::from_str(json)
+    .map_err(|_| QDiscError::Json(json.to_string()))?;
+  if let Value::Array(array) = &json {
+    for entry in array.iter() {
+      if let Value::Object(map) = entry {
+        if let Some(kind) = map.get("kind").and_then(|k| k.as_str()) {
+          if kinds.contains(&kind) {
+            result.push(QueueType::parse(kind, map)?);
+          }
+        }
+      }
+    }
+  } else {
+    warn!("Failed to parse TC queue stats data array.");
+    return Err(QDiscError::ArrayInvalid);
+  }
+
+  Ok(result)
+}
+
 #[derive(Error, Debug)]
 pub enum QDiscError {
-  #[error("Unknown queue kind")]
-  UnknownQdisc(String),
   #[error("Error parsing queue information JSON")]
   Json(String),
   #[error("Unable to parse TC data array")]
@@ -77,6 +222,16 @@ pub enum QDiscError {
   HtbOpts,
   #[error("Unable to parse fq_codel options")]
   CodelOpts,
+  #[error("Unable to parse fq options")]
+  FqOpts,
+  #[error("Unable to parse fq_pie options")]
+  FqPieOpts,
+  #[error("Unable to parse sfq options")]
+  SfqOpts,
+  #[error("Unable to parse fifo options")]
+  FifoOpts,
+  #[error("Unable to parse tbf options")]
+  TbfOpts,
 }
 
 /// Used to extract TC handles without unwrapping.
@@ -99,3 +254,45 @@ macro_rules! parse_tc_handle {
     }
   };
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use lqos_bus::TcHandle;
+
+  #[test]
+  fn unknown_kinds_do_not_fail_the_tree() {
+    let json = r#"[
+      {"kind":"htb","handle":"1:","root":true,"options":{"r2q":10,"default":"0x2","direct_packets_stat":0},"bytes":10,"packets":1,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0},
+      {"kind":"choke","handle":"20:","parent":"1:2","options":{"limit":1000},"bytes":500,"packets":5,"drops":2,"overlimits":0,"requeues":0,"backlog":0,"qlen":1}
+    ]"#;
+    let tree = deserialize_tc_tree(json).unwrap();
+    assert_eq!(tree.len(), 2);
+    if let QueueType::Generic(generic) = &tree[1] {
+      assert_eq!(generic.kind, "choke");
+      assert_eq!(generic.parent, TcHandle::from_string("1:2").unwrap());
+      assert_eq!(generic.drops, 2);
+    } else {
+      panic!("Expected a generic qdisc, got {:?}", tree[1]);
+    }
+  }
+
+  #[test]
+  fn parse_new_kinds() {
+    let json = r#"[
+      {"kind":"fq","handle":"8001:","parent":"1:3","options":{"limit":10000,"flow_limit":100,"buckets":1024,"horizon_drop":true},"bytes":1350,"packets":15,"drops":1,"overlimits":0,"requeues":0,"backlog":0,"qlen":0,"flows":2,"ce_mark":4},
+      {"kind":"fq_pie","handle":"8002:","parent":"1:4","options":{"limit":10240,"flows":1024,"target":15000,"ecn":true},"bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0,"ecn_mark":7},
+      {"kind":"sfq","handle":"8003:","parent":"1:5","options":{"limit":127,"quantum":1514,"depth":127,"divisor":1024,"perturb":10},"bytes":0,"packets":0,"drops":3,"overlimits":0,"requeues":0,"backlog":0,"qlen":0},
+      {"kind":"pfifo_fast","handle":"8004:","root":true,"refcnt":2,"options":{"bands":3,"priomap":[1,2,2,2,1,2,0,0,1,1,1,1,1,1,1,1],"multiqueue":false},"bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0},
+      {"kind":"tbf","handle":"8005:","parent":"1:6","options":{"rate":1250000,"burst":32767,"lat":50000},"bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}
+    ]"#;
+    let tree = deserialize_tc_tree(json).unwrap();
+    assert_eq!(tree.len(), 5);
+    let marks: Vec<u32> =
+      tree.iter().map(|q| q.basic_counters().unwrap().marks).collect();
+    assert_eq!(marks, vec![4, 7, 0, 0, 0]);
+    assert_eq!(tree[2].basic_counters().unwrap().drops, 3);
+    assert!(matches!(tree[3], QueueType::PfifoFast(..)));
+    assert!(matches!(tree[4], QueueType::Tbf(..)));
+  }
+}
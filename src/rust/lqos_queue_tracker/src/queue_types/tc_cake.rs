@@ -1,5 +1,8 @@
 use super::QDiscError;
-use crate::parse_tc_handle;
+use crate::{
+  netlink::{attributes, Attribute, NetlinkQdisc},
+  parse_tc_handle,
+};
 use log::warn;
 use log_once::info_once;
 use lqos_bus::TcHandle;
@@ -23,6 +26,51 @@ dashy_table_enum!(
 );
 string_table_enum!(BandWidth, unlimited); // in the present implementation with htb, always unlimited
 
+// Netlink attribute types, from `linux/pkt_sched.h`
+const TCA_CAKE_BASE_RATE64: u16 = 2;
+const TCA_CAKE_DIFFSERV_MODE: u16 = 3;
+const TCA_CAKE_FLOW_MODE: u16 = 5;
+const TCA_CAKE_OVERHEAD: u16 = 6;
+const TCA_CAKE_RTT: u16 = 7;
+const TCA_CAKE_NAT: u16 = 11;
+const TCA_CAKE_RAW: u16 = 12;
+const TCA_CAKE_WASH: u16 = 13;
+const TCA_CAKE_INGRESS: u16 = 15;
+const TCA_CAKE_ACK_FILTER: u16 = 16;
+const TCA_CAKE_SPLIT_GSO: u16 = 17;
+const TCA_CAKE_FWMARK: u16 = 18;
+
+const TCA_CAKE_STATS_CAPACITY_ESTIMATE64: u16 = 2;
+const TCA_CAKE_STATS_MEMORY_LIMIT: u16 = 3;
+const TCA_CAKE_STATS_MEMORY_USED: u16 = 4;
+const TCA_CAKE_STATS_AVG_NETOFF: u16 = 5;
+const TCA_CAKE_STATS_MIN_NETLEN: u16 = 6;
+const TCA_CAKE_STATS_MAX_NETLEN: u16 = 7;
+const TCA_CAKE_STATS_MIN_ADJLEN: u16 = 8;
+const TCA_CAKE_STATS_MAX_ADJLEN: u16 = 9;
+const TCA_CAKE_STATS_TIN_STATS: u16 = 10;
+
+const TCA_CAKE_TIN_STATS_SENT_PACKETS: u16 = 2;
+const TCA_CAKE_TIN_STATS_SENT_BYTES64: u16 = 3;
+const TCA_CAKE_TIN_STATS_DROPPED_PACKETS: u16 = 4;
+const TCA_CAKE_TIN_STATS_ACKS_DROPPED_PACKETS: u16 = 6;
+const TCA_CAKE_TIN_STATS_ECN_MARKED_PACKETS: u16 = 8;
+const TCA_CAKE_TIN_STATS_BACKLOG_BYTES: u16 = 11;
+const TCA_CAKE_TIN_STATS_THRESHOLD_RATE64: u16 = 12;
+const TCA_CAKE_TIN_STATS_TARGET_US: u16 = 13;
+const TCA_CAKE_TIN_STATS_INTERVAL_US: u16 = 14;
+const TCA_CAKE_TIN_STATS_WAY_INDIRECT_HITS: u16 = 15;
+const TCA_CAKE_TIN_STATS_WAY_MISSES: u16 = 16;
+const TCA_CAKE_TIN_STATS_WAY_COLLISIONS: u16 = 17;
+const TCA_CAKE_TIN_STATS_PEAK_DELAY_US: u16 = 18;
+const TCA_CAKE_TIN_STATS_AVG_DELAY_US: u16 = 19;
+const TCA_CAKE_TIN_STATS_BASE_DELAY_US: u16 = 20;
+const TCA_CAKE_TIN_STATS_SPARSE_FLOWS: u16 = 21;
+const TCA_CAKE_TIN_STATS_BULK_FLOWS: u16 = 22;
+const TCA_CAKE_TIN_STATS_UNRESPONSIVE_FLOWS: u16 = 23;
+const TCA_CAKE_TIN_STATS_MAX_SKBLEN: u16 = 24;
+const TCA_CAKE_TIN_STATS_FLOW_QUANTUM: u16 = 25;
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct TcCake {
   pub(crate) handle: TcHandle,
@@ -148,7 +196,120 @@ impl TcCake {
   }
 }
 
+impl TcCake {
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Result<Self, QDiscError> {
+    let mut result = Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcCakeOptions::from_netlink(&qdisc.options)?,
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+      drops: qdisc.drops,
+      ..Default::default()
+    };
+    for attribute in attributes(&qdisc.xstats) {
+      match attribute.kind {
+        TCA_CAKE_STATS_CAPACITY_ESTIMATE64 => {
+          result.capacity_estimate = attribute.as_u64() as u32
+        }
+        TCA_CAKE_STATS_MEMORY_LIMIT => result.memory_limit = attribute.as_u32(),
+        TCA_CAKE_STATS_MEMORY_USED => result.memory_used = attribute.as_u32(),
+        TCA_CAKE_STATS_AVG_NETOFF => {
+          result.avg_hdr_offset = attribute.as_u32() as u16
+        }
+        TCA_CAKE_STATS_MIN_NETLEN => {
+          result.min_network_size = attribute.as_u32() as u16
+        }
+        TCA_CAKE_STATS_MAX_NETLEN => {
+          result.max_network_size = attribute.as_u32() as u16
+        }
+        TCA_CAKE_STATS_MIN_ADJLEN => {
+          result.min_adj_size = attribute.as_u32() as u16
+        }
+        TCA_CAKE_STATS_MAX_ADJLEN => {
+          result.max_adj_size = attribute.as_u32() as u16
+        }
+        TCA_CAKE_STATS_TIN_STATS => {
+          // One nested attribute per tin, numbered from 1
+          for tin in attribute.nested() {
+            result.tins.push(TcCakeTin::from_netlink(&tin));
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+}
+
 impl TcCakeOptions {
+  /// Decodes the netlink options, naming the enumerations as `tc` does
+  /// so that both readers produce identical results.
+  fn from_netlink(options: &[u8]) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_CAKE_BASE_RATE64 => {
+          result.bandwidth = if attribute.as_u64() == 0 {
+            BandWidth::unlimited
+          } else {
+            BandWidth::Unknown
+          }
+        }
+        TCA_CAKE_DIFFSERV_MODE => {
+          result.diffserv = DiffServ::from_str(match attribute.as_u32() {
+            0 => "diffserv3",
+            1 => "diffserv4",
+            2 => "diffserv8",
+            3 => "besteffort",
+            4 => "precedence",
+            _ => "",
+          })
+        }
+        TCA_CAKE_FLOW_MODE => {
+          result.flowmode = FlowMode::from_str(match attribute.as_u32() {
+            0 => "flowblind",
+            1 => "srchost",
+            2 => "dsthost",
+            3 => "hosts",
+            4 => "flows",
+            5 => "dual-srchost",
+            6 => "dual-dsthost",
+            7 => "triple-isolate",
+            _ => "",
+          })
+        }
+        TCA_CAKE_ACK_FILTER => {
+          result.ack_filter = AckFilter::from_str(match attribute.as_u32() {
+            0 => "disabled",
+            1 => "ack-filter",
+            2 => "ack-filter-aggressive",
+            _ => "",
+          })
+        }
+        TCA_CAKE_OVERHEAD => result.overhead = attribute.as_i32() as u16,
+        TCA_CAKE_RTT => result.rtt = attribute.as_u32() as u64,
+        TCA_CAKE_NAT => result.nat = attribute.as_u32() != 0,
+        // The kernel only sends this attribute in raw mode
+        TCA_CAKE_RAW => result.raw = true,
+        TCA_CAKE_WASH => result.wash = attribute.as_u32() != 0,
+        TCA_CAKE_INGRESS => result.ingress = attribute.as_u32() != 0,
+        TCA_CAKE_SPLIT_GSO => result.split_gso = attribute.as_u32() != 0,
+        TCA_CAKE_FWMARK => {
+          result.fwmark =
+            TcHandle::from_string(&format!("0x{:x}", attribute.as_u32()))
+              .unwrap_or_default();
+        }
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+
   fn from_json(value: &Value) -> Result<Self, QDiscError> {
     match value {
       Value::Object(map) => {
@@ -196,6 +357,72 @@ impl TcCakeOptions {
 }
 
 impl TcCakeTin {
+  fn from_netlink(tin: &Attribute) -> Self {
+    let mut result = Self::default();
+    for attribute in tin.nested() {
+      match attribute.kind {
+        TCA_CAKE_TIN_STATS_SENT_PACKETS => {
+          result.sent_packets = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_SENT_BYTES64 => {
+          result.sent_bytes = attribute.as_u64()
+        }
+        TCA_CAKE_TIN_STATS_DROPPED_PACKETS => result.drops = attribute.as_u32(),
+        TCA_CAKE_TIN_STATS_ACKS_DROPPED_PACKETS => {
+          result.ack_drops = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_ECN_MARKED_PACKETS => {
+          result.ecn_marks = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_BACKLOG_BYTES => {
+          result.backlog_bytes = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_THRESHOLD_RATE64 => {
+          result.threshold_rate = attribute.as_u64()
+        }
+        TCA_CAKE_TIN_STATS_TARGET_US => result.target_us = attribute.as_u32(),
+        TCA_CAKE_TIN_STATS_INTERVAL_US => {
+          result.interval_us = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_WAY_INDIRECT_HITS => {
+          result.way_indirect_hits = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_WAY_MISSES => {
+          result.way_misses = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_WAY_COLLISIONS => {
+          result.way_collisions = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_PEAK_DELAY_US => {
+          result.peak_delay_us = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_AVG_DELAY_US => {
+          result.avg_delay_us = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_BASE_DELAY_US => {
+          result.base_delay_us = attribute.as_u32()
+        }
+        TCA_CAKE_TIN_STATS_SPARSE_FLOWS => {
+          result.sparse_flows = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_BULK_FLOWS => {
+          result.bulk_flows = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_UNRESPONSIVE_FLOWS => {
+          result.unresponsive_flows = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_MAX_SKBLEN => {
+          result.max_pkt_len = attribute.as_u32() as u16
+        }
+        TCA_CAKE_TIN_STATS_FLOW_QUANTUM => {
+          result.flow_quantum = attribute.as_u32() as u16
+        }
+        _ => {}
+      }
+    }
+    result
+  }
+
   fn from_json(value: &Value) -> Result<Self, QDiscError> {
     match value {
       Value::Object(map) => {
@@ -403,3 +630,218 @@ impl TcCakeTin {
 },
 
  */
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::parse_qdisc;
+
+  /// An `RTM_NEWQDISC` payload for `cake diffserv4 triple-isolate` on
+  /// 1:6, laid out as the kernel sends it on x86_64. The CAKE statistics
+  /// are nested attributes, with one nested set per tin.
+  const CAKE: &[u8] = &[
+    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00,
+    0x06, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x09, 0x00, 0x01, 0x00,
+    0x63, 0x61, 0x6b, 0x65, 0x00, 0x00, 0x00, 0x00, 0x88, 0x00, 0x02, 0x80,
+    0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0x07, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x07, 0x00,
+    0xa0, 0x86, 0x01, 0x00, 0x08, 0x00, 0x08, 0x00, 0x88, 0x13, 0x00, 0x00,
+    0x08, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0a, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x05, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x38, 0x03, 0x07, 0x80,
+    0x14, 0x00, 0x01, 0x00, 0x80, 0x96, 0x98, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x08, 0x00,
+    0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x03, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xfc, 0x02, 0x04, 0x00,
+    0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x03, 0x00, 0x00, 0x00, 0x40, 0x00, 0x08, 0x00, 0x04, 0x00,
+    0x00, 0x55, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0x0e, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x06, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x07, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x2a, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x09, 0x00, 0xea, 0x05, 0x00, 0x00, 0xb4, 0x02, 0x0a, 0x80,
+    0xac, 0x00, 0x01, 0x80, 0x08, 0x00, 0x02, 0x00, 0xe8, 0x03, 0x00, 0x00,
+    0x0c, 0x00, 0x03, 0x00, 0x40, 0x42, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00,
+    0x68, 0x89, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00,
+    0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00,
+    0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x12, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00,
+    0x2c, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00, 0x14, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0xac, 0x00, 0x02, 0x80, 0x08, 0x00, 0x02, 0x00,
+    0xd0, 0x07, 0x00, 0x00, 0x0c, 0x00, 0x03, 0x00, 0x80, 0x84, 0x1e, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00,
+    0x0a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x0c, 0x00, 0x0c, 0x00, 0xd0, 0x12, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0d, 0x00, 0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00,
+    0xa0, 0x86, 0x01, 0x00, 0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x12, 0x00, 0xd1, 0x07, 0x00, 0x00,
+    0x08, 0x00, 0x13, 0x00, 0x2d, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00,
+    0x15, 0x00, 0x00, 0x00, 0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x16, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x08, 0x00, 0x19, 0x00, 0xea, 0x05, 0x00, 0x00, 0xac, 0x00, 0x03, 0x80,
+    0x08, 0x00, 0x02, 0x00, 0xb8, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x03, 0x00,
+    0xc0, 0xc6, 0x2d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x08, 0x00, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00,
+    0xd4, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00, 0x38, 0x9c, 0x1c, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00, 0x88, 0x13, 0x00, 0x00,
+    0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00, 0x08, 0x00, 0x0f, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x12, 0x00,
+    0xd2, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00, 0x2e, 0x01, 0x00, 0x00,
+    0x08, 0x00, 0x14, 0x00, 0x16, 0x00, 0x00, 0x00, 0x08, 0x00, 0x15, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x18, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0xac, 0x00, 0x04, 0x80, 0x08, 0x00, 0x02, 0x00, 0xa0, 0x0f, 0x00, 0x00,
+    0x0c, 0x00, 0x03, 0x00, 0x00, 0x09, 0x3d, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x1e, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0b, 0x00, 0xbe, 0x11, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00,
+    0xa0, 0x25, 0x26, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00,
+    0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00,
+    0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x12, 0x00, 0xd3, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00,
+    0x2f, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00, 0x17, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x28, 0x00, 0x03, 0x00, 0x80, 0x96, 0x98, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x27, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfc, 0x02, 0x04, 0x00,
+    0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x03, 0x00, 0x00, 0x00, 0x40, 0x00, 0x08, 0x00, 0x04, 0x00,
+    0x00, 0x55, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0x0e, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x06, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x07, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x2a, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x09, 0x00, 0xea, 0x05, 0x00, 0x00, 0xb4, 0x02, 0x0a, 0x80,
+    0xac, 0x00, 0x01, 0x80, 0x08, 0x00, 0x02, 0x00, 0xe8, 0x03, 0x00, 0x00,
+    0x0c, 0x00, 0x03, 0x00, 0x40, 0x42, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00,
+    0x68, 0x89, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00,
+    0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00,
+    0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x12, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00,
+    0x2c, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00, 0x14, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0xac, 0x00, 0x02, 0x80, 0x08, 0x00, 0x02, 0x00,
+    0xd0, 0x07, 0x00, 0x00, 0x0c, 0x00, 0x03, 0x00, 0x80, 0x84, 0x1e, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00,
+    0x0a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x0c, 0x00, 0x0c, 0x00, 0xd0, 0x12, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0d, 0x00, 0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00,
+    0xa0, 0x86, 0x01, 0x00, 0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x12, 0x00, 0xd1, 0x07, 0x00, 0x00,
+    0x08, 0x00, 0x13, 0x00, 0x2d, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00,
+    0x15, 0x00, 0x00, 0x00, 0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x16, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x08, 0x00, 0x19, 0x00, 0xea, 0x05, 0x00, 0x00, 0xac, 0x00, 0x03, 0x80,
+    0x08, 0x00, 0x02, 0x00, 0xb8, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x03, 0x00,
+    0xc0, 0xc6, 0x2d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x08, 0x00, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0b, 0x00,
+    0xd4, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00, 0x38, 0x9c, 0x1c, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00, 0x88, 0x13, 0x00, 0x00,
+    0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00, 0x08, 0x00, 0x0f, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x12, 0x00,
+    0xd2, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00, 0x2e, 0x01, 0x00, 0x00,
+    0x08, 0x00, 0x14, 0x00, 0x16, 0x00, 0x00, 0x00, 0x08, 0x00, 0x15, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x18, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0xac, 0x00, 0x04, 0x80, 0x08, 0x00, 0x02, 0x00, 0xa0, 0x0f, 0x00, 0x00,
+    0x0c, 0x00, 0x03, 0x00, 0x00, 0x09, 0x3d, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x1e, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0b, 0x00, 0xbe, 0x11, 0x00, 0x00, 0x0c, 0x00, 0x0c, 0x00,
+    0xa0, 0x25, 0x26, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00,
+    0x88, 0x13, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xa0, 0x86, 0x01, 0x00,
+    0x08, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x12, 0x00, 0xd3, 0x07, 0x00, 0x00, 0x08, 0x00, 0x13, 0x00,
+    0x2f, 0x01, 0x00, 0x00, 0x08, 0x00, 0x14, 0x00, 0x17, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x15, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x16, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x18, 0x00, 0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x19, 0x00,
+    0xea, 0x05, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn parse_netlink_cake() {
+    let qdisc = parse_qdisc(CAKE).unwrap();
+    assert_eq!(qdisc.kind, "cake");
+    let cake = TcCake::from_netlink(&qdisc).unwrap();
+    assert_eq!(cake.handle, TcHandle::from_u32(0x0006_0000));
+    assert_eq!(cake.parent, TcHandle::from_u32(0x0001_0006));
+    assert_eq!(cake.bytes, 10_000_000);
+    assert_eq!(cake.packets, 10_000);
+    assert_eq!(cake.drops, 6);
+    assert_eq!(cake.overlimits, 1);
+    assert_eq!(cake.memory_limit, 4194304);
+    assert_eq!(cake.memory_used, 21760);
+    assert_eq!(cake.avg_hdr_offset, 14);
+    assert_eq!(cake.min_network_size, 42);
+    assert_eq!(cake.max_network_size, 1514);
+    assert_eq!(cake.min_adj_size, 42);
+    assert_eq!(cake.max_adj_size, 1514);
+
+    let options = &cake.options;
+    assert_eq!(options.bandwidth, BandWidth::unlimited);
+    assert_eq!(options.diffserv, DiffServ::diffserv4);
+    assert_eq!(options.flowmode, FlowMode::triple_isolate);
+    // tc calls a disabled filter "disabled", which the JSON reader doesn't
+    // recognise either.
+    assert_eq!(options.ack_filter, AckFilter::Unknown);
+    assert_eq!(options.rtt, 100000);
+    assert!(options.split_gso);
+    assert!(!options.nat && !options.wash && !options.ingress && !options.raw);
+
+    assert_eq!(cake.tins.len(), 4);
+    for (i, tin) in cake.tins.iter().enumerate() {
+      let n = i as u32 + 1;
+      assert_eq!(tin.sent_packets, 1000 * n);
+      assert_eq!(tin.sent_bytes, 1_000_000 * n as u64);
+      assert_eq!(tin.drops, i as u32);
+      assert_eq!(tin.ecn_marks, 10 * i as u32);
+      assert_eq!(tin.backlog_bytes, 1514 * i as u32);
+      assert_eq!(tin.threshold_rate, 625000 * n as u64);
+      assert_eq!(tin.target_us, 5000);
+      assert_eq!(tin.interval_us, 100000);
+      assert_eq!(tin.way_misses, i as u16);
+      assert_eq!(tin.peak_delay_us, 2000 + i as u32);
+      assert_eq!(tin.avg_delay_us, 300 + i as u32);
+      assert_eq!(tin.base_delay_us, 20 + i as u32);
+      assert_eq!(tin.sparse_flows, 1);
+      assert_eq!(tin.bulk_flows, i as u16);
+      assert_eq!(tin.max_pkt_len, 1514);
+      assert_eq!(tin.flow_quantum, 1514);
+    }
+  }
+}
@@ -0,0 +1,165 @@
+/*
+{"kind":"pfifo","handle":"8001:","root":true,"refcnt":2,"options":{"limit":100},
+    "bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}
+{"kind":"pfifo_fast","handle":"8003:","root":true,"refcnt":2,"options":{"bands":3,"priomap":[1,2,2,2,1,2,0,0,1,1,1,1,1,1,1,1],"multiqueue":false},
+    "bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}
+*/
+
+use super::QDiscError;
+use crate::{
+  netlink::{read_u32, NetlinkQdisc},
+  parse_tc_handle,
+};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+/// `pfifo` and `bfifo`. The limit is in packets for `pfifo`, and bytes
+/// for `bfifo`.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcFifo {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  limit: u32,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcPfifoFast {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  bands: u32,
+  priomap: Vec<u8>,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+impl TcFifo {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "options" => {
+          if let Value::Object(options) = value {
+            result.limit =
+              options.get("limit").and_then(|v| v.as_u64()).unwrap_or(0)
+                as u32;
+          } else {
+            return Err(QDiscError::FifoOpts);
+          }
+        }
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-fifo json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Self {
+    Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      // struct tc_fifo_qopt
+      limit: read_u32(&qdisc.options, 0),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    }
+  }
+}
+
+impl TcPfifoFast {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "options" => {
+          if let Value::Object(options) = value {
+            result.bands =
+              options.get("bands").and_then(|v| v.as_u64()).unwrap_or(0)
+                as u32;
+            if let Some(Value::Array(priomap)) = options.get("priomap") {
+              result.priomap = priomap
+                .iter()
+                .map(|v| v.as_u64().unwrap_or(0) as u8)
+                .collect();
+            }
+          } else {
+            return Err(QDiscError::FifoOpts);
+          }
+        }
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-pfifo_fast json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Self {
+    // struct tc_prio_qopt
+    let options = &qdisc.options;
+    Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      bands: read_u32(options, 0),
+      priomap: options.get(4..20).map(|p| p.to_vec()).unwrap_or_default(),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    }
+  }
+}
@@ -0,0 +1,220 @@
+/*
+{"kind":"fq","handle":"8001:","parent":"7fff:1",
+    "options":{"limit":10000,"flow_limit":100,"buckets":1024,"orphan_mask":1023,"quantum":3028,"initial_quantum":15140,"low_rate_threshold":550000,
+    "refill_delay":40000,"timer_slack":10000,"horizon":10000000,"horizon_drop":true},
+    "bytes":1350,"packets":15,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0,"flows":2,"inactive":2,"throttled":0,
+    "gc":0,"highprio":0,"flows_plimit":0,"pkts_too_long":0,"alloc_errors":0,"ce_mark":0,"horizon_drops":0,"horizon_caps":0}
+*/
+
+use super::QDiscError;
+use crate::{
+  netlink::{attributes, read_u32, read_u64, NetlinkQdisc},
+  parse_tc_handle,
+};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+const TCA_FQ_PLIMIT: u16 = 1;
+const TCA_FQ_FLOW_PLIMIT: u16 = 2;
+const TCA_FQ_QUANTUM: u16 = 3;
+const TCA_FQ_INITIAL_QUANTUM: u16 = 4;
+const TCA_FQ_FLOW_MAX_RATE: u16 = 7;
+const TCA_FQ_BUCKETS_LOG: u16 = 8;
+const TCA_FQ_FLOW_REFILL_DELAY: u16 = 9;
+const TCA_FQ_ORPHAN_MASK: u16 = 10;
+const TCA_FQ_LOW_RATE_THRESHOLD: u16 = 11;
+const TCA_FQ_TIMER_SLACK: u16 = 13;
+const TCA_FQ_HORIZON: u16 = 14;
+const TCA_FQ_HORIZON_DROP: u16 = 15;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcFq {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  options: TcFqOptions,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+  flows: u32,
+  inactive: u32,
+  throttled: u32,
+  gc: u64,
+  highprio: u64,
+  flows_plimit: u64,
+  pkts_too_long: u64,
+  alloc_errors: u64,
+  pub(crate) ce_mark: u64,
+  horizon_drops: u64,
+  horizon_caps: u64,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+struct TcFqOptions {
+  limit: u32,
+  flow_limit: u32,
+  buckets: u32,
+  orphan_mask: u32,
+  quantum: u32,
+  initial_quantum: u32,
+  maxrate: u64,
+  low_rate_threshold: u64,
+  refill_delay: u64,
+  timer_slack: u64,
+  horizon: u64,
+  horizon_drop: bool,
+}
+
+impl TcFq {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "flows" => result.flows = value.as_u64().unwrap_or(0) as u32,
+        "inactive" => result.inactive = value.as_u64().unwrap_or(0) as u32,
+        "throttled" => result.throttled = value.as_u64().unwrap_or(0) as u32,
+        "gc" => result.gc = value.as_u64().unwrap_or(0),
+        "highprio" => result.highprio = value.as_u64().unwrap_or(0),
+        "flows_plimit" => result.flows_plimit = value.as_u64().unwrap_or(0),
+        "pkts_too_long" => result.pkts_too_long = value.as_u64().unwrap_or(0),
+        "alloc_errors" => result.alloc_errors = value.as_u64().unwrap_or(0),
+        "ce_mark" => result.ce_mark = value.as_u64().unwrap_or(0),
+        "horizon_drops" => result.horizon_drops = value.as_u64().unwrap_or(0),
+        "horizon_caps" => result.horizon_caps = value.as_u64().unwrap_or(0),
+        "options" => result.options = TcFqOptions::from_json(value)?,
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-fq json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(
+    qdisc: &NetlinkQdisc,
+  ) -> Result<Self, QDiscError> {
+    // struct tc_fq_qd_stats
+    let x = &qdisc.xstats;
+    Ok(Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcFqOptions::from_netlink(&qdisc.options)?,
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+      gc: read_u64(x, 0),
+      highprio: read_u64(x, 8),
+      flows_plimit: read_u64(x, 32),
+      pkts_too_long: read_u64(x, 40),
+      alloc_errors: read_u64(x, 48),
+      flows: read_u32(x, 64),
+      inactive: read_u32(x, 68),
+      throttled: read_u32(x, 72),
+      ce_mark: read_u64(x, 80),
+      horizon_drops: read_u64(x, 88),
+      horizon_caps: read_u64(x, 96),
+    })
+  }
+}
+
+impl TcFqOptions {
+  fn from_netlink(options: &[u8]) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_FQ_PLIMIT => result.limit = attribute.as_u32(),
+        TCA_FQ_FLOW_PLIMIT => result.flow_limit = attribute.as_u32(),
+        TCA_FQ_QUANTUM => result.quantum = attribute.as_u32(),
+        TCA_FQ_INITIAL_QUANTUM => result.initial_quantum = attribute.as_u32(),
+        TCA_FQ_FLOW_MAX_RATE => {
+          // ~0U means unlimited, which tc doesn't print
+          let rate = attribute.as_u32();
+          if rate != u32::MAX {
+            result.maxrate = rate as u64;
+          }
+        }
+        TCA_FQ_BUCKETS_LOG => result.buckets = 1 << attribute.as_u32(),
+        TCA_FQ_FLOW_REFILL_DELAY => {
+          result.refill_delay = attribute.as_u32() as u64
+        }
+        TCA_FQ_ORPHAN_MASK => result.orphan_mask = attribute.as_u32(),
+        TCA_FQ_LOW_RATE_THRESHOLD => {
+          result.low_rate_threshold = attribute.as_u32() as u64
+        }
+        TCA_FQ_TIMER_SLACK => result.timer_slack = attribute.as_u32() as u64,
+        TCA_FQ_HORIZON => result.horizon = attribute.as_u32() as u64,
+        TCA_FQ_HORIZON_DROP => {
+          result.horizon_drop = attribute.payload.first() == Some(&1)
+        }
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+
+  fn from_json(value: &Value) -> Result<Self, QDiscError> {
+    match value {
+      Value::Object(map) => {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+          match key.as_str() {
+            "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+            "flow_limit" => {
+              result.flow_limit = value.as_u64().unwrap_or(0) as u32
+            }
+            "buckets" => result.buckets = value.as_u64().unwrap_or(0) as u32,
+            "orphan_mask" => {
+              result.orphan_mask = value.as_u64().unwrap_or(0) as u32
+            }
+            "quantum" => result.quantum = value.as_u64().unwrap_or(0) as u32,
+            "initial_quantum" => {
+              result.initial_quantum = value.as_u64().unwrap_or(0) as u32
+            }
+            "maxrate" => result.maxrate = value.as_u64().unwrap_or(0),
+            "low_rate_threshold" => {
+              result.low_rate_threshold = value.as_u64().unwrap_or(0)
+            }
+            "refill_delay" => {
+              result.refill_delay = value.as_u64().unwrap_or(0)
+            }
+            "timer_slack" => result.timer_slack = value.as_u64().unwrap_or(0),
+            "horizon" => result.horizon = value.as_u64().unwrap_or(0),
+            "horizon_drop" => {
+              result.horizon_drop = value.as_bool().unwrap_or(false)
+            }
+            _ => {
+              info_once!("Unknown entry in tc-fq-options json decoder: {key}");
+            }
+          }
+        }
+        Ok(result)
+      }
+      _ => Err(QDiscError::FqOpts),
+    }
+  }
+}
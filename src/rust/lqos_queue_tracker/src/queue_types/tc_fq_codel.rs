@@ -6,28 +6,41 @@
 */
 
 use super::QDiscError;
-use crate::parse_tc_handle;
+use crate::{
+  netlink::{attributes, read_u32, NetlinkQdisc},
+  parse_tc_handle,
+};
 use log_once::info_once;
 use lqos_bus::TcHandle;
 use serde::Serialize;
 use serde_json::Value;
 
+const TCA_FQ_CODEL_TARGET: u16 = 1;
+const TCA_FQ_CODEL_LIMIT: u16 = 2;
+const TCA_FQ_CODEL_INTERVAL: u16 = 3;
+const TCA_FQ_CODEL_ECN: u16 = 4;
+const TCA_FQ_CODEL_FLOWS: u16 = 5;
+const TCA_FQ_CODEL_QUANTUM: u16 = 6;
+const TCA_FQ_CODEL_DROP_BATCH_SIZE: u16 = 8;
+const TCA_FQ_CODEL_MEMORY_LIMIT: u16 = 9;
+const TCA_FQ_CODEL_XSTATS_QDISC: u32 = 0;
+
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct TcFqCodel {
   handle: TcHandle,
   pub(crate) parent: TcHandle,
   options: TcFqCodelOptions,
-  bytes: u64,
-  packets: u32, // FIXME - for long term data we have to worry about wrapping
-  drops: u32,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32, // FIXME - for long term data we have to worry about wrapping
+  pub(crate) drops: u32,
   overlimits: u32,
   requeues: u32,
-  backlog: u32,
-  qlen: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
   maxpacket: u16,
   drop_overlimit: u32,
   new_flow_count: u32,
-  ecn_mark: u32,
+  pub(crate) ecn_mark: u32,
   new_flows_len: u16,
   old_flows_len: u16,
 }
@@ -89,7 +102,56 @@ impl TcFqCodel {
   }
 }
 
+impl TcFqCodel {
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Result<Self, QDiscError> {
+    let mut result = Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcFqCodelOptions::from_netlink(&qdisc.options)?,
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+      ..Default::default()
+    };
+    // struct tc_fq_codel_xstats
+    let x = &qdisc.xstats;
+    if read_u32(x, 0) == TCA_FQ_CODEL_XSTATS_QDISC {
+      result.maxpacket = read_u32(x, 4) as u16;
+      result.drop_overlimit = read_u32(x, 8);
+      result.ecn_mark = read_u32(x, 12);
+      result.new_flow_count = read_u32(x, 16);
+      result.new_flows_len = read_u32(x, 20) as u16;
+      result.old_flows_len = read_u32(x, 24) as u16;
+    }
+    Ok(result)
+  }
+}
+
 impl TcFqCodelOptions {
+  fn from_netlink(options: &[u8]) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_FQ_CODEL_TARGET => result.target = attribute.as_u32() as u64,
+        TCA_FQ_CODEL_LIMIT => result.limit = attribute.as_u32(),
+        TCA_FQ_CODEL_INTERVAL => result.interval = attribute.as_u32() as u64,
+        TCA_FQ_CODEL_ECN => result.ecn = attribute.as_u32() != 0,
+        TCA_FQ_CODEL_FLOWS => result.flows = attribute.as_u32() as u16,
+        TCA_FQ_CODEL_QUANTUM => result.quantum = attribute.as_u32() as u16,
+        TCA_FQ_CODEL_DROP_BATCH_SIZE => {
+          result.drop_batch = attribute.as_u32() as u16
+        }
+        TCA_FQ_CODEL_MEMORY_LIMIT => result.memory_limit = attribute.as_u32(),
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+
   fn from_json(value: &Value) -> Result<Self, QDiscError> {
     match value {
       Value::Object(map) => {
@@ -121,3 +183,72 @@ impl TcFqCodelOptions {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::parse_qdisc;
+
+  /// An `RTM_NEWQDISC` payload for `fq_codel` on 1:5, laid out as the
+  /// kernel sends it on x86_64: the `tcmsg` header, then `TCA_KIND`,
+  /// `TCA_OPTIONS`, `TCA_STATS2` and the legacy stats attributes.
+  const FQ_CODEL: &[u8] = &[
+    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00,
+    0x05, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x01, 0x00,
+    0x66, 0x71, 0x5f, 0x63, 0x6f, 0x64, 0x65, 0x6c, 0x00, 0x00, 0x00, 0x00,
+    0x4c, 0x00, 0x02, 0x80, 0x08, 0x00, 0x01, 0x00, 0x87, 0x13, 0x00, 0x00,
+    0x08, 0x00, 0x02, 0x00, 0x00, 0x28, 0x00, 0x00, 0x08, 0x00, 0x03, 0x00,
+    0x9f, 0x86, 0x01, 0x00, 0x08, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x05, 0x00, 0x00, 0x04, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+    0xea, 0x05, 0x00, 0x00, 0x08, 0x00, 0x07, 0x00, 0xff, 0xff, 0xff, 0xff,
+    0x08, 0x00, 0x08, 0x00, 0x40, 0x00, 0x00, 0x00, 0x08, 0x00, 0x09, 0x00,
+    0x00, 0x00, 0x00, 0x02, 0x05, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x68, 0x00, 0x07, 0x80, 0x14, 0x00, 0x01, 0x00, 0x15, 0xcd, 0x5b, 0x07,
+    0x00, 0x00, 0x00, 0x00, 0xcd, 0x81, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x0c, 0x00, 0x08, 0x00, 0xcd, 0x81, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x18, 0x00, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, 0xbe, 0x11, 0x00, 0x00,
+    0x11, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x2c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x05, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x2c, 0x01, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x00, 0x03, 0x00,
+    0x15, 0xcd, 0x5b, 0x07, 0x00, 0x00, 0x00, 0x00, 0xcd, 0x81, 0x01, 0x00,
+    0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0xbe, 0x11, 0x00, 0x00,
+    0x2c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x05, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x2c, 0x01, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn parse_netlink_fq_codel() {
+    let qdisc = parse_qdisc(FQ_CODEL).unwrap();
+    assert_eq!(qdisc.kind, "fq_codel");
+    let fq_codel = TcFqCodel::from_netlink(&qdisc).unwrap();
+    assert_eq!(fq_codel.handle, TcHandle::from_u32(0x0005_0000));
+    assert_eq!(fq_codel.parent, TcHandle::from_u32(0x0001_0005));
+    assert_eq!(fq_codel.bytes, 123456789);
+    assert_eq!(fq_codel.packets, 98765);
+    assert_eq!(fq_codel.qlen, 3);
+    assert_eq!(fq_codel.backlog, 4542);
+    assert_eq!(fq_codel.drops, 17);
+    assert_eq!(fq_codel.requeues, 2);
+    assert_eq!(fq_codel.maxpacket, 1514);
+    assert_eq!(fq_codel.drop_overlimit, 5);
+    assert_eq!(fq_codel.ecn_mark, 42);
+    assert_eq!(fq_codel.new_flow_count, 300);
+    assert_eq!(fq_codel.new_flows_len, 1);
+    assert_eq!(fq_codel.old_flows_len, 2);
+
+    let options = &fq_codel.options;
+    assert_eq!(options.target, 4999);
+    assert_eq!(options.limit, 10240);
+    assert_eq!(options.interval, 99999);
+    assert!(options.ecn);
+    assert_eq!(options.flows, 1024);
+    assert_eq!(options.quantum, 1514);
+    assert_eq!(options.drop_batch, 64);
+    assert_eq!(options.memory_limit, 33554432);
+  }
+}
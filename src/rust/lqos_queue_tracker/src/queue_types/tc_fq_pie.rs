@@ -0,0 +1,206 @@
+/*
+{"kind":"fq_pie","handle":"8001:","parent":"1:a",
+    "options":{"limit":10240,"flows":1024,"target":15000,"tupdate":15000,"alpha":2,"beta":20,"quantum":1514,"memory_limit":33554432,
+    "ecn_prob":10,"ecn":true,"bytemode":false,"dq_rate_estimator":false},
+    "bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0,"pkts_in":0,"overlimit":0,"overmemory":0,
+    "dropped":0,"ecn_mark":0,"new_flow_count":0,"new_flows_len":0,"old_flows_len":0,"memory_used":0}
+*/
+
+use super::QDiscError;
+use crate::{
+  netlink::{attributes, read_u32, NetlinkQdisc},
+  parse_tc_handle,
+};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+const TCA_FQ_PIE_LIMIT: u16 = 1;
+const TCA_FQ_PIE_FLOWS: u16 = 2;
+const TCA_FQ_PIE_TARGET: u16 = 3;
+const TCA_FQ_PIE_TUPDATE: u16 = 4;
+const TCA_FQ_PIE_ALPHA: u16 = 5;
+const TCA_FQ_PIE_BETA: u16 = 6;
+const TCA_FQ_PIE_QUANTUM: u16 = 7;
+const TCA_FQ_PIE_MEMORY_LIMIT: u16 = 8;
+const TCA_FQ_PIE_ECN_PROB: u16 = 9;
+const TCA_FQ_PIE_ECN: u16 = 10;
+const TCA_FQ_PIE_BYTEMODE: u16 = 11;
+const TCA_FQ_PIE_DQ_RATE_ESTIMATOR: u16 = 12;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcFqPie {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  options: TcFqPieOptions,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+  pkts_in: u32,
+  overlimit: u32,
+  overmemory: u32,
+  dropped: u32,
+  pub(crate) ecn_mark: u32,
+  new_flow_count: u32,
+  new_flows_len: u32,
+  old_flows_len: u32,
+  memory_used: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+struct TcFqPieOptions {
+  limit: u32,
+  flows: u32,
+  target: u32,  // us
+  tupdate: u32, // us
+  alpha: u32,
+  beta: u32,
+  quantum: u32,
+  memory_limit: u32,
+  ecn_prob: u32,
+  ecn: bool,
+  bytemode: bool,
+  dq_rate_estimator: bool,
+}
+
+impl TcFqPie {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "pkts_in" => result.pkts_in = value.as_u64().unwrap_or(0) as u32,
+        "overlimit" => result.overlimit = value.as_u64().unwrap_or(0) as u32,
+        "overmemory" => result.overmemory = value.as_u64().unwrap_or(0) as u32,
+        "dropped" => result.dropped = value.as_u64().unwrap_or(0) as u32,
+        "ecn_mark" => result.ecn_mark = value.as_u64().unwrap_or(0) as u32,
+        "new_flow_count" => {
+          result.new_flow_count = value.as_u64().unwrap_or(0) as u32
+        }
+        "new_flows_len" => {
+          result.new_flows_len = value.as_u64().unwrap_or(0) as u32
+        }
+        "old_flows_len" => {
+          result.old_flows_len = value.as_u64().unwrap_or(0) as u32
+        }
+        "memory_used" => {
+          result.memory_used = value.as_u64().unwrap_or(0) as u32
+        }
+        "options" => result.options = TcFqPieOptions::from_json(value)?,
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-fq_pie json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(
+    qdisc: &NetlinkQdisc,
+  ) -> Result<Self, QDiscError> {
+    // struct tc_fq_pie_xstats
+    let x = &qdisc.xstats;
+    Ok(Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcFqPieOptions::from_netlink(&qdisc.options)?,
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+      pkts_in: read_u32(x, 0),
+      dropped: read_u32(x, 4),
+      overlimit: read_u32(x, 8),
+      overmemory: read_u32(x, 12),
+      ecn_mark: read_u32(x, 16),
+      new_flow_count: read_u32(x, 20),
+      new_flows_len: read_u32(x, 24),
+      old_flows_len: read_u32(x, 28),
+      memory_used: read_u32(x, 32),
+    })
+  }
+}
+
+impl TcFqPieOptions {
+  fn from_netlink(options: &[u8]) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_FQ_PIE_LIMIT => result.limit = attribute.as_u32(),
+        TCA_FQ_PIE_FLOWS => result.flows = attribute.as_u32(),
+        TCA_FQ_PIE_TARGET => result.target = attribute.as_u32(),
+        TCA_FQ_PIE_TUPDATE => result.tupdate = attribute.as_u32(),
+        TCA_FQ_PIE_ALPHA => result.alpha = attribute.as_u32(),
+        TCA_FQ_PIE_BETA => result.beta = attribute.as_u32(),
+        TCA_FQ_PIE_QUANTUM => result.quantum = attribute.as_u32(),
+        TCA_FQ_PIE_MEMORY_LIMIT => result.memory_limit = attribute.as_u32(),
+        TCA_FQ_PIE_ECN_PROB => result.ecn_prob = attribute.as_u32(),
+        TCA_FQ_PIE_ECN => result.ecn = attribute.as_u32() != 0,
+        TCA_FQ_PIE_BYTEMODE => result.bytemode = attribute.as_u32() != 0,
+        TCA_FQ_PIE_DQ_RATE_ESTIMATOR => {
+          result.dq_rate_estimator = attribute.as_u32() != 0
+        }
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+
+  fn from_json(value: &Value) -> Result<Self, QDiscError> {
+    match value {
+      Value::Object(map) => {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+          match key.as_str() {
+            "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+            "flows" => result.flows = value.as_u64().unwrap_or(0) as u32,
+            "target" => result.target = value.as_u64().unwrap_or(0) as u32,
+            "tupdate" => result.tupdate = value.as_u64().unwrap_or(0) as u32,
+            "alpha" => result.alpha = value.as_u64().unwrap_or(0) as u32,
+            "beta" => result.beta = value.as_u64().unwrap_or(0) as u32,
+            "quantum" => result.quantum = value.as_u64().unwrap_or(0) as u32,
+            "memory_limit" => {
+              result.memory_limit = value.as_u64().unwrap_or(0) as u32
+            }
+            "ecn_prob" => result.ecn_prob = value.as_u64().unwrap_or(0) as u32,
+            "ecn" => result.ecn = value.as_bool().unwrap_or(false),
+            "bytemode" => result.bytemode = value.as_bool().unwrap_or(false),
+            "dq_rate_estimator" => {
+              result.dq_rate_estimator = value.as_bool().unwrap_or(false)
+            }
+            _ => {
+              info_once!(
+                "Unknown entry in tc-fq_pie-options json decoder: {key}"
+              );
+            }
+          }
+        }
+        Ok(result)
+      }
+      _ => Err(QDiscError::FqPieOpts),
+    }
+  }
+}
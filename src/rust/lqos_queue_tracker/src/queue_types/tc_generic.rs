@@ -0,0 +1,66 @@
+//! Any qdisc kind that we don't have a decoder for. Only the statistics
+//! that every qdisc reports are kept, so that an unexpected qdisc in a
+//! tree doesn't stop the rest of the tree from being read.
+
+use crate::{netlink::NetlinkQdisc, parse_tc_handle};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcGeneric {
+  pub(crate) kind: String,
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+impl TcGeneric {
+  pub(crate) fn from_json(
+    kind: &str,
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Self {
+    let mut result = Self { kind: kind.to_string(), ..Default::default() };
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        _ => {} // Options and extended stats vary by kind
+      }
+    }
+    result
+  }
+
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Self {
+    Self {
+      kind: qdisc.kind.clone(),
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    }
+  }
+}
@@ -4,23 +4,29 @@
 */
 
 use super::QDiscError;
-use crate::parse_tc_handle;
+use crate::{
+  netlink::{attributes, read_u32, NetlinkQdisc},
+  parse_tc_handle,
+};
 use log_once::info_once;
 use lqos_bus::TcHandle;
 use serde::Serialize;
 use serde_json::Value;
 
+const TCA_HTB_INIT: u16 = 2;
+const TCA_HTB_DIRECT_QLEN: u16 = 5;
+
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct TcHtb {
   handle: TcHandle,
   parent: TcHandle,
-  bytes: u64,
-  packets: u32,
-  drops: u32,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
   overlimits: u32,
   requeues: u32,
-  backlog: u32,
-  qlen: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
   options: TcHtbOptions,
 }
 
@@ -63,7 +69,46 @@ impl TcHtb {
   }
 }
 
+impl TcHtb {
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Result<Self, QDiscError> {
+    Ok(Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+      options: TcHtbOptions::from_netlink(&qdisc.options)?,
+    })
+  }
+}
+
 impl TcHtbOptions {
+  fn from_netlink(options: &[u8]) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_HTB_INIT => {
+          // struct tc_htb_glob
+          let glob = attribute.payload;
+          result.r2q = read_u32(glob, 4);
+          // tc prints this as "0x2", which the JSON decoder reads as
+          // a major number. Do the same, so both readers agree.
+          result.default =
+            TcHandle::from_string(&format!("0x{:x}", read_u32(glob, 8)))
+              .unwrap_or_default();
+          result.direct_packets_stat = read_u32(glob, 16);
+        }
+        TCA_HTB_DIRECT_QLEN => result.direct_qlen = attribute.as_u32(),
+        _ => {}
+      }
+    }
+    Ok(result)
+  }
+
   fn from_json(value: &Value) -> Result<Self, QDiscError> {
     match value {
       Value::Object(map) => {
@@ -91,3 +136,44 @@ impl TcHtbOptions {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::parse_qdisc;
+
+  /// An `RTM_NEWQDISC` payload for the root HTB qdisc 1:, laid out as the
+  /// kernel sends it on x86_64. The qdisc has no extended statistics.
+  const HTB: &[u8] = &[
+    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+    0xff, 0xff, 0xff, 0xff, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0x00,
+    0x68, 0x74, 0x62, 0x00, 0x24, 0x00, 0x02, 0x80, 0x18, 0x00, 0x02, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+    0xe8, 0x03, 0x00, 0x00, 0x05, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x3c, 0x00, 0x07, 0x80, 0x14, 0x00, 0x01, 0x00, 0x80, 0x84, 0x1e, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xdc, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x0c, 0x00, 0x08, 0x00, 0xdc, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x18, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x28, 0x00, 0x03, 0x00, 0x80, 0x84, 0x1e, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xdc, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn parse_netlink_htb() {
+    let qdisc = parse_qdisc(HTB).unwrap();
+    assert_eq!(qdisc.kind, "htb");
+    let htb = TcHtb::from_netlink(&qdisc).unwrap();
+    assert_eq!(htb.handle, TcHandle::from_u32(0x0001_0000));
+    assert_eq!(htb.parent, TcHandle::from_u32(0xFFFF_FFFF));
+    assert_eq!(htb.bytes, 2_000_000);
+    assert_eq!(htb.packets, 1500);
+    assert_eq!(htb.options.r2q, 10);
+    assert_eq!(htb.options.default, TcHandle::from_u32(0x0002_0000));
+    assert_eq!(htb.options.direct_packets_stat, 7);
+    assert_eq!(htb.options.direct_qlen, 1000);
+  }
+}
@@ -0,0 +1,147 @@
+//! HTB classes, as opposed to the HTB qdisc. Classes carry the shaping
+//! rates, and the token counters that show whether a class is running
+//! into its ceiling and borrowing from its parent.
+
+use crate::netlink::{attributes, read_u32, ticks_to_us, NetlinkQdisc};
+use lqos_bus::TcHandle;
+use serde::Serialize;
+
+const TCA_HTB_PARMS: u16 = 1;
+const TCA_HTB_RATE64: u16 = 6;
+const TCA_HTB_CEIL64: u16 = 7;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcHtbClass {
+  pub(crate) handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  pub(crate) rate: u64, // bytes per second
+  pub(crate) ceil: u64, // bytes per second
+  burst: u32,
+  cburst: u32,
+  quantum: u32,
+  level: u32,
+  prio: u32,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  pub(crate) overlimits: u32,
+  requeues: u32,
+  backlog: u32,
+  qlen: u32,
+  pub(crate) lends: u32,
+  pub(crate) borrows: u32,
+  giants: u32,
+  pub(crate) tokens: i32,
+  pub(crate) ctokens: i32,
+}
+
+impl TcHtbClass {
+  pub(crate) fn from_netlink(class: &NetlinkQdisc) -> Self {
+    // struct tc_htb_xstats
+    let x = &class.xstats;
+    let mut result = Self {
+      handle: TcHandle::from_u32(class.handle),
+      parent: TcHandle::from_u32(class.parent),
+      bytes: class.bytes,
+      packets: class.packets as u32,
+      drops: class.drops,
+      overlimits: class.overlimits,
+      requeues: class.requeues,
+      backlog: class.backlog,
+      qlen: class.qlen,
+      lends: read_u32(x, 0),
+      borrows: read_u32(x, 4),
+      giants: read_u32(x, 8),
+      tokens: read_u32(x, 12) as i32,
+      ctokens: read_u32(x, 16) as i32,
+      ..Default::default()
+    };
+
+    let mut buffer = 0;
+    let mut cbuffer = 0;
+    for attribute in attributes(&class.options) {
+      match attribute.kind {
+        TCA_HTB_PARMS => {
+          // struct tc_htb_opt: two 12 byte tc_ratespecs, then buffer,
+          // cbuffer, quantum, level and prio.
+          let parms = attribute.payload;
+          if result.rate == 0 {
+            result.rate = read_u32(parms, 8) as u64;
+          }
+          if result.ceil == 0 {
+            result.ceil = read_u32(parms, 20) as u64;
+          }
+          buffer = read_u32(parms, 24);
+          cbuffer = read_u32(parms, 28);
+          result.quantum = read_u32(parms, 32);
+          result.level = read_u32(parms, 36);
+          result.prio = read_u32(parms, 40);
+        }
+        TCA_HTB_RATE64 => result.rate = attribute.as_u64(),
+        TCA_HTB_CEIL64 => result.ceil = attribute.as_u64(),
+        _ => {}
+      }
+    }
+    result.burst = (result.rate * ticks_to_us(buffer) / 1_000_000) as u32;
+    result.cburst = (result.ceil * ticks_to_us(cbuffer) / 1_000_000) as u32;
+    result
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::parse_qdisc;
+
+  /// An `RTM_NEWTCLASS` payload for HTB class 1:5, laid out as the
+  /// kernel sends it on x86_64. Its ceiling (40 Gbit/s) doesn't fit the
+  /// 32-bit `tc_ratespec`, so it is also sent as `TCA_HTB_CEIL64`.
+  const HTB_CLASS: &[u8] = &[
+    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, 0x00,
+    0x02, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0x00,
+    0x68, 0x74, 0x62, 0x00, 0x40, 0x00, 0x02, 0x80, 0x30, 0x00, 0x01, 0x00,
+    0x00, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x20, 0xbc, 0xbe, 0x00,
+    0x00, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+    0xa8, 0x61, 0x00, 0x00, 0x09, 0x3d, 0x00, 0x00, 0xea, 0x05, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x07, 0x00,
+    0x00, 0xf2, 0x05, 0x2a, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0c, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x54, 0x00, 0x07, 0x80, 0x14, 0x00, 0x01, 0x00,
+    0x00, 0xf2, 0x05, 0x2a, 0x01, 0x00, 0x00, 0x00, 0x00, 0x09, 0x3d, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x08, 0x00, 0x00, 0x09, 0x3d, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x78, 0x00, 0x00, 0x00, 0x18, 0x00, 0x04, 0x00, 0x64, 0x00, 0x00, 0x00,
+    0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x48, 0xf4, 0xff, 0xff,
+    0x7d, 0x00, 0x00, 0x00, 0x28, 0x00, 0x03, 0x00, 0x00, 0xf2, 0x05, 0x2a,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x09, 0x3d, 0x00, 0x03, 0x00, 0x00, 0x00,
+    0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x04, 0x00,
+    0x64, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x48, 0xf4, 0xff, 0xff, 0x7d, 0x00, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn parse_netlink_htb_class() {
+    let class = parse_qdisc(HTB_CLASS).unwrap();
+    assert_eq!(class.kind, "htb");
+    let htb = TcHtbClass::from_netlink(&class);
+    assert_eq!(htb.handle, TcHandle::from_u32(0x0001_0005));
+    assert_eq!(htb.parent, TcHandle::from_u32(0x0001_0002));
+    assert_eq!(htb.rate, 12_500_000);
+    assert_eq!(htb.ceil, 5_000_000_000);
+    assert_eq!(htb.burst, 20_000);
+    assert_eq!(htb.cburst, 5_000_000);
+    assert_eq!(htb.quantum, 1514);
+    assert_eq!(htb.level, 0);
+    assert_eq!(htb.prio, 3);
+    assert_eq!(htb.bytes, 5_000_000_000);
+    assert_eq!(htb.packets, 4_000_000);
+    assert_eq!(htb.drops, 3);
+    assert_eq!(htb.overlimits, 120);
+    assert_eq!(htb.lends, 100);
+    assert_eq!(htb.borrows, 20);
+    assert_eq!(htb.giants, 0);
+    assert_eq!(htb.tokens, -3000);
+    assert_eq!(htb.ctokens, 125);
+  }
+}
@@ -3,23 +3,25 @@
 */
 
 use super::QDiscError;
-use crate::parse_tc_handle;
+use crate::{netlink::NetlinkQdisc, parse_tc_handle};
 use log_once::info_once;
 use lqos_bus::TcHandle;
 use serde::Serialize;
 use serde_json::Value;
 
+const TC_H_ROOT: u32 = 0xFFFFFFFF;
+
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct TcMultiQueue {
   handle: TcHandle,
   root: bool,
-  bytes: u64,
-  packets: u32, // FIXME These can overflow in older linuxes
-  drops: u32,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32, // FIXME These can overflow in older linuxes
+  pub(crate) drops: u32,
   overlimits: u32,
   requeues: u32, // what does requeues really mean?
-  backlog: u32,
-  qlen: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
 }
 
 impl TcMultiQueue {
@@ -49,4 +51,18 @@ impl TcMultiQueue {
     }
     Ok(result)
   }
+
+  pub(crate) fn from_netlink(qdisc: &NetlinkQdisc) -> Self {
+    Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      root: qdisc.parent == TC_H_ROOT,
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    }
+  }
 }
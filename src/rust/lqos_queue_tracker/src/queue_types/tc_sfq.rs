@@ -0,0 +1,128 @@
+/*
+{"kind":"sfq","handle":"8001:","parent":"1:a","options":{"limit":127,"quantum":1514,"depth":127,"divisor":1024,"perturb":10},
+    "bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}
+*/
+
+use super::QDiscError;
+use crate::{
+  netlink::{read_u32, NetlinkQdisc},
+  parse_tc_handle,
+};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcSfq {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  options: TcSfqOptions,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+struct TcSfqOptions {
+  limit: u32,
+  quantum: u32,
+  depth: u32,
+  divisor: u32,
+  flows: u32,
+  perturb: u32, // seconds
+  headdrop: bool,
+}
+
+impl TcSfq {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "options" => result.options = TcSfqOptions::from_json(value)?,
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-sfq json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(
+    qdisc: &NetlinkQdisc,
+  ) -> Result<Self, QDiscError> {
+    Ok(Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcSfqOptions::from_netlink(&qdisc.options),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    })
+  }
+}
+
+impl TcSfqOptions {
+  /// SFQ sends a `struct tc_sfq_qopt_v1` rather than nested attributes.
+  fn from_netlink(options: &[u8]) -> Self {
+    Self {
+      quantum: read_u32(options, 0),
+      perturb: read_u32(options, 4),
+      limit: read_u32(options, 8),
+      divisor: read_u32(options, 12),
+      flows: read_u32(options, 16),
+      depth: read_u32(options, 20),
+      headdrop: read_u32(options, 24) != 0,
+    }
+  }
+
+  fn from_json(value: &Value) -> Result<Self, QDiscError> {
+    match value {
+      Value::Object(map) => {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+          match key.as_str() {
+            "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+            "quantum" => result.quantum = value.as_u64().unwrap_or(0) as u32,
+            "depth" => result.depth = value.as_u64().unwrap_or(0) as u32,
+            "divisor" => result.divisor = value.as_u64().unwrap_or(0) as u32,
+            "perturb" => result.perturb = value.as_u64().unwrap_or(0) as u32,
+            "headdrop" => result.headdrop = value.as_bool().unwrap_or(true),
+            "flows" => result.flows = value.as_u64().unwrap_or(0) as u32,
+            _ => {
+              info_once!(
+                "Unknown entry in tc-sfq-options json decoder: {key}"
+              );
+            }
+          }
+        }
+        Ok(result)
+      }
+      _ => Err(QDiscError::SfqOpts),
+    }
+  }
+}
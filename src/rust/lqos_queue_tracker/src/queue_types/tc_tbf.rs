@@ -0,0 +1,170 @@
+/*
+{"kind":"tbf","handle":"8004:","root":true,"refcnt":2,"options":{"rate":1250000,"burst":32767,"lat":50000},
+    "bytes":0,"packets":0,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0}
+*/
+
+use super::QDiscError;
+use crate::{
+  netlink::{attributes, read_u32, ticks_to_us, NetlinkQdisc},
+  parse_tc_handle,
+};
+use log_once::info_once;
+use lqos_bus::TcHandle;
+use serde::Serialize;
+use serde_json::Value;
+
+const TCA_TBF_PARMS: u16 = 1;
+const TCA_TBF_RATE64: u16 = 4;
+const TCA_TBF_PRATE64: u16 = 5;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcTbf {
+  handle: TcHandle,
+  pub(crate) parent: TcHandle,
+  options: TcTbfOptions,
+  pub(crate) bytes: u64,
+  pub(crate) packets: u32,
+  pub(crate) drops: u32,
+  overlimits: u32,
+  requeues: u32,
+  pub(crate) backlog: u32,
+  pub(crate) qlen: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+struct TcTbfOptions {
+  rate: u64, // bytes per second
+  burst: u32,
+  peakrate: u64,
+  minburst: u32,
+  lat: u32, // us
+  limit: u32,
+}
+
+impl TcTbf {
+  pub(crate) fn from_json(
+    map: &serde_json::Map<std::string::String, Value>,
+  ) -> Result<Self, QDiscError> {
+    let mut result = Self::default();
+    for (key, value) in map.iter() {
+      match key.as_str() {
+        "handle" => {
+          parse_tc_handle!(result.handle, value);
+        }
+        "parent" => {
+          parse_tc_handle!(result.parent, value);
+        }
+        "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+        "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+        "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+        "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+        "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+        "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+        "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+        "options" => result.options = TcTbfOptions::from_json(value)?,
+        "kind" | "root" | "refcnt" => {}
+        _ => {
+          info_once!("Unknown entry in tc-tbf json decoder: {key}");
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  pub(crate) fn from_netlink(
+    qdisc: &NetlinkQdisc,
+  ) -> Result<Self, QDiscError> {
+    Ok(Self {
+      handle: TcHandle::from_u32(qdisc.handle),
+      parent: TcHandle::from_u32(qdisc.parent),
+      options: TcTbfOptions::from_netlink(&qdisc.options),
+      bytes: qdisc.bytes,
+      packets: qdisc.packets as u32,
+      drops: qdisc.drops,
+      overlimits: qdisc.overlimits,
+      requeues: qdisc.requeues,
+      backlog: qdisc.backlog,
+      qlen: qdisc.qlen,
+    })
+  }
+}
+
+impl TcTbfOptions {
+  /// The kernel reports the bucket sizes as times, which `tc` turns back
+  /// into the burst sizes and latency that were configured. Do the same
+  /// arithmetic, so that both readers agree. (Some `tc` versions leave
+  /// `peakrate` out of their JSON, so only this reader reports it.)
+  fn from_netlink(options: &[u8]) -> Self {
+    let mut result = Self::default();
+    let mut buffer_ticks = 0;
+    let mut mtu_ticks = 0;
+    for attribute in attributes(options) {
+      match attribute.kind {
+        TCA_TBF_PARMS => {
+          // struct tc_tbf_qopt: two 12 byte tc_ratespecs, then limit,
+          // buffer and mtu.
+          let parms = attribute.payload;
+          if result.rate == 0 {
+            result.rate = read_u32(parms, 8) as u64;
+          }
+          if result.peakrate == 0 {
+            result.peakrate = read_u32(parms, 20) as u64;
+          }
+          result.limit = read_u32(parms, 24);
+          buffer_ticks = read_u32(parms, 28);
+          mtu_ticks = read_u32(parms, 32);
+        }
+        TCA_TBF_RATE64 => result.rate = attribute.as_u64(),
+        TCA_TBF_PRATE64 => result.peakrate = attribute.as_u64(),
+        _ => {}
+      }
+    }
+    if result.rate == 0 {
+      return result;
+    }
+
+    let buffer_us = ticks_to_us(buffer_ticks) as f64;
+    result.burst = (result.rate as f64 * buffer_us / 1_000_000.0) as u32;
+    let mut latency =
+      1_000_000.0 * (result.limit as f64 / result.rate as f64) - buffer_us;
+    if result.peakrate != 0 {
+      let mtu_us = ticks_to_us(mtu_ticks) as f64;
+      result.minburst = (result.peakrate as f64 * mtu_us / 1_000_000.0) as u32;
+      let peak_latency =
+        1_000_000.0 * (result.limit as f64 / result.peakrate as f64) - mtu_us;
+      latency = f64::max(latency, peak_latency);
+    }
+    // tc prints the latency if it makes sense, and the raw limit otherwise
+    if latency >= 0.0 {
+      result.lat = latency as u32;
+      result.limit = 0;
+    }
+    result
+  }
+
+  fn from_json(value: &Value) -> Result<Self, QDiscError> {
+    match value {
+      Value::Object(map) => {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+          match key.as_str() {
+            "rate" => result.rate = value.as_u64().unwrap_or(0),
+            "burst" => result.burst = value.as_u64().unwrap_or(0) as u32,
+            "peakrate" => result.peakrate = value.as_u64().unwrap_or(0),
+            "minburst" => result.minburst = value.as_u64().unwrap_or(0) as u32,
+            "lat" => result.lat = value.as_u64().unwrap_or(0) as u32,
+            "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+            "linklayer" | "overhead" => {}
+            _ => {
+              info_once!(
+                "Unknown entry in tc-tbf-options json decoder: {key}"
+              );
+            }
+          }
+        }
+        Ok(result)
+      }
+      _ => Err(QDiscError::TbfOpts),
+    }
+  }
+}
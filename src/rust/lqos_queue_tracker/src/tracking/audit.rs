@@ -0,0 +1,255 @@
+use crate::{
+  netlink::{dump_classes, dump_qdiscs},
+  queue_structure::{read_queueing_structure, QueueNode},
+  queue_types::TcHtbClass,
+};
+use lqos_bus::{
+  BusResponse, QueueTreeAudit, QueueTreeClass, QueueTreeRateMismatch, TcHandle,
+};
+use lqos_config::LibreQoSConfig;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A class that `queuingStructure.json` says should exist.
+struct ExpectedClass {
+  interface: String,
+  class_id: TcHandle,
+  name: Option<String>,
+  rate: u64, // bits per second
+  ceil: u64, // bits per second
+  is_circuit: bool,
+}
+
+/// What the kernel has on one interface.
+#[derive(Default)]
+struct LiveTree {
+  /// HTB classes, keyed by handle
+  classes: HashMap<u32, TcHtbClass>,
+  /// The parents of every qdisc
+  qdisc_parents: HashSet<u32>,
+}
+
+/// Lists the download and upload classes for every node in the queue
+/// structure.
+fn expected_classes(
+  nodes: &[QueueNode],
+  config: &LibreQoSConfig,
+) -> Vec<ExpectedClass> {
+  let (download_interface, upload_interface) = if config.on_a_stick_mode {
+    (&config.internet_interface, &config.internet_interface)
+  } else {
+    (&config.isp_interface, &config.internet_interface)
+  };
+  let mut result = Vec::new();
+  for node in nodes.iter() {
+    // Devices are flattened into the structure too, but have no class.
+    if node.class_id == TcHandle::default() {
+      continue;
+    }
+    let name = node.circuit_id.clone().or(node.name.clone());
+    result.push(ExpectedClass {
+      interface: download_interface.clone(),
+      class_id: node.class_id,
+      name: name.clone(),
+      rate: node.download_bandwidth_mbps_min * 1_000_000,
+      ceil: node.download_bandwidth_mbps * 1_000_000,
+      is_circuit: node.circuit_id.is_some(),
+    });
+    result.push(ExpectedClass {
+      interface: upload_interface.clone(),
+      class_id: node.up_class_id,
+      name,
+      rate: node.upload_bandwidth_mbps_min * 1_000_000,
+      ceil: node.upload_bandwidth_mbps * 1_000_000,
+      is_circuit: node.circuit_id.is_some(),
+    });
+  }
+  result
+}
+
+fn read_live_tree(interface: &str) -> Result<LiveTree, String> {
+  let classes = dump_classes(interface)
+    .map_err(|e| format!("Unable to read classes on {interface}: {e}"))?;
+  let qdiscs = dump_qdiscs(interface)
+    .map_err(|e| format!("Unable to read qdiscs on {interface}: {e}"))?;
+  Ok(LiveTree {
+    classes: classes
+      .iter()
+      .filter(|c| c.kind == "htb")
+      .map(|c| (c.handle, TcHtbClass::from_netlink(c)))
+      .collect(),
+    qdisc_parents: qdiscs.iter().map(|q| q.parent).collect(),
+  })
+}
+
+/// Compares the expected classes with what is on each interface.
+fn compare(
+  expected: &[ExpectedClass],
+  live: &HashMap<String, LiveTree>,
+  monitor_mode: bool,
+) -> QueueTreeAudit {
+  let mut result = QueueTreeAudit::default();
+  let mut seen: HashSet<(&str, u32)> = HashSet::new();
+  for class in expected.iter() {
+    let handle = class.class_id.as_u32();
+    seen.insert((&class.interface, handle));
+    let tree = live.get(&class.interface);
+    let report = QueueTreeClass {
+      interface: class.interface.clone(),
+      class_id: class.class_id,
+      name: class.name.clone(),
+    };
+    let live_class = tree.and_then(|t| t.classes.get(&handle));
+    if let Some(live_class) = live_class {
+      let actual_rate = live_class.rate * 8;
+      let actual_ceil = live_class.ceil * 8;
+      if actual_rate != class.rate || actual_ceil != class.ceil {
+        result.rate_mismatches.push(QueueTreeRateMismatch {
+          class: report.clone(),
+          expected_rate: class.rate,
+          expected_ceil: class.ceil,
+          actual_rate,
+          actual_ceil,
+        });
+      }
+      let has_qdisc =
+        tree.map(|t| t.qdisc_parents.contains(&handle)).unwrap_or(false);
+      if class.is_circuit && !monitor_mode && !has_qdisc {
+        result.missing_qdiscs.push(report);
+      }
+    } else {
+      result.missing_classes.push(report);
+    }
+  }
+
+  // Sorted, so that the report is stable from one run to the next
+  let interfaces: BTreeSet<&String> = live.keys().collect();
+  for interface in interfaces {
+    let mut handles: Vec<u32> = live[interface]
+      .classes
+      .keys()
+      .filter(|handle| !seen.contains(&(interface.as_str(), **handle)))
+      // x:1 and x:2 are LibreQoS's per-CPU root and default classes
+      .filter(|handle| TcHandle::from_u32(**handle).get_major_minor().1 > 2)
+      .copied()
+      .collect();
+    handles.sort_unstable();
+    result.extra_classes.extend(handles.into_iter().map(|handle| {
+      QueueTreeClass {
+        interface: interface.clone(),
+        class_id: TcHandle::from_u32(handle),
+        name: None,
+      }
+    }));
+  }
+  result
+}
+
+/// Compares the live `tc` tree on the shaping interfaces with
+/// `queuingStructure.json`.
+pub fn audit_queue_tree() -> BusResponse {
+  let config = LibreQoSConfig::load();
+  if config.is_err() {
+    return BusResponse::Fail("Unable to read LibreQoS config".to_string());
+  }
+  let config = config.unwrap();
+  let nodes = read_queueing_structure();
+  if nodes.is_err() {
+    return BusResponse::Fail(
+      "Unable to read queuingStructure.json".to_string(),
+    );
+  }
+  let expected = expected_classes(&nodes.unwrap(), &config);
+
+  let mut live = HashMap::new();
+  for interface in [&config.isp_interface, &config.internet_interface] {
+    if live.contains_key(interface) {
+      continue;
+    }
+    match read_live_tree(interface) {
+      Ok(tree) => live.insert(interface.clone(), tree),
+      Err(e) => return BusResponse::Fail(e),
+    };
+  }
+  BusResponse::QueueTreeAudit(Box::new(compare(
+    &expected,
+    &live,
+    config.monitor_mode,
+  )))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::netlink::NetlinkQdisc;
+
+  fn expected(handle: &str, name: &str, mbps: u64) -> ExpectedClass {
+    ExpectedClass {
+      interface: "eth1".to_string(),
+      class_id: TcHandle::from_string(handle).unwrap(),
+      name: Some(name.to_string()),
+      rate: mbps * 1_000_000 / 2,
+      ceil: mbps * 1_000_000,
+      is_circuit: true,
+    }
+  }
+
+  fn htb_class(handle: &str, mbps: u64) -> (u32, TcHtbClass) {
+    // struct tc_htb_opt, with the rate and ceiling in bytes per second
+    let mut parms = vec![0u8; 44];
+    let rate = (mbps * 1_000_000 / 16) as u32;
+    let ceil = (mbps * 1_000_000 / 8) as u32;
+    parms[8..12].copy_from_slice(&rate.to_ne_bytes());
+    parms[20..24].copy_from_slice(&ceil.to_ne_bytes());
+    let mut options = Vec::new();
+    options.extend_from_slice(&48u16.to_ne_bytes());
+    options.extend_from_slice(&1u16.to_ne_bytes());
+    options.extend_from_slice(&parms);
+    let handle = TcHandle::from_string(handle).unwrap().as_u32();
+    let class = TcHtbClass::from_netlink(&NetlinkQdisc {
+      kind: "htb".to_string(),
+      handle,
+      options,
+      ..Default::default()
+    });
+    (handle, class)
+  }
+
+  #[test]
+  fn reports_drift() {
+    let expected = vec![
+      expected("1:3", "ok", 100),
+      expected("1:4", "slow", 100),
+      expected("1:5", "gone", 100),
+    ];
+    let mut tree = LiveTree::default();
+    for (handle, class) in
+      [htb_class("1:1", 1000), htb_class("1:2", 1000), htb_class("1:3", 100)]
+    {
+      tree.classes.insert(handle, class);
+    }
+    let (handle, class) = htb_class("1:4", 50);
+    tree.classes.insert(handle, class);
+    let (handle, class) = htb_class("1:9", 10);
+    tree.classes.insert(handle, class);
+    tree.qdisc_parents.insert(TcHandle::from_string("1:3").unwrap().as_u32());
+    let live = HashMap::from([("eth1".to_string(), tree)]);
+
+    let audit = compare(&expected, &live, false);
+    assert_eq!(audit.missing_classes.len(), 1);
+    assert_eq!(audit.missing_classes[0].name.as_deref(), Some("gone"));
+    assert_eq!(audit.extra_classes.len(), 1);
+    assert_eq!(
+      audit.extra_classes[0].class_id,
+      TcHandle::from_string("1:9").unwrap()
+    );
+    assert_eq!(audit.rate_mismatches.len(), 1);
+    assert_eq!(audit.rate_mismatches[0].expected_ceil, 100_000_000);
+    assert_eq!(audit.rate_mismatches[0].actual_ceil, 50_000_000);
+    // 1:4 has no qdisc; 1:3 does
+    assert_eq!(audit.missing_qdiscs.len(), 1);
+    assert!(!audit.is_clean());
+
+    let audit = compare(&expected[1..2], &live, true);
+    assert!(audit.missing_qdiscs.is_empty());
+  }
+}
@@ -0,0 +1,144 @@
+use lqos_bus::{BufferbloatDistribution, BufferbloatGrade};
+use std::collections::VecDeque;
+
+/// How many busy samples are kept, per direction, for grading?
+const GRADE_WINDOW: usize = 60;
+
+/// How many busy samples are needed before a direction is graded?
+const MIN_LOADED_SAMPLES: usize = 3;
+
+/// A circuit counts as busy if it averaged at least this fraction of its
+/// plan rate since the previous sweep. Delay on an idle queue says
+/// nothing about bufferbloat.
+const LOADED_FRACTION: f64 = 0.5;
+
+/// Did a circuit move enough data, over `elapsed_secs`, to count as
+/// busy? `capacity_mbps` is the plan rate.
+pub(crate) fn is_loaded(
+  bytes: u64,
+  elapsed_secs: u64,
+  capacity_mbps: u64,
+) -> bool {
+  if elapsed_secs == 0 || capacity_mbps == 0 {
+    return false;
+  }
+  let bits_per_second = (bytes * 8) as f64 / elapsed_secs as f64;
+  bits_per_second >= capacity_mbps as f64 * 1_000_000.0 * LOADED_FRACTION
+}
+
+/// Grades an average queueing delay.
+pub(crate) fn grade_for_delay(delay_us: u32) -> BufferbloatGrade {
+  match delay_us {
+    0..=29_999 => BufferbloatGrade::A,
+    30_000..=59_999 => BufferbloatGrade::B,
+    60_000..=199_999 => BufferbloatGrade::C,
+    200_000..=399_999 => BufferbloatGrade::D,
+    _ => BufferbloatGrade::F,
+  }
+}
+
+/// Queueing delays seen in one direction while the circuit was busy.
+#[derive(Default)]
+pub(crate) struct LoadedDelays {
+  delays_us: VecDeque<u32>,
+}
+
+impl LoadedDelays {
+  pub(crate) fn record(&mut self, delay_us: u32) {
+    if self.delays_us.len() >= GRADE_WINDOW {
+      self.delays_us.pop_front();
+    }
+    self.delays_us.push_back(delay_us);
+  }
+
+  pub(crate) fn len(&self) -> u32 {
+    self.delays_us.len() as u32
+  }
+
+  /// The average delay, if there are enough samples to grade.
+  pub(crate) fn average(&self) -> Option<u32> {
+    if self.delays_us.len() < MIN_LOADED_SAMPLES {
+      return None;
+    }
+    let total: u64 = self.delays_us.iter().map(|d| *d as u64).sum();
+    Some((total / self.delays_us.len() as u64) as u32)
+  }
+}
+
+/// Grades a circuit on its worse direction. A direction that hasn't been
+/// busy doesn't count against the circuit.
+pub(crate) fn circuit_grade(
+  down: &LoadedDelays,
+  up: &LoadedDelays,
+) -> Option<BufferbloatGrade> {
+  match (down.average(), up.average()) {
+    (Some(down), Some(up)) => Some(grade_for_delay(u32::max(down, up))),
+    (Some(delay), None) | (None, Some(delay)) => Some(grade_for_delay(delay)),
+    (None, None) => None,
+  }
+}
+
+/// Tallies circuit grades.
+pub(crate) fn distribution(
+  grades: impl Iterator<Item = Option<BufferbloatGrade>>,
+) -> BufferbloatDistribution {
+  let mut result = BufferbloatDistribution::default();
+  for grade in grades {
+    match grade {
+      Some(BufferbloatGrade::A) => result.a += 1,
+      Some(BufferbloatGrade::B) => result.b += 1,
+      Some(BufferbloatGrade::C) => result.c += 1,
+      Some(BufferbloatGrade::D) => result.d += 1,
+      Some(BufferbloatGrade::F) => result.f += 1,
+      None => result.ungraded += 1,
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn grade_boundaries() {
+    assert_eq!(grade_for_delay(0), BufferbloatGrade::A);
+    assert_eq!(grade_for_delay(29_999), BufferbloatGrade::A);
+    assert_eq!(grade_for_delay(30_000), BufferbloatGrade::B);
+    assert_eq!(grade_for_delay(150_000), BufferbloatGrade::C);
+    assert_eq!(grade_for_delay(250_000), BufferbloatGrade::D);
+    assert_eq!(grade_for_delay(400_000), BufferbloatGrade::F);
+  }
+
+  #[test]
+  fn load_threshold() {
+    // 100 Mbps plan, 30 seconds: 50 Mbps is 187.5 MB
+    assert!(is_loaded(187_500_000, 30, 100));
+    assert!(!is_loaded(100_000_000, 30, 100));
+    assert!(!is_loaded(187_500_000, 0, 100));
+  }
+
+  #[test]
+  fn grades_the_worse_direction() {
+    let mut down = LoadedDelays::default();
+    let mut up = LoadedDelays::default();
+    assert_eq!(circuit_grade(&down, &up), None);
+    for _ in 0..MIN_LOADED_SAMPLES {
+      down.record(5_000);
+    }
+    assert_eq!(circuit_grade(&down, &up), Some(BufferbloatGrade::A));
+    for _ in 0..MIN_LOADED_SAMPLES {
+      up.record(80_000);
+    }
+    assert_eq!(circuit_grade(&down, &up), Some(BufferbloatGrade::C));
+  }
+
+  #[test]
+  fn distribution_counts_grades() {
+    let grades =
+      vec![Some(BufferbloatGrade::A), Some(BufferbloatGrade::A), None];
+    let result = distribution(grades.into_iter());
+    assert_eq!(result.a, 2);
+    assert_eq!(result.ungraded, 1);
+  }
+}
@@ -0,0 +1,74 @@
+use crate::{
+  htb_class_store::{HtbClassStore, HTB_CLASSES},
+  netlink::dump_classes,
+  queue_structure::QUEUE_STRUCTURE,
+  queue_types::TcHtbClass,
+};
+use log::warn;
+use lqos_bus::TcHandle;
+use lqos_config::LibreQoSConfig;
+use std::collections::{HashMap, HashSet};
+
+/// Reads every HTB class on `interface`, keyed by its handle.
+fn read_htb_classes(interface: &str) -> Option<HashMap<u32, TcHtbClass>> {
+  match dump_classes(interface) {
+    Ok(classes) => Some(
+      classes
+        .iter()
+        .filter(|c| c.kind == "htb")
+        .map(|c| (c.handle, TcHtbClass::from_netlink(c)))
+        .collect(),
+    ),
+    Err(e) => {
+      warn!("Unable to read HTB classes from {interface}: {e:?}");
+      None
+    }
+  }
+}
+
+/// Updates the HTB class history of every node in the queue structure.
+pub(crate) fn track_htb_classes() {
+  let config = LibreQoSConfig::load();
+  if config.is_err() {
+    return;
+  }
+  let config = config.unwrap();
+
+  let nodes: Vec<(TcHandle, TcHandle)> =
+    if let Some(queues) = &QUEUE_STRUCTURE.read().unwrap().maybe_queues {
+      queues.iter().map(|q| (q.class_id, q.up_class_id)).collect()
+    } else {
+      return;
+    };
+
+  let download_interface = if config.on_a_stick_mode {
+    &config.internet_interface
+  } else {
+    &config.isp_interface
+  };
+  let download = read_htb_classes(download_interface);
+  let upload = if config.on_a_stick_mode {
+    download.clone()
+  } else {
+    read_htb_classes(&config.internet_interface)
+  };
+  if let (Some(download), Some(upload)) = (download, upload) {
+    for (down_class, up_class) in nodes.iter() {
+      if let (Some(down), Some(up)) =
+        (download.get(&down_class.as_u32()), upload.get(&up_class.as_u32()))
+      {
+        if let Some(mut store) = HTB_CLASSES.get_mut(down_class) {
+          store.update(down, up);
+        } else {
+          HTB_CLASSES
+            .insert(*down_class, HtbClassStore::new(down.clone(), up.clone()));
+        }
+      }
+    }
+
+    // Forget classes that are no longer in the queue structure
+    let known: HashSet<&TcHandle> =
+      nodes.iter().map(|(down, _)| down).collect();
+    HTB_CLASSES.retain(|class, _| known.contains(class));
+  }
+}
@@ -1,11 +1,24 @@
 use crate::{
   circuit_to_queue::CIRCUIT_TO_QUEUE, interval::QUEUE_MONITOR_INTERVAL,
-  queue_store::QueueStore, tracking::reader::read_named_queue_from_interface,
+  queue_store::QueueStore,
 };
 use log::info;
-use lqos_config::LibreQoSConfig;
+use lqos_config::{LibreQoSConfig, QueueStatsBackend};
 use lqos_utils::fdtimer::periodic;
+mod audit;
+pub use audit::audit_queue_tree;
+mod bufferbloat;
+mod htb_classes;
 mod reader;
+pub use reader::{
+  read_named_queue_from_interface, read_named_queue_netlink,
+  read_named_queue_tc, set_queue_stats_backend, QueueReaderError,
+};
+mod sweep;
+pub use sweep::{
+  all_circuit_queue_summaries, bufferbloat_distribution,
+  get_circuit_bufferbloat, get_circuit_queue_summary,
+};
 mod watched_queues;
 use self::watched_queues::expire_watched_queues;
 use watched_queues::WATCHED_QUEUES;
@@ -80,18 +93,32 @@ pub fn spawn_queue_monitor() {
   std::thread::spawn(|| {
     // Setup the queue monitor loop
     info!("Starting Queue Monitor Thread.");
-    let interval_ms = if let Ok(config) = lqos_config::EtcLqos::load() {
-      config.queue_check_period_ms
-    } else {
-      1000
-    };
+    let (interval_ms, backend, htb_class_stats) =
+      if let Ok(config) = lqos_config::EtcLqos::load() {
+        (
+          config.queue_check_period_ms,
+          config.queue_stats_backend.unwrap_or_default(),
+          config.htb_class_stats.unwrap_or(false),
+        )
+      } else {
+        (1000, QueueStatsBackend::default(), false)
+      };
+    set_queue_stats_backend(backend);
+    info!("Reading queue statistics with {backend:?}.");
+    sweep::spawn_queue_sweep();
     QUEUE_MONITOR_INTERVAL
       .store(interval_ms, std::sync::atomic::Ordering::Relaxed);
     info!("Queue check period set to {interval_ms} ms.");
+    if htb_class_stats {
+      info!("Tracking HTB class statistics.");
+    }
 
     // Setup the Linux timer fd system
     periodic(interval_ms, "Queue Reader", &mut || {
       track_queues();
+      if htb_class_stats {
+        htb_classes::track_htb_classes();
+      }
     });
   });
 }
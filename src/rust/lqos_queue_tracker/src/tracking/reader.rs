@@ -1,14 +1,72 @@
-use crate::{deserialize_tc_tree, queue_types::QueueType};
+use crate::{
+  deserialize_tc_tree,
+  netlink::dump_qdiscs,
+  queue_types::{deserialize_tc_tree_kinds, QueueType},
+};
 use log::error;
 use lqos_bus::TcHandle;
-use std::process::Command;
+use lqos_config::QueueStatsBackend;
+use std::{
+  process::Command,
+  sync::atomic::{AtomicBool, Ordering},
+};
 use thiserror::Error;
 
 const TC: &str = "/sbin/tc";
 
+static USE_NETLINK: AtomicBool = AtomicBool::new(false);
+
+/// Selects how queue statistics are read from the kernel.
+pub fn set_queue_stats_backend(backend: QueueStatsBackend) {
+  USE_NETLINK.store(backend == QueueStatsBackend::Netlink, Ordering::Relaxed);
+}
+
+/// Reads the qdiscs attached to `tc_handle` on `interface`, using the
+/// selected backend.
 pub fn read_named_queue_from_interface(
   interface: &str,
   tc_handle: TcHandle,
+) -> Result<Vec<QueueType>, QueueReaderError> {
+  if USE_NETLINK.load(Ordering::Relaxed) {
+    read_named_queue_netlink(interface, tc_handle)
+  } else {
+    read_named_queue_tc(interface, tc_handle)
+  }
+}
+
+/// Reads the qdiscs attached to `tc_handle` on `interface` over
+/// rtnetlink.
+pub fn read_named_queue_netlink(
+  interface: &str,
+  tc_handle: TcHandle,
+) -> Result<Vec<QueueType>, QueueReaderError> {
+  let qdiscs = dump_qdiscs(interface);
+  if let Err(e) = qdiscs {
+    error!("Failed to read qdiscs from {interface} over netlink: {e:?}");
+    return Err(QueueReaderError::Netlink);
+  }
+  let mut result = Vec::new();
+  for qdisc in qdiscs.unwrap().iter() {
+    if qdisc.parent != tc_handle.as_u32() {
+      continue;
+    }
+    match QueueType::from_netlink(qdisc) {
+      Ok(queue) => result.push(queue),
+      Err(e) => {
+        error!("Failed to decode netlink qdisc.");
+        error!("{:?}", e);
+        return Err(QueueReaderError::Deserialization);
+      }
+    }
+  }
+  Ok(result)
+}
+
+/// Reads the qdiscs attached to `tc_handle` on `interface` by running
+/// `tc -s -j qdisc show` and parsing the result.
+pub fn read_named_queue_tc(
+  interface: &str,
+  tc_handle: TcHandle,
 ) -> Result<Vec<QueueType>, QueueReaderError> {
   let command_output = Command::new(TC)
     .args([
@@ -49,6 +107,58 @@ pub fn read_named_queue_from_interface(
   Ok(result.unwrap())
 }
 
+/// Reads every qdisc of the listed `kinds` on `interface` in a single
+/// pass, using the selected backend.
+pub(crate) fn read_all_queues_from_interface(
+  interface: &str,
+  kinds: &[&str],
+) -> Result<Vec<QueueType>, QueueReaderError> {
+  if USE_NETLINK.load(Ordering::Relaxed) {
+    let qdiscs = dump_qdiscs(interface);
+    if let Err(e) = qdiscs {
+      error!("Failed to read qdiscs from {interface} over netlink: {e:?}");
+      return Err(QueueReaderError::Netlink);
+    }
+    let mut result = Vec::new();
+    for qdisc in qdiscs.unwrap().iter() {
+      if !kinds.contains(&qdisc.kind.as_str()) {
+        continue;
+      }
+      match QueueType::from_netlink(qdisc) {
+        Ok(queue) => result.push(queue),
+        Err(e) => {
+          error!("Failed to decode netlink qdisc.");
+          error!("{:?}", e);
+          return Err(QueueReaderError::Deserialization);
+        }
+      }
+    }
+    Ok(result)
+  } else {
+    let command_output = Command::new(TC)
+      .args(["-s", "-j", "qdisc", "show", "dev", interface])
+      .output();
+    if command_output.is_err() {
+      error!("Failed to call process tc -s -j qdisc show dev {interface}");
+      error!("{:?}", command_output);
+      return Err(QueueReaderError::CommandError);
+    }
+    let json = String::from_utf8(command_output.unwrap().stdout);
+    if json.is_err() {
+      error!("Failed to convert byte stream to UTF-8 string");
+      error!("{:?}", json);
+      return Err(QueueReaderError::Utf8Error);
+    }
+    let result = deserialize_tc_tree_kinds(&json.unwrap(), kinds);
+    if result.is_err() {
+      error!("Failed to deserialize TC tree result.");
+      error!("{:?}", result);
+      return Err(QueueReaderError::Deserialization);
+    }
+    Ok(result.unwrap())
+  }
+}
+
 #[derive(Error, Debug)]
 pub enum QueueReaderError {
   #[error("Subprocess call failed")]
@@ -57,4 +167,6 @@ pub enum QueueReaderError {
   Utf8Error,
   #[error("Deserialization Error")]
   Deserialization,
+  #[error("Netlink Error")]
+  Netlink,
 }
@@ -0,0 +1,448 @@
+use super::{
+  bufferbloat::{circuit_grade, distribution, is_loaded, LoadedDelays},
+  reader::read_all_queues_from_interface,
+};
+use crate::{
+  queue_diff::make_queue_diff,
+  queue_history::{history_config, store_sweep},
+  queue_structure::QUEUE_STRUCTURE,
+  queue_types::QueueType,
+};
+use dashmap::DashMap;
+use log::{info, warn};
+use lqos_bus::{
+  BusResponse, CircuitBufferbloat, CircuitQueueSummary, QueueHistoryRecord,
+  QueueSweepSample,
+};
+use lqos_config::{LibreQoSConfig, QueueHistoryConfig};
+use lqos_utils::{fdtimer::periodic, unix_time::unix_now};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How many sweep samples are kept per circuit?
+const SWEEP_HISTORY: usize = 120;
+
+/// The leaf qdisc kinds that the sweep reads.
+const SWEEP_KINDS: [&str; 2] = ["cake", "fq_codel"];
+
+static SWEPT_QUEUES: Lazy<DashMap<String, SweptCircuit>> =
+  Lazy::new(DashMap::new);
+
+/// Cumulative counters, as read from a leaf qdisc.
+#[derive(Clone, Copy, Default)]
+struct LeafCounters {
+  bytes: u64,
+  drops: u64,
+  marks: u64,
+  avg_delay_us: u32,
+  peak_delay_us: u32,
+  /// Delay added by queueing: CAKE's average delay, less its base delay,
+  /// for the worst tin. `None` for kinds that don't report delay.
+  queue_delay_us: Option<u32>,
+}
+
+impl LeafCounters {
+  fn from_queue(queue: &QueueType) -> Option<Self> {
+    match queue {
+      QueueType::Cake(cake) => Some(Self {
+        bytes: cake.bytes,
+        drops: cake.drops as u64,
+        marks: cake.tins.iter().map(|t| t.ecn_marks as u64).sum(),
+        avg_delay_us: cake
+          .tins
+          .iter()
+          .map(|t| t.avg_delay_us)
+          .max()
+          .unwrap_or(0),
+        peak_delay_us: cake
+          .tins
+          .iter()
+          .map(|t| t.peak_delay_us)
+          .max()
+          .unwrap_or(0),
+        queue_delay_us: cake
+          .tins
+          .iter()
+          .map(|t| t.avg_delay_us.saturating_sub(t.base_delay_us))
+          .max(),
+      }),
+      QueueType::FqCodel(codel) => Some(Self {
+        bytes: codel.bytes,
+        drops: codel.drops as u64,
+        marks: codel.ecn_mark as u64,
+        ..Default::default()
+      }),
+      _ => None,
+    }
+  }
+
+  fn parent(queue: &QueueType) -> Option<u32> {
+    match queue {
+      QueueType::Cake(cake) => Some(cake.parent.as_u32()),
+      QueueType::FqCodel(codel) => Some(codel.parent.as_u32()),
+      _ => None,
+    }
+  }
+}
+
+/// Counters go backwards when LibreQoS rebuilds the queues. When that
+/// happens, the new counter value is everything since the rebuild.
+fn counter_delta(previous: u64, current: u64) -> u64 {
+  if current >= previous {
+    current - previous
+  } else {
+    current
+  }
+}
+
+#[derive(Default)]
+struct SweptCircuit {
+  previous: Option<(u64, LeafCounters, LeafCounters)>,
+  total_drops: (u64, u64),
+  total_marks: (u64, u64),
+  history: VecDeque<QueueSweepSample>,
+  loaded_delays: (LoadedDelays, LoadedDelays),
+  /// The last queues read, kept only while queue history is enabled.
+  previous_queues: Option<(QueueType, QueueType)>,
+}
+
+impl SweptCircuit {
+  /// `capacity_mbps` is the circuit's plan rate, `(download, upload)`.
+  fn update(
+    &mut self,
+    now: u64,
+    down: LeafCounters,
+    up: LeafCounters,
+    capacity_mbps: (u64, u64),
+  ) {
+    if let Some((prev_time, prev_down, prev_up)) = self.previous {
+      let elapsed = now.saturating_sub(prev_time);
+      if let Some(delay) = down.queue_delay_us {
+        let bytes = counter_delta(prev_down.bytes, down.bytes);
+        if is_loaded(bytes, elapsed, capacity_mbps.0) {
+          self.loaded_delays.0.record(delay);
+        }
+      }
+      if let Some(delay) = up.queue_delay_us {
+        let bytes = counter_delta(prev_up.bytes, up.bytes);
+        if is_loaded(bytes, elapsed, capacity_mbps.1) {
+          self.loaded_delays.1.record(delay);
+        }
+      }
+
+      let drops = (
+        counter_delta(prev_down.drops, down.drops),
+        counter_delta(prev_up.drops, up.drops),
+      );
+      let marks = (
+        counter_delta(prev_down.marks, down.marks),
+        counter_delta(prev_up.marks, up.marks),
+      );
+      self.total_drops.0 += drops.0;
+      self.total_drops.1 += drops.1;
+      self.total_marks.0 += marks.0;
+      self.total_marks.1 += marks.1;
+      if self.history.len() >= SWEEP_HISTORY {
+        self.history.pop_front();
+      }
+      self.history.push_back(QueueSweepSample {
+        unix_time: now,
+        drops,
+        marks,
+        avg_delay_us: (down.avg_delay_us, up.avg_delay_us),
+        peak_delay_us: (down.peak_delay_us, up.peak_delay_us),
+      });
+    }
+    self.previous = Some((now, down, up));
+  }
+
+  /// Diffs the queues against the previous sweep's, for the on-disk
+  /// history. Nothing is recorded for the first sweep, or when the
+  /// circuit's qdisc kind has changed.
+  fn history_record(
+    &mut self,
+    now: u64,
+    circuit_id: &str,
+    down: &QueueType,
+    up: &QueueType,
+  ) -> Option<QueueHistoryRecord> {
+    let record = if let Some((prev_down, prev_up)) = &self.previous_queues {
+      match (make_queue_diff(prev_down, down), make_queue_diff(prev_up, up)) {
+        (Ok(download), Ok(upload)) => Some(QueueHistoryRecord {
+          unix_time: now,
+          circuit_id: circuit_id.to_string(),
+          download: download.into(),
+          upload: upload.into(),
+        }),
+        _ => None,
+      }
+    } else {
+      None
+    };
+    self.previous_queues = Some((down.clone(), up.clone()));
+    record
+  }
+
+  fn bufferbloat(&self, circuit_id: &str) -> CircuitBufferbloat {
+    let (down, up) = &self.loaded_delays;
+    CircuitBufferbloat {
+      circuit_id: circuit_id.to_string(),
+      grade: circuit_grade(down, up),
+      queue_delay_us: (down.average().unwrap_or(0), up.average().unwrap_or(0)),
+      loaded_samples: (down.len(), up.len()),
+    }
+  }
+
+  fn summarize(
+    &self,
+    circuit_id: &str,
+    full_history: bool,
+  ) -> CircuitQueueSummary {
+    let history = if full_history {
+      self.history.iter().cloned().collect()
+    } else {
+      self.history.back().cloned().into_iter().collect()
+    };
+    CircuitQueueSummary {
+      circuit_id: circuit_id.to_string(),
+      total_drops: self.total_drops,
+      total_marks: self.total_marks,
+      history,
+    }
+  }
+}
+
+/// Reads every leaf qdisc on `interface`, keyed by its parent class.
+fn read_all_leaves(interface: &str) -> Option<HashMap<u32, QueueType>> {
+  match read_all_queues_from_interface(interface, &SWEEP_KINDS) {
+    Ok(queues) => Some(
+      queues
+        .into_iter()
+        .filter_map(|q| Some((LeafCounters::parent(&q)?, q)))
+        .collect(),
+    ),
+    Err(e) => {
+      warn!("Queue sweep was unable to read {interface}: {e:?}");
+      None
+    }
+  }
+}
+
+/// A circuit to read, from the queue structure.
+struct SweepTarget {
+  circuit_id: String,
+  down_class: u32,
+  up_class: u32,
+  capacity_mbps: (u64, u64),
+}
+
+fn sweep_queues(history: Option<&QueueHistoryConfig>) {
+  let config = LibreQoSConfig::load();
+  if config.is_err() {
+    warn!("Unable to read LibreQoS config. Skipping queue sweep.");
+    return;
+  }
+  let config = config.unwrap();
+
+  let circuits: Vec<SweepTarget> = if let Some(queues) =
+    &QUEUE_STRUCTURE.read().unwrap().maybe_queues
+  {
+    queues
+      .iter()
+      .filter_map(|q| {
+        q.circuit_id.as_ref().map(|id| SweepTarget {
+          circuit_id: id.clone(),
+          down_class: q.class_id.as_u32(),
+          up_class: q.up_class_id.as_u32(),
+          capacity_mbps: (q.download_bandwidth_mbps, q.upload_bandwidth_mbps),
+        })
+      })
+      .collect()
+  } else {
+    return;
+  };
+
+  let download_interface = if config.on_a_stick_mode {
+    &config.internet_interface
+  } else {
+    &config.isp_interface
+  };
+  let download = read_all_leaves(download_interface);
+  let upload = if config.on_a_stick_mode {
+    download.clone()
+  } else {
+    read_all_leaves(&config.internet_interface)
+  };
+  if let (Some(download), Some(upload)) = (download, upload) {
+    let now = unix_now().unwrap_or(0);
+    let mut records = Vec::new();
+    for target in circuits.iter() {
+      if let (Some(down), Some(up)) =
+        (download.get(&target.down_class), upload.get(&target.up_class))
+      {
+        let mut circuit =
+          SWEPT_QUEUES.entry(target.circuit_id.clone()).or_default();
+        if let (Some(down), Some(up)) =
+          (LeafCounters::from_queue(down), LeafCounters::from_queue(up))
+        {
+          circuit.update(now, down, up, target.capacity_mbps);
+        }
+        if history.is_some() {
+          records.extend(circuit.history_record(
+            now,
+            &target.circuit_id,
+            down,
+            up,
+          ));
+        }
+      }
+    }
+    if let Some(history) = history {
+      store_sweep(history, &records);
+    }
+
+    // Forget circuits that are no longer in the queue structure
+    let known: HashSet<&String> =
+      circuits.iter().map(|t| &t.circuit_id).collect();
+    SWEPT_QUEUES.retain(|id, _| known.contains(id));
+  }
+}
+
+/// Starts the whole-box queue sweep, if it is enabled in
+/// `/etc/lqos.conf`.
+pub(crate) fn spawn_queue_sweep() {
+  let sweep = lqos_config::EtcLqos::load()
+    .ok()
+    .and_then(|cfg| cfg.queue_sweep)
+    .filter(|sweep| sweep.enabled);
+  let history = history_config();
+  if let Some(sweep) = sweep {
+    let interval_secs = u64::max(sweep.interval_secs, 1);
+    std::thread::spawn(move || {
+      info!("Sweeping all queues every {interval_secs} seconds.");
+      if let Some(history) = &history {
+        info!("Writing queue history to {}.", history.directory);
+      }
+      periodic(interval_secs * 1000, "Queue Sweep", &mut || {
+        sweep_queues(history.as_ref());
+      });
+    });
+  } else if history.is_some() {
+    warn!("Queue history needs [queue_sweep]. No history will be written.");
+  }
+}
+
+/// Retrieves the queue sweep history for a circuit.
+pub fn get_circuit_queue_summary(circuit_id: &str) -> BusResponse {
+  BusResponse::CircuitQueueSummary(
+    SWEPT_QUEUES
+      .get(circuit_id)
+      .map(|c| Box::new(c.summarize(circuit_id, true))),
+  )
+}
+
+/// Retrieves queue sweep totals, and the latest sample, for every
+/// circuit.
+pub fn all_circuit_queue_summaries() -> BusResponse {
+  BusResponse::CircuitQueueSummaries(
+    SWEPT_QUEUES.iter().map(|c| c.value().summarize(c.key(), false)).collect(),
+  )
+}
+
+/// Retrieves a circuit's rolling bufferbloat grade.
+pub fn get_circuit_bufferbloat(circuit_id: &str) -> BusResponse {
+  BusResponse::CircuitBufferbloat(
+    SWEPT_QUEUES.get(circuit_id).map(|c| c.bufferbloat(circuit_id)),
+  )
+}
+
+/// Counts how many swept circuits have each bufferbloat grade.
+pub fn bufferbloat_distribution() -> BusResponse {
+  BusResponse::BufferbloatDistribution(distribution(
+    SWEPT_QUEUES.iter().map(|c| c.value().bufferbloat(c.key()).grade),
+  ))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use lqos_bus::BufferbloatGrade;
+
+  fn counters(drops: u64, marks: u64) -> LeafCounters {
+    LeafCounters {
+      drops,
+      marks,
+      avg_delay_us: 10,
+      peak_delay_us: 20,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn first_sweep_only_records_baseline() {
+    let mut circuit = SweptCircuit::default();
+    circuit.update(1, counters(100, 5), counters(50, 2), (100, 100));
+    assert!(circuit.history.is_empty());
+    assert_eq!(circuit.total_drops, (0, 0));
+  }
+
+  #[test]
+  fn sweeps_accumulate_deltas() {
+    let mut circuit = SweptCircuit::default();
+    circuit.update(1, counters(100, 5), counters(50, 2), (100, 100));
+    circuit.update(2, counters(110, 8), counters(51, 2), (100, 100));
+    circuit.update(3, counters(115, 8), counters(53, 3), (100, 100));
+    assert_eq!(circuit.total_drops, (15, 3));
+    assert_eq!(circuit.total_marks, (3, 1));
+    assert_eq!(circuit.history.len(), 2);
+    assert_eq!(circuit.history[0].drops, (10, 1));
+    assert_eq!(circuit.history[1].peak_delay_us, (20, 20));
+  }
+
+  #[test]
+  fn counter_reset_counts_from_zero() {
+    let mut circuit = SweptCircuit::default();
+    circuit.update(1, counters(100, 5), counters(50, 2), (100, 100));
+    circuit.update(2, counters(4, 1), counters(50, 2), (100, 100));
+    assert_eq!(circuit.total_drops, (4, 0));
+    assert_eq!(circuit.total_marks, (1, 0));
+  }
+
+  #[test]
+  fn history_is_bounded() {
+    let mut circuit = SweptCircuit::default();
+    for i in 0..SWEEP_HISTORY as u64 + 10 {
+      circuit.update(i, counters(i, 0), counters(i, 0), (100, 100));
+    }
+    assert_eq!(circuit.history.len(), SWEEP_HISTORY);
+    let summary = circuit.summarize("x", false);
+    assert_eq!(summary.history.len(), 1);
+  }
+
+  #[test]
+  fn busy_sweeps_are_graded() {
+    // 100 Mbps plan, sweeps 10 seconds apart. 125 MB in 10 seconds is
+    // a full 100 Mbps.
+    let busy = |bytes: u64, delay: u32| LeafCounters {
+      bytes,
+      queue_delay_us: Some(delay),
+      ..Default::default()
+    };
+    let mut circuit = SweptCircuit::default();
+    circuit.update(0, busy(0, 0), busy(0, 0), (100, 100));
+    assert_eq!(circuit.bufferbloat("x").grade, None);
+    for i in 1..=3 {
+      circuit.update(
+        i * 10,
+        busy(i * 125_000_000, 45_000),
+        busy(0, 900_000),
+        (100, 100),
+      );
+    }
+    let bloat = circuit.bufferbloat("x");
+    assert_eq!(bloat.grade, Some(BufferbloatGrade::B));
+    assert_eq!(bloat.queue_delay_us, (45_000, 0));
+    // The idle upload's huge delay doesn't count
+    assert_eq!(bloat.loaded_samples, (3, 0));
+  }
+}
@@ -1,5 +1,6 @@
 use colored::Colorize;
 use default_net::{get_interfaces, interface::InterfaceType, Interface};
+use lqos_config::{migrate_isp_config, ISP_CONFIG_SHIM};
 use uuid::Uuid;
 use std::{fs, path::Path, process::Command};
 
@@ -141,57 +142,33 @@ vlan_mapping = []
 [usage_stats]
 send_anonymous = {ALLOW_ANONYMOUS}
 anonymous_server = \"stats.libreqos.io:9125\"
+
+[shaping]
+isp_interface = \"{ISP}\"
+internet_interface = \"{INTERNET}\"
+total_download_mbps = {DOWNLOAD}
+total_upload_mbps = {UPLOAD}
+generated_download_mbps = {DOWNLOAD}
+generated_upload_mbps = {UPLOAD}
 ";
 
-fn write_etc_lqos_conf(internet: &str, isp: &str, allow_anonymous: bool) {
+fn write_etc_lqos_conf(
+  internet: &str,
+  isp: &str,
+  download: u32,
+  upload: u32,
+  allow_anonymous: bool,
+) {
   let new_id = Uuid::new_v4().to_string();
   let output =
     ETC_LQOS_CONF.replace("{INTERNET}", internet).replace("{ISP}", isp)
     .replace("{NODE_ID}", &new_id)
-    .replace("{ALLOW_ANONYMOUS}", &allow_anonymous.to_string());
+    .replace("{ALLOW_ANONYMOUS}", &allow_anonymous.to_string())
+    .replace("{DOWNLOAD}", &download.to_string())
+    .replace("{UPLOAD}", &upload.to_string());
   fs::write(LQOS_CONF, output).expect("Unable to write file");
 }
 
-pub fn write_isp_config_py(
-  dir: &str,
-  download: u32,
-  upload: u32,
-  lan: &str,
-  internet: &str,
-) {
-  // Copy ispConfig.example.py to ispConfig.py
-  let orig = format!("{dir}ispConfig.example.py");
-  let dest = format!("{dir}ispConfig.py");
-  std::fs::copy(orig, &dest).unwrap();
-
-  let config_file = std::fs::read_to_string(&dest).unwrap();
-  let mut new_config_file = String::new();
-  config_file.split('\n').for_each(|line| {
-    if line.starts_with('#') {
-      new_config_file += line;
-      new_config_file += "\n";
-    } else if line.contains("upstreamBandwidthCapacityDownloadMbps") {
-      new_config_file +=
-        &format!("upstreamBandwidthCapacityDownloadMbps = {download}\n");
-    } else if line.contains("upstreamBandwidthCapacityUploadMbps") {
-      new_config_file +=
-        &format!("upstreamBandwidthCapacityUploadMbps = {upload}\n");
-    } else if line.contains("interfaceA") {
-      new_config_file += &format!("interfaceA = \"{lan}\"\n");
-    } else if line.contains("interfaceB") {
-      new_config_file += &format!("interfaceB = \"{internet}\"\n");
-    } else if line.contains("generatedPNDownloadMbps") {
-      new_config_file += &format!("generatedPNDownloadMbps = {download}\n");
-    } else if line.contains("generatedPNUploadMbps") {
-      new_config_file += &format!("generatedPNUploadMbps = {upload}\n");
-    } else {
-      new_config_file += line;
-      new_config_file += "\n";
-    }
-  });
-  std::fs::write(&dest, new_config_file).unwrap();
-}
-
 fn write_network_json() {
   let output = "{}\n";
   fs::write(NETWORK_JSON, output).expect("Unable to write file");
@@ -223,7 +200,29 @@ fn anonymous() -> bool {
   }
 }
 
+/// `lqos_setup migrate` moves an existing `ispConfig.py` into
+/// `/etc/lqos.conf`.
+fn migrate() {
+  match migrate_isp_config() {
+    Ok(unknown) => {
+      println!("{}", "ispConfig.py has been moved to /etc/lqos.conf".green());
+      println!("The original is kept as ispConfig.py.migrated");
+      for name in unknown.iter() {
+        println!("{} {name}", "Not recognised, so not migrated:".yellow());
+      }
+    }
+    Err(e) => {
+      println!("{}", format!("Unable to migrate ispConfig.py: {e}").red());
+      std::process::exit(1);
+    }
+  }
+}
+
 fn main() {
+  if std::env::args().nth(1).as_deref() == Some("migrate") {
+    migrate();
+    return;
+  }
   println!("{:^80}", "LibreQoS 1.4 Setup Assistant".yellow().on_blue());
   println!();
   let interfaces = get_available_interfaces();
@@ -237,27 +236,30 @@ fn main() {
     );
     get_internet_interface(&interfaces, &mut if_internet);
     get_isp_interface(&interfaces, &mut if_isp);
+    let upload = get_bandwidth(true);
+    let download = get_bandwidth(false);
     let allow_anonymous = anonymous();
     if let (Some(internet), Some(isp)) = (&if_internet, &if_isp) {
-      write_etc_lqos_conf(internet, isp, allow_anonymous);
+      write_etc_lqos_conf(internet, isp, download, upload, allow_anonymous);
     }
   }
 
   if should_build(ISP_CONF) {
-    println!("{}{}", ISP_CONF.cyan(), "does not exist, building one.".white());
-    get_internet_interface(&interfaces, &mut if_internet);
-    get_isp_interface(&interfaces, &mut if_isp);
-    let upload = get_bandwidth(true);
-    let download = get_bandwidth(false);
-    if let (Some(internet), Some(isp)) = (&if_internet, &if_isp) {
-      write_isp_config_py(
-        "/opt/libreqos/src/",
-        download,
-        upload,
-        isp,
-        internet,
-      )
-    }
+    println!(
+      "{}{}",
+      ISP_CONF.cyan(),
+      "does not exist, making one that reads /etc/lqos.conf.".white()
+    );
+    fs::write(ISP_CONF, ISP_CONFIG_SHIM).expect("Unable to write file");
+  } else if fs::read_to_string(ISP_CONF).ok().as_deref()
+    != Some(ISP_CONFIG_SHIM)
+  {
+    println!(
+      "{}",
+      "Settings now live in /etc/lqos.conf. Run `lqos_setup migrate` to \
+      move them there from ispConfig.py."
+        .magenta()
+    );
   }
 
   if should_build(NETWORK_JSON) {
@@ -52,6 +52,10 @@ struct dissector_t
     __u16 window;
     __u32 tsval;
     __u32 tsecr;
+    // TCP sequence number (host byte order)
+    __u32 sequence;
+    // Length of the TCP payload, excluding headers
+    __u32 tcp_payload;
 };
 
 // Representation of the VLAN header type.
@@ -342,6 +346,19 @@ static __always_inline void snoop(struct dissector_t *dissector)
 
             dissector->tcp_flags = flags;
             dissector->window = hdr->window;
+            dissector->sequence = bpf_ntohl(hdr->seq);
+
+            __u32 ip_payload = 0;
+            if (dissector->eth_type == ETH_P_IP)
+            {
+                ip_payload = bpf_ntohs(dissector->ip_header.iph->tot_len) - (dissector->ip_header.iph->ihl * 4);
+            }
+            else if (dissector->eth_type == ETH_P_IPV6)
+            {
+                ip_payload = bpf_ntohs(dissector->ip_header.ip6h->payload_len);
+            }
+            __u32 tcp_header_len = hdr->doff * 4;
+            dissector->tcp_payload = ip_payload > tcp_header_len ? ip_payload - tcp_header_len : 0;
 
             parse_tcp_ts(hdr, dissector->end, &dissector->tsval, &dissector->tsecr);
         }
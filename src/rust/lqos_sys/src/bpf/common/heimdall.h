@@ -7,12 +7,21 @@
 #include "debug.h"
 #include "dissector.h"
 
-#define PACKET_OCTET_SIZE 128
+// Largest snap length a capture session may request: a full 1500 byte
+// MTU, plus an Ethernet header and up to two VLAN tags.
+#define PACKET_OCTET_SIZE 1522
+
+// Ringbuffer size for Heimdall events. Events are only as long as the
+// snap length requested for the session, so this holds roughly 2,600
+// full-MTU captures or 20,000 128 byte captures.
+#define HEIMDALL_RINGBUF_SIZE (4 * 1024 * 1024)
 
 // Array containing one element, the Heimdall configuration
 struct heimdall_config_t
 {
     __u32 monitor_mode; // 0 = Off, 1 = Targets only, 2 = Analysis Mode
+    __u32 snaplen; // Number of packet octets to capture in Analysis Mode
+    __u32 sample_rate; // If non-zero, track flows for all shaped hosts, counting 1 in N packets
 };
 
 // Pinned map containing the Heimdall config
@@ -39,7 +48,7 @@ struct
 // Perf map for communicating with userspace
 struct {
 	__uint(type, BPF_MAP_TYPE_RINGBUF);
-	__uint(max_entries, 256 * 1024 /* 256 KB */);
+	__uint(max_entries, HEIMDALL_RINGBUF_SIZE);
 } heimdall_events SEC(".maps");
 
 // Basic event type to send to userspace when "hyperfocused" on a
@@ -60,6 +69,16 @@ struct heimdall_event {
     __u8 dump[PACKET_OCTET_SIZE];
 };
 
+// A heimdall_event is far too large for the eBPF stack, so it is
+// assembled in a per-CPU scratch area instead.
+struct
+{
+    __uint(type, BPF_MAP_TYPE_PERCPU_ARRAY);
+    __type(key, __u32);
+    __type(value, struct heimdall_event);
+    __uint(max_entries, 1);
+} heimdall_scratch SEC(".maps");
+
 struct heimdall_key
 {
     struct in6_addr src;
@@ -86,6 +105,42 @@ struct
     __uint(pinning, LIBBPF_PIN_BY_NAME);
 } heimdall SEC(".maps");
 
+// Number of recent RTT samples kept per TCP flow. Must be a power of 2.
+#define HEIMDALL_RTT_SAMPLES 16
+
+// If a "late" segment arrives this soon after the highest sequence
+// number seen, and we don't have an RTT yet, assume it was reordered
+// rather than retransmitted.
+#define HEIMDALL_REORDER_NS 3000000
+
+// TCP analytics for a single direction of a flow. RTT samples belong to
+// the flow whose TSval was echoed, so they measure the path from the
+// shaper to the flow's destination and back.
+struct heimdall_tcp_data {
+    __u64 tsval_time; // When `tsval` was first sent (0 once echoed)
+    __u64 next_seq_time; // When `next_seq` last advanced
+    __u32 tsval; // Most recent TSval sent on this flow
+    __u32 next_seq; // Sequence number following the highest one seen
+    __u32 rtt_min_us;
+    __u32 rtt_max_us;
+    __u32 rtt_samples_us[HEIMDALL_RTT_SAMPLES]; // Ring of recent samples
+    __u32 rtt_count; // Total RTT samples taken
+    __u32 retransmits;
+    __u32 out_of_order;
+    __u32 zero_windows;
+};
+
+// Map for tracking TCP analytics for Heimdall flows. This isn't per-CPU,
+// because the two directions of a flow may be handled by different CPUs.
+struct
+{
+    __uint(type, BPF_MAP_TYPE_LRU_HASH);
+    __type(key, struct heimdall_key);
+    __type(value, struct heimdall_tcp_data);
+    __uint(max_entries, MAX_FLOWS);
+    __uint(pinning, LIBBPF_PIN_BY_NAME);
+} heimdall_tcp SEC(".maps");
+
 static __always_inline __u8 get_heimdall_mode()
 {
     __u32 index = 0;
@@ -103,6 +158,30 @@ static __always_inline __u8 get_heimdall_mode()
     }
 }
 
+static __always_inline __u32 get_heimdall_snaplen()
+{
+    __u32 index = 0;
+    struct heimdall_config_t *cfg = (struct heimdall_config_t *)bpf_map_lookup_elem(&heimdall_config, &index);
+    if (cfg && cfg->snaplen < PACKET_OCTET_SIZE)
+    {
+        return cfg->snaplen;
+    }
+    return PACKET_OCTET_SIZE;
+}
+
+// Returns the "1 in N" packet sampling rate for tracking flows on all
+// shaped hosts, or 0 if only watched hosts should be tracked.
+static __always_inline __u32 get_heimdall_sample_rate()
+{
+    __u32 index = 0;
+    struct heimdall_config_t *cfg = (struct heimdall_config_t *)bpf_map_lookup_elem(&heimdall_config, &index);
+    if (cfg)
+    {
+        return cfg->sample_rate;
+    }
+    return 0;
+}
+
 static __always_inline bool is_heimdall_watching(struct dissector_t *dissector, int effective_direction)
 {
     if (effective_direction == 2) {
@@ -119,60 +198,160 @@ static __always_inline bool is_heimdall_watching(struct dissector_t *dissector,
     return false;
 }
 
-static __always_inline void update_heimdall(struct dissector_t *dissector, __u32 size, __u8 mode)
+// Tracks RTT (from TCP timestamps), retransmissions, reordering and
+// zero-window events for a TCP flow. Only meaningful when every packet
+// on the flow is seen, so it isn't used when sampling.
+static __always_inline void update_heimdall_tcp(struct dissector_t *dissector, struct heimdall_key *key)
 {
-    if (mode == 1) {
-        // Don't report any non-ICMP without ports
-        if (dissector->ip_protocol != 1 && (dissector->src_port == 0 || dissector->dst_port == 0))
-            return;
-        // Don't report ICMP with invalid numbers
-        if (dissector->ip_protocol == 1 && dissector->src_port > 18) return;
-        struct heimdall_key key = {0};
-        key.src = dissector->src_ip;
-        key.dst = dissector->dst_ip;
-        key.ip_protocol = dissector->ip_protocol;
-        key.src_port = bpf_ntohs(dissector->src_port);
-        key.dst_port = bpf_ntohs(dissector->dst_port);
-        struct heimdall_data *counter = (struct heimdall_data *)bpf_map_lookup_elem(&heimdall, &key);
-        if (counter)
+    __u64 now = bpf_ktime_get_boot_ns();
+    struct heimdall_tcp_data *tcp = (struct heimdall_tcp_data *)bpf_map_lookup_elem(&heimdall_tcp, key);
+    if (!tcp)
+    {
+        struct heimdall_tcp_data new_tcp = {0};
+        bpf_map_update_elem(&heimdall_tcp, key, &new_tcp, BPF_NOEXIST);
+        tcp = (struct heimdall_tcp_data *)bpf_map_lookup_elem(&heimdall_tcp, key);
+        if (!tcp) return;
+    }
+
+    // Zero-window advertisements (ignoring resets)
+    if (dissector->window == 0 && (dissector->tcp_flags & 4) == 0)
+    {
+        __sync_fetch_and_add(&tcp->zero_windows, 1);
+    }
+
+    // Sequence tracking for data segments
+    if (dissector->tcp_payload > 0)
+    {
+        __u32 seq = dissector->sequence;
+        if (tcp->next_seq == 0 || (__s32)(seq - tcp->next_seq) >= 0)
         {
-            counter->last_seen = bpf_ktime_get_boot_ns();
-            counter->packets += 1;
-            counter->bytes += size;
-            if (dissector->tos != 0)
-            {
-                counter->tos = dissector->tos;
-            }
+            tcp->next_seq = seq + dissector->tcp_payload;
+            tcp->next_seq_time = now;
         }
         else
         {
-            struct heimdall_data counter = {0};
-            counter.last_seen = bpf_ktime_get_boot_ns();
-            counter.bytes = size;
-            counter.packets = 1;
-            counter.tos = dissector->tos;
-            if (bpf_map_update_elem(&heimdall, &key, &counter, BPF_NOEXIST) != 0)
+            __u64 reorder_window = tcp->rtt_min_us > 0 ? (__u64)tcp->rtt_min_us * 1000 : HEIMDALL_REORDER_NS;
+            if (now - tcp->next_seq_time < reorder_window)
             {
-                bpf_debug("Failed to insert tracking");
+                __sync_fetch_and_add(&tcp->out_of_order, 1);
             }
+            else
+            {
+                __sync_fetch_and_add(&tcp->retransmits, 1);
+            }
+        }
+    }
+
+    // Remember when each new TSval was first sent
+    if (dissector->tsval != 0 && dissector->tsval != tcp->tsval)
+    {
+        tcp->tsval = dissector->tsval;
+        tcp->tsval_time = now;
+    }
+
+    // If this packet echoes the reverse flow's TSval, that's an RTT
+    // sample for the reverse flow.
+    if (dissector->tsecr != 0)
+    {
+        struct heimdall_key reverse = {0};
+        reverse.src = key->dst;
+        reverse.dst = key->src;
+        reverse.ip_protocol = key->ip_protocol;
+        reverse.src_port = key->dst_port;
+        reverse.dst_port = key->src_port;
+        struct heimdall_tcp_data *rev = (struct heimdall_tcp_data *)bpf_map_lookup_elem(&heimdall_tcp, &reverse);
+        if (rev && rev->tsval_time != 0 && rev->tsval == dissector->tsecr)
+        {
+            __u32 rtt_us = (now - rev->tsval_time) / 1000;
+            rev->tsval_time = 0; // Only the first echo counts
+            if (rev->rtt_min_us == 0 || rtt_us < rev->rtt_min_us) rev->rtt_min_us = rtt_us;
+            if (rtt_us > rev->rtt_max_us) rev->rtt_max_us = rtt_us;
+            __u32 slot = rev->rtt_count & (HEIMDALL_RTT_SAMPLES - 1);
+            rev->rtt_samples_us[slot] = rtt_us;
+            rev->rtt_count++;
+        }
+    }
+}
+
+// Adds a packet to the flow tracking map. "scale" is the number of
+// packets this one represents, so that sampled counts can be scaled back
+// up to an estimate of the real traffic.
+static __always_inline void update_heimdall_flow(struct dissector_t *dissector, __u32 size, __u32 scale)
+{
+    // Don't report any non-ICMP without ports
+    if (dissector->ip_protocol != 1 && (dissector->src_port == 0 || dissector->dst_port == 0))
+        return;
+    // Don't report ICMP with invalid numbers
+    if (dissector->ip_protocol == 1 && dissector->src_port > 18) return;
+    struct heimdall_key key = {0};
+    key.src = dissector->src_ip;
+    key.dst = dissector->dst_ip;
+    key.ip_protocol = dissector->ip_protocol;
+    key.src_port = bpf_ntohs(dissector->src_port);
+    key.dst_port = bpf_ntohs(dissector->dst_port);
+    struct heimdall_data *counter = (struct heimdall_data *)bpf_map_lookup_elem(&heimdall, &key);
+    if (counter)
+    {
+        counter->last_seen = bpf_ktime_get_boot_ns();
+        counter->packets += scale;
+        counter->bytes += (__u64)size * scale;
+        if (dissector->tos != 0)
+        {
+            counter->tos = dissector->tos;
         }
+    }
+    else
+    {
+        struct heimdall_data counter = {0};
+        counter.last_seen = bpf_ktime_get_boot_ns();
+        counter.bytes = (__u64)size * scale;
+        counter.packets = scale;
+        counter.tos = dissector->tos;
+        if (bpf_map_update_elem(&heimdall, &key, &counter, BPF_NOEXIST) != 0)
+        {
+            bpf_debug("Failed to insert tracking");
+        }
+    }
+
+    if (dissector->ip_protocol == IPPROTO_TCP && scale == 1)
+    {
+        update_heimdall_tcp(dissector, &key);
+    }
+}
+
+static __always_inline void update_heimdall(struct dissector_t *dissector, __u32 size, __u8 mode)
+{
+    if (mode == 1) {
+        update_heimdall_flow(dissector, size, 1);
     } else if (mode == 2) {
-        struct heimdall_event event = {0};
-        event.timetamp = bpf_ktime_get_boot_ns();
-        event.src = dissector->src_ip;
-        event.dst = dissector->dst_ip;
-        event.src_port = dissector->src_port;
-        event.dst_port = dissector->dst_port;
-        event.ip_protocol = dissector->ip_protocol;
-        event.tos = dissector->tos;
-        event.size = size;
-        event.tcp_flags = dissector->tcp_flags;
-        event.tcp_window = dissector->window;
-        event.tsval = dissector->tsval;
-        event.tsecr = dissector->tsecr;
-        //if (size > PACKET_OCTET_SIZE) size = PACKET_OCTET_SIZE;
-        bpf_probe_read_kernel(&event.dump, PACKET_OCTET_SIZE, dissector->start);
-        bpf_ringbuf_output(&heimdall_events, &event, sizeof(event), 0);
+        __u32 index = 0;
+        struct heimdall_event *event = (struct heimdall_event *)bpf_map_lookup_elem(&heimdall_scratch, &index);
+        if (!event) return;
+        event->timetamp = bpf_ktime_get_boot_ns();
+        event->src = dissector->src_ip;
+        event->dst = dissector->dst_ip;
+        event->src_port = dissector->src_port;
+        event->dst_port = dissector->dst_port;
+        event->ip_protocol = dissector->ip_protocol;
+        event->tos = dissector->tos;
+        event->size = size;
+        event->tcp_flags = dissector->tcp_flags;
+        event->tcp_window = dissector->window;
+        event->tsval = dissector->tsval;
+        event->tsecr = dissector->tsecr;
+
+        // Only send as many octets as the session asked for, so that
+        // short snap lengths don't waste ringbuffer space.
+        __u32 captured = get_heimdall_snaplen();
+        if (captured > size) captured = size;
+        if (captured > PACKET_OCTET_SIZE) captured = PACKET_OCTET_SIZE;
+        bpf_probe_read_kernel(&event->dump, captured, dissector->start);
+        bpf_ringbuf_output(
+            &heimdall_events,
+            event,
+            offsetof(struct heimdall_event, dump) + captured,
+            0
+        );
     }
     
     // Commented out because we don't really care - some will be missed
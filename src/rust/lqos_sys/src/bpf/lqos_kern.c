@@ -141,6 +141,12 @@ int xdp_prog(struct xdp_md *ctx)
             bpf_debug("(XDP) Storing Heimdall Data");
 #endif            
             update_heimdall(&dissector, ctx->data_end - ctx->data, heimdall_mode);
+        } else if (heimdall_mode > 0) {
+            // Opt-in: sampled flow tracking for every shaped host
+            __u32 sample_rate = get_heimdall_sample_rate();
+            if (sample_rate > 0 && bpf_get_prandom_u32() % sample_rate == 0) {
+                update_heimdall_flow(&dissector, ctx->data_end - ctx->data, sample_rate);
+            }
         }
 
         // Handle CPU redirection if there is one specified
@@ -1,6 +1,7 @@
 use anyhow::{Error, Result};
 use libbpf_sys::{
-  bpf_map_get_next_key, bpf_map_lookup_elem, bpf_obj_get,
+  bpf_map_delete_elem, bpf_map_get_next_key, bpf_map_lookup_elem,
+  bpf_obj_get,
 };
 use std::fmt::Debug;
 use std::{
@@ -68,6 +69,22 @@ where
       }
     }
   }
+
+  /// Delete an entry from the underlying eBPF map, on every CPU.
+  /// Deleting a key that isn't there isn't an error.
+  ///
+  /// ## Arguments
+  ///
+  /// * `key` - the key to delete.
+  pub fn delete(&mut self, key: &mut K) -> Result<()> {
+    let key_ptr: *mut K = key;
+    let err = unsafe { bpf_map_delete_elem(self.fd, key_ptr as *mut c_void) };
+    if err != 0 && err != -2 {
+      Err(Error::msg("Unable to delete from map"))
+    } else {
+      Ok(())
+    }
+  }
 }
 
 impl<K, V> Drop for BpfPerCpuMap<K, V> {
@@ -16,12 +16,17 @@ use crate::{
 };
 use anyhow::Result;
 use log::{info, warn};
-use lqos_bus::{BusRequest, BusResponse, UnixSocketServer};
+use lqos_bus::{BusRequest, BusResponse, BusStreamReceiver, UnixSocketServer};
 use lqos_config::LibreQoSConfig;
 use lqos_heimdall::{n_second_packet_dump, perf_interface::heimdall_handle_events, start_heimdall};
 use lqos_queue_tracker::{
-  add_watched_queue, get_raw_circuit_data, spawn_queue_monitor,
-  spawn_queue_structure_monitor,
+  add_watched_queue, all_circuit_queue_summaries, audit_queue_tree,
+  bufferbloat_distribution, get_circuit_bufferbloat, get_circuit_queue_info,
+  get_circuit_queue_summary, get_circuits_on_cpu, get_circuits_under_class,
+  get_htb_class_data, get_queue_cpu_totals, get_queue_history,
+  get_raw_circuit_data,
+  spawn_queue_monitor,
+  spawn_queue_structure_monitor, update_circuit_rates, CircuitRates,
 };
 use lqos_sys::LibreQoSKernels;
 use signal_hook::{
@@ -72,6 +77,7 @@ async fn main() -> Result<()> {
   };
 
   // Spawn tracking sub-systems
+  lqos_heimdall::set_circuit_lookup(shaped_devices_tracker::circuit_id_for_ip);
   join!(
     start_heimdall(),
     spawn_queue_structure_monitor(),
@@ -120,7 +126,7 @@ async fn main() -> Result<()> {
   let server = UnixSocketServer::new().expect("Unable to spawn server");
 
   // Main bus listen loop
-  server.listen(handle_bus_requests).await?;
+  server.listen(handle_bus_requests, handle_stream_request).await?;
   Ok(())
 }
 
@@ -166,15 +172,61 @@ fn handle_bus_requests(
         add_watched_queue(circuit_id);
         lqos_bus::BusResponse::Ack
       }
+      BusRequest::GetCircuitQueueSummary(circuit_id) => {
+        get_circuit_queue_summary(circuit_id)
+      }
+      BusRequest::AllCircuitQueueSummaries => all_circuit_queue_summaries(),
+      BusRequest::GetHtbClassData(id) => get_htb_class_data(id),
+      BusRequest::GetQueueHistory { circuit_id, start, end } => {
+        get_queue_history(circuit_id, *start, *end)
+      }
+      BusRequest::GetCircuitQueueInfo(id) => get_circuit_queue_info(id),
+      BusRequest::CircuitsOnCpu(cpu) => get_circuits_on_cpu(*cpu),
+      BusRequest::CircuitsUnderClass(class_id) => {
+        get_circuits_under_class(*class_id)
+      }
+      BusRequest::QueueCpuTotals => get_queue_cpu_totals(),
+      BusRequest::GetCircuitBufferbloat(circuit_id) => {
+        get_circuit_bufferbloat(circuit_id)
+      }
+      BusRequest::BufferbloatDistribution => bufferbloat_distribution(),
+      BusRequest::AuditQueueTree => audit_queue_tree(),
+      BusRequest::UpdateCircuitRates {
+        circuit_id,
+        download_min_mbps,
+        download_max_mbps,
+        upload_min_mbps,
+        upload_max_mbps,
+      } => update_circuit_rates(
+        circuit_id,
+        CircuitRates {
+          download_min: *download_min_mbps,
+          download_max: *download_max_mbps,
+          upload_min: *upload_min_mbps,
+          upload_max: *upload_max_mbps,
+        },
+      ),
       BusRequest::UpdateLqosDTuning(..) => tuning::tune_lqosd_from_bus(req),
       #[cfg(feature = "equinix_tests")]
       BusRequest::RequestLqosEquinixTest => lqos_daht_test::lqos_daht_test(),
+      BusRequest::ChangeShapedDevices { changes, reload } => {
+        shaped_devices_tracker::change_shaped_devices(changes, *reload)
+      }
       BusRequest::ValidateShapedDevicesCsv => {
         validation::validate_shaped_devices_csv()
       }
       BusRequest::GetNetworkMap { parent } => {
         shaped_devices_tracker::get_one_network_map_layer(*parent)
       }
+      BusRequest::ChangeNetworkJson { changes, reload } => {
+        shaped_devices_tracker::change_network_json(changes, *reload)
+      }
+      BusRequest::GetNetworkMapById { id } => {
+        shaped_devices_tracker::get_network_map_layer_by_id(id)
+      }
+      BusRequest::GetNodeIndicesFromIds(ids) => {
+        shaped_devices_tracker::map_node_ids(ids)
+      }
       BusRequest::TopMapQueues(n_queues) => {
         shaped_devices_tracker::get_top_n_root_queues(*n_queues)
       }
@@ -184,6 +236,9 @@ fn handle_bus_requests(
       BusRequest::GetFunnel { target: parent } => {
         shaped_devices_tracker::get_funnel(parent)
       }
+      BusRequest::CheckNetworkCapacity => {
+        shaped_devices_tracker::check_network_capacity()
+      }
       BusRequest::GetLqosStats => {
         BusResponse::LqosdStats { 
           bus_requests: BUS_REQUESTS.load(std::sync::atomic::Ordering::Relaxed),
@@ -196,16 +251,25 @@ fn handle_bus_requests(
         }
       }
       BusRequest::GetFlowStats(ip) => get_flow_stats(ip),
+      BusRequest::GetCircuitFlows(circuit_id) => {
+        throughput_tracker::get_circuit_flows(circuit_id)
+      }
+      BusRequest::TopFlowApplications(n) => {
+        throughput_tracker::top_flow_applications(*n)
+      }
+      BusRequest::TopFlowDestinations(n) => {
+        throughput_tracker::top_flow_destinations(*n)
+      }
       BusRequest::GetPacketHeaderDump(id) => {
         BusResponse::PacketDump(n_second_packet_dump(*id))
       }
       BusRequest::GetPcapDump(id) => {
         BusResponse::PcapDump(lqos_heimdall::n_second_pcap(*id))
       }
-      BusRequest::GatherPacketData(ip) => {
+      BusRequest::GatherPacketData { ip, snaplen } => {
         let ip = ip.parse::<IpAddr>();
         if let Ok(ip) = ip {
-          if let Some((session_id, countdown)) = lqos_heimdall::hyperfocus_on_target(ip.into()) {
+          if let Some((session_id, countdown)) = lqos_heimdall::hyperfocus_on_target(ip.into(), *snaplen) {
             BusResponse::PacketCollectionSession{session_id, countdown}
           } else {
             BusResponse::Fail("Busy".to_string())
@@ -214,6 +278,23 @@ fn handle_bus_requests(
           BusResponse::Fail("Invalid IP".to_string())
         }
       }
+      BusRequest::StreamPacketData { .. } => {
+        BusResponse::Fail("Streaming requests must use BusStream".to_string())
+      }
     });
   }
 }
+
+fn handle_stream_request(
+  request: &BusRequest,
+) -> Result<BusStreamReceiver, String> {
+  BUS_REQUESTS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  match request {
+    BusRequest::StreamPacketData { ip, snaplen } => {
+      let ip = ip.parse::<IpAddr>().map_err(|_| "Invalid IP".to_string())?;
+      lqos_heimdall::stream_packets(ip.into(), *snaplen)
+        .ok_or_else(|| "Busy".to_string())
+    }
+    _ => Err("Not a streaming request".to_string()),
+  }
+}
@@ -1,10 +1,13 @@
 use anyhow::Result;
 use log::{error, info, warn};
 use lqos_bus::BusResponse;
-use lqos_config::{ConfigShapedDevices, NetworkJsonTransport};
-use lqos_utils::file_watcher::FileWatcher;
+use lqos_config::{
+  ConfigShapedDevices, ConfigSnapshot, NetworkJsonTransport,
+  ShapedDevicesChange,
+};
+use lqos_utils::{file_watcher::FileWatcher, XdpIpAddress};
 use once_cell::sync::Lazy;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use tokio::task::spawn_blocking;
 mod netjson;
 pub use netjson::*;
@@ -12,8 +15,23 @@ pub use netjson::*;
 pub static SHAPED_DEVICES: Lazy<RwLock<ConfigShapedDevices>> =
   Lazy::new(|| RwLock::new(ConfigShapedDevices::default()));
 
+/// Held while ShapedDevices.csv is being changed over the bus, so that
+/// two changes can't read the same file and lose one another's edits.
+static SHAPED_DEVICES_CHANGE: Mutex<()> = Mutex::new(());
+
+/// Snapshots the configuration files after one of them has changed. A
+/// snapshot is only taken if something differs from the last one, so it
+/// doesn't matter that the file watchers also see changes made over the
+/// bus.
+pub(crate) fn snapshot_config(source: &str) {
+  if let Err(e) = ConfigSnapshot::take(source) {
+    warn!("Unable to snapshot the configuration: {e}");
+  }
+}
+
 fn load_shaped_devices() {
   info!("ShapedDevices.csv has changed. Attempting to load it.");
+  snapshot_config("ShapedDevices.csv changed on disk");
   let shaped_devices = ConfigShapedDevices::load();
   if let Ok(new_file) = shaped_devices {
     info!("ShapedDevices.csv loaded");
@@ -26,6 +44,36 @@ fn load_shaped_devices() {
   }
 }
 
+/// Applies changes to ShapedDevices.csv, and optionally has LibreQoS.py
+/// apply them to the queues. The file watcher will also notice the new
+/// file, but the in-memory copy is updated straight away so that the
+/// change is visible as soon as this returns.
+pub fn change_shaped_devices(
+  changes: &[ShapedDevicesChange],
+  reload: bool,
+) -> BusResponse {
+  let _lock = SHAPED_DEVICES_CHANGE.lock().unwrap();
+  match ConfigShapedDevices::change(changes) {
+    Ok(new_file) => {
+      info!("ShapedDevices.csv changed over the bus");
+      snapshot_config("ShapedDevices.csv changed over the bus");
+      *SHAPED_DEVICES.write().unwrap() = new_file;
+      crate::throughput_tracker::THROUGHPUT_TRACKER.refresh_circuit_ids();
+      if !reload {
+        return BusResponse::Ack;
+      }
+      match lqos_config::update_libreqos() {
+        Ok(message) => BusResponse::ReloadLibreQoS(message),
+        Err(e) => {
+          warn!("Unable to run LibreQoS.py --updateonly: {e:?}");
+          BusResponse::Fail(format!("Saved, but unable to reload: {e}"))
+        }
+      }
+    }
+    Err(e) => BusResponse::Fail(e.to_string()),
+  }
+}
+
 pub async fn shaped_devices_watcher() {
   spawn_blocking(|| {
     info!("Watching for ShapedDevices.csv changes");
@@ -55,6 +103,15 @@ fn watch_for_shaped_devices_changing() -> Result<()> {
   }
 }
 
+/// Finds the circuit ID to which an IP address belongs, if any.
+pub fn circuit_id_for_ip(ip: &XdpIpAddress) -> Option<String> {
+  let devices = SHAPED_DEVICES.read().unwrap();
+  devices
+    .trie
+    .longest_match(ip.as_ipv6())
+    .map(|(_, id)| devices.devices[*id].circuit_id.clone())
+}
+
 pub fn get_one_network_map_layer(parent_idx: usize) -> BusResponse {
   let net_json = NETWORK_JSON.read().unwrap();
   if let Some(parent) = net_json.get_cloned_entry_by_index(parent_idx) {
@@ -96,6 +153,9 @@ pub fn get_top_n_root_queues(n_queues: usize) -> BusResponse {
           rtts: Vec::new(),
           parents: Vec::new(),
           immediate_parent: None,
+          id: None,
+          node_type: None,
+          tags: Vec::new(),
         },
       ));
     }
@@ -116,9 +176,27 @@ pub fn map_node_names(nodes: &[usize]) -> BusResponse {
   BusResponse::NodeNames(result)
 }
 
+pub fn get_network_map_layer_by_id(id: &str) -> BusResponse {
+  let index = NETWORK_JSON.read().unwrap().get_index_for_id(id);
+  if let Some(index) = index {
+    get_one_network_map_layer(index)
+  } else {
+    BusResponse::Fail("No such node".to_string())
+  }
+}
+
+pub fn map_node_ids(ids: &[String]) -> BusResponse {
+  let reader = NETWORK_JSON.read().unwrap();
+  let result = ids
+    .iter()
+    .filter_map(|id| reader.get_index_for_id(id).map(|idx| (id.clone(), idx)))
+    .collect();
+  BusResponse::NodeIndices(result)
+}
+
 pub fn get_funnel(circuit_id: &str) -> BusResponse {
   let reader = NETWORK_JSON.read().unwrap();
-  if let Some(index) = reader.get_index_for_name(circuit_id) {
+  if let Some(index) = reader.get_index_for_id_or_name(circuit_id) {
     // Reverse the scanning order and skip the last entry (the parent)
     let mut result = Vec::new();
     for idx in reader.nodes[index].parents.iter().rev().skip(1) {
@@ -129,3 +207,9 @@ pub fn get_funnel(circuit_id: &str) -> BusResponse {
 
   BusResponse::Fail("Unknown Node".into())
 }
+
+pub fn check_network_capacity() -> BusResponse {
+  let net_json = NETWORK_JSON.read().unwrap();
+  let devices = SHAPED_DEVICES.read().unwrap();
+  BusResponse::NetworkCapacity(net_json.check_capacity(&devices.devices))
+}
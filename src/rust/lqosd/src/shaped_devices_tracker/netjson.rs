@@ -1,14 +1,19 @@
 use anyhow::Result;
 use log::{error, info, warn};
-use lqos_config::NetworkJson;
+use lqos_bus::BusResponse;
+use lqos_config::{NetworkJson, NetworkJsonChange};
 use lqos_utils::file_watcher::FileWatcher;
 use once_cell::sync::Lazy;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use tokio::task::spawn_blocking;
 
 pub static NETWORK_JSON: Lazy<RwLock<NetworkJson>> =
   Lazy::new(|| RwLock::new(NetworkJson::default()));
 
+/// Held while network.json is being changed over the bus, so that two
+/// changes can't read the same file and lose one another's edits.
+static NETWORK_JSON_CHANGE: Mutex<()> = Mutex::new(());
+
 pub async fn network_json_watcher() {
   spawn_blocking(|| {
     info!("Watching for network.kson changes");
@@ -37,6 +42,7 @@ fn watch_for_network_json_changing() -> Result<()> {
 }
 
 fn load_network_json() {
+  super::snapshot_config("network.json changed on disk");
   let njs = NetworkJson::load();
   if let Ok(njs) = njs {
     let mut write_lock = NETWORK_JSON.write().unwrap();
@@ -48,3 +54,35 @@ fn load_network_json() {
     warn!("Unable to load network.json");
   }
 }
+
+/// Applies changes to network.json, and optionally reloads LibreQoS to
+/// rebuild the queues. The new tree replaces the in-memory copy straight
+/// away, and every tracked host's parent nodes are looked up again, since
+/// moving a node changes the parents of everything below it.
+pub fn change_network_json(
+  changes: &[NetworkJsonChange],
+  reload: bool,
+) -> BusResponse {
+  let _lock = NETWORK_JSON_CHANGE.lock().unwrap();
+  match NetworkJson::change(changes) {
+    Ok(njs) => {
+      super::snapshot_config("network.json changed over the bus");
+      let mut write_lock = NETWORK_JSON.write().unwrap();
+      *write_lock = njs;
+      std::mem::drop(write_lock);
+      crate::throughput_tracker::THROUGHPUT_TRACKER
+        .refresh_circuit_ids();
+      if !reload {
+        return BusResponse::Ack;
+      }
+      match lqos_config::load_libreqos() {
+        Ok(message) => BusResponse::ReloadLibreQoS(message),
+        Err(e) => {
+          warn!("Unable to reload LibreQoS: {e:?}");
+          BusResponse::Fail(format!("Saved, but unable to reload: {e}"))
+        }
+      }
+    }
+    Err(e) => BusResponse::Fail(e.to_string()),
+  }
+}
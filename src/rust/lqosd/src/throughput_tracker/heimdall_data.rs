@@ -1,7 +1,8 @@
-use std::net::IpAddr;
-use lqos_bus::BusResponse;
-use lqos_heimdall::heimdall_watch_ip;
+use std::{collections::HashMap, net::IpAddr};
+use lqos_bus::{BusResponse, FlowSummary};
+use lqos_heimdall::{heimdall_watch_ip, FlowData, FlowKey};
 use lqos_utils::XdpIpAddress;
+use crate::shaped_devices_tracker::SHAPED_DEVICES;
 
 pub fn get_flow_stats(ip: &str) -> BusResponse {
   let ip = ip.parse::<IpAddr>();
@@ -11,4 +12,211 @@ pub fn get_flow_stats(ip: &str) -> BusResponse {
     return lqos_heimdall::get_flow_stats(ip);
   }
   BusResponse::Fail("No Stats or bad IP".to_string())
-}
\ No newline at end of file
+}
+
+/// Lists flows for every IP address belonging to a circuit. Unless
+/// Heimdall is tracking all hosts, this only includes hosts that are
+/// currently being watched.
+pub fn get_circuit_flows(circuit_id: &str) -> BusResponse {
+  let devices = SHAPED_DEVICES.read().unwrap();
+  let in_circuit = |ip: &XdpIpAddress| {
+    devices
+      .trie
+      .longest_match(ip.as_ipv6())
+      .map(|(_, id)| devices.devices[*id].circuit_id == circuit_id)
+      .unwrap_or(false)
+  };
+  lqos_heimdall::get_flow_stats_matching(|key| {
+    in_circuit(&key.src) || in_circuit(&key.dst)
+  })
+}
+
+/// A flow seen from the shaped host's point of view.
+struct LocalFlow<'a> {
+  remote: &'a XdpIpAddress,
+  remote_port: u16,
+  download: bool,
+}
+
+/// Works out which end of a flow is the shaped host. Returns `None` if
+/// neither end is in `ShapedDevices.csv`.
+fn localize<'a>(
+  key: &'a FlowKey,
+  is_shaped: &impl Fn(&XdpIpAddress) -> bool,
+) -> Option<LocalFlow<'a>> {
+  if is_shaped(&key.dst) {
+    Some(LocalFlow { remote: &key.src, remote_port: key.src_port, download: true })
+  } else if is_shaped(&key.src) {
+    Some(LocalFlow { remote: &key.dst, remote_port: key.dst_port, download: false })
+  } else {
+    None
+  }
+}
+
+/// Names an application from its protocol and server port.
+fn application_name(proto: u8, port: u16) -> String {
+  let known = match (proto, port) {
+    (1, _) => Some("ICMP"),
+    (6, 80) => Some("HTTP"),
+    (6, 443) => Some("HTTPS"),
+    (17, 443) => Some("QUIC"),
+    (6 | 17, 53) => Some("DNS"),
+    (6, 853) => Some("DNS over TLS"),
+    (6, 22) => Some("SSH"),
+    (6, 25 | 465 | 587) => Some("SMTP"),
+    (6, 143 | 993) => Some("IMAP"),
+    (6, 110 | 995) => Some("POP3"),
+    (17, 123) => Some("NTP"),
+    (6 | 17, 1194) => Some("OpenVPN"),
+    (17, 51820) => Some("WireGuard"),
+    (17, 500 | 4500) => Some("IPsec"),
+    (6 | 17, 3478) => Some("STUN/TURN"),
+    (6 | 17, 5060 | 5061) => Some("SIP"),
+    (6 | 17, 3074) => Some("Xbox Live"),
+    (6 | 17, 3659) => Some("EA Games"),
+    _ => None,
+  };
+  let proto_name = match proto {
+    6 => "TCP",
+    17 => "UDP",
+    _ => "ICMP",
+  };
+  match known {
+    Some(name) if proto == 1 => name.to_string(),
+    Some(name) => format!("{name} ({proto_name}/{port})"),
+    None => format!("{proto_name}/{port}"),
+  }
+}
+
+fn add_to_summary(
+  summaries: &mut HashMap<String, FlowSummary>,
+  name: String,
+  flow: &LocalFlow,
+  data: &FlowData,
+) {
+  let entry = summaries.entry(name.clone()).or_insert(FlowSummary {
+    name,
+    bits_per_second: (0, 0),
+    bytes: (0, 0),
+    flows: 0,
+  });
+  if flow.download {
+    entry.bits_per_second.0 += data.bytes_per_second * 8;
+    entry.bytes.0 += data.bytes;
+  } else {
+    entry.bits_per_second.1 += data.bytes_per_second * 8;
+    entry.bytes.1 += data.bytes;
+  }
+  entry.flows += 1;
+}
+
+fn top_n_summaries(
+  n: usize,
+  group_by: impl Fn(&FlowKey, &LocalFlow) -> String,
+) -> Vec<FlowSummary> {
+  let devices = SHAPED_DEVICES.read().unwrap();
+  let is_shaped =
+    |ip: &XdpIpAddress| devices.trie.longest_match(ip.as_ipv6()).is_some();
+
+  let mut summaries = HashMap::new();
+  lqos_heimdall::for_each_flow(|key, data| {
+    if let Some(flow) = localize(key, &is_shaped) {
+      add_to_summary(&mut summaries, group_by(key, &flow), &flow, data);
+    }
+  });
+
+  top_n(summaries, n)
+}
+
+/// The `n` busiest summaries, by current throughput in both directions,
+/// then by bytes downloaded.
+fn top_n(summaries: HashMap<String, FlowSummary>, n: usize) -> Vec<FlowSummary> {
+  let mut result: Vec<FlowSummary> = summaries.into_values().collect();
+  result.sort_by(|a, b| {
+    let total_a = a.bits_per_second.0 + a.bits_per_second.1;
+    let total_b = b.bits_per_second.0 + b.bits_per_second.1;
+    total_b.cmp(&total_a).then(b.bytes.0.cmp(&a.bytes.0))
+  });
+  result.truncate(n);
+  result
+}
+
+/// Top N applications, by current throughput.
+pub fn top_flow_applications(n: usize) -> BusResponse {
+  BusResponse::TopFlowApplications(top_n_summaries(n, |key, flow| {
+    application_name(key.proto, flow.remote_port)
+  }))
+}
+
+/// Top N remote IP addresses, by current throughput.
+pub fn top_flow_destinations(n: usize) -> BusResponse {
+  BusResponse::TopFlowDestinations(top_n_summaries(n, |_, flow| {
+    flow.remote.as_ip().to_string()
+  }))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn names_applications() {
+    assert_eq!(application_name(6, 443), "HTTPS (TCP/443)");
+    assert_eq!(application_name(17, 443), "QUIC (UDP/443)");
+    assert_eq!(application_name(17, 53), "DNS (UDP/53)");
+    assert_eq!(application_name(6, 587), "SMTP (TCP/587)");
+    assert_eq!(application_name(1, 8), "ICMP");
+    assert_eq!(application_name(6, 8443), "TCP/8443");
+    assert_eq!(application_name(17, 51820), "WireGuard (UDP/51820)");
+    assert_eq!(application_name(6, 51820), "TCP/51820");
+  }
+
+  fn ip(s: &str) -> XdpIpAddress {
+    XdpIpAddress::from_ip(s.parse().unwrap())
+  }
+
+  fn flow(src: &str, src_port: u16, dst: &str, dst_port: u16) -> FlowKey {
+    FlowKey { src: ip(src), dst: ip(dst), proto: 6, src_port, dst_port }
+  }
+
+  fn data(bytes: u64, bytes_per_second: u64) -> FlowData {
+    FlowData { bytes, bytes_per_second, ..Default::default() }
+  }
+
+  #[test]
+  fn summarizes_flows_by_application() {
+    let customer = ip("100.64.0.1");
+    let is_shaped = |ip: &XdpIpAddress| *ip == customer;
+    let flows = [
+      // Downloading over HTTPS from two servers
+      (flow("1.1.1.1", 443, "100.64.0.1", 50000), data(1000, 100)),
+      (flow("8.8.8.8", 443, "100.64.0.1", 50001), data(3000, 200)),
+      // Uploading over HTTPS
+      (flow("100.64.0.1", 50000, "1.1.1.1", 443), data(500, 50)),
+      // A busier SSH session
+      (flow("100.64.0.1", 50002, "9.9.9.9", 22), data(100, 1000)),
+      // Nothing to do with a shaped host
+      (flow("9.9.9.9", 443, "8.8.8.8", 50003), data(9999, 9999)),
+    ];
+    let mut summaries = HashMap::new();
+    for (key, data) in flows.iter() {
+      if let Some(local) = localize(key, &is_shaped) {
+        let name = application_name(key.proto, local.remote_port);
+        add_to_summary(&mut summaries, name, &local, data);
+      }
+    }
+
+    let top = top_n(summaries.clone(), 10);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0].name, "SSH (TCP/22)");
+    assert_eq!(top[0].bits_per_second, (0, 8000));
+    assert_eq!(top[1].name, "HTTPS (TCP/443)");
+    assert_eq!(top[1].flows, 3);
+    assert_eq!(top[1].bytes, (4000, 500));
+    assert_eq!(top[1].bits_per_second, (2400, 400));
+
+    let top = top_n(summaries, 1);
+    assert_eq!(top.len(), 1);
+    assert_eq!(top[0].name, "SSH (TCP/22)");
+  }
+}
@@ -1,7 +1,10 @@
 mod throughput_entry;
 mod tracking_data;
 mod heimdall_data;
-pub use heimdall_data::get_flow_stats;
+pub use heimdall_data::{
+  get_circuit_flows, get_flow_stats, top_flow_applications,
+  top_flow_destinations,
+};
 use crate::{
   shaped_devices_tracker::NETWORK_JSON,
   throughput_tracker::tracking_data::ThroughputTracker, stats::TIME_TO_POLL_HOSTS,
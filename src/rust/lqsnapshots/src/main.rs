@@ -0,0 +1,97 @@
+//! Lists, compares and restores the snapshots of `/etc/lqos.conf`,
+//! `ShapedDevices.csv` and `network.json` that are taken whenever one of
+//! them changes. For example, to undo a bad integration sync:
+//!
+//! `lqsnapshots list`
+//! `lqsnapshots diff 20230514-093012 current`
+//! `lqsnapshots restore 20230514-093012 --reload`
+
+use anyhow::{Error, Result};
+use clap::{Parser, Subcommand};
+use lqos_config::{ConfigSnapshot, CURRENT_FILES};
+use std::process::exit;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+  #[command(subcommand)]
+  command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+  /// List snapshots, newest first
+  List,
+  /// Take a snapshot now, if anything has changed since the last one
+  Take {
+    /// What made the change, such as the name of an integration
+    #[arg(long, default_value = "lqsnapshots")]
+    source: String,
+  },
+  /// Show what changed between two snapshots. Use "current" for the
+  /// live files.
+  Diff {
+    /// The older snapshot
+    from: String,
+    /// The newer snapshot
+    #[arg(default_value = CURRENT_FILES)]
+    to: String,
+  },
+  /// Copy a snapshot's files back into place. The live files are
+  /// snapshotted first.
+  Restore {
+    /// The snapshot to restore
+    id: String,
+    /// Why the snapshot is being restored
+    #[arg(long, default_value = "lqsnapshots")]
+    source: String,
+    /// Reload LibreQoS afterwards, to rebuild the queues
+    #[arg(long)]
+    reload: bool,
+  },
+}
+
+fn main() -> Result<()> {
+  let cli = Args::parse();
+  match cli.command {
+    Some(Commands::List) => {
+      println!("{:<20} {:<45} Source", "Snapshot", "Files");
+      for snapshot in ConfigSnapshot::list()? {
+        println!(
+          "{:<20} {:<45} {}",
+          snapshot.id,
+          snapshot.files.join(", "),
+          snapshot.source
+        );
+      }
+    }
+    Some(Commands::Take { source }) => match ConfigSnapshot::take(&source)? {
+      Some(snapshot) => println!("Took snapshot {}", snapshot.id),
+      None => println!("Nothing has changed since the last snapshot"),
+    },
+    Some(Commands::Diff { from, to }) => {
+      let diff = ConfigSnapshot::diff(&from, &to)?;
+      if diff.is_empty() {
+        println!("No differences");
+      } else {
+        print!("{diff}");
+      }
+    }
+    Some(Commands::Restore { id, source, reload }) => {
+      let snapshot = ConfigSnapshot::restore(&id, &source)?;
+      println!("Restored {}: {}", snapshot.id, snapshot.files.join(", "));
+      if reload {
+        let output = lqos_config::load_libreqos()
+          .map_err(|e| Error::msg(format!("Unable to reload: {e}")))?;
+        println!("{output}");
+      } else {
+        println!("lqosd will pick up the files; reload LibreQoS to rebuild the queues.");
+      }
+    }
+    None => {
+      println!("Run with --help to see instructions");
+      exit(0);
+    }
+  }
+  Ok(())
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use lqos_config::{UserRole, WebUsers};
-use std::process::exit;
+use lqos_config::{ApiScope, UserRole, WebUsers};
+use std::{
+  process::exit,
+  time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Parser)]
 #[command()]
@@ -31,8 +34,35 @@ enum Commands {
     /// Username to remove
     username: String,
   },
+  /// Log a user out of every web UI session
+  Revoke {
+    /// Username whose sessions to end
+    username: String,
+  },
   /// List users
   List,
+  /// Create an API key, for scripts using the node manager's API
+  AddKey {
+    /// Name of the key
+    #[arg(long)]
+    name: String,
+
+    /// What the key may do: read_stats, manage_devices, reload or
+    /// capture_packets. Repeat for more than one.
+    #[arg(long = "scope", required = true)]
+    scopes: Vec<ApiScope>,
+
+    /// Days until the key stops working. Never expires if left out.
+    #[arg(long)]
+    expires_days: Option<u64>,
+  },
+  /// Remove an API key
+  DelKey {
+    /// Name of the key to remove
+    name: String,
+  },
+  /// List API keys
+  ListKeys,
 }
 
 fn main() -> Result<()> {
@@ -45,10 +75,32 @@ fn main() -> Result<()> {
     Some(Commands::Del { username }) => {
       users.remove_user(&username)?;
     }
+    Some(Commands::Revoke { username }) => {
+      users.revoke_sessions(&username)?;
+    }
     Some(Commands::List) => {
       println!("All Users\n");
       users.print_users()?;
     }
+    Some(Commands::AddKey { name, scopes, expires_days }) => {
+      let expires = expires_days.map(|days| {
+        SystemTime::now()
+          .duration_since(UNIX_EPOCH)
+          .map(|d| d.as_secs())
+          .unwrap_or(0)
+          + days * 86400
+      });
+      let key = users.add_api_key(&name, &scopes, expires)?;
+      println!("{key}");
+      eprintln!("Keep this key safe. It won't be shown again.");
+    }
+    Some(Commands::DelKey { name }) => {
+      users.remove_api_key(&name)?;
+    }
+    Some(Commands::ListKeys) => {
+      println!("All API Keys\n");
+      users.print_api_keys();
+    }
     None => {
       println!("Run with --help to see instructions");
       exit(0);